@@ -0,0 +1,137 @@
+//! Benchmarks of the wallet's hot paths
+//!
+//! Baselines for scanning and proving work: decrypting a batch of encrypted
+//! outputs as a scan does, building Merkle trees from relayer leaves, hashing
+//! note commitments and nullifiers, and the V2 AES-GCM envelope.
+//!
+//! Run with `cargo bench --bench hot_paths`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use privacy_cash::constants::MERKLE_TREE_DEPTH;
+use privacy_cash::encryption::EncryptionService;
+use privacy_cash::keypair::ZkKeypair;
+use privacy_cash::merkle_tree::{MerkleTree, SparseMerkleTree, DEFAULT_ZERO};
+use privacy_cash::utxo::{Utxo, UtxoVersion};
+use solana_sdk::signature::Keypair;
+
+fn wallet() -> EncryptionService {
+    let mut service = EncryptionService::new();
+    service
+        .derive_encryption_key_from_wallet(&Keypair::new())
+        .expect("derive keys");
+    service
+}
+
+/// `count` V2 notes owned by `service`
+fn notes(service: &EncryptionService, count: u64) -> Vec<Utxo> {
+    let keypair = ZkKeypair::from_hex(&service.get_utxo_private_key_v2().expect("V2 key"))
+        .expect("V2 keypair");
+    (0..count)
+        .map(|i| Utxo::new(1_000 + i, keypair.clone(), i, None, Some(UtxoVersion::V2)))
+        .collect()
+}
+
+/// Leaves that look like commitments
+fn leaves(count: u64) -> Vec<String> {
+    let service = wallet();
+    notes(&service, count)
+        .iter()
+        .map(|note| note.get_commitment().expect("commitment"))
+        .collect()
+}
+
+fn decrypt_outputs(c: &mut Criterion) {
+    let service = wallet();
+    let other = wallet();
+    let mut group = c.benchmark_group("decrypt_outputs");
+    for count in [64u64, 256] {
+        // A scan mostly sees other wallets' notes: one in four is ours
+        let outputs: Vec<Vec<u8>> = notes(&service, count / 4)
+            .iter()
+            .map(|note| service.encrypt_utxo(note))
+            .chain(notes(&other, count - count / 4).iter().map(|note| other.encrypt_utxo(note)))
+            .collect::<Result<_, _>>()
+            .expect("encrypt");
+
+        group.throughput(Throughput::Elements(count));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &outputs, |b, outputs| {
+            b.iter(|| {
+                outputs
+                    .iter()
+                    .filter(|output| service.classify_decrypt_utxo(output).is_ok())
+                    .count()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn build_merkle_tree(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build_merkle_tree");
+    group.sample_size(10);
+    for count in [256u64, 1024] {
+        let leaves = leaves(count);
+        group.throughput(Throughput::Elements(count));
+        group.bench_with_input(BenchmarkId::new("dense", count), &leaves, |b, leaves| {
+            b.iter(|| {
+                MerkleTree::with_elements(MERKLE_TREE_DEPTH, leaves.clone(), DEFAULT_ZERO)
+                    .expect("tree")
+                    .root()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("sparse", count), &leaves, |b, leaves| {
+            b.iter(|| {
+                SparseMerkleTree::with_elements(MERKLE_TREE_DEPTH, leaves.clone(), DEFAULT_ZERO)
+                    .expect("tree")
+                    .root()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn commitments_and_nullifiers(c: &mut Criterion) {
+    let service = wallet();
+    let mut group = c.benchmark_group("note_hashes");
+    for count in [16u64, 128] {
+        let notes = notes(&service, count);
+        group.throughput(Throughput::Elements(count));
+        group.bench_with_input(BenchmarkId::new("commitment", count), &notes, |b, notes| {
+            b.iter(|| {
+                for note in notes {
+                    black_box(note.get_commitment().expect("commitment"));
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("nullifier", count), &notes, |b, notes| {
+            b.iter(|| {
+                for note in notes {
+                    black_box(note.get_nullifier().expect("nullifier"));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn aes_gcm_round_trip(c: &mut Criterion) {
+    let service = wallet();
+    let note = notes(&service, 1).remove(0);
+    let plaintext = note.serialize_for_encryption();
+
+    c.bench_function("aes_gcm_round_trip", |b| {
+        b.iter(|| {
+            let encrypted = service.encrypt(black_box(plaintext.as_bytes())).expect("encrypt");
+            service.decrypt(&encrypted).expect("decrypt")
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    decrypt_outputs,
+    build_merkle_tree,
+    commitments_and_nullifiers,
+    aes_gcm_round_trip
+);
+criterion_main!(benches);
@@ -8,12 +8,11 @@
 //!
 //! Run with: cargo run --example basic_usage
 
-use privacy_cash::{PrivacyCash, Result, USDC_MINT};
+use privacy_cash::{tokens, Lamports, PrivacyCash, Result};
 use solana_sdk::{
     pubkey::Pubkey,
     signature::{Keypair, Signer},
 };
-use std::str::FromStr;
 
 /// SOL example: deposit, check balance, withdraw
 async fn sol_example(client: &PrivacyCash, recipient: &Pubkey) -> Result<()> {
@@ -22,9 +21,9 @@ async fn sol_example(client: &PrivacyCash, recipient: &Pubkey) -> Result<()> {
     // Check initial private balance
     let balance = client.get_private_balance().await?;
     println!(
-        "Initial private SOL balance: {} lamports ({:.6} SOL)",
+        "Initial private SOL balance: {} lamports ({})",
         balance.lamports,
-        balance.lamports as f64 / 1_000_000_000.0
+        balance.amount()
     );
 
     // Deposit 0.01 SOL
@@ -35,9 +34,9 @@ async fn sol_example(client: &PrivacyCash, recipient: &Pubkey) -> Result<()> {
     // Check balance after deposit
     let balance = client.get_private_balance().await?;
     println!(
-        "Balance after deposit: {} lamports ({:.6} SOL)",
+        "Balance after deposit: {} lamports ({})",
         balance.lamports,
-        balance.lamports as f64 / 1_000_000_000.0
+        balance.amount()
     );
 
     // Withdraw 0.005 SOL
@@ -54,9 +53,9 @@ async fn sol_example(client: &PrivacyCash, recipient: &Pubkey) -> Result<()> {
     // Check final balance
     let balance = client.get_private_balance().await?;
     println!(
-        "Final private SOL balance: {} lamports ({:.6} SOL)",
+        "Final private SOL balance: {} lamports ({})",
         balance.lamports,
-        balance.lamports as f64 / 1_000_000_000.0
+        balance.amount()
     );
 
     Ok(())
@@ -168,8 +167,8 @@ async fn main() -> Result<()> {
     // Check on-chain SOL balance
     let sol_balance = client.get_sol_balance()?;
     println!(
-        "On-chain SOL balance: {:.6} SOL",
-        sol_balance as f64 / 1_000_000_000.0
+        "On-chain SOL balance: {}",
+        Lamports(sol_balance)
     );
 
     // Optional: clear cache for fresh start
@@ -192,9 +191,9 @@ async fn main() -> Result<()> {
 
     let sol_balance = client.get_private_balance().await?;
     println!(
-        "Private SOL balance: {} lamports ({:.6} SOL)",
+        "Private SOL balance: {} lamports ({})",
         sol_balance.lamports,
-        sol_balance.lamports as f64 / 1_000_000_000.0
+        sol_balance.amount()
     );
 
     let usdc_balance = client.get_private_balance_usdc().await?;
@@ -204,8 +203,7 @@ async fn main() -> Result<()> {
     );
 
     // Check other supported tokens
-    let usdt_mint = Pubkey::from_str("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB").unwrap();
-    let usdt_balance = client.get_private_balance_spl(&usdt_mint).await?;
+    let usdt_balance = client.get_private_balance_spl(&tokens::USDT_MINT).await?;
     println!(
         "Private USDT balance: {} base units ({:.2} USDT)",
         usdt_balance.base_units, usdt_balance.amount
@@ -6,12 +6,11 @@
 //! Or using a JSON keypair file:
 //!   SOLANA_PRIVATE_KEY=$(cat ~/.config/solana/id.json) cargo run --example check_balance
 
-use privacy_cash::{PrivacyCash, Signer};
-use solana_sdk::signature::Keypair;
-use std::str::FromStr;
+use privacy_cash::prelude::*;
+use privacy_cash::tokens;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
 
     println!("🔒 Privacy Cash Balance Checker\n");
@@ -45,7 +44,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Check on-chain SOL balance
     match client.get_sol_balance() {
         Ok(balance) => {
-            println!("📊 On-chain SOL: {:.6} SOL", balance as f64 / 1e9);
+            println!("📊 On-chain SOL: {}", Lamports(balance));
         }
         Err(e) => {
             println!("❌ Failed to get SOL balance: {}", e);
@@ -58,10 +57,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Fetching private SOL balance...");
     match client.get_private_balance().await {
         Ok(balance) => {
-            println!("✅ Private SOL: {} lamports ({:.6} SOL)", 
-                balance.lamports, 
-                balance.lamports as f64 / 1e9
-            );
+            println!("✅ Private SOL: {}", balance);
         }
         Err(e) => {
             println!("❌ Private SOL error: {}", e);
@@ -72,10 +68,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("\nFetching private USDC balance...");
     match client.get_private_balance_usdc().await {
         Ok(balance) => {
-            println!("✅ Private USDC: {} base units ({:.2} USDC)", 
-                balance.base_units, 
-                balance.amount
-            );
+            println!("✅ Private USDC: {}", balance);
         }
         Err(e) => {
             println!("❌ Private USDC error: {}", e);
@@ -84,15 +77,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Check private USDT balance
     println!("\nFetching private USDT balance...");
-    let usdt_mint = solana_sdk::pubkey::Pubkey::from_str(
-        "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB"
-    )?;
-    match client.get_private_balance_spl(&usdt_mint).await {
+    match client.get_private_balance_spl(&tokens::USDT_MINT).await {
         Ok(balance) => {
-            println!("✅ Private USDT: {} base units ({:.2} USDT)", 
-                balance.base_units, 
-                balance.amount
-            );
+            println!("✅ Private USDT: {}", balance);
         }
         Err(e) => {
             println!("❌ Private USDT error: {}", e);
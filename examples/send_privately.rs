@@ -12,7 +12,7 @@
 //!   # Send 10 USDC to a recipient
 //!   SOLANA_PRIVATE_KEY=<key> cargo run --release --example send_privately -- 10 usdc RecipientPubkey
 
-use privacy_cash::{send_privately, Signer};
+use privacy_cash::{send_privately, Lamports, Signer, TokenAmount};
 use solana_sdk::signature::Keypair;
 use std::env;
 
@@ -103,8 +103,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 fn format_amount(amount: u64, token: &str) -> String {
     match token {
-        "sol" => format!("{:.6}", amount as f64 / 1_000_000_000.0),
-        "usdc" | "usdt" => format!("{:.2}", amount as f64 / 1_000_000.0),
+        "sol" => Lamports(amount).to_sol_string(),
+        "usdc" | "usdt" => TokenAmount::new(amount, 6).to_string(),
         _ => amount.to_string(),
     }
 }
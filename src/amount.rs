@@ -0,0 +1,323 @@
+//! Exact amounts for SOL and SPL tokens
+//!
+//! Amounts are held in base units and converted to and from decimal strings
+//! with integer arithmetic, so `"0.3"` SOL is exactly 300_000_000 lamports
+//! rather than whatever `0.3 * 1e9` truncates to.
+
+use crate::error::{PrivacyCashError, Result};
+use crate::utxo::format_base_units;
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+/// Decimals of native SOL
+pub const SOL_DECIMALS: u32 = 9;
+
+/// Largest amount a single transaction can move
+///
+/// The program takes the external amount as a signed 64-bit integer.
+pub const MAX_TRANSACT_BASE_UNITS: u64 = i64::MAX as u64;
+
+/// How to drop the digits past the displayed precision
+///
+/// Use `Floor` for amounts a user can act on, so a balance is never shown
+/// higher than what can actually be withdrawn, and `Round` for plain display.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rounding {
+    /// Toward zero
+    #[default]
+    Floor,
+    /// To the nearest value, halves away from zero
+    Round,
+    /// Away from zero
+    Ceil,
+}
+
+/// An amount of native SOL in lamports
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Lamports(pub u64);
+
+impl Lamports {
+    /// Wrap a lamport count
+    pub const fn new(lamports: u64) -> Self {
+        Self(lamports)
+    }
+
+    /// Parse a decimal SOL amount such as `"1.25"`
+    pub fn from_sol(sol: &str) -> Result<Self> {
+        parse_base_units(sol, SOL_DECIMALS).map(Self)
+    }
+
+    /// Format as a decimal SOL amount, trimming trailing zeros
+    pub fn to_sol_string(&self) -> String {
+        format_base_units(self.0, SOL_DECIMALS)
+    }
+
+    /// Format as SOL with exactly `precision` decimal places
+    pub fn to_sol_string_rounded(&self, precision: u32, rounding: Rounding) -> String {
+        format_base_units_rounded(self.0, SOL_DECIMALS, precision, rounding)
+    }
+
+    /// Add, returning `None` on overflow
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    /// Subtract, returning `None` on underflow
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+}
+
+impl From<u64> for Lamports {
+    fn from(lamports: u64) -> Self {
+        Self(lamports)
+    }
+}
+
+impl From<Lamports> for u64 {
+    fn from(lamports: Lamports) -> Self {
+        lamports.0
+    }
+}
+
+impl std::str::FromStr for Lamports {
+    type Err = PrivacyCashError;
+
+    fn from_str(sol: &str) -> Result<Self> {
+        Self::from_sol(sol)
+    }
+}
+
+impl std::fmt::Display for Lamports {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} SOL", self.to_sol_string())
+    }
+}
+
+/// An amount of an SPL token in base units
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TokenAmount {
+    /// Amount in the token's smallest unit
+    pub base_units: u64,
+    /// Number of decimals of the token
+    pub decimals: u32,
+}
+
+impl TokenAmount {
+    /// Wrap a base-unit amount
+    pub const fn new(base_units: u64, decimals: u32) -> Self {
+        Self { base_units, decimals }
+    }
+
+    /// Parse a decimal token amount such as `"10.5"`
+    pub fn parse(amount: &str, decimals: u32) -> Result<Self> {
+        Ok(Self::new(parse_base_units(amount, decimals)?, decimals))
+    }
+
+    /// Format as a decimal token amount, trimming trailing zeros
+    pub fn to_decimal_string(&self) -> String {
+        format_base_units(self.base_units, self.decimals)
+    }
+
+    /// Format with exactly `precision` decimal places
+    pub fn to_decimal_string_rounded(&self, precision: u32, rounding: Rounding) -> String {
+        format_base_units_rounded(self.base_units, self.decimals, precision, rounding)
+    }
+
+    /// Add, returning `None` on overflow or if the decimals differ
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        if self.decimals != other.decimals {
+            return None;
+        }
+        let base_units = self.base_units.checked_add(other.base_units)?;
+        Some(Self::new(base_units, self.decimals))
+    }
+
+    /// Subtract, returning `None` on underflow or if the decimals differ
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        if self.decimals != other.decimals {
+            return None;
+        }
+        let base_units = self.base_units.checked_sub(other.base_units)?;
+        Some(Self::new(base_units, self.decimals))
+    }
+}
+
+impl std::fmt::Display for TokenAmount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_decimal_string())
+    }
+}
+
+/// Parse a non-negative decimal amount into base units
+///
+/// Rejects signs, exponents, more fractional digits than `decimals`, and
+/// values that do not fit in a `u64`.
+pub fn parse_base_units(amount: &str, decimals: u32) -> Result<u64> {
+    let invalid = |reason: &str| {
+        PrivacyCashError::InvalidInput(format!("Invalid amount '{}': {}", amount, reason))
+    };
+
+    let trimmed = amount.trim();
+    let (whole, fraction) = trimmed.split_once('.').unwrap_or((trimmed, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(invalid("empty"));
+    }
+    if !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+        return Err(invalid("expected digits and at most one '.'"));
+    }
+    if fraction.len() > decimals as usize {
+        return Err(invalid(&format!("more than {} decimal places", decimals)));
+    }
+
+    let overflow = || invalid("too large");
+    let scale = 10u64.checked_pow(decimals).ok_or_else(overflow)?;
+    let whole: u64 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().map_err(|_| overflow())?
+    };
+    let fraction: u64 = if fraction.is_empty() {
+        0
+    } else {
+        let padded = format!("{:0<width$}", fraction, width = decimals as usize);
+        padded.parse().map_err(|_| overflow())?
+    };
+
+    whole
+        .checked_mul(scale)
+        .and_then(|units| units.checked_add(fraction))
+        .ok_or_else(overflow)
+}
+
+/// Format base units with exactly `precision` decimal places
+///
+/// Works on the integer amount, so the result is exact; digits past
+/// `decimals` are zeros. A rounded-up amount may carry into the whole part,
+/// e.g. 0.9999 at 2 places is `"1.00"` with `Round` or `Ceil`.
+pub fn format_base_units_rounded(
+    base_units: u64,
+    decimals: u32,
+    precision: u32,
+    rounding: Rounding,
+) -> String {
+    let kept_places = precision.min(decimals);
+    let step = 10u128.pow(decimals - kept_places);
+    let units = u128::from(base_units);
+    let remainder = units % step;
+    let mut kept = units / step;
+    let round_up = match rounding {
+        Rounding::Floor => false,
+        Rounding::Round => remainder * 2 >= step,
+        Rounding::Ceil => remainder > 0,
+    };
+    if round_up {
+        kept += 1;
+    }
+
+    let scale = 10u128.pow(kept_places);
+    let whole = kept / scale;
+    if precision == 0 {
+        return whole.to_string();
+    }
+    let mut fraction = match kept_places {
+        0 => String::new(),
+        places => format!("{:0width$}", kept % scale, width = places as usize),
+    };
+    fraction.push_str(&"0".repeat((precision - kept_places) as usize));
+    format!("{}.{}", whole, fraction)
+}
+
+/// Convert a floating-point amount to base units, rounding to the nearest unit
+///
+/// For callers that only have an `f64`; goes through the shortest decimal
+/// representation so `0.3` becomes exactly `300_000_000` at 9 decimals.
+pub fn base_units_from_f64(amount: f64, decimals: u32) -> Result<u64> {
+    if !amount.is_finite() || amount < 0.0 {
+        return Err(PrivacyCashError::InvalidInput(format!("Invalid amount: {}", amount)));
+    }
+    parse_base_units(&format!("{:.*}", decimals as usize, amount), decimals)
+}
+
+/// Convert an arbitrary-precision amount to base units for a transaction
+///
+/// Fails with `InvalidInput` above [`MAX_TRANSACT_BASE_UNITS`] rather than
+/// truncating.
+pub fn base_units_from_biguint(amount: &BigUint) -> Result<u64> {
+    amount
+        .to_u64()
+        .filter(|units| *units <= MAX_TRANSACT_BASE_UNITS)
+        .ok_or_else(|| {
+            PrivacyCashError::InvalidInput(format!(
+                "Amount {} exceeds the per-transaction maximum of {} base units",
+                amount, MAX_TRANSACT_BASE_UNITS
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_base_units() {
+        assert_eq!(parse_base_units("1.25", 9).unwrap(), 1_250_000_000);
+        assert_eq!(parse_base_units("0.3", 9).unwrap(), 300_000_000);
+        assert_eq!(parse_base_units(".5", 6).unwrap(), 500_000);
+        assert_eq!(parse_base_units("42", 0).unwrap(), 42);
+        assert!(parse_base_units("0.0000001", 6).is_err());
+        assert!(parse_base_units("-1", 9).is_err());
+        assert!(parse_base_units("1e9", 9).is_err());
+        assert!(parse_base_units("1.2.3", 9).is_err());
+        assert!(parse_base_units("", 9).is_err());
+        assert!(parse_base_units("18446744074", 9).is_err());
+
+        assert_eq!(base_units_from_f64(0.3, 9).unwrap(), 300_000_000);
+        assert!(base_units_from_f64(-0.1, 9).is_err());
+
+        let max = BigUint::from(MAX_TRANSACT_BASE_UNITS);
+        assert_eq!(base_units_from_biguint(&max).unwrap(), MAX_TRANSACT_BASE_UNITS);
+        assert!(base_units_from_biguint(&(max + 1u32)).is_err());
+        assert!(base_units_from_biguint(&BigUint::from(u128::MAX)).is_err());
+    }
+
+    #[test]
+    fn test_amount_types() {
+        let sol: Lamports = "1.5".parse().unwrap();
+        assert_eq!(sol, Lamports(1_500_000_000));
+        assert_eq!(sol.to_string(), "1.5 SOL");
+        assert_eq!(sol.checked_sub(Lamports(2_000_000_000)), None);
+
+        let usdc = TokenAmount::parse("10.25", 6).unwrap();
+        assert_eq!(usdc.to_string(), "10.25");
+        assert_eq!(usdc.checked_add(TokenAmount::new(1, 9)), None);
+        assert_eq!(
+            usdc.checked_add(TokenAmount::new(750_000, 6)),
+            Some(TokenAmount::new(11_000_000, 6))
+        );
+    }
+
+    #[test]
+    fn test_format_rounded() {
+        let sol = Lamports(1_234_567_890);
+        assert_eq!(sol.to_sol_string_rounded(4, Rounding::Floor), "1.2345");
+        assert_eq!(sol.to_sol_string_rounded(4, Rounding::Round), "1.2346");
+        assert_eq!(sol.to_sol_string_rounded(4, Rounding::Ceil), "1.2346");
+        assert_eq!(sol.to_sol_string_rounded(0, Rounding::Round), "1");
+        assert_eq!(sol.to_sol_string_rounded(12, Rounding::Floor), "1.234567890000");
+
+        let usdc = TokenAmount::new(9_995_000, 6);
+        assert_eq!(usdc.to_decimal_string_rounded(2, Rounding::Floor), "9.99");
+        assert_eq!(usdc.to_decimal_string_rounded(2, Rounding::Round), "10.00");
+        assert_eq!(TokenAmount::new(1, 6).to_decimal_string_rounded(2, Rounding::Ceil), "0.01");
+        assert_eq!(TokenAmount::new(1, 6).to_decimal_string_rounded(2, Rounding::Floor), "0.00");
+
+        let max = format_base_units_rounded(u64::MAX, 0, 1, Rounding::Ceil);
+        assert_eq!(max, format!("{}.0", u64::MAX));
+    }
+}
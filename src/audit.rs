@@ -0,0 +1,277 @@
+//! Local audit log of deposits and withdrawals
+//!
+//! Each completed operation appends one JSON line to the wallet's log in
+//! [`Storage`]. Lines are chained: every entry stores the hash of the one
+//! before it and its own HMAC over both, keyed by a secret derived from the
+//! wallet's UTXO key. Editing, reordering or deleting an entry in the middle
+//! of the log shows up in [`verify_chain`], and without the key a modified
+//! log cannot be re-chained. Dropping entries from the end cannot be told
+//! apart from a shorter log. Appends go through a compare-and-swap, so
+//! concurrent clients never drop each other's lines.
+//!
+//! The log is kept apart from the note cache and survives `clear_cache`. It
+//! records amounts, recipients and signatures in plain text, so clients that
+//! want no local trace can turn it off with `PrivacyCash::set_audit_log`.
+
+use crate::clock::clock;
+use crate::constants::LSK_AUDIT_LOG;
+use crate::deposit::DepositResult;
+use crate::deposit_spl::DepositSplResult;
+use crate::error::{PrivacyCashError, Result};
+use crate::get_utxos::localstorage_key;
+use crate::preview::TransactionKind;
+use crate::storage::Storage;
+use crate::withdraw::WithdrawResult;
+use crate::withdraw_spl::WithdrawSplResult;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use solana_sdk::pubkey::Pubkey;
+use std::time::UNIX_EPOCH;
+
+/// `prev_hash` of the first entry
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One completed operation, before it is chained into the log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Deposit or withdrawal
+    pub operation: TransactionKind,
+    /// Token name, e.g. "sol" or "usdc"
+    pub token: String,
+    /// Base units entering the pool (deposit) or reaching the recipient
+    pub amount: u64,
+    /// Fees paid in base units: the pool's fee plus, on withdrawals, the
+    /// Nova Shield fee paid from the public wallet. Network fees are not
+    /// included.
+    pub fee: u64,
+    /// Withdrawal recipient; `None` for deposits
+    pub recipient: Option<String>,
+    /// Transaction signature
+    pub signature: String,
+}
+
+impl AuditRecord {
+    /// A SOL deposit of `lamports`
+    pub fn deposit(lamports: u64, result: &DepositResult) -> Self {
+        Self {
+            operation: TransactionKind::Deposit,
+            token: "sol".to_string(),
+            amount: lamports,
+            fee: result.fee_in_lamports,
+            recipient: None,
+            signature: result.signature.clone(),
+        }
+    }
+
+    /// An SPL token deposit of `base_units`
+    pub fn deposit_spl(
+        token: impl Into<String>,
+        base_units: u64,
+        result: &DepositSplResult,
+    ) -> Self {
+        Self {
+            operation: TransactionKind::Deposit,
+            token: token.into(),
+            amount: base_units,
+            fee: result.fee_base_units,
+            recipient: None,
+            signature: result.signature.clone(),
+        }
+    }
+
+    /// A SOL withdrawal that also paid `nova_shield_fee` lamports
+    pub fn withdraw(result: &WithdrawResult, nova_shield_fee: u64) -> Self {
+        Self {
+            operation: TransactionKind::Withdraw,
+            token: "sol".to_string(),
+            amount: result.amount_in_lamports,
+            fee: result.fee_in_lamports + nova_shield_fee,
+            recipient: Some(result.recipient.clone()),
+            signature: result.signature.clone(),
+        }
+    }
+
+    /// An SPL token withdrawal that also paid `nova_shield_fee` base units
+    pub fn withdraw_spl(
+        token: impl Into<String>,
+        result: &WithdrawSplResult,
+        nova_shield_fee: u64,
+    ) -> Self {
+        Self {
+            operation: TransactionKind::Withdraw,
+            token: token.into(),
+            amount: result.base_units,
+            fee: result.fee_base_units + nova_shield_fee,
+            recipient: Some(result.recipient.clone()),
+            signature: result.signature.clone(),
+        }
+    }
+}
+
+/// A line of the audit log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Unix time in seconds when the entry was appended
+    pub timestamp: u64,
+    /// What was done
+    #[serde(flatten)]
+    pub record: AuditRecord,
+    /// `hash` of the previous entry
+    pub prev_hash: String,
+    /// HMAC-SHA256 over `prev_hash` and this entry's fields, hex encoded
+    pub hash: String,
+}
+
+impl AuditEntry {
+    fn compute_hash(&self, audit_key: &[u8]) -> String {
+        let record = &self.record;
+        let preimage = format!(
+            "{}|{}|{:?}|{}|{}|{}|{}|{}",
+            self.prev_hash,
+            self.timestamp,
+            record.operation,
+            record.token,
+            record.amount,
+            record.fee,
+            record.recipient.as_deref().unwrap_or_default(),
+            record.signature,
+        );
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(audit_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(preimage.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+/// Storage key of a wallet's audit log
+pub fn audit_log_key(public_key: &Pubkey) -> String {
+    format!("{}{}", LSK_AUDIT_LOG, localstorage_key(public_key))
+}
+
+/// Append `record` to the log at `key`, returning the chained entry
+///
+/// `audit_key` keys the chain; see
+/// [`derive_audit_key`](crate::encryption::EncryptionService::derive_audit_key).
+pub fn append(
+    storage: &Storage,
+    key: &str,
+    audit_key: &[u8],
+    record: AuditRecord,
+) -> Result<AuditEntry> {
+    let timestamp = clock()
+        .system_time()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+
+    loop {
+        let current = storage.get(key);
+        let prev_hash = match current.as_deref().and_then(|log| log.lines().last()) {
+            Some(line) => parse_line(line)?.hash,
+            None => GENESIS_HASH.to_string(),
+        };
+
+        let mut entry = AuditEntry {
+            timestamp,
+            record: record.clone(),
+            prev_hash,
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash(audit_key);
+
+        let line = serde_json::to_string(&entry)?;
+        let updated = match current.as_deref() {
+            Some(log) => format!("{}\n{}", log, line),
+            None => line,
+        };
+        if storage.cas(key, current.as_deref(), &updated) {
+            return Ok(entry);
+        }
+    }
+}
+
+/// Read every entry of the log at `key`, oldest first
+pub fn read(storage: &Storage, key: &str) -> Result<Vec<AuditEntry>> {
+    storage
+        .get(key)
+        .map(|log| log.lines().map(parse_line).collect())
+        .unwrap_or_else(|| Ok(Vec::new()))
+}
+
+/// Check that the entries form an unbroken hash chain under `audit_key`
+///
+/// Returns the position of the first entry whose hash or link does not
+/// match, or `None` if the whole log is intact.
+pub fn verify_chain(entries: &[AuditEntry], audit_key: &[u8]) -> Option<usize> {
+    let mut prev_hash = GENESIS_HASH;
+    for (position, entry) in entries.iter().enumerate() {
+        if entry.prev_hash != prev_hash || entry.hash != entry.compute_hash(audit_key) {
+            return Some(position);
+        }
+        prev_hash = &entry.hash;
+    }
+    None
+}
+
+fn parse_line(line: &str) -> Result<AuditEntry> {
+    serde_json::from_str(line)
+        .map_err(|e| PrivacyCashError::StorageError(format!("Unreadable audit log entry: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(amount: u64, signature: &str) -> AuditRecord {
+        AuditRecord {
+            operation: TransactionKind::Withdraw,
+            token: "sol".to_string(),
+            amount,
+            fee: 5_000,
+            recipient: Some(Pubkey::new_unique().to_string()),
+            signature: signature.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_audit_log_chain() {
+        let storage = Storage::memory();
+        let key = audit_log_key(&Pubkey::new_unique());
+        let audit_key = [7u8; 32];
+        assert!(read(&storage, &key).unwrap().is_empty());
+
+        let first = append(&storage, &key, &audit_key, record(10, "sig1")).unwrap();
+        append(&storage, &key, &audit_key, record(20, "sig2")).unwrap();
+        append(&storage, &key, &audit_key, record(30, "sig3")).unwrap();
+
+        let mut entries = read(&storage, &key).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0], first);
+        assert_eq!(entries[1].prev_hash, first.hash);
+        assert_eq!(verify_chain(&entries, &audit_key), None);
+        // Without the key the chain cannot be checked, or rebuilt
+        assert_eq!(verify_chain(&entries, &[8u8; 32]), Some(0));
+
+        // Editing an amount or dropping an entry breaks the chain
+        entries[1].record.amount = 2;
+        assert_eq!(verify_chain(&entries, &audit_key), Some(1));
+        entries.remove(1);
+        assert_eq!(verify_chain(&entries, &audit_key), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_audit_timestamp_uses_clock() {
+        let _guard = crate::http::TEST_HOOKS_LOCK.lock().await;
+        let mock = std::sync::Arc::new(crate::clock::MockClock::new());
+        crate::clock::set_clock(mock.clone());
+        mock.advance(std::time::Duration::from_secs(3_600));
+
+        let storage = Storage::memory();
+        let entry = append(&storage, "audit", &[7u8; 32], record(10, "sig1"));
+        crate::clock::reset_clock();
+
+        let now = std::time::SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        assert!(entry.unwrap().timestamp >= now + 3_590);
+    }
+}
@@ -0,0 +1,201 @@
+//! Blindings of new output notes
+//!
+//! By default every output note gets a random blinding, so a note can only be
+//! rebuilt from its encrypted output stored by the relayer. With
+//! [`BlindingSource::Derived`] the n-th output's blinding is derived from the
+//! V2 UTXO private key and n (see [`EncryptionService::derive_blinding`]),
+//! and the counter is kept in [`Storage`]. Given the amounts, e.g. from the
+//! public amounts of deposit transactions, the notes can then be rebuilt from
+//! the wallet seed alone.
+//!
+//! The counter is reserved before the note is built, so a failed transaction
+//! skips a value rather than reusing it. On a new device or after the cache
+//! is lost, [`recover_blinding_counter`] moves the counter past the notes
+//! found by a scan, crossing such gaps.
+
+use crate::constants::{BLINDING_RECOVERY_GAP, LSK_BLINDING_COUNTER};
+use crate::encryption::EncryptionService;
+use crate::error::Result;
+use crate::storage::Storage;
+use crate::utxo::{Utxo, UtxoVersion};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// How output notes get their blinding
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BlindingSource {
+    /// A random blinding per note
+    #[default]
+    Random,
+    /// Derived from the sender's UTXO key and a persisted counter
+    Derived,
+}
+
+impl BlindingSource {
+    /// Blinding for the next output note of `encryption_service`'s owner
+    pub fn next(
+        self,
+        encryption_service: &EncryptionService,
+        storage: &Storage,
+    ) -> Result<BigUint> {
+        match self {
+            BlindingSource::Random => Ok(Utxo::random_blinding()),
+            BlindingSource::Derived => {
+                let counter = reserve_counter(encryption_service, storage)?;
+                encryption_service.derive_blinding(counter)
+            }
+        }
+    }
+}
+
+/// Number of derived blindings used so far, i.e. the next counter value
+pub fn blinding_counter(encryption_service: &EncryptionService, storage: &Storage) -> Result<u64> {
+    let (_, counter) = CounterKeys::of(encryption_service)?.load(storage);
+    Ok(counter)
+}
+
+/// Move the counter to at least `next`, returning the counter afterwards
+///
+/// The counter never moves back, so values already handed out are not reused.
+pub fn advance_blinding_counter(
+    encryption_service: &EncryptionService,
+    storage: &Storage,
+    next: u64,
+) -> Result<u64> {
+    let (_, counter) = update_counter(encryption_service, storage, |counter| counter.max(next))?;
+    Ok(counter)
+}
+
+/// Move the counter past the highest derived blinding among `utxos`
+///
+/// Derived blindings are tried in order until `BLINDING_RECOVERY_GAP` values
+/// in a row match none of the notes, so values skipped by failed
+/// transactions are crossed. Only the given notes are matched: a value used
+/// solely by a note that is no longer among them can be handed out again.
+/// Returns the counter afterwards.
+pub fn recover_blinding_counter(
+    encryption_service: &EncryptionService,
+    storage: &Storage,
+    utxos: &[Utxo],
+) -> Result<u64> {
+    let blindings: HashSet<&BigUint> = utxos.iter().map(|utxo| &utxo.blinding).collect();
+    let mut next = 0;
+    let mut counter = 0;
+    while counter < next + BLINDING_RECOVERY_GAP {
+        if blindings.contains(&encryption_service.derive_blinding(counter)?) {
+            next = counter + 1;
+        }
+        counter += 1;
+    }
+    advance_blinding_counter(encryption_service, storage, next)
+}
+
+/// Take the next counter value, persisting the increment first
+fn reserve_counter(encryption_service: &EncryptionService, storage: &Storage) -> Result<u64> {
+    let (counter, _) = update_counter(encryption_service, storage, |counter| counter + 1)?;
+    Ok(counter)
+}
+
+/// Apply `update` to the stored counter, returning the old and new values
+fn update_counter(
+    encryption_service: &EncryptionService,
+    storage: &Storage,
+    update: impl Fn(u64) -> u64,
+) -> Result<(u64, u64)> {
+    let keys = CounterKeys::of(encryption_service)?;
+    loop {
+        let (current, counter) = keys.load(storage);
+        let updated = update(counter);
+        if storage.cas(&keys.key, current.as_deref(), &updated.to_string()) {
+            return Ok((counter, updated));
+        }
+    }
+}
+
+/// Storage keys of an identity's counter
+struct CounterKeys {
+    /// Keyed by the full V2 UTXO pubkey, not the wallet
+    key: String,
+    /// Earlier key, built from the first 16 characters of the UTXO pubkey
+    legacy: String,
+}
+
+impl CounterKeys {
+    fn of(encryption_service: &EncryptionService) -> Result<Self> {
+        let zk_pubkey =
+            encryption_service.utxo_keypair_for_version(UtxoVersion::V2)?.pubkey_string();
+        Ok(Self {
+            key: format!("{}{}", LSK_BLINDING_COUNTER, zk_pubkey),
+            legacy: format!("{}{}", LSK_BLINDING_COUNTER, &zk_pubkey[..16.min(zk_pubkey.len())]),
+        })
+    }
+
+    /// Raw value under `key` and the counter, falling back to the legacy key
+    fn load(&self, storage: &Storage) -> (Option<String>, u64) {
+        let current = storage.get(&self.key);
+        let counter = current
+            .clone()
+            .or_else(|| storage.get(&self.legacy))
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+        (current, counter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Keypair;
+
+    #[test]
+    fn test_derived_blindings() {
+        let mut service = EncryptionService::new();
+        service.derive_encryption_key_from_wallet(&Keypair::new()).unwrap();
+        let storage = Storage::memory();
+
+        let source = BlindingSource::Derived;
+        let first = source.next(&service, &storage).unwrap();
+        let second = source.next(&service, &storage).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(blinding_counter(&service, &storage).unwrap(), 2);
+
+        // Recomputable from the key and counter alone
+        assert_eq!(service.derive_blinding(0).unwrap(), first);
+        assert_eq!(service.derive_blinding(1).unwrap(), second);
+
+        let mut other = EncryptionService::new();
+        other.derive_encryption_key_from_wallet(&Keypair::new()).unwrap();
+        assert_ne!(other.derive_blinding(0).unwrap(), first);
+
+        BlindingSource::Random.next(&service, &storage).unwrap();
+        assert_eq!(blinding_counter(&service, &storage).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_recover_blinding_counter() {
+        let mut service = EncryptionService::new();
+        service.derive_encryption_key_from_wallet(&Keypair::new()).unwrap();
+        let keypair = service.utxo_keypair_for_version(UtxoVersion::V2).unwrap();
+        let note = |counter: u64| {
+            let blinding = service.derive_blinding(counter).unwrap();
+            Utxo::with_blinding(1_000u64, blinding, keypair.clone(), 0, None, None)
+        };
+
+        // Values 1-4 were skipped by failed transactions; a random note never matches
+        let storage = Storage::memory();
+        let utxos = [note(0), note(5), Utxo::new(1_000u64, keypair.clone(), 0, None, None)];
+        assert_eq!(recover_blinding_counter(&service, &storage, &utxos).unwrap(), 6);
+        assert_eq!(blinding_counter(&service, &storage).unwrap(), 6);
+        assert_eq!(recover_blinding_counter(&service, &storage, &[]).unwrap(), 6);
+        assert_eq!(advance_blinding_counter(&service, &storage, 3).unwrap(), 6);
+        assert_eq!(advance_blinding_counter(&service, &storage, 9).unwrap(), 9);
+
+        // A counter stored under the short key of earlier versions is picked up
+        let legacy = Storage::memory();
+        let keys = CounterKeys::of(&service).unwrap();
+        legacy.set(&keys.legacy, "4");
+        assert_eq!(BlindingSource::Derived.next(&service, &legacy).unwrap(), note(4).blinding);
+        assert_eq!(legacy.get(&keys.key).as_deref(), Some("5"));
+    }
+}
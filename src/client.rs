@@ -2,38 +2,107 @@
 //!
 //! Provides a high-level interface for interacting with Privacy Cash.
 
+use crate::amount::base_units_from_biguint;
+use crate::audit::{self, audit_log_key, AuditEntry, AuditRecord};
+use crate::blinding::{
+    advance_blinding_counter, blinding_counter, recover_blinding_counter, BlindingSource,
+};
+use crate::cheque::Cheque;
+use crate::clock;
 use crate::constants::{
-    get_supported_tokens, LSK_ENCRYPTED_OUTPUTS, LSK_FETCH_OFFSET, 
-    NOVA_SHIELD_FEE_RATE, NOVA_SHIELD_FEE_WALLET, NOVA_SHIELD_REFERRER, USDC_MINT,
+    find_token_by_mint, get_supported_tokens, DEFAULT_CIRCUIT_PATH, DEFAULT_PROOF_TIMEOUT_SECS,
+    INDEX_POLL_ATTEMPTS, INDEX_POLL_INTERVAL_SECS, LAMPORTS_PER_SOL, LSK_ENCRYPTED_OUTPUTS,
+    LSK_FETCH_OFFSET, LSK_SPENT_NULLIFIERS, LSK_TREE_STATE, NOVA_SHIELD_FEE_RATE,
+    NOVA_SHIELD_FEE_WALLET, NOVA_SHIELD_REFERRER, PROGRAM_ID, RELAYER_API_URL, USDC_MINT,
+};
+use crate::consolidate::{
+    consolidate, filter_mergeable, plan_consolidation, resume_consolidation, ConsolidateParams,
+    ConsolidationPlan, ConsolidationResult,
 };
+use crate::delivery::{verify_withdrawal, verify_withdrawal_spl};
 use crate::deposit::{deposit, DepositParams, DepositResult};
 use crate::deposit_spl::{deposit_spl, DepositSplParams, DepositSplResult};
-use crate::encryption::EncryptionService;
+use crate::diagnostics::{parse_tree_state, redact_url, CircuitDiagnostics, Diagnostics};
+use crate::encryption::{ChangeOutput, EncryptionService, OutputTarget};
 use crate::error::{PrivacyCashError, Result};
-use crate::get_utxos::{get_private_balance, localstorage_key};
-use crate::get_utxos_spl::get_private_balance_spl;
-use crate::storage::Storage;
-use crate::utxo::{Balance, SplBalance};
-use crate::withdraw::{withdraw, WithdrawParams, WithdrawResult};
-use crate::withdraw_spl::{withdraw_spl, WithdrawSplParams, WithdrawSplResult};
+use crate::get_utxos::{
+    are_utxos_spent_with_commitment, find_cached_output, get_private_balance, get_utxo_details,
+    get_utxos, get_utxos_with_options, localstorage_key, scan_range, ScanOptions,
+};
+use crate::get_utxos_spl::{get_private_balance_spl, get_utxos_spl_with_options};
+use crate::hasher::is_circuit_compatible;
+use crate::idempotency::{self, idempotency_storage_key, KeyState};
+use crate::keypair::ZkKeypair;
+use crate::migrate::{migrate_v1_to_v2, MigrateParams, MigrationResult};
+use crate::network_state::{refresh_network_state, NetworkState};
+use crate::pool_stats::{pool_stats, PoolStats};
+use crate::preview::{
+    preview_transaction, ApprovalHook, TransactionKind, TransactionPreview, TxPreview,
+};
+use crate::prover::{resolve_circuit_path, validate_circuit_files, CircuitSource};
+use crate::prover_rust::estimated_proof_time;
+use crate::reconcile::{reconcile, Reconciliation};
+use crate::scan::{scan_all, WalletSummary};
+use crate::self_test::{self_test, SelfTestReport};
+use crate::storage::{
+    CacheClearSummary, CacheFormat, CacheStats, Storage, TokenCacheStats,
+};
+use crate::tree_audit::{verify_tree_consistency, TreeConsistency};
+use crate::utils::{is_commitment_included, transaction_status, verify_note, TransactionStatus};
+use crate::utxo::{
+    get_balance_from_utxos, get_balance_from_utxos_spl, Balance, SplBalance, Utxo, UtxoDetail,
+    UtxoVersion,
+};
+use crate::wallet_lock::{OperationState, WalletLock};
+use crate::withdraw::{
+    check_chosen_inputs, check_fee_limit, check_withdrawable, withdraw, withdraw_fee,
+    withdraw_fee_config, NetWithdrawResult, WithdrawOutcome, WithdrawParams, WithdrawResult,
+};
+use crate::withdraw_spl::{
+    gross_for_net, spl_withdraw_fee_config, withdraw_spl, DeliveryMode, WithdrawSplParams,
+    WithdrawSplResult,
+};
+use futures::stream::{self, StreamExt};
+use num_bigint::BigUint;
+use sha2::{Digest, Sha256};
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::{
+    commitment_config::CommitmentConfig,
     pubkey::Pubkey,
-    signature::{Keypair, Signer},
+    signature::{Keypair, Signature, Signer},
     system_instruction,
     transaction::Transaction,
 };
 use spl_associated_token_account::get_associated_token_address;
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
+
+/// Prefixes of the per-token cache entries
+const CACHE_PREFIXES: [&str; 4] =
+    [LSK_FETCH_OFFSET, LSK_ENCRYPTED_OUTPUTS, LSK_TREE_STATE, LSK_SPENT_NULLIFIERS];
 
 /// Main Privacy Cash client
+///
+/// The client is `Send + Sync` and cheap to clone: clones share the RPC
+/// client and cache backend. Relayer requests from every instance go through
+/// the process-wide [`crate::http::transport`], so many wallets scanned
+/// concurrently share one connection pool; see [`PrivacyCash::scan_wallets`].
+///
+/// Operations that sign (deposits, withdrawals, migration, consolidation)
+/// run one at a time per wallet, across clones and separately built clients;
+/// concurrent calls queue. See [`PrivacyCash::operation_state`].
+#[derive(Clone)]
 pub struct PrivacyCash {
     /// Solana RPC connection
-    connection: RpcClient,
+    connection: Arc<RpcClient>,
+
+    /// User's public key
+    pubkey: Pubkey,
 
-    /// User's keypair
-    keypair: Arc<Keypair>,
+    /// User's keypair (`None` for read-only clients)
+    keypair: Option<Arc<Keypair>>,
 
     /// Encryption service
     encryption_service: EncryptionService,
@@ -43,12 +112,31 @@ pub struct PrivacyCash {
 
     /// Path to circuit files
     circuit_path: String,
+
+    /// Where the circuit files were found (`None` if not located)
+    circuit_source: Option<CircuitSource>,
+
+    /// Hook that approves each transaction before it is relayed
+    approval: Option<Arc<ApprovalHook>>,
+
+    /// Queue for this wallet's signing operations
+    operation_lock: Arc<WalletLock>,
+
+    /// How new output notes get their blindings
+    blinding_source: BlindingSource,
+
+    /// Limit on generating each proof
+    proof_timeout: Duration,
+
+    /// Whether completed deposits and withdrawals are written to the audit log
+    audit_log: bool,
 }
 
 impl std::fmt::Debug for PrivacyCash {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("PrivacyCash")
-            .field("pubkey", &self.keypair.pubkey())
+            .field("pubkey", &self.pubkey)
+            .field("read_only", &self.keypair.is_none())
             .finish()
     }
 }
@@ -82,13 +170,186 @@ impl PrivacyCash {
     /// * `keypair` - User's Solana keypair
     /// * `cache_dir` - Optional custom cache directory
     /// * `circuit_path` - Optional custom path to circuit files
+    ///
+    /// Without a `circuit_path`, the circuit files are looked up through the
+    /// `PRIVACY_CASH_CIRCUIT_PATH` environment variable, then in
+    /// `circuit/transaction2` next to the executable and under the working
+    /// directory. Fails with `CircuitNotFound` if none has valid files.
     pub fn with_options(
         rpc_url: &str,
         keypair: Keypair,
         cache_dir: Option<PathBuf>,
         circuit_path: Option<String>,
     ) -> Result<Self> {
-        let connection = RpcClient::new(rpc_url.to_string());
+        let mut encryption_service = EncryptionService::new();
+        encryption_service.derive_encryption_key_from_wallet(&keypair)?;
+
+        Self::build(
+            rpc_url,
+            keypair.pubkey(),
+            Some(Arc::new(keypair)),
+            encryption_service,
+            cache_dir,
+            circuit_path,
+        )
+    }
+
+    /// Create a client without a wallet keypair that can scan and report balances
+    ///
+    /// The client cannot sign, but the signature is as sensitive as the
+    /// wallet itself: it derives the keys that own the shielded notes, so
+    /// anyone holding it can spend them from any fee-paying account. Only
+    /// pass it where the wallet's secret key would be acceptable. To give a
+    /// third party a view of the balance, export a viewing key and use
+    /// [`watch_only`](Self::watch_only) instead.
+    ///
+    /// # Arguments
+    /// * `rpc_url` - Solana RPC URL
+    /// * `pubkey` - Owner's public key
+    /// * `signature` - Owner's signature over `SIGN_MESSAGE`, exported from the owning wallet
+    /// * `cache_dir` - Optional custom cache directory
+    ///
+    /// Operations that sign transactions fail with `ReadOnlyClient`.
+    pub fn read_only(
+        rpc_url: &str,
+        pubkey: Pubkey,
+        signature: &[u8],
+        cache_dir: Option<PathBuf>,
+    ) -> Result<Self> {
+        if signature.len() != 64 {
+            return Err(PrivacyCashError::InvalidInput(format!(
+                "Expected a 64-byte signature, got {} bytes",
+                signature.len()
+            )));
+        }
+
+        let mut encryption_service = EncryptionService::new();
+        encryption_service.derive_encryption_key_from_signature(signature)?;
+
+        Self::build(rpc_url, pubkey, None, encryption_service, cache_dir, None)
+    }
+
+    /// Create a watch-only client from an exported viewing key
+    ///
+    /// The client can scan, decrypt and report balances for `pubkey`, but it
+    /// holds no wallet keypair, so every operation that signs fails with
+    /// `ReadOnlyClient`. Only V2 notes are visible; legacy V1 notes are skipped.
+    ///
+    /// # Arguments
+    /// * `rpc_url` - Solana RPC URL
+    /// * `pubkey` - Owner's public key
+    /// * `viewing_key` - Key from `export_viewing_key` on the owning client
+    /// * `cache_dir` - Optional custom cache directory
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use privacy_cash::PrivacyCash;
+    /// use solana_sdk::signature::{Keypair, Signer};
+    ///
+    /// # async fn example() -> privacy_cash::Result<()> {
+    /// let owner = Keypair::new();
+    /// let pubkey = owner.pubkey();
+    /// let owner_client = PrivacyCash::new("https://api.mainnet-beta.solana.com", owner)?;
+    /// let viewing_key = owner_client.export_viewing_key()?;
+    ///
+    /// let auditor = PrivacyCash::watch_only(
+    ///     "https://api.mainnet-beta.solana.com",
+    ///     pubkey,
+    ///     &viewing_key,
+    ///     None,
+    /// )?;
+    /// println!("{}", auditor.get_private_balance().await?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn watch_only(
+        rpc_url: &str,
+        pubkey: Pubkey,
+        viewing_key: &str,
+        cache_dir: Option<PathBuf>,
+    ) -> Result<Self> {
+        let encryption_service = EncryptionService::from_viewing_key(viewing_key)?;
+        Self::build(rpc_url, pubkey, None, encryption_service, cache_dir, None)
+    }
+
+    /// Create a client whose shielded notes belong to a separate ZK identity
+    ///
+    /// The wallet `keypair` still signs and pays for transactions, but the note
+    /// keypair and encryption key are derived from `identity_signature` (a
+    /// 64-byte signature over `SIGN_MESSAGE` from another wallet, or any
+    /// 64-byte secret seed). Notes deposited this way can only be spent by a
+    /// client created with the same identity.
+    ///
+    /// Without a `cache_dir`, the UTXO cache goes to a per-identity
+    /// subdirectory so it does not mix with the wallet's own cache.
+    ///
+    /// # Arguments
+    /// * `rpc_url` - Solana RPC URL
+    /// * `keypair` - Wallet keypair that signs transactions
+    /// * `identity_signature` - 64-byte seed for the shielded identity
+    /// * `cache_dir` - Optional custom cache directory
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use privacy_cash::{PrivacyCash, SIGN_MESSAGE};
+    /// use solana_sdk::signature::{Keypair, Signer};
+    ///
+    /// let wallet = Keypair::new();
+    /// let identity = Keypair::new();
+    /// let seed = identity.sign_message(SIGN_MESSAGE.as_bytes());
+    /// let client = PrivacyCash::with_identity(
+    ///     "https://api.mainnet-beta.solana.com",
+    ///     wallet,
+    ///     seed.as_ref(),
+    ///     None,
+    /// ).unwrap();
+    /// ```
+    pub fn with_identity(
+        rpc_url: &str,
+        keypair: Keypair,
+        identity_signature: &[u8],
+        cache_dir: Option<PathBuf>,
+    ) -> Result<Self> {
+        if identity_signature.len() != 64 {
+            return Err(PrivacyCashError::InvalidInput(format!(
+                "Expected a 64-byte identity signature, got {} bytes",
+                identity_signature.len()
+            )));
+        }
+
+        let mut encryption_service = EncryptionService::new();
+        encryption_service.derive_encryption_key_from_signature(identity_signature)?;
+
+        let cache_dir = match cache_dir {
+            Some(dir) => dir,
+            None => {
+                let utxo_private_key = encryption_service.get_utxo_private_key_v2()?;
+                let zk_pubkey = ZkKeypair::from_hex(&utxo_private_key)?.pubkey().to_string();
+                std::env::current_dir()?
+                    .join("cache")
+                    .join(format!("identity-{}", &zk_pubkey[..16.min(zk_pubkey.len())]))
+            }
+        };
+
+        Self::build(
+            rpc_url,
+            keypair.pubkey(),
+            Some(Arc::new(keypair)),
+            encryption_service,
+            Some(cache_dir),
+            None,
+        )
+    }
+
+    fn build(
+        rpc_url: &str,
+        pubkey: Pubkey,
+        keypair: Option<Arc<Keypair>>,
+        encryption_service: EncryptionService,
+        cache_dir: Option<PathBuf>,
+        circuit_path: Option<String>,
+    ) -> Result<Self> {
+        let connection = Arc::new(RpcClient::new(rpc_url.to_string()));
 
         let storage = if let Some(dir) = cache_dir {
             Storage::file(dir)?
@@ -96,28 +357,183 @@ impl PrivacyCash {
             Storage::default_file()?
         };
 
-        let mut encryption_service = EncryptionService::new();
-        encryption_service.derive_encryption_key_from_wallet(&keypair);
-
-        // Default circuit path - users need to download circuit files
-        let circuit_path = circuit_path.unwrap_or_else(|| {
-            std::env::current_dir()
-                .map(|p| p.join("circuit").join("transaction2").to_string_lossy().to_string())
-                .unwrap_or_else(|_| "./circuit/transaction2".to_string())
-        });
+        // Spending clients need the circuit files, so a missing circuit fails
+        // here rather than at the first proof; read-only clients never prove
+        let (circuit_path, circuit_source) = match resolve_circuit_path(circuit_path.as_deref()) {
+            Ok(circuit) => {
+                log::info!("Using circuit files at {} ({:?})", circuit.path, circuit.source);
+                (circuit.path, Some(circuit.source))
+            }
+            Err(e) if keypair.is_some() => return Err(e),
+            Err(_) => (DEFAULT_CIRCUIT_PATH.to_string(), None),
+        };
 
         Ok(Self {
             connection,
-            keypair: Arc::new(keypair),
+            pubkey,
+            keypair,
             encryption_service,
             storage,
             circuit_path,
+            circuit_source,
+            approval: None,
+            operation_lock: WalletLock::for_wallet(&pubkey),
+            blinding_source: BlindingSource::default(),
+            proof_timeout: Duration::from_secs(DEFAULT_PROOF_TIMEOUT_SECS),
+            audit_log: true,
         })
     }
 
     /// Get the user's public key
     pub fn pubkey(&self) -> Pubkey {
-        self.keypair.pubkey()
+        self.pubkey
+    }
+
+    /// Get the shielded public key that receives private transfers
+    ///
+    /// Derived from the V2 UTXO key; this is the key another user creates and
+    /// encrypts notes to. Watch-only clients share it with the owner.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// println!("Shielded address: {}", client.zk_pubkey()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn zk_pubkey(&self) -> Result<String> {
+        let keypair = self.encryption_service.utxo_keypair_for_version(UtxoVersion::V2)?;
+        Ok(keypair.pubkey_string())
+    }
+
+    /// Whether a signing operation is running on this wallet and how many
+    /// are queued behind it
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use privacy_cash::wallet_lock::OperationState;
+    ///
+    /// # fn example(client: &privacy_cash::PrivacyCash) {
+    /// if let OperationState::Running { queued } = client.operation_state() {
+    ///     println!("busy, {} more waiting", queued);
+    /// }
+    /// # }
+    /// ```
+    pub fn operation_state(&self) -> OperationState {
+        self.operation_lock.state()
+    }
+
+    /// Whether this client was created without a signing keypair
+    pub fn is_read_only(&self) -> bool {
+        self.keypair.is_none()
+    }
+
+    /// Get the signing keypair, failing for read-only clients
+    fn signer(&self) -> Result<&Keypair> {
+        self.keypair.as_deref().ok_or(PrivacyCashError::ReadOnlyClient)
+    }
+
+    /// Deposit parameters with this client's signer, identity, storage,
+    /// circuit, approval hook, blinding source and proof timeout
+    fn deposit_params(&self) -> Result<DepositParams<'_>> {
+        Ok(DepositParams {
+            approval: self.approval.as_deref(),
+            blinding: self.blinding_source,
+            proof_timeout: self.proof_timeout,
+            ..DepositParams::new(
+                &self.connection,
+                self.signer()?,
+                &self.encryption_service,
+                &self.storage,
+                &self.circuit_path,
+            )
+        })
+    }
+
+    /// Pay the Nova Shield fee on a SOL withdrawal from the public wallet
+    fn collect_nova_fee(&self, keypair: &Keypair, fee: u64) -> Result<()> {
+        if fee == 0 {
+            return Ok(());
+        }
+        let payer = keypair.pubkey();
+        let transfer_ix = system_instruction::transfer(&payer, &NOVA_SHIELD_FEE_WALLET, fee);
+        let recent_blockhash = self.connection.get_latest_blockhash()?;
+        let tx = Transaction::new_signed_with_payer(
+            &[transfer_ix],
+            Some(&payer),
+            &[keypair],
+            recent_blockhash,
+        );
+        self.connection.send_and_confirm_transaction(&tx)?;
+        log::info!("Nova Shield fee collected: {} lamports", fee);
+        Ok(())
+    }
+
+    /// Pay the Nova Shield fee on an SPL withdrawal from the public token
+    /// account, creating the fee wallet's token account if needed
+    fn collect_nova_fee_spl(
+        &self,
+        keypair: &Keypair,
+        mint_address: &Pubkey,
+        fee: u64,
+    ) -> Result<()> {
+        if fee == 0 {
+            return Ok(());
+        }
+        let payer = keypair.pubkey();
+        let user_ata = get_associated_token_address(&payer, mint_address);
+        let nova_shield_ata = get_associated_token_address(&NOVA_SHIELD_FEE_WALLET, mint_address);
+
+        let mut instructions = Vec::new();
+        if self.connection.get_account(&nova_shield_ata).is_err() {
+            instructions.push(
+                spl_associated_token_account::instruction::create_associated_token_account(
+                    &payer,
+                    &NOVA_SHIELD_FEE_WALLET,
+                    mint_address,
+                    &spl_token::id(),
+                ),
+            );
+        }
+        instructions.push(
+            spl_token::instruction::transfer(
+                &spl_token::id(),
+                &user_ata,
+                &nova_shield_ata,
+                &payer,
+                &[],
+                fee,
+            )
+            .map_err(|e| PrivacyCashError::TransactionError(e.to_string()))?,
+        );
+
+        let recent_blockhash = self.connection.get_latest_blockhash()?;
+        let tx = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&payer),
+            &[keypair],
+            recent_blockhash,
+        );
+        self.connection.send_and_confirm_transaction(&tx)?;
+        log::info!("Nova Shield SPL fee collected: {} base units", fee);
+        Ok(())
+    }
+
+    /// SPL counterpart of [`deposit_params`](Self::deposit_params)
+    fn deposit_spl_params<'a>(&'a self, mint_address: &'a Pubkey) -> Result<DepositSplParams<'a>> {
+        Ok(DepositSplParams {
+            approval: self.approval.as_deref(),
+            blinding: self.blinding_source,
+            proof_timeout: self.proof_timeout,
+            ..DepositSplParams::new(
+                &self.connection,
+                self.signer()?,
+                &self.encryption_service,
+                &self.storage,
+                mint_address,
+                &self.circuit_path,
+            )
+        })
     }
 
     // ============ SOL Operations ============
@@ -137,19 +553,19 @@ impl PrivacyCash {
     /// # }
     /// ```
     pub async fn deposit(&self, lamports: u64) -> Result<DepositResult> {
+        let _operation = self.operation_lock.acquire("deposit").await;
         // Use Nova Shield referrer by default for revenue sharing
         let referrer = NOVA_SHIELD_REFERRER.as_deref();
         
-        deposit(DepositParams {
-            connection: &self.connection,
-            keypair: &self.keypair,
-            encryption_service: &self.encryption_service,
-            storage: &self.storage,
+        let result = deposit(DepositParams {
             amount_in_lamports: lamports,
-            key_base_path: &self.circuit_path,
             referrer,
+            ..self.deposit_params()?
         })
-        .await
+        .await?;
+
+        self.audit(AuditRecord::deposit(lamports, &result));
+        Ok(result)
     }
 
     /// Deposit SOL with a referrer
@@ -158,16 +574,154 @@ impl PrivacyCash {
         lamports: u64,
         referrer: &str,
     ) -> Result<DepositResult> {
-        deposit(DepositParams {
-            connection: &self.connection,
-            keypair: &self.keypair,
-            encryption_service: &self.encryption_service,
-            storage: &self.storage,
+        let _operation = self.operation_lock.acquire("deposit").await;
+        let result = deposit(DepositParams {
             amount_in_lamports: lamports,
-            key_base_path: &self.circuit_path,
             referrer: Some(referrer),
+            ..self.deposit_params()?
+        })
+        .await?;
+
+        self.audit(AuditRecord::deposit(lamports, &result));
+        Ok(result)
+    }
+
+    /// Deposit SOL at most once per idempotency key
+    ///
+    /// For services that retry on timeouts: pass the same `idempotency_key`
+    /// on every attempt of one logical deposit. The key goes to the relayer
+    /// so it can drop a duplicate transaction, and is remembered locally for
+    /// `IDEMPOTENCY_WINDOW_SECS` with the amount and the signature of the
+    /// last transaction signed for it. A key that already completed returns
+    /// the original signature without depositing again. A key whose earlier
+    /// attempt failed or timed out first looks up that attempt's transaction:
+    /// if it landed, its signature is returned; otherwise the deposit is
+    /// retried with a warning, since an attempt still in flight may land.
+    /// Reusing a key for a different amount fails with `InvalidInput`.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let result = client.deposit_idempotent(10_000_000, "order-8731").await?;
+    /// println!("Deposit tx: {}", result.signature);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn deposit_idempotent(
+        &self,
+        lamports: u64,
+        idempotency_key: &str,
+    ) -> Result<DepositResult> {
+        let params = self.deposit_params()?;
+        let _operation = self.operation_lock.acquire("deposit").await;
+        let storage_key = idempotency_storage_key(&self.pubkey);
+        let complete = |result: &DepositResult| {
+            // The deposit landed, so a storage failure must not make the caller retry
+            let signature = &result.signature;
+            let recorded =
+                idempotency::complete(&self.storage, &storage_key, idempotency_key, signature);
+            if let Err(e) = recorded {
+                log::warn!("Failed to record deposit {}: {}", idempotency_key, e);
+            }
+            self.audit(AuditRecord::deposit(lamports, result));
+        };
+        match idempotency::begin(&self.storage, &storage_key, idempotency_key, lamports)? {
+            KeyState::Completed(signature) => {
+                log::info!("Deposit {} already completed: {}", idempotency_key, signature);
+                return Ok(DepositResult {
+                    signature,
+                    output_commitments: Vec::new(),
+                    fee_in_lamports: 0,
+                });
+            }
+            KeyState::Pending(Some(signature)) if self.transaction_landed(&signature)? => {
+                log::info!("Earlier attempt of deposit {} landed: {}", idempotency_key, signature);
+                let result = DepositResult {
+                    signature,
+                    output_commitments: Vec::new(),
+                    fee_in_lamports: 0,
+                };
+                complete(&result);
+                return Ok(result);
+            }
+            KeyState::Pending(_) => log::warn!(
+                "Retrying deposit {}; an earlier attempt may have landed",
+                idempotency_key
+            ),
+            KeyState::New => {}
+        }
+
+        let record_signed = |signature: &str| {
+            idempotency::record_submitted(&self.storage, &storage_key, idempotency_key, signature)
+        };
+        let result = deposit(DepositParams {
+            amount_in_lamports: lamports,
+            referrer: NOVA_SHIELD_REFERRER.as_deref(),
+            idempotency_key: Some(idempotency_key),
+            on_signed: Some(&record_signed),
+            ..params
+        })
+        .await?;
+
+        complete(&result);
+        Ok(result)
+    }
+
+    /// Whether the transaction with `signature` was processed without error
+    fn transaction_landed(&self, signature: &str) -> Result<bool> {
+        let signature = Signature::from_str(signature)
+            .map_err(|e| PrivacyCashError::InvalidInput(format!("Bad signature: {}", e)))?;
+        Ok(matches!(self.connection.get_signature_status(&signature)?, Some(Ok(()))))
+    }
+
+    /// Deposit an arbitrary-precision amount of lamports
+    ///
+    /// Same as [`deposit`](Self::deposit), for callers that carry amounts as
+    /// `BigUint`. Fails with `InvalidInput` above
+    /// [`MAX_TRANSACT_BASE_UNITS`](crate::amount::MAX_TRANSACT_BASE_UNITS).
+    pub async fn deposit_amount(&self, lamports: &BigUint) -> Result<DepositResult> {
+        self.deposit(base_units_from_biguint(lamports)?).await
+    }
+
+    /// Deposit SOL and wait until the new note is in the private balance
+    ///
+    /// `deposit` returns once the relayer has the encrypted output; the
+    /// balance scan may lag behind that. This polls the scan every
+    /// `INDEX_POLL_INTERVAL_SECS` until it finds one of the deposit's own
+    /// output notes, failing with `IndexingTimeout` after
+    /// `INDEX_POLL_ATTEMPTS` scans. Other notes arriving or being spent in
+    /// the meantime do not affect the wait.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let (result, balance) = client.deposit_and_wait_indexed(10_000_000).await?;
+    /// println!("Deposit {} indexed, balance: {} lamports", result.signature, balance.lamports);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn deposit_and_wait_indexed(
+        &self,
+        lamports: u64,
+    ) -> Result<(DepositResult, Balance)> {
+        let result = self.deposit(lamports).await?;
+
+        for _ in 0..INDEX_POLL_ATTEMPTS {
+            let utxos = self.get_utxos_with_options(None, ScanOptions::default()).await?;
+            let indexed = utxos.iter().any(|utxo| {
+                utxo.get_commitment().is_ok_and(|c| result.output_commitments.contains(&c))
+            });
+            if indexed {
+                return Ok((result, get_balance_from_utxos(&utxos)));
+            }
+            log::debug!("Deposit {} not indexed yet", result.signature);
+            clock::sleep(Duration::from_secs(INDEX_POLL_INTERVAL_SECS)).await;
+        }
+
+        Err(PrivacyCashError::IndexingTimeout {
+            signature: result.signature,
+            attempts: INDEX_POLL_ATTEMPTS,
         })
-        .await
     }
 
     /// Withdraw SOL from Privacy Cash
@@ -190,10 +744,65 @@ impl PrivacyCash {
         lamports: u64,
         recipient: Option<&Pubkey>,
     ) -> Result<WithdrawResult> {
-        let self_pubkey = self.keypair.pubkey();
+        self.withdraw_capped(lamports, recipient, None, None).await
+    }
+
+    /// Withdraw SOL, refusing to pay more than `max_fee` to the relayer
+    ///
+    /// Protects against a misconfigured or malicious relayer inflating its
+    /// fee: if the fee computed from the relayer's config is above `max_fee`,
+    /// the withdrawal fails with `FeeExceedsLimit` before any fee is collected
+    /// or anything is relayed. The Nova Shield fee is not counted.
+    ///
+    /// # Arguments
+    /// * `lamports` - Amount to withdraw in lamports
+    /// * `recipient` - Optional recipient address (defaults to self)
+    /// * `max_fee` - Largest relayer fee to accept, in lamports
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use privacy_cash::PrivacyCashError;
+    ///
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// match client.withdraw_with_max_fee(100_000_000, None, 2_000_000).await {
+    ///     Ok(result) => println!("{}", result),
+    ///     Err(PrivacyCashError::FeeExceedsLimit { fee, .. }) => {
+    ///         println!("Relayer asks {} lamports, not withdrawing", fee)
+    ///     }
+    ///     Err(e) => return Err(e),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn withdraw_with_max_fee(
+        &self,
+        lamports: u64,
+        recipient: Option<&Pubkey>,
+        max_fee: u64,
+    ) -> Result<WithdrawResult> {
+        self.withdraw_capped(lamports, recipient, Some(max_fee), None).await
+    }
+
+    async fn withdraw_capped(
+        &self,
+        lamports: u64,
+        recipient: Option<&Pubkey>,
+        max_fee: Option<u64>,
+        input_utxos: Option<&[Utxo]>,
+    ) -> Result<WithdrawResult> {
+        let keypair = self.signer()?;
+        let _operation = self.operation_lock.acquire("withdraw").await;
+        let self_pubkey = keypair.pubkey();
         let recipient = recipient.unwrap_or(&self_pubkey);
+
+        // Check the minimum and the fee cap before collecting any fee
+        crate::config::Config::check_minimum_withdrawal("sol", LAMPORTS_PER_SOL, lamports).await?;
+        if max_fee.is_some() {
+            let (relayer_fee, _, _) = self.estimate_withdraw_fees(lamports).await?;
+            check_fee_limit(relayer_fee, max_fee)?;
+        }
         
-        // Calculate and collect Nova Shield fee (1% of withdrawal amount)
+        // Calculate Nova Shield fee (1% of withdrawal amount)
         let nova_shield_fee = (lamports as f64 * *NOVA_SHIELD_FEE_RATE) as u64;
         
         if nova_shield_fee > 0 {
@@ -206,68 +815,254 @@ impl PrivacyCash {
                     have: public_balance,
                 });
             }
-            
-            // Transfer Nova Shield fee
-            let transfer_ix = system_instruction::transfer(
-                &self_pubkey,
-                &NOVA_SHIELD_FEE_WALLET,
-                nova_shield_fee,
-            );
-            
-            let recent_blockhash = self.connection.get_latest_blockhash()?;
-            let tx = Transaction::new_signed_with_payer(
-                &[transfer_ix],
-                Some(&self_pubkey),
-                &[&*self.keypair],
-                recent_blockhash,
-            );
-            
-            self.connection.send_and_confirm_transaction(&tx)?;
-            log::info!("Nova Shield fee collected: {} lamports", nova_shield_fee);
         }
+
+        // Collected only once the withdrawal is approved, just before it is relayed
+        let collect_fee = move |_: &TxPreview| self.collect_nova_fee(keypair, nova_shield_fee);
         
         // Use Nova Shield referrer by default for revenue sharing
         let referrer = NOVA_SHIELD_REFERRER.as_deref();
 
-        withdraw(WithdrawParams {
+        let result = withdraw(WithdrawParams {
             connection: &self.connection,
-            keypair: &self.keypair,
+            keypair,
             encryption_service: &self.encryption_service,
             storage: &self.storage,
             amount_in_lamports: lamports,
             recipient,
             key_base_path: &self.circuit_path,
             referrer,
+            approval: self.approval.as_deref(),
+            before_relay: Some(&collect_fee),
+            max_fee,
+            input_utxos,
+            output_targets: Default::default(),
+            change_output: Default::default(),
+            blinding: self.blinding_source,
+            proof_timeout: self.proof_timeout,
         })
-        .await
+        .await?;
+
+        self.audit(AuditRecord::withdraw(&result, nova_shield_fee));
+        Ok(result)
     }
 
-    /// Withdraw SOL with a referrer
-    pub async fn withdraw_with_referrer(
+    /// Withdraw an arbitrary-precision amount of lamports
+    ///
+    /// Same as [`withdraw`](Self::withdraw), for callers that carry amounts as
+    /// `BigUint`.
+    pub async fn withdraw_amount(
         &self,
-        lamports: u64,
+        lamports: &BigUint,
         recipient: Option<&Pubkey>,
-        referrer: &str,
     ) -> Result<WithdrawResult> {
-        let self_pubkey = self.keypair.pubkey();
-        let recipient = recipient.unwrap_or(&self_pubkey);
-
-        withdraw(WithdrawParams {
-            connection: &self.connection,
-            keypair: &self.keypair,
-            encryption_service: &self.encryption_service,
-            storage: &self.storage,
-            amount_in_lamports: lamports,
-            recipient,
-            key_base_path: &self.circuit_path,
-            referrer: Some(referrer),
-        })
-        .await
+        self.withdraw(base_units_from_biguint(lamports)?, recipient).await
     }
 
-    /// Withdraw ALL private SOL to recipient
+    /// Withdraw SOL, reporting balance problems as data instead of errors
     ///
-    /// This is a convenience method that withdraws the entire private SOL balance.
+    /// Unlike `withdraw`, this never sends a partial withdrawal. When the two
+    /// largest notes cannot cover `lamports` plus the relayer fee, it returns
+    /// `Insufficient` or `NeedsConsolidation` with the largest amount one
+    /// transaction can send, without paying any fee.
+    ///
+    /// # Arguments
+    /// * `lamports` - Amount to withdraw in lamports
+    /// * `recipient` - Optional recipient address (defaults to self)
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use privacy_cash::withdraw::WithdrawOutcome;
+    ///
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// match client.try_withdraw(10_000_000, None).await? {
+    ///     WithdrawOutcome::Ok(result) => println!("{}", result),
+    ///     WithdrawOutcome::Insufficient { max_single_tx, .. } => {
+    ///         println!("You can withdraw at most {} lamports", max_single_tx)
+    ///     }
+    ///     WithdrawOutcome::NeedsConsolidation { notes, .. } => {
+    ///         println!("Balance is split over {} notes, consolidate first", notes)
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn try_withdraw(
+        &self,
+        lamports: u64,
+        recipient: Option<&Pubkey>,
+    ) -> Result<WithdrawOutcome> {
+        self.signer()?;
+
+        let utxos = get_utxos(
+            &self.connection,
+            &self.pubkey,
+            &self.encryption_service,
+            &self.storage,
+            None,
+        )
+        .await?;
+        let amounts: Vec<u64> = utxos.iter().map(|u| u.amount_u64()).collect();
+
+        let (fee_rate, rent_lamports) = withdraw_fee_config().await?;
+        if let Some(outcome) = check_withdrawable(&amounts, lamports, fee_rate, rent_lamports) {
+            return Ok(outcome);
+        }
+
+        // Spend the notes that were checked, so the flow cannot fall back to
+        // a partial withdrawal
+        let mut inputs: Vec<Utxo> = utxos.into_iter().filter(|u| u.amount_u64() > 0).collect();
+        inputs.sort_by(|a, b| b.amount.cmp(&a.amount));
+        inputs.truncate(2);
+        let result = self.withdraw_capped(lamports, recipient, None, Some(&inputs)).await?;
+        Ok(WithdrawOutcome::Ok(result))
+    }
+
+    /// Withdraw SOL spending exactly the given notes
+    ///
+    /// Coin control: instead of the two largest notes, the one or two notes
+    /// with these commitments (e.g. from [`utxo_details`](Self::utxo_details))
+    /// are spent, so only they are linked by the transaction. Each must be an
+    /// unspent note of this wallet, and together they must cover `lamports`
+    /// plus the relayer fee; this is checked before any fee is paid and never
+    /// withdraws partially.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let notes = client.utxo_details().await?;
+    /// let oldest = notes.iter().filter(|n| !n.spent).min_by_key(|n| n.index).unwrap();
+    /// let result = client
+    ///     .withdraw_with_inputs(&[oldest.commitment.as_str()], 10_000_000, None)
+    ///     .await?;
+    /// println!("{}", result);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn withdraw_with_inputs(
+        &self,
+        commitments: &[&str],
+        lamports: u64,
+        recipient: Option<&Pubkey>,
+    ) -> Result<WithdrawResult> {
+        self.signer()?;
+
+        let utxos = get_utxos(
+            &self.connection,
+            &self.pubkey,
+            &self.encryption_service,
+            &self.storage,
+            None,
+        )
+        .await?;
+        let chosen = commitments
+            .iter()
+            .map(|commitment| {
+                utxos
+                    .iter()
+                    .find(|utxo| utxo.get_commitment().is_ok_and(|c| c == *commitment))
+                    .cloned()
+                    .ok_or_else(|| {
+                        PrivacyCashError::InvalidInput(format!(
+                            "No unspent note with commitment {}",
+                            commitment
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let inputs = check_chosen_inputs(&chosen)?;
+
+        // Fail before the Nova Shield fee is collected
+        let (relayer_fee, _, _) = self.estimate_withdraw_fees(lamports).await?;
+        let have: u64 = inputs.iter().map(Utxo::amount_u64).sum();
+        let need = lamports.saturating_add(relayer_fee);
+        if have < need {
+            return Err(PrivacyCashError::InsufficientBalance { have, need });
+        }
+
+        self.withdraw_capped(lamports, recipient, None, Some(&inputs)).await
+    }
+
+    /// Withdraw so the recipient receives exactly `net_lamports`
+    ///
+    /// SOL withdrawals take the relayer fee from the private balance on top of
+    /// the amount sent, so the recipient receives the requested amount and the
+    /// gross is `net_lamports` plus the fee. Never withdraws partially: fails
+    /// with `InsufficientBalance` when one transaction cannot cover both.
+    ///
+    /// # Arguments
+    /// * `net_lamports` - Amount the recipient must receive, in lamports
+    /// * `recipient` - Optional recipient address (defaults to self)
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let result = client.withdraw_exact_net(50_000_000, None).await?;
+    /// println!("Recipient got {}, {} left the pool", result.net, result.gross);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn withdraw_exact_net(
+        &self,
+        net_lamports: u64,
+        recipient: Option<&Pubkey>,
+    ) -> Result<NetWithdrawResult<WithdrawResult>> {
+        match self.try_withdraw(net_lamports, recipient).await? {
+            WithdrawOutcome::Ok(withdrawal) => Ok(NetWithdrawResult {
+                gross: withdrawal.amount_in_lamports + withdrawal.fee_in_lamports,
+                net: withdrawal.amount_in_lamports,
+                withdrawal,
+            }),
+            WithdrawOutcome::Insufficient { max_single_tx, .. }
+            | WithdrawOutcome::NeedsConsolidation { max_single_tx, .. } => {
+                Err(PrivacyCashError::InsufficientBalance {
+                    have: max_single_tx,
+                    need: net_lamports,
+                })
+            }
+        }
+    }
+
+    /// Withdraw SOL with a referrer
+    pub async fn withdraw_with_referrer(
+        &self,
+        lamports: u64,
+        recipient: Option<&Pubkey>,
+        referrer: &str,
+    ) -> Result<WithdrawResult> {
+        let _operation = self.operation_lock.acquire("withdraw").await;
+        let self_pubkey = self.pubkey;
+        let recipient = recipient.unwrap_or(&self_pubkey);
+
+        let result = withdraw(WithdrawParams {
+            connection: &self.connection,
+            keypair: self.signer()?,
+            encryption_service: &self.encryption_service,
+            storage: &self.storage,
+            amount_in_lamports: lamports,
+            recipient,
+            key_base_path: &self.circuit_path,
+            referrer: Some(referrer),
+            approval: self.approval.as_deref(),
+            before_relay: None,
+            max_fee: None,
+            input_utxos: None,
+            output_targets: Default::default(),
+            change_output: Default::default(),
+            blinding: self.blinding_source,
+            proof_timeout: self.proof_timeout,
+        })
+        .await?;
+
+        self.audit(AuditRecord::withdraw(&result, 0));
+        Ok(result)
+    }
+
+    /// Withdraw ALL private SOL to recipient
+    ///
+    /// This is a convenience method that withdraws the entire private SOL balance.
+    /// Fails with `BelowMinimum` without collecting any fee if the balance is
+    /// under the relayer's minimum withdrawal.
     ///
     /// # Arguments
     /// * `recipient` - Optional recipient address (defaults to self)
@@ -305,23 +1100,113 @@ impl PrivacyCash {
     /// ```rust,no_run
     /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
     /// let balance = client.get_private_balance().await?;
-    /// println!("Private balance: {} lamports ({} SOL)",
-    ///     balance.lamports,
-    ///     balance.lamports as f64 / 1_000_000_000.0
-    /// );
+    /// println!("Private balance: {} lamports ({})", balance.lamports, balance.amount());
     /// # Ok(())
     /// # }
     /// ```
     pub async fn get_private_balance(&self) -> Result<Balance> {
         get_private_balance(
             &self.connection,
-            &self.keypair.pubkey(),
+            &self.pubkey,
             &self.encryption_service,
             &self.storage,
         )
         .await
     }
 
+    /// Get private SOL balance from a full scan, ignoring the cache
+    ///
+    /// Scans from index 0 without reading or updating the cached outputs and
+    /// offset, so the result can be compared with `get_private_balance` to
+    /// check the cache. Unlike `clear_cache`, the cache is left intact.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let cached = client.get_private_balance().await?;
+    /// let fresh = client.get_private_balance_fresh().await?;
+    /// if cached.lamports != fresh.lamports {
+    ///     println!("Cache is out of date, clearing it");
+    ///     client.clear_cache().await;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_private_balance_fresh(&self) -> Result<Balance> {
+        let utxos = self.get_utxos_with_options(None, ScanOptions::fresh()).await?;
+        Ok(get_balance_from_utxos(&utxos))
+    }
+
+    /// Get an upper bound on the private SOL balance, skipping the spent-check
+    ///
+    /// Sums every decryptable note without asking the RPC whether its
+    /// nullifier is spent, which saves most of a warm scan's latency. Notes
+    /// spent since the last checked scan are still counted, so use this for
+    /// display only; deposits and withdrawals always run the full check.
+    /// The cache is left as is.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let estimate = client.get_private_balance_optimistic().await?;
+    /// println!("Up to {} lamports", estimate.lamports);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_private_balance_optimistic(&self) -> Result<Balance> {
+        let utxos = self.get_utxos_with_options(None, ScanOptions::optimistic()).await?;
+        Ok(get_balance_from_utxos(&utxos))
+    }
+
+    /// Get the private SOL balance of another shielded identity
+    ///
+    /// Scans with `encryption_service` instead of the client's own keys, so one
+    /// client can aggregate several shielded sub-accounts. Each identity gets
+    /// its own cache namespace, so scans of different identities never share
+    /// a fetch offset.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use privacy_cash::encryption::EncryptionService;
+    ///
+    /// # async fn example(
+    /// #     client: &privacy_cash::PrivacyCash,
+    /// #     sub_accounts: &[EncryptionService],
+    /// # ) -> privacy_cash::Result<()> {
+    /// let mut total = 0;
+    /// for identity in sub_accounts {
+    ///     total += client.get_private_balance_as(identity).await?.lamports;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_private_balance_as(
+        &self,
+        encryption_service: &EncryptionService,
+    ) -> Result<Balance> {
+        let cache_owner = self.cache_owner_for(encryption_service)?;
+        get_private_balance(&self.connection, &cache_owner, encryption_service, &self.storage)
+            .await
+    }
+
+    /// List every owned SOL note, spent and unspent
+    ///
+    /// Always scans the full history (the cache only keeps unspent notes), so
+    /// this is slower than `get_private_balance`.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// for note in client.utxo_details().await? {
+    ///     println!("#{} {} lamports spent={}", note.index, note.amount, note.spent);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn utxo_details(&self) -> Result<Vec<UtxoDetail>> {
+        get_utxo_details(&self.connection, &self.encryption_service).await
+    }
+
     // ============ SPL Token Operations ============
 
     /// Deposit SPL tokens into Privacy Cash
@@ -346,20 +1231,45 @@ impl PrivacyCash {
         base_units: u64,
         mint_address: &Pubkey,
     ) -> Result<DepositSplResult> {
+        let _operation = self.operation_lock.acquire("deposit_spl").await;
         // Use Nova Shield referrer by default for revenue sharing
         let referrer = NOVA_SHIELD_REFERRER.as_deref();
         
-        deposit_spl(DepositSplParams {
-            connection: &self.connection,
-            keypair: &self.keypair,
-            encryption_service: &self.encryption_service,
-            storage: &self.storage,
+        let result = deposit_spl(DepositSplParams {
             base_units,
-            mint_address,
-            key_base_path: &self.circuit_path,
             referrer,
+            ..self.deposit_spl_params(mint_address)?
         })
-        .await
+        .await?;
+
+        self.audit(AuditRecord::deposit_spl(token_name(mint_address), base_units, &result));
+        Ok(result)
+    }
+
+    /// Deposit an arbitrary-precision amount of SPL tokens
+    ///
+    /// Same as [`deposit_spl`](Self::deposit_spl), for tokens with many
+    /// decimals whose amounts are carried as `BigUint`. Fails with
+    /// `InvalidInput` above
+    /// [`MAX_TRANSACT_BASE_UNITS`](crate::amount::MAX_TRANSACT_BASE_UNITS).
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use num_bigint::BigUint;
+    /// use privacy_cash::tokens::USDC_MINT;
+    ///
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let amount: BigUint = "2500000".parse().unwrap();
+    /// client.deposit_spl_amount(&amount, &USDC_MINT).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn deposit_spl_amount(
+        &self,
+        base_units: &BigUint,
+        mint_address: &Pubkey,
+    ) -> Result<DepositSplResult> {
+        self.deposit_spl(base_units_from_biguint(base_units)?, mint_address).await
     }
 
     /// Deposit USDC (convenience method)
@@ -379,64 +1289,169 @@ impl PrivacyCash {
         mint_address: &Pubkey,
         recipient: Option<&Pubkey>,
     ) -> Result<WithdrawSplResult> {
-        let self_pubkey = self.keypair.pubkey();
+        let delivery_mode = DeliveryMode::default();
+        self.withdraw_spl_with_delivery(base_units, mint_address, recipient, delivery_mode)
+            .await
+    }
+
+    /// Withdraw an arbitrary-precision amount of SPL tokens
+    ///
+    /// Same as [`withdraw_spl`](Self::withdraw_spl), for callers that carry
+    /// amounts as `BigUint`.
+    pub async fn withdraw_spl_amount(
+        &self,
+        base_units: &BigUint,
+        mint_address: &Pubkey,
+        recipient: Option<&Pubkey>,
+    ) -> Result<WithdrawSplResult> {
+        self.withdraw_spl(base_units_from_biguint(base_units)?, mint_address, recipient).await
+    }
+
+    /// Withdraw SPL tokens, choosing how they reach the recipient
+    ///
+    /// [`DeliveryMode::ViaSelf`] forwards the tokens with a public transfer
+    /// from the signer, which links the signer and the recipient on-chain. If
+    /// that transfer fails after the withdrawal landed, the result carries the
+    /// withdrawal signature and `transfer_error`, and the tokens stay in the
+    /// signer's token account; do not retry the withdrawal itself.
+    ///
+    /// # Arguments
+    /// * `base_units` - Amount in base units
+    /// * `mint_address` - Token mint address
+    /// * `recipient` - Optional recipient address (defaults to self)
+    /// * `delivery_mode` - Direct to an existing account, create it, or route via self
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use privacy_cash::withdraw_spl::DeliveryMode;
+    /// use solana_sdk::pubkey::Pubkey;
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let recipient = Pubkey::new_unique();
+    /// let result = client
+    ///     .withdraw_spl_with_delivery(
+    ///         1_000_000,
+    ///         &privacy_cash::USDC_MINT,
+    ///         Some(&recipient),
+    ///         DeliveryMode::ViaSelf,
+    ///     )
+    ///     .await?;
+    /// println!("Transfer tx: {:?}", result.transfer_signature);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn withdraw_spl_with_delivery(
+        &self,
+        base_units: u64,
+        mint_address: &Pubkey,
+        recipient: Option<&Pubkey>,
+        delivery_mode: DeliveryMode,
+    ) -> Result<WithdrawSplResult> {
+        self.withdraw_spl_capped(base_units, mint_address, recipient, delivery_mode, None)
+            .await
+    }
+
+    /// Withdraw SPL tokens, refusing to pay more than `max_fee` to the relayer
+    ///
+    /// The SPL counterpart of [`withdraw_with_max_fee`](Self::withdraw_with_max_fee).
+    ///
+    /// # Arguments
+    /// * `base_units` - Amount in base units
+    /// * `mint_address` - Token mint address
+    /// * `recipient` - Optional recipient address (defaults to self)
+    /// * `max_fee` - Largest relayer fee to accept, in base units
+    pub async fn withdraw_spl_with_max_fee(
+        &self,
+        base_units: u64,
+        mint_address: &Pubkey,
+        recipient: Option<&Pubkey>,
+        max_fee: u64,
+    ) -> Result<WithdrawSplResult> {
+        let delivery_mode = DeliveryMode::default();
+        self.withdraw_spl_capped(base_units, mint_address, recipient, delivery_mode, Some(max_fee))
+            .await
+    }
+
+    /// Withdraw SPL tokens so the recipient receives exactly `net_base_units`
+    ///
+    /// SPL withdrawals take the relayer fee out of the amount, so the gross is
+    /// solved from the fee config as `(net + rent) / (1 - rate)`, adjusted for
+    /// rounding. Never withdraws partially: fails with
+    /// `InsufficientTokenBalance` when one transaction cannot cover the gross.
+    ///
+    /// # Arguments
+    /// * `net_base_units` - Amount the recipient must receive, in base units
+    /// * `mint_address` - Token mint address
+    /// * `recipient` - Optional recipient address (defaults to self)
+    pub async fn withdraw_spl_exact_net(
+        &self,
+        net_base_units: u64,
+        mint_address: &Pubkey,
+        recipient: Option<&Pubkey>,
+    ) -> Result<NetWithdrawResult<WithdrawSplResult>> {
+        self.signer()?;
+        let token = find_token_by_mint(mint_address)
+            .ok_or_else(|| PrivacyCashError::TokenNotSupported(mint_address.to_string()))?;
+
+        let (fee_rate, rent_base_units) = spl_withdraw_fee_config(&token).await?;
+        let gross = gross_for_net(net_base_units, fee_rate, rent_base_units)?;
+
+        // One transaction spends at most the two largest notes
+        let utxos = self.get_utxos_with_options(Some(mint_address), ScanOptions::default()).await?;
+        let mut amounts: Vec<u64> = utxos.iter().map(|u| u.amount_u64()).collect();
+        amounts.sort_unstable_by(|a, b| b.cmp(a));
+        let spendable: u64 = amounts.iter().take(2).sum();
+        if spendable < gross {
+            return Err(PrivacyCashError::InsufficientTokenBalance {
+                token: token.name.to_string(),
+                have: spendable,
+                need: gross,
+            });
+        }
+
+        let withdrawal = self.withdraw_spl(gross, mint_address, recipient).await?;
+        Ok(NetWithdrawResult {
+            gross,
+            net: withdrawal.base_units,
+            withdrawal,
+        })
+    }
+
+    async fn withdraw_spl_capped(
+        &self,
+        base_units: u64,
+        mint_address: &Pubkey,
+        recipient: Option<&Pubkey>,
+        delivery_mode: DeliveryMode,
+        max_fee: Option<u64>,
+    ) -> Result<WithdrawSplResult> {
+        let keypair = self.signer()?;
+        let _operation = self.operation_lock.acquire("withdraw_spl").await;
+        let self_pubkey = keypair.pubkey();
         let recipient = recipient.unwrap_or(&self_pubkey);
+
+        // Check the minimum and the fee cap before collecting any fee
+        let token = find_token_by_mint(mint_address)
+            .ok_or_else(|| PrivacyCashError::TokenNotSupported(mint_address.to_string()))?;
+        let units_per_token = token.units_per_token;
+        crate::config::Config::check_minimum_withdrawal(token.name, units_per_token, base_units).await?;
+        if max_fee.is_some() {
+            let (relayer_fee, _, _) =
+                self.estimate_withdraw_fees_spl(base_units, token.name).await?;
+            check_fee_limit(relayer_fee, max_fee)?;
+        }
         
-        // Calculate Nova Shield fee (1% of withdrawal amount)
+        // Calculate Nova Shield fee (1% of withdrawal amount), collected only
+        // once the withdrawal is approved, just before it is relayed
         let nova_shield_fee = (base_units as f64 * *NOVA_SHIELD_FEE_RATE) as u64;
-        
-        if nova_shield_fee > 0 {
-            // Transfer Nova Shield fee in SPL tokens
-            let user_ata = get_associated_token_address(&self_pubkey, mint_address);
-            let nova_shield_ata = get_associated_token_address(&NOVA_SHIELD_FEE_WALLET, mint_address);
-            
-            // Check if Nova Shield ATA exists, create if needed
-            if self.connection.get_account(&nova_shield_ata).is_err() {
-                let create_ata_ix = spl_associated_token_account::instruction::create_associated_token_account(
-                    &self_pubkey,
-                    &NOVA_SHIELD_FEE_WALLET,
-                    mint_address,
-                    &spl_token::id(),
-                );
-                
-                let recent_blockhash = self.connection.get_latest_blockhash()?;
-                let tx = Transaction::new_signed_with_payer(
-                    &[create_ata_ix],
-                    Some(&self_pubkey),
-                    &[&*self.keypair],
-                    recent_blockhash,
-                );
-                self.connection.send_and_confirm_transaction(&tx)?;
-            }
-            
-            // Transfer fee
-            let transfer_ix = spl_token::instruction::transfer(
-                &spl_token::id(),
-                &user_ata,
-                &nova_shield_ata,
-                &self_pubkey,
-                &[],
-                nova_shield_fee,
-            ).map_err(|e| PrivacyCashError::TransactionError(e.to_string()))?;
-            
-            let recent_blockhash = self.connection.get_latest_blockhash()?;
-            let tx = Transaction::new_signed_with_payer(
-                &[transfer_ix],
-                Some(&self_pubkey),
-                &[&*self.keypair],
-                recent_blockhash,
-            );
-            
-            self.connection.send_and_confirm_transaction(&tx)?;
-            log::info!("Nova Shield SPL fee collected: {} base units", nova_shield_fee);
-        }
+        let collect_fee =
+            move |_: &TxPreview| self.collect_nova_fee_spl(keypair, mint_address, nova_shield_fee);
         
         // Use Nova Shield referrer by default for revenue sharing
         let referrer = NOVA_SHIELD_REFERRER.as_deref();
 
-        withdraw_spl(WithdrawSplParams {
+        let result = withdraw_spl(WithdrawSplParams {
             connection: &self.connection,
-            keypair: &self.keypair,
+            keypair,
             encryption_service: &self.encryption_service,
             storage: &self.storage,
             base_units,
@@ -444,8 +1459,19 @@ impl PrivacyCash {
             recipient,
             key_base_path: &self.circuit_path,
             referrer,
+            delivery_mode,
+            approval: self.approval.as_deref(),
+            before_relay: Some(&collect_fee),
+            max_fee,
+            output_targets: Default::default(),
+            change_output: Default::default(),
+            blinding: self.blinding_source,
+            proof_timeout: self.proof_timeout,
         })
-        .await
+        .await?;
+
+        self.audit(AuditRecord::withdraw_spl(token_name(mint_address), &result, nova_shield_fee));
+        Ok(result)
     }
 
     /// Withdraw USDC (convenience method)
@@ -478,6 +1504,8 @@ impl PrivacyCash {
 
     /// Withdraw ALL of a specific SPL token
     ///
+    /// Fails with `BelowMinimum` if the balance is under the token's minimum withdrawal.
+    ///
     /// # Arguments
     /// * `mint_address` - Token mint address
     /// * `recipient` - Optional recipient address (defaults to self)
@@ -518,7 +1546,8 @@ impl PrivacyCash {
     /// ```rust,no_run
     /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
     /// let result = client.withdraw_all_usdc(None).await?;
-    /// println!("Withdrawn {} USDC", result.base_units as f64 / 1_000_000.0);
+    /// let amount = privacy_cash::TokenAmount::new(result.base_units, 6);
+    /// println!("Withdrawn {} USDC", amount);
     /// # Ok(())
     /// # }
     /// ```
@@ -548,7 +1577,7 @@ impl PrivacyCash {
     pub async fn get_private_balance_spl(&self, mint_address: &Pubkey) -> Result<SplBalance> {
         get_private_balance_spl(
             &self.connection,
-            &self.keypair.pubkey(),
+            &self.pubkey,
             &self.encryption_service,
             &self.storage,
             mint_address,
@@ -556,155 +1585,1608 @@ impl PrivacyCash {
         .await
     }
 
+    /// Get private SPL token balance from a full scan, ignoring the cache
+    ///
+    /// See [`get_private_balance_fresh`](Self::get_private_balance_fresh).
+    pub async fn get_private_balance_spl_fresh(&self, mint_address: &Pubkey) -> Result<SplBalance> {
+        let token = find_token_by_mint(mint_address)
+            .ok_or_else(|| PrivacyCashError::TokenNotSupported(mint_address.to_string()))?;
+        let utxos = self.get_utxos_with_options(Some(mint_address), ScanOptions::fresh()).await?;
+        Ok(get_balance_from_utxos_spl(&utxos, token.units_per_token).with_symbol(token.name))
+    }
+
+    /// Get the private SPL token balance of another shielded identity
+    ///
+    /// See [`get_private_balance_as`](Self::get_private_balance_as).
+    pub async fn get_private_balance_spl_as(
+        &self,
+        encryption_service: &EncryptionService,
+        mint_address: &Pubkey,
+    ) -> Result<SplBalance> {
+        let cache_owner = self.cache_owner_for(encryption_service)?;
+        get_private_balance_spl(
+            &self.connection,
+            &cache_owner,
+            encryption_service,
+            &self.storage,
+            mint_address,
+        )
+        .await
+    }
+
     /// Get private USDC balance (convenience method)
     pub async fn get_private_balance_usdc(&self) -> Result<SplBalance> {
         self.get_private_balance_spl(&USDC_MINT).await
     }
 
-    // ============ Cache Management ============
+    // ============ Cheques ============
 
-    /// Clear the UTXO cache
+    /// Deposit SOL into a claimable cheque
     ///
-    /// By default, downloaded UTXOs are cached locally for faster subsequent queries.
-    /// Call this method to clear the cache and force a full refresh.
-    pub async fn clear_cache(&self) {
-        let pubkey = self.keypair.pubkey();
-        let storage_key = localstorage_key(&pubkey);
-
-        // Clear SOL cache
-        self.storage
-            .remove(&format!("{}{}", LSK_FETCH_OFFSET, storage_key));
-        self.storage
-            .remove(&format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key));
-
-        // Clear SPL token caches
+    /// The note goes to a fresh shielded identity whose seed is returned in the
+    /// [`Cheque`]; the recipient claims it with [`claim_cheque`](Self::claim_cheque).
+    /// The wallet's own notes are not merged in, so the cheque holds exactly
+    /// `lamports`.
+    ///
+    /// The cheque is a bearer secret: anyone who sees the string can claim
+    /// the funds. Until it is claimed, the creator can take the funds back
+    /// by claiming it.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let cheque = client.create_cheque(10_000_000).await?;
+    /// // Send this string to the recipient over a private channel
+    /// let secret = cheque.to_string();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn create_cheque(&self, lamports: u64) -> Result<Cheque> {
+        if lamports == 0 {
+            return Err(PrivacyCashError::InvalidInput(
+                "Cheque amount must be greater than zero".to_string(),
+            ));
+        }
+        let params = self.deposit_params()?;
+        let _operation = self.operation_lock.acquire("create_cheque").await;
+
+        let mut cheque = Cheque::generate(lamports);
+        let identity = cheque.identity()?;
+        let result = deposit(DepositParams {
+            amount_in_lamports: lamports,
+            referrer: NOVA_SHIELD_REFERRER.as_deref(),
+            input_utxos: Some(&[]),
+            output_targets: [OutputTarget::Recipient(&identity), OutputTarget::Sender],
+            change_output: ChangeOutput::Second,
+            blinding: BlindingSource::Random,
+            ..params
+        })
+        .await?;
+
+        self.audit(AuditRecord::deposit(lamports, &result));
+        cheque.signature = Some(result.signature);
+        Ok(cheque)
+    }
+
+    /// Claim a cheque by withdrawing its note to `claimer_keypair`
+    ///
+    /// `claimer_keypair` signs and pays for the withdrawal; the note's value,
+    /// less the usual withdrawal fees, lands in its public wallet. Fails with
+    /// `InsufficientBalance` once the cheque has been claimed.
+    ///
+    /// The claim is a [`withdraw_all`](Self::withdraw_all), so the Nova Shield
+    /// fee is paid from the claimer's public SOL rather than from the note.
+    /// `claimer_keypair` must already hold that fee plus 5000 lamports for the
+    /// transfer, or the claim fails with `InsufficientBalance`; an unfunded
+    /// `Keypair::new()` cannot claim a cheque.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use privacy_cash::{cheque::Cheque, PrivacyCash};
+    /// use solana_sdk::signature::Keypair;
+    ///
+    /// # async fn example(secret: &str, funded_claimer: Keypair) -> privacy_cash::Result<()> {
+    /// let cheque: Cheque = secret.parse()?;
+    /// // The claimer pays the Nova Shield fee from its public SOL
+    /// let result = PrivacyCash::claim_cheque(
+    ///     "https://api.mainnet-beta.solana.com",
+    ///     funded_claimer,
+    ///     &cheque,
+    /// ).await?;
+    /// println!("Claimed {} lamports", result.amount_in_lamports);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn claim_cheque(
+        rpc_url: &str,
+        claimer_keypair: Keypair,
+        cheque: &Cheque,
+    ) -> Result<WithdrawResult> {
+        let client = Self::with_identity(rpc_url, claimer_keypair, cheque.seed(), None)?;
+        client.withdraw_all(None).await
+    }
+
+    // ============ Migration ============
+
+    /// Migrate legacy V1 SOL notes to the V2 encryption format
+    ///
+    /// Re-spends every V1 note (two per transaction) into a V2 note of the same
+    /// value. No funds leave the pool, but each transaction pays network fees.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let result = client.migrate_v1_to_v2().await?;
+    /// println!("Migrated {} notes in {} txs", result.notes_migrated, result.signatures.len());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn migrate_v1_to_v2(&self) -> Result<MigrationResult> {
+        let _operation = self.operation_lock.acquire("migrate").await;
+        migrate_v1_to_v2(MigrateParams {
+            connection: &self.connection,
+            keypair: self.signer()?,
+            encryption_service: &self.encryption_service,
+            storage: &self.storage,
+            mint_address: None,
+            key_base_path: &self.circuit_path,
+            referrer: NOVA_SHIELD_REFERRER.as_deref(),
+            approval: self.approval.as_deref(),
+            blinding: self.blinding_source,
+            proof_timeout: self.proof_timeout,
+        })
+        .await
+    }
+
+    /// Migrate legacy V1 SPL token notes to the V2 encryption format
+    pub async fn migrate_v1_to_v2_spl(&self, mint_address: &Pubkey) -> Result<MigrationResult> {
+        let _operation = self.operation_lock.acquire("migrate").await;
+        migrate_v1_to_v2(MigrateParams {
+            connection: &self.connection,
+            keypair: self.signer()?,
+            encryption_service: &self.encryption_service,
+            storage: &self.storage,
+            mint_address: Some(mint_address),
+            key_base_path: &self.circuit_path,
+            referrer: NOVA_SHIELD_REFERRER.as_deref(),
+            approval: self.approval.as_deref(),
+            blinding: self.blinding_source,
+            proof_timeout: self.proof_timeout,
+        })
+        .await
+    }
+
+    // ============ Consolidation ============
+
+    /// Merge all unspent SOL notes into one
+    ///
+    /// Spends two notes per transaction until at most one is left. Progress
+    /// is saved after every transaction; if the run is interrupted, call
+    /// [`resume_consolidation`](Self::resume_consolidation).
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let result = client.consolidate().await?;
+    /// println!("Merged notes in {} txs", result.progress.signatures.len());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn consolidate(&self) -> Result<ConsolidationResult> {
+        let _operation = self.operation_lock.acquire("consolidate").await;
+        consolidate(self.consolidate_params(None)?).await
+    }
+
+    /// Merge all unspent SPL token notes into one
+    pub async fn consolidate_spl(&self, mint_address: &Pubkey) -> Result<ConsolidationResult> {
+        let _operation = self.operation_lock.acquire("consolidate").await;
+        consolidate(self.consolidate_params(Some(mint_address))?).await
+    }
+
+    /// Continue an interrupted SOL consolidation
+    ///
+    /// Rescans the wallet and carries on from the stored progress. Returns
+    /// `None` if there is no unfinished consolidation.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// if let Some(result) = client.resume_consolidation().await? {
+    ///     println!("Finished after {} rounds", result.progress.rounds_completed);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn resume_consolidation(&self) -> Result<Option<ConsolidationResult>> {
+        let _operation = self.operation_lock.acquire("consolidate").await;
+        resume_consolidation(self.consolidate_params(None)?).await
+    }
+
+    /// Continue an interrupted SPL token consolidation
+    pub async fn resume_consolidation_spl(
+        &self,
+        mint_address: &Pubkey,
+    ) -> Result<Option<ConsolidationResult>> {
+        let _operation = self.operation_lock.acquire("consolidate").await;
+        resume_consolidation(self.consolidate_params(Some(mint_address))?).await
+    }
+
+    /// Plan a consolidation of SOL or SPL token notes without running it
+    ///
+    /// Counts the unspent notes of the token (`None` for SOL) and estimates
+    /// the rounds, transactions and time [`consolidate`](Self::consolidate)
+    /// would take, using this machine's average proof time.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let plan = client.consolidation_plan(None).await?;
+    /// println!(
+    ///     "This will take {} transactions (~{} minutes)",
+    ///     plan.transactions,
+    ///     plan.estimated_time.as_secs().div_ceil(60)
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn consolidation_plan(
+        &self,
+        mint_address: Option<&Pubkey>,
+    ) -> Result<ConsolidationPlan> {
+        let utxos = self.get_utxos_with_options(mint_address, ScanOptions::default()).await?;
+        let notes = filter_mergeable(utxos).len();
+        Ok(plan_consolidation(notes, estimated_proof_time(&self.storage)))
+    }
+
+    fn consolidate_params<'a>(
+        &'a self,
+        mint_address: Option<&'a Pubkey>,
+    ) -> Result<ConsolidateParams<'a>> {
+        Ok(ConsolidateParams {
+            connection: &self.connection,
+            keypair: self.signer()?,
+            encryption_service: &self.encryption_service,
+            storage: &self.storage,
+            mint_address,
+            key_base_path: &self.circuit_path,
+            referrer: NOVA_SHIELD_REFERRER.as_deref(),
+            approval: self.approval.as_deref(),
+            blinding: self.blinding_source,
+            proof_timeout: self.proof_timeout,
+        })
+    }
+
+    // ============ Wallet Scanning ============
+
+    /// Decrypt a caller-supplied list of encrypted outputs
+    ///
+    /// For notes delivered by push (a relayer webhook, a shared backup) rather
+    /// than found by scanning. Returns one entry per output, in order: the
+    /// note if it is ours, `None` otherwise. Nothing is fetched or cached, so
+    /// each note keeps the index it was encrypted with, and spent notes are
+    /// returned too.
+    ///
+    /// # Arguments
+    /// * `encrypted_outputs` - Hex-encoded encrypted outputs
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # fn example(client: &privacy_cash::PrivacyCash, outputs: Vec<String>) {
+    /// for (output, note) in outputs.iter().zip(client.decrypt_outputs(&outputs)) {
+    ///     if let Some(note) = note {
+    ///         println!("{}: {} base units", output, note.amount);
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn decrypt_outputs(&self, encrypted_outputs: &[String]) -> Vec<Option<Utxo>> {
+        encrypted_outputs
+            .iter()
+            .map(|output| self.encryption_service.decrypt_utxo_from_hex(output).ok())
+            .collect()
+    }
+
+    /// Check which notes are spent, reading `confirmed` state
+    ///
+    /// Fast enough for display, but a spend seen at `confirmed` can still be
+    /// rolled back; see [`are_utxos_spent_finalized`](Self::are_utxos_spent_finalized).
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let notes = client.get_utxos_with_options(None, Default::default()).await?;
+    /// let spent = client.are_utxos_spent(&notes).await?;
+    /// println!("{} of {} notes spent", spent.iter().filter(|s| **s).count(), notes.len());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn are_utxos_spent(&self, utxos: &[Utxo]) -> Result<Vec<bool>> {
+        are_utxos_spent_with_commitment(&self.connection, utxos, CommitmentConfig::confirmed())
+            .await
+    }
+
+    /// Check which notes are spent, reading `finalized` state
+    ///
+    /// A note reported spent stays spent, but a spend that is not finalized
+    /// yet still reads as unspent. Deposits and withdrawals run this check on
+    /// their inputs before proving.
+    pub async fn are_utxos_spent_finalized(&self, utxos: &[Utxo]) -> Result<Vec<bool>> {
+        are_utxos_spent_with_commitment(&self.connection, utxos, CommitmentConfig::finalized())
+            .await
+    }
+
+    /// Fetch unspent notes with an explicit scan range
+    ///
+    /// Scans SOL when `mint_address` is `None`, otherwise that SPL token.
+    /// `ScanOptions::from_offset` starts at a given output index, and
+    /// `ScanOptions::incremental` fails with `RescanRequired` rather than
+    /// rescanning the whole tree when the cache is missing or stale.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use privacy_cash::get_utxos::ScanOptions;
+    /// use privacy_cash::PrivacyCashError;
+    ///
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// match client.get_utxos_with_options(None, ScanOptions::incremental()).await {
+    ///     Ok(utxos) => println!("{} notes", utxos.len()),
+    ///     Err(PrivacyCashError::RescanRequired(reason)) => eprintln!("skipped: {}", reason),
+    ///     Err(e) => return Err(e),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_utxos_with_options(
+        &self,
+        mint_address: Option<&Pubkey>,
+        options: ScanOptions,
+    ) -> Result<Vec<Utxo>> {
+        match mint_address {
+            Some(mint) => {
+                get_utxos_spl_with_options(
+                    &self.connection,
+                    &self.pubkey,
+                    &self.encryption_service,
+                    &self.storage,
+                    mint,
+                    None,
+                    options,
+                )
+                .await
+            }
+            None => {
+                get_utxos_with_options(
+                    &self.connection,
+                    &self.pubkey,
+                    &self.encryption_service,
+                    &self.storage,
+                    None,
+                    options,
+                )
+                .await
+            }
+        }
+    }
+
+    /// Fetch and decrypt our notes among the outputs at indices `[start, end)`
+    ///
+    /// For inspecting one slice of a token's tree (`None` for SOL). The cache
+    /// and its fetch offset are left untouched, and spends are not checked.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// for note in client.scan_range(4_900, 5_100, None).await? {
+    ///     println!("#{} {} lamports", note.index, note.amount_u64());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn scan_range(
+        &self,
+        start: u64,
+        end: u64,
+        mint_address: Option<&Pubkey>,
+    ) -> Result<Vec<Utxo>> {
+        let token_name = relayer_token_name(mint_address)?;
+        let mut utxos = scan_range(&self.encryption_service, start, end, token_name).await?;
+        // A token's tree may hold notes of other mints
+        if let (Some(mint), Some(_)) = (mint_address, token_name) {
+            let mint = mint.to_string();
+            utxos.retain(|utxo| utxo.mint_address == mint);
+        }
+        Ok(utxos)
+    }
+
+    /// Scan SOL and every supported SPL token in one call
+    ///
+    /// Returns balances, note counts and dust per token. A token that fails
+    /// to scan is reported in its summary's `error` field rather than failing
+    /// the whole call.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let summary = client.scan_all().await?;
+    /// for token in &summary.tokens {
+    ///     println!("{}: {} ({} notes)", token.token, token.balance, token.utxo_count);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn scan_all(&self) -> Result<WalletSummary> {
+        scan_all(&self.connection, &self.pubkey, &self.encryption_service, &self.storage).await
+    }
+
+    /// Scan many wallets concurrently
+    ///
+    /// Runs [`scan_all`](Self::scan_all) for up to `max_concurrent` clients at
+    /// a time and returns one result per client, in input order. All clients
+    /// share the process-wide relayer transport; wrap it in a
+    /// [`LimitedTransport`](crate::http::LimitedTransport) to bound the total
+    /// number of relayer requests in flight. Clients built with
+    /// [`set_connection`](Self::set_connection) can also share one RPC client.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use privacy_cash::http::{set_transport, transport, LimitedTransport};
+    /// use privacy_cash::PrivacyCash;
+    /// use std::sync::Arc;
+    ///
+    /// # async fn example(clients: Vec<PrivacyCash>) {
+    /// set_transport(Arc::new(LimitedTransport::new(transport(), 8)));
+    /// let summaries = PrivacyCash::scan_wallets(&clients, 32).await;
+    /// for (client, summary) in clients.iter().zip(summaries) {
+    ///     match summary {
+    ///         Ok(summary) => println!("{}: {} tokens", client.pubkey(), summary.tokens.len()),
+    ///         Err(e) => eprintln!("{}: {}", client.pubkey(), e),
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub async fn scan_wallets(
+        clients: &[PrivacyCash],
+        max_concurrent: usize,
+    ) -> Vec<Result<WalletSummary>> {
+        stream::iter(clients)
+            .map(|client| client.scan_all())
+            .buffered(max_concurrent.max(1))
+            .collect()
+            .await
+    }
+
+    // ============ Previews ============
+
+    /// Preview the note arithmetic of a deposit or withdrawal
+    ///
+    /// Uses the same input selection and fee calculation as the real flow but
+    /// does not generate a proof or submit anything.
+    ///
+    /// # Arguments
+    /// * `mint_address` - Token mint, or `None` for SOL
+    /// * `amount` - Amount in base units
+    /// * `kind` - Deposit or withdrawal
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use privacy_cash::preview::TransactionKind;
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let preview = client.preview_transaction(None, 10_000_000, TransactionKind::Withdraw).await?;
+    /// println!("Change note: {} lamports, fee: {}", preview.change_amount, preview.fee);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn preview_transaction(
+        &self,
+        mint_address: Option<&Pubkey>,
+        amount: u64,
+        kind: TransactionKind,
+    ) -> Result<TransactionPreview> {
+        preview_transaction(
+            &self.connection,
+            &self.pubkey,
+            &self.encryption_service,
+            &self.storage,
+            mint_address,
+            amount,
+            kind,
+        )
+        .await
+    }
+
+    // ============ Tree Verification ============
+
+    /// Rebuild the Merkle tree from the relayer's leaves and compare roots
+    ///
+    /// Checks the SOL tree when `mint_address` is `None`, otherwise the tree of
+    /// that SPL token. A report that is not consistent means the relayer's
+    /// claimed root does not follow from the leaves it serves. This downloads
+    /// every leaf in the tree, so it is meant for audits rather than routine use.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let report = client.verify_tree_consistency(None).await?;
+    /// if !report.is_consistent() {
+    ///     eprintln!("relayer root {} != local {}", report.remote_root, report.local_root);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn verify_tree_consistency(
+        &self,
+        mint_address: Option<&Pubkey>,
+    ) -> Result<TreeConsistency> {
+        verify_tree_consistency(relayer_token_name(mint_address)?).await
+    }
+
+    // ============ Pool Statistics ============
+
+    /// Aggregate statistics of the SOL pool (`None`) or an SPL token's pool
+    ///
+    /// Reports the number of commitments in the tree, the pool's total value
+    /// if the relayer exposes it, and the anonymity set of this wallet's
+    /// newest unspent note. Scans the wallet's notes for the latter.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let stats = client.pool_stats(None).await?;
+    /// println!("The pool holds {} commitments", stats.commitments);
+    /// if let Some(size) = stats.anonymity_set {
+    ///     println!("Your anonymity set: {}", size);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn pool_stats(&self, mint_address: Option<&Pubkey>) -> Result<PoolStats> {
+        let token_name = relayer_token_name(mint_address)?;
+        let notes = self.get_utxos_with_options(mint_address, ScanOptions::default()).await?;
+        pool_stats(token_name, &notes).await
+    }
+
+    // ============ Cache Management ============
+
+    /// Clear the UTXO cache
+    ///
+    /// By default, downloaded UTXOs are cached locally for faster subsequent queries.
+    /// Call this method to clear the cache and force a full refresh.
+    ///
+    /// Returns how many cache entries were removed and for which tokens, so a
+    /// summary with no keys removed means there was no cache to clear.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) {
+    /// let cleared = client.clear_cache().await;
+    /// println!("Removed {} entries for {:?}", cleared.keys_removed, cleared.tokens);
+    /// # }
+    /// ```
+    pub async fn clear_cache(&self) -> CacheClearSummary {
+        let mut summary = CacheClearSummary::default();
+
+        for (token, storage_key) in self.cache_namespaces() {
+            let removed = CACHE_PREFIXES
+                .into_iter()
+                .map(|prefix| format!("{}{}", prefix, storage_key))
+                .filter(|key| self.storage.remove_existing(key))
+                .count();
+            if removed > 0 {
+                summary.keys_removed += removed;
+                if !summary.tokens.iter().any(|t| t == token) {
+                    summary.tokens.push(token.to_string());
+                }
+            }
+        }
+
+        summary
+    }
+
+    /// Report the size of the UTXO cache per token
+    ///
+    /// Read-only: sums the size of each token's cache entries (file sizes for
+    /// the file cache) and counts the cached encrypted notes.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # fn example(client: &privacy_cash::PrivacyCash) {
+    /// let stats = client.cache_stats();
+    /// println!("{} bytes, {} notes", stats.total_bytes(), stats.total_notes());
+    /// for token in &stats.tokens {
+    ///     println!("{}: {} entries, {} bytes", token.token, token.entries, token.bytes);
+    /// }
+    /// # }
+    /// ```
+    pub fn cache_stats(&self) -> CacheStats {
+        let mut stats = CacheStats::default();
+
+        for (token, storage_key) in self.cache_namespaces() {
+            let sizes: Vec<u64> = CACHE_PREFIXES
+                .into_iter()
+                .filter_map(|prefix| self.storage.size_of(&format!("{}{}", prefix, storage_key)))
+                .collect();
+            if sizes.is_empty() {
+                continue;
+            }
+
+            let outputs_key = format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key);
+            let notes = self.storage.get_outputs(&outputs_key).map_or(0, |o| o.len());
+
+            let index = match stats.tokens.iter().position(|t| t.token == token) {
+                Some(index) => index,
+                None => {
+                    stats.tokens.push(TokenCacheStats {
+                        token: token.to_string(),
+                        ..Default::default()
+                    });
+                    stats.tokens.len() - 1
+                }
+            };
+            let entry = &mut stats.tokens[index];
+            entry.entries += sizes.len();
+            entry.bytes += sizes.iter().sum::<u64>();
+            entry.notes += notes;
+        }
+
+        stats
+    }
+
+    /// Cache key suffix of every token: SOL, then SPL tokens keyed by the
+    /// owner's token account
+    fn cache_namespaces(&self) -> Vec<(&'static str, String)> {
+        let mut caches = vec![("sol", localstorage_key(&self.pubkey))];
         for token in get_supported_tokens() {
-            let ata = get_associated_token_address(&pubkey, &token.mint);
-            let ata_key = localstorage_key(&ata);
+            let ata = get_associated_token_address(&self.pubkey, &token.mint);
+            caches.push((token.name, localstorage_key(&ata)));
+        }
+        caches
+    }
+
+    /// Clear the UTXO cache and rescan every supported token from the start
+    ///
+    /// Recovers a balance that shows zero because the cached fetch offset no
+    /// longer matches the relayer (offsets past the end of the tree are also
+    /// reset automatically on every scan).
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let summary = client.force_full_rescan().await?;
+    /// for token in &summary.tokens {
+    ///     println!("{}: {} base units", token.token, token.balance);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn force_full_rescan(&self) -> Result<WalletSummary> {
+        self.clear_cache().await;
+        self.scan_all().await
+    }
+
+    /// Compare the cached notes with a fresh scan that bypasses the cache
+    ///
+    /// Reconciles SOL when `mint_address` is `None`, otherwise that SPL token.
+    /// Reports cached notes the scan no longer returns, notes the cache
+    /// missed, and notes cached twice. Neither the cache nor the scan state
+    /// is modified; call [`force_full_rescan`](Self::force_full_rescan) to
+    /// repair an inconsistent cache.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let report = client.reconcile(None).await?;
+    /// for note in &report.only_cached {
+    ///     println!("stale: {} at {} (spent: {})", note.amount, note.index, note.spent);
+    /// }
+    /// for note in &report.only_fresh {
+    ///     println!("missed: {} at {}", note.amount, note.index);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn reconcile(&self, mint_address: Option<&Pubkey>) -> Result<Reconciliation> {
+        reconcile(
+            &self.connection,
+            &self.pubkey,
+            &self.encryption_service,
+            &self.storage,
+            mint_address,
+        )
+        .await
+    }
+
+    // ============ Fee Estimation ============
+
+    /// Estimate total fees for a SOL withdrawal
+    /// 
+    /// Returns (privacy_cash_fee, nova_shield_fee, total_fee)
+    pub async fn estimate_withdraw_fees(&self, lamports: u64) -> Result<(u64, u64, u64)> {
+        // Privacy Cash fee: 0.35% + rent, as charged by the withdrawal
+        let (fee_rate, rent_lamports) = withdraw_fee_config().await?;
+        let pc_fee = withdraw_fee(lamports, fee_rate, rent_lamports);
+        
+        // Nova Shield fee: 1%
+        let ns_fee = (lamports as f64 * *NOVA_SHIELD_FEE_RATE) as u64;
+        
+        Ok((pc_fee, ns_fee, pc_fee + ns_fee))
+    }
+
+    /// Estimate total fees for an SPL token withdrawal
+    /// 
+    /// Returns (privacy_cash_fee, nova_shield_fee, total_fee) in base units
+    pub async fn estimate_withdraw_fees_spl(&self, base_units: u64, token_name: &str) -> Result<(u64, u64, u64)> {
+        let config = crate::config::Config::get().await?;
+        
+        let rent_fee = config.rent_fees.get(token_name).copied().unwrap_or(0.85);
+        let units_per_token = match token_name {
+            "usdc" | "usdt" => 1_000_000.0,
+            _ => 1_000_000_000.0,
+        };
+        
+        // Privacy Cash fee
+        let pc_fee = (base_units as f64 * config.withdraw_fee_rate 
+            + units_per_token * rent_fee) as u64;
+        
+        // Nova Shield fee: 1%
+        let ns_fee = (base_units as f64 * *NOVA_SHIELD_FEE_RATE) as u64;
+        
+        Ok((pc_fee, ns_fee, pc_fee + ns_fee))
+    }
+
+    /// Get current Nova Shield fee rate
+    pub fn get_nova_shield_fee_rate() -> f64 {
+        *NOVA_SHIELD_FEE_RATE
+    }
+
+    // ============ Token Support (Dynamic) ============
+
+    /// Get list of all supported token names
+    /// 
+    /// This fetches dynamically from the Privacy Cash API, so new tokens
+    /// are automatically supported when Privacy Cash adds them.
+    /// 
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let tokens = client.get_supported_tokens().await?;
+    /// for token in tokens {
+    ///     println!("{}: min={}, rent_fee={}, price=${:.2}",
+    ///         token.name, token.min_withdrawal, token.rent_fee, token.price_usd);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_supported_tokens(&self) -> Result<Vec<crate::config::SupportedToken>> {
+        crate::config::Config::get_supported_tokens().await
+    }
+
+    /// Get list of supported token names
+    pub async fn get_supported_token_names(&self) -> Result<Vec<String>> {
+        crate::config::Config::get_supported_token_names().await
+    }
+
+    /// Check if a token is supported
+    /// 
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// if client.is_token_supported("usdc").await? {
+    ///     println!("USDC is supported!");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn is_token_supported(&self, token_name: &str) -> Result<bool> {
+        crate::config::Config::is_token_supported(token_name).await
+    }
+
+    /// Get minimum withdrawal amount for a token
+    pub async fn get_minimum_withdrawal(&self, token_name: &str) -> Result<f64> {
+        crate::config::Config::get_minimum_withdrawal(token_name).await
+    }
+
+    /// Get current token price in USD
+    pub async fn get_token_price(&self, token_name: &str) -> Result<f64> {
+        crate::config::Config::get_token_price(token_name).await
+    }
+
+    /// Get Privacy Cash configuration (fees, minimums, etc.)
+    pub async fn get_config(&self) -> Result<crate::config::Config> {
+        crate::config::Config::get().await
+    }
+
+    /// Pre-fetch the relayer config and every token's tree state
+    ///
+    /// Call this when a deposit or withdrawal screen loads so the config is
+    /// already cached and the relayer connection is warm when the user
+    /// submits. The returned snapshot can be shown to the user directly.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let state = client.refresh_network_state().await?;
+    /// if let Some(sol) = state.tree_states.get("sol") {
+    ///     println!("SOL tree has {} leaves", sol.next_index);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn refresh_network_state(&self) -> Result<NetworkState> {
+        refresh_network_state().await
+    }
+
+    /// Refresh the configuration, taking fee rates from the on-chain program
+    ///
+    /// Later operations use the on-chain fee rates instead of the relayer's,
+    /// so a misreporting relayer cannot change how much a withdrawal delivers.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let config = client.refresh_config_from_chain().await?;
+    /// println!("Withdraw fee rate: {}", config.withdraw_fee_rate);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn refresh_config_from_chain(&self) -> Result<crate::config::Config> {
+        crate::config::Config::fetch_with_on_chain(&self.connection).await
+    }
+
+    // ============ Utility Methods ============
+
+    /// Whether proofs built in this process can be accepted on chain
+    ///
+    /// False when the installed Poseidon backend (see
+    /// [`set_hasher`](crate::hasher::set_hasher)) does not match the circuit,
+    /// in which case every deposit and withdrawal would be rejected after
+    /// proving. Check it at startup rather than after a failed transaction.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use privacy_cash::PrivacyCash;
+    ///
+    /// assert!(PrivacyCash::is_proof_compatible(), "Poseidon backend is not circuit-compatible");
+    /// ```
+    pub fn is_proof_compatible() -> bool {
+        is_circuit_compatible()
+    }
+
+    /// Expected duration of the next proof on this machine
+    ///
+    /// A moving average of past proof times, kept in the client's storage so
+    /// a UI can show a calibrated estimate. Before the first proof it returns
+    /// a default of 45 seconds.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// println!("Generating proof (~{}s)...", client.estimated_proof_time().as_secs());
+    /// client.deposit(10_000_000).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn estimated_proof_time(&self) -> std::time::Duration {
+        estimated_proof_time(&self.storage)
+    }
+
+    /// Check keys, Poseidon, notes, the Merkle tree, encryption and proving offline
+    ///
+    /// Runs each step of the transaction pipeline locally against the
+    /// configured circuit files and reports which ones fail, e.g. missing
+    /// circuit files or an incompatible Poseidon backend. Nothing is sent to
+    /// the network. The proof step takes as long as a real proof.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) {
+    /// let report = client.self_test().await;
+    /// for step in &report.steps {
+    ///     match &step.error {
+    ///         None => println!("ok    {}", step.name),
+    ///         Some(e) => println!("FAIL  {}: {}", step.name, e),
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub async fn self_test(&self) -> SelfTestReport {
+        self_test(&self.encryption_service, &self.circuit_path).await
+    }
+
+    /// Snapshot of the configuration and cache state for support tickets
+    ///
+    /// Collects the RPC and relayer endpoints, program ID, circuit file
+    /// validity, cache stats, the last-seen tree root and size per token,
+    /// proving mode and supported tokens. Built offline; URLs are stripped of
+    /// credentials and query strings, and no keys or addresses are included.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// println!("{}", client.diagnostics().to_json()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn diagnostics(&self) -> Diagnostics {
+        let circuit_error = validate_circuit_files(&self.circuit_path).err();
+        let tree_states = self
+            .cache_namespaces()
+            .into_iter()
+            .filter_map(|(token, storage_key)| {
+                let value = self.storage.get(&format!("{}{}", LSK_TREE_STATE, storage_key))?;
+                parse_tree_state(token, &value)
+            })
+            .collect();
+
+        Diagnostics {
+            sdk_version: env!("CARGO_PKG_VERSION").to_string(),
+            rpc_url: redact_url(&self.connection.url()),
+            relayer_url: redact_url(&RELAYER_API_URL),
+            program_id: PROGRAM_ID.to_string(),
+            read_only: self.is_read_only(),
+            proving_mode: proving_mode(self.is_read_only()).to_string(),
+            circuit: CircuitDiagnostics {
+                path: self.circuit_path.clone(),
+                source: self.circuit_source,
+                valid: circuit_error.is_none(),
+                error: circuit_error,
+            },
+            cache: self.cache_stats(),
+            tree_states,
+            supported_tokens: get_supported_tokens().iter().map(|t| t.name.to_string()).collect(),
+        }
+    }
 
-            self.storage
-                .remove(&format!("{}{}", LSK_FETCH_OFFSET, ata_key));
-            self.storage
-                .remove(&format!("{}{}", LSK_ENCRYPTED_OUTPUTS, ata_key));
+    /// Key that namespaces the scan cache of an identity
+    ///
+    /// The client's own identity uses its wallet key; any other identity uses
+    /// a key derived from its UTXO public key.
+    fn cache_owner_for(&self, encryption_service: &EncryptionService) -> Result<Pubkey> {
+        let utxo_private_key = encryption_service.get_utxo_private_key_v2()?;
+        let own_key = self.encryption_service.get_utxo_private_key_v2().ok();
+        if own_key.as_deref() == Some(utxo_private_key.as_str()) {
+            return Ok(self.pubkey);
         }
+
+        let utxo_pubkey = ZkKeypair::from_hex(&utxo_private_key)?.pubkey_string();
+        let digest = Sha256::digest(format!("privacy-cash-identity:{}", utxo_pubkey));
+        Ok(Pubkey::new_from_array(digest.into()))
     }
 
-    // ============ Fee Estimation ============
+    /// Get the Solana RPC client
+    pub fn connection(&self) -> &RpcClient {
+        &self.connection
+    }
 
-    /// Estimate total fees for a SOL withdrawal
-    /// 
-    /// Returns (privacy_cash_fee, nova_shield_fee, total_fee)
-    pub async fn estimate_withdraw_fees(&self, lamports: u64) -> Result<(u64, u64, u64)> {
-        let config = crate::config::Config::get().await?;
-        
-        // Privacy Cash fee: 0.35% + rent
-        let pc_fee = (lamports as f64 * config.withdraw_fee_rate 
-            + 1_000_000_000.0 * config.withdraw_rent_fee) as u64;
-        
-        // Nova Shield fee: 1%
-        let ns_fee = (lamports as f64 * *NOVA_SHIELD_FEE_RATE) as u64;
-        
-        Ok((pc_fee, ns_fee, pc_fee + ns_fee))
+    /// Use a shared RPC client, e.g. one connection for many wallet clients
+    pub fn set_connection(&mut self, connection: Arc<RpcClient>) {
+        self.connection = connection;
     }
 
-    /// Estimate total fees for an SPL token withdrawal
-    /// 
-    /// Returns (privacy_cash_fee, nova_shield_fee, total_fee) in base units
-    pub async fn estimate_withdraw_fees_spl(&self, base_units: u64, token_name: &str) -> Result<(u64, u64, u64)> {
-        let config = crate::config::Config::get().await?;
-        
-        let rent_fee = config.rent_fees.get(token_name).copied().unwrap_or(0.85);
-        let units_per_token = match token_name {
-            "usdc" | "usdt" => 1_000_000.0,
-            _ => 1_000_000_000.0,
-        };
-        
-        // Privacy Cash fee
-        let pc_fee = (base_units as f64 * config.withdraw_fee_rate 
-            + units_per_token * rent_fee) as u64;
-        
-        // Nova Shield fee: 1%
-        let ns_fee = (base_units as f64 * *NOVA_SHIELD_FEE_RATE) as u64;
-        
-        Ok((pc_fee, ns_fee, pc_fee + ns_fee))
+    /// Get the current SOL balance (public, on-chain)
+    pub fn get_sol_balance(&self) -> Result<u64> {
+        Ok(self.connection.get_balance(&self.pubkey)?)
     }
 
-    /// Get current Nova Shield fee rate
-    pub fn get_nova_shield_fee_rate() -> f64 {
-        *NOVA_SHIELD_FEE_RATE
+    /// Check whether a previously returned transaction signature confirmed
+    ///
+    /// Use this after a `ConfirmationTimeout` to tell a transaction that
+    /// landed late from one that was dropped, before submitting again.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use privacy_cash::utils::TransactionStatus;
+    /// use privacy_cash::PrivacyCashError;
+    ///
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// match client.withdraw(10_000_000, None).await {
+    ///     Err(PrivacyCashError::ConfirmationTimeout { signature, .. }) => {
+    ///         if client.transaction_status(&signature)? == TransactionStatus::Confirmed {
+    ///             println!("Withdrawal landed: {}", signature);
+    ///         }
+    ///     }
+    ///     other => println!("{:?}", other.map(|r| r.signature)),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn transaction_status(&self, signature: &str) -> Result<TransactionStatus> {
+        transaction_status(&self.connection, signature)
     }
 
-    // ============ Token Support (Dynamic) ============
+    /// Check that a SOL withdrawal's recipient received the net amount
+    ///
+    /// Compares the recipient's lamports before and after the confirmed
+    /// withdrawal transaction, so later activity on the account does not
+    /// matter. Returns `false` if the balance did not grow by exactly
+    /// `amount_in_lamports`, which is already net of the relayer fee.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let result = client.withdraw(10_000_000, None).await?;
+    /// assert!(client.verify_withdrawal(&result)?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn verify_withdrawal(&self, result: &WithdrawResult) -> Result<bool> {
+        verify_withdrawal(&self.connection, result)
+    }
 
-    /// Get list of all supported token names
-    /// 
-    /// This fetches dynamically from the Privacy Cash API, so new tokens
-    /// are automatically supported when Privacy Cash adds them.
-    /// 
+    /// Check that an SPL withdrawal's recipient received the net amount
+    ///
+    /// Like [`verify_withdrawal`](Self::verify_withdrawal), for the
+    /// recipient's `mint` token balance. For [`DeliveryMode::ViaSelf`] the
+    /// follow-up transfer is checked, since that is what reaches the recipient.
+    pub fn verify_withdrawal_spl(
+        &self,
+        mint_address: &Pubkey,
+        result: &WithdrawSplResult,
+    ) -> Result<bool> {
+        verify_withdrawal_spl(&self.connection, mint_address, result)
+    }
+
+    /// Check that a decrypted note is present in the relayer's Merkle tree
+    ///
+    /// Useful when recovering a wallet or debugging a balance that will not spend:
+    /// a note from a corrupted cache or a bad decryption will not be found.
+    pub async fn verify_note(&self, utxo: &Utxo) -> Result<bool> {
+        verify_note(utxo).await
+    }
+
+    /// Check that the relayer's tree contains a commitment before spending it
+    ///
+    /// Checks the SOL tree when `mint_address` is `None`, otherwise the tree of
+    /// that SPL token. A note the relayer does not recognize yet cannot be
+    /// spent: the relayer has no Merkle proof for it and the withdrawal would
+    /// be rejected. Unlike [`verify_note`](Self::verify_note) this only needs
+    /// the commitment, e.g. one returned by a deposit.
+    ///
     /// # Example
     /// ```rust,no_run
     /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
-    /// let tokens = client.get_supported_tokens().await?;
-    /// for token in tokens {
-    ///     println!("{}: min={}, rent_fee={}, price=${:.2}",
-    ///         token.name, token.min_withdrawal, token.rent_fee, token.price_usd);
+    /// let commitment = "1234";
+    /// if !client.is_commitment_included(commitment, None).await? {
+    ///     println!("not indexed yet, try again shortly");
     /// }
     /// # Ok(())
     /// # }
     /// ```
-    pub async fn get_supported_tokens(&self) -> Result<Vec<crate::config::SupportedToken>> {
-        crate::config::Config::get_supported_tokens().await
+    pub async fn is_commitment_included(
+        &self,
+        commitment: &str,
+        mint_address: Option<&Pubkey>,
+    ) -> Result<bool> {
+        is_commitment_included(commitment, relayer_token_name(mint_address)?).await
     }
 
-    /// Get list of supported token names
-    pub async fn get_supported_token_names(&self) -> Result<Vec<String>> {
-        crate::config::Config::get_supported_token_names().await
+    /// Give one of this wallet's notes the UTXO keypair of its own version
+    ///
+    /// Legacy V1 notes are spent with the V1 UTXO key and V2 notes with the V2
+    /// key; a note built with the wrong one has the wrong nullifier. Notes from
+    /// scans already carry the right key; use this on notes built by hand
+    /// before passing them as inputs.
+    pub fn with_version_keypair(&self, utxo: &Utxo) -> Result<Utxo> {
+        self.encryption_service.with_version_keypair(utxo)
     }
 
-    /// Check if a token is supported
-    /// 
+    /// Get the encrypted output for a note
+    ///
+    /// Returns the exact on-chain ciphertext when the note is in the local
+    /// cache (unspent notes from the last scan). Otherwise the note is
+    /// encrypted again with this wallet's key.
+    ///
+    /// V2 encryption uses a random IV, so a freshly encrypted output decrypts
+    /// to the same note but never matches the on-chain bytes, and a second
+    /// call returns different bytes again. Only cached ciphertext can be used
+    /// for relayer lookups such as `/utxos/indices`.
+    pub fn encrypted_output_for(&self, utxo: &Utxo) -> Result<Vec<u8>> {
+        let owner = match Pubkey::from_str(&utxo.mint_address)
+            .ok()
+            .and_then(|mint| find_token_by_mint(&mint))
+        {
+            Some(token) if token.name != "sol" => {
+                get_associated_token_address(&self.pubkey, &token.mint)
+            }
+            _ => self.pubkey,
+        };
+
+        let cached = find_cached_output(
+            &self.storage,
+            &localstorage_key(&owner),
+            &self.encryption_service,
+            &utxo.get_commitment()?,
+        )?;
+        match cached {
+            Some(bytes) => Ok(bytes),
+            None => self.encryption_service.encrypt_utxo(utxo),
+        }
+    }
+
+    /// Export the shielded viewing key for a watch-only client
+    ///
+    /// The viewing key is the V2 note encryption key. In this protocol the
+    /// note spending key is derived from it, so the SDK's watch-only client
+    /// refuses to spend but anyone holding the key could build a withdrawal
+    /// proof with other tooling. Share it only with parties trusted with funds.
+    pub fn export_viewing_key(&self) -> Result<String> {
+        self.encryption_service.export_viewing_key()
+    }
+
+    /// Set a custom circuit path
+    pub fn set_circuit_path(&mut self, path: &str) {
+        self.circuit_path = path.to_string();
+        self.circuit_source = Some(CircuitSource::Explicit);
+    }
+
+    /// Base path of the circuit files used for proofs
+    pub fn circuit_path(&self) -> &str {
+        &self.circuit_path
+    }
+
+    /// Where the circuit files were found, `None` for a read-only client
+    /// that found none
+    pub fn circuit_source(&self) -> Option<CircuitSource> {
+        self.circuit_source
+    }
+
+    /// Require approval of every deposit and withdrawal before it is relayed
+    ///
+    /// The hook sees the final amount, fee, recipient, nullifiers and
+    /// commitments after the proof is built. Returning `false` aborts with
+    /// `UserRejected` and nothing is sent to the relayer. The Nova Shield fee
+    /// transfer made by the client before a withdrawal is not covered.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # fn example(client: &mut privacy_cash::PrivacyCash) {
+    /// client.set_approval_hook(|tx| {
+    ///     println!("{:?} {} {} (fee {})", tx.kind, tx.amount, tx.token, tx.fee);
+    ///     tx.fee < 10_000_000
+    /// });
+    /// # }
+    /// ```
+    pub fn set_approval_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&TxPreview) -> bool + Send + Sync + 'static,
+    {
+        self.approval = Some(Arc::new(hook));
+    }
+
+    /// Remove the approval hook
+    pub fn clear_approval_hook(&mut self) {
+        self.approval = None;
+    }
+
+    /// Set the format for encrypted-outputs cache files
+    ///
+    /// `CacheFormat::Binary` roughly halves cache size for large wallets.
+    /// Existing caches in either format are still read.
+    pub fn set_cache_format(&mut self, format: CacheFormat) {
+        self.storage.set_cache_format(format);
+    }
+
+    /// Set the limit on generating each proof (default 120s)
+    ///
+    /// Proving on slow devices can take longer than the default; a proof
+    /// that runs past the limit fails with `ProofTimeout`.
+    pub fn set_proof_timeout(&mut self, timeout: Duration) {
+        self.proof_timeout = timeout;
+    }
+
+    /// Set how new output notes get their blindings
+    ///
+    /// `BlindingSource::Derived` derives each blinding from the UTXO key and
+    /// a counter kept in the cache directory, so notes can be rebuilt from the
+    /// wallet seed without the relayer's encrypted outputs; see
+    /// [`crate::blinding`]. Notes created so far keep their random blindings.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use privacy_cash::blinding::BlindingSource;
+    ///
+    /// # async fn example(client: &mut privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// client.set_blinding_source(BlindingSource::Derived);
+    /// client.deposit(20_000_000).await?;
+    /// println!("{} derived blindings used", client.blinding_counter()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_blinding_source(&mut self, source: BlindingSource) {
+        self.blinding_source = source;
+    }
+
+    /// Number of derived blindings used so far; see [`derive_blinding`](Self::derive_blinding)
+    pub fn blinding_counter(&self) -> Result<u64> {
+        blinding_counter(&self.encryption_service, &self.storage)
+    }
+
+    /// Move the blinding counter to at least `next`; it never moves back
+    ///
+    /// Returns the counter afterwards.
+    pub fn advance_blinding_counter(&self, next: u64) -> Result<u64> {
+        advance_blinding_counter(&self.encryption_service, &self.storage, next)
+    }
+
+    /// Move the blinding counter past the derived blindings of this wallet's notes
+    ///
+    /// Run this before depositing with `BlindingSource::Derived` on a new
+    /// device or after losing the cache directory, so new notes do not reuse
+    /// blinding values. Scans SOL and every supported token. Only unspent
+    /// notes are found; see [`crate::blinding::recover_blinding_counter`].
+    ///
     /// # Example
     /// ```rust,no_run
     /// # async fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
-    /// if client.is_token_supported("usdc").await? {
-    ///     println!("USDC is supported!");
+    /// let counter = client.recover_blinding_counter().await?;
+    /// println!("Next derived blinding: {}", counter);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn recover_blinding_counter(&self) -> Result<u64> {
+        let mut utxos = self.get_utxos_with_options(None, ScanOptions::default()).await?;
+        for token in get_supported_tokens().iter().filter(|token| token.name != "sol") {
+            let options = ScanOptions::default();
+            utxos.extend(self.get_utxos_with_options(Some(&token.mint), options).await?);
+        }
+        recover_blinding_counter(&self.encryption_service, &self.storage, &utxos)
+    }
+
+    /// The `counter`-th derived blinding of this identity
+    ///
+    /// With a note's amount, mint and counter, rebuilds its commitment
+    /// without the relayer, e.g. to recover notes on a new device.
+    pub fn derive_blinding(&self, counter: u64) -> Result<BigUint> {
+        self.encryption_service.derive_blinding(counter)
+    }
+
+    /// Turn the local audit log on or off (on by default)
+    ///
+    /// The log keeps amounts, recipients and signatures in the cache
+    /// directory; turn it off to leave no local record of operations.
+    /// Existing entries are kept.
+    pub fn set_audit_log(&mut self, enabled: bool) {
+        self.audit_log = enabled;
+    }
+
+    /// Completed deposits and withdrawals of this wallet, oldest first
+    ///
+    /// Entries are hash-chained under a key derived from this wallet; check
+    /// them with [`verify_audit_log`](Self::verify_audit_log).
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # fn example(client: &privacy_cash::PrivacyCash) -> privacy_cash::Result<()> {
+    /// let entries = client.audit_log()?;
+    /// if let Some(position) = client.verify_audit_log()? {
+    ///     println!("Audit log was modified at entry {}", position);
+    /// }
+    /// for entry in &entries {
+    ///     println!("{} {:?} {} {}", entry.timestamp, entry.record.operation,
+    ///         entry.record.amount, entry.record.token);
     /// }
     /// # Ok(())
     /// # }
     /// ```
-    pub async fn is_token_supported(&self, token_name: &str) -> Result<bool> {
-        crate::config::Config::is_token_supported(token_name).await
+    pub fn audit_log(&self) -> Result<Vec<AuditEntry>> {
+        audit::read(&self.storage, &audit_log_key(&self.pubkey))
     }
 
-    /// Get minimum withdrawal amount for a token
-    pub async fn get_minimum_withdrawal(&self, token_name: &str) -> Result<f64> {
-        crate::config::Config::get_minimum_withdrawal(token_name).await
+    /// Check the audit log's hash chain
+    ///
+    /// Returns the position of the first edited, reordered or missing entry,
+    /// or `None` if the log is intact. See [`audit::verify_chain`].
+    pub fn verify_audit_log(&self) -> Result<Option<usize>> {
+        let audit_key = self.encryption_service.derive_audit_key()?;
+        Ok(audit::verify_chain(&self.audit_log()?, &audit_key))
     }
 
-    /// Get current token price in USD
-    pub async fn get_token_price(&self, token_name: &str) -> Result<f64> {
-        crate::config::Config::get_token_price(token_name).await
+    /// Append a completed operation to the audit log, if enabled
+    ///
+    /// The operation has already happened, so a failed write is only logged.
+    fn audit(&self, record: AuditRecord) {
+        if !self.audit_log {
+            return;
+        }
+        let appended = self.encryption_service.derive_audit_key().and_then(|audit_key| {
+            audit::append(&self.storage, &audit_log_key(&self.pubkey), &audit_key, record)
+        });
+        if let Err(e) = appended {
+            log::warn!("Failed to write the audit log: {}", e);
+        }
     }
+}
 
-    /// Get Privacy Cash configuration (fees, minimums, etc.)
-    pub async fn get_config(&self) -> Result<crate::config::Config> {
-        crate::config::Config::get().await
+/// Token name of a mint for the audit log, the mint itself if unknown
+fn token_name(mint_address: &Pubkey) -> String {
+    find_token_by_mint(mint_address)
+        .map(|token| token.name.to_string())
+        .unwrap_or_else(|| mint_address.to_string())
+}
+
+/// How the client generates proofs, for [`Diagnostics`]
+fn proving_mode(read_only: bool) -> &'static str {
+    if read_only {
+        "none"
+    } else if crate::external_prover::external_prover().is_some() {
+        "external"
+    } else {
+        "rust"
     }
+}
 
-    // ============ Utility Methods ============
+/// Relayer token name for a mint, `None` for SOL
+fn relayer_token_name(mint_address: Option<&Pubkey>) -> Result<Option<&'static str>> {
+    let Some(mint) = mint_address else {
+        return Ok(None);
+    };
+    let token = find_token_by_mint(mint)
+        .ok_or_else(|| PrivacyCashError::TokenNotSupported(mint.to_string()))?;
+    Ok((token.name != "sol").then_some(token.name))
+}
 
-    /// Get the Solana RPC client
-    pub fn connection(&self) -> &RpcClient {
-        &self.connection
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::SIGN_MESSAGE;
+    use crate::prover::install_test_circuit;
+    use crate::utxo::UtxoVersion;
+    use std::path::PathBuf;
+
+    /// Cache directory of a test client, removed when dropped
+    struct TestCacheDir(PathBuf);
+
+    impl TestCacheDir {
+        fn new(owner: &Pubkey) -> Self {
+            Self(std::env::temp_dir().join(format!("privacy-cash-test-{}", owner)))
+        }
+
+        fn path(&self) -> Option<PathBuf> {
+            Some(self.0.clone())
+        }
     }
 
-    /// Get the current SOL balance (public, on-chain)
-    pub fn get_sol_balance(&self) -> Result<u64> {
-        Ok(self.connection.get_balance(&self.keypair.pubkey())?)
+    impl Drop for TestCacheDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
     }
 
-    /// Set a custom circuit path
-    pub fn set_circuit_path(&mut self, path: &str) {
-        self.circuit_path = path.to_string();
+    /// A spending client with a fresh keypair, talking to `rpc_url`
+    fn test_client_at(rpc_url: &str) -> (PrivacyCash, TestCacheDir) {
+        install_test_circuit();
+        let keypair = Keypair::new();
+        let cache_dir = TestCacheDir::new(&keypair.pubkey());
+        let client = PrivacyCash::with_options(rpc_url, keypair, cache_dir.path(), None).unwrap();
+        (client, cache_dir)
+    }
+
+    /// A spending client with a fresh keypair and its own cache directory
+    fn test_client() -> (PrivacyCash, TestCacheDir) {
+        test_client_at("http://127.0.0.1:8899")
+    }
+
+    #[test]
+    fn test_client_is_shareable() {
+        fn assert_shareable<T: Clone + Send + Sync + 'static>() {}
+        assert_shareable::<PrivacyCash>();
+    }
+
+    #[test]
+    fn test_proof_timeout_reaches_params() {
+        let (mut client, _dir) = test_client();
+        let default = Duration::from_secs(DEFAULT_PROOF_TIMEOUT_SECS);
+        assert_eq!(client.deposit_params().unwrap().proof_timeout, default);
+
+        client.set_proof_timeout(Duration::from_secs(600));
+        assert_eq!(client.deposit_params().unwrap().proof_timeout.as_secs(), 600);
+        let spl = client.deposit_spl_params(&USDC_MINT).unwrap();
+        assert_eq!(spl.proof_timeout.as_secs(), 600);
+        assert_eq!(client.consolidate_params(None).unwrap().proof_timeout.as_secs(), 600);
+    }
+
+    #[test]
+    fn test_cache_owner_for() {
+        let keypair = Keypair::new();
+        let signature = keypair.sign_message(SIGN_MESSAGE.as_bytes());
+        let cache_dir = TestCacheDir::new(&keypair.pubkey());
+        let client = PrivacyCash::read_only(
+            "http://127.0.0.1:8899",
+            keypair.pubkey(),
+            signature.as_ref(),
+            cache_dir.path(),
+        )
+        .unwrap();
+
+        let mut own = EncryptionService::new();
+        own.derive_encryption_key_from_signature(signature.as_ref()).unwrap();
+        let mut other = EncryptionService::new();
+        other.derive_encryption_key_from_wallet(&Keypair::new()).unwrap();
+
+        assert_eq!(client.cache_owner_for(&own).unwrap(), keypair.pubkey());
+        let other_owner = client.cache_owner_for(&other).unwrap();
+        assert_ne!(other_owner, keypair.pubkey());
+        assert_eq!(client.cache_owner_for(&other).unwrap(), other_owner);
+        assert!(client.cache_owner_for(&EncryptionService::new()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_only_client() {
+        let keypair = Keypair::new();
+        let signature = keypair.sign_message(SIGN_MESSAGE.as_bytes());
+        let cache_dir = TestCacheDir::new(&keypair.pubkey());
+
+        let client = PrivacyCash::read_only(
+            "http://127.0.0.1:8899",
+            keypair.pubkey(),
+            signature.as_ref(),
+            cache_dir.path(),
+        )
+        .unwrap();
+
+        assert!(client.is_read_only());
+        assert_eq!(client.pubkey(), keypair.pubkey());
+        assert!(matches!(
+            client.deposit(1_000_000).await,
+            Err(PrivacyCashError::ReadOnlyClient)
+        ));
+    }
+
+    #[test]
+    fn test_with_identity_uses_separate_keys() {
+        install_test_circuit();
+        let wallet = Keypair::new();
+        let identity = Keypair::new();
+        let seed = identity.sign_message(SIGN_MESSAGE.as_bytes());
+        let cache_dir = TestCacheDir::new(&wallet.pubkey());
+
+        let mut wallet_encryption = EncryptionService::new();
+        wallet_encryption.derive_encryption_key_from_wallet(&wallet).unwrap();
+
+        let client = PrivacyCash::with_identity(
+            "http://127.0.0.1:8899",
+            wallet,
+            seed.as_ref(),
+            cache_dir.path(),
+        )
+        .unwrap();
+
+        assert!(!client.is_read_only());
+        assert_ne!(
+            client.encryption_service.get_utxo_private_key_v2().unwrap(),
+            wallet_encryption.get_utxo_private_key_v2().unwrap()
+        );
+        let short = PrivacyCash::with_identity("http://127.0.0.1:8899", Keypair::new(), &[1u8; 8], None);
+        assert!(matches!(short, Err(PrivacyCashError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_decrypt_outputs() {
+        let (client, _cache_dir) = test_client();
+
+        let zk_keypair =
+            ZkKeypair::from_hex(&client.encryption_service.get_utxo_private_key_v2().unwrap())
+                .unwrap();
+        let note = Utxo::new(5u64, zk_keypair, 3, None, Some(UtxoVersion::V2));
+        let ours = hex::encode(client.encryption_service.encrypt_utxo(&note).unwrap());
+
+        let mut stranger = EncryptionService::new();
+        stranger.derive_encryption_key_from_wallet(&Keypair::new()).unwrap();
+        let theirs = hex::encode(stranger.encrypt_utxo(&note).unwrap());
+
+        let outputs = vec![theirs, ours, "not hex".to_string()];
+        let notes = client.decrypt_outputs(&outputs);
+        assert_eq!(notes.len(), 3);
+        assert!(notes[0].is_none() && notes[2].is_none());
+        let decrypted = notes[1].as_ref().unwrap();
+        assert_eq!((decrypted.amount.clone(), decrypted.index), (BigUint::from(5u64), 3));
+    }
+
+    #[tokio::test]
+    async fn test_clear_cache_summary() {
+        let (client, _cache_dir) = test_client();
+        assert!(client.clear_cache().await.is_empty());
+
+        let sol_key = localstorage_key(&client.pubkey);
+        let usdc_key = localstorage_key(&get_associated_token_address(&client.pubkey, &USDC_MINT));
+        client.storage.set(&format!("{}{}", LSK_FETCH_OFFSET, sol_key), "40");
+        client.storage.set(&format!("{}{}", LSK_TREE_STATE, sol_key), "1:40");
+        client.storage.set(&format!("{}{}", LSK_FETCH_OFFSET, usdc_key), "8");
+        assert_eq!(client.cache_stats().total_bytes(), 7);
+
+        let outputs = vec!["aa".to_string(), "bb".to_string()];
+        client.storage.set_outputs(&format!("{}{}", LSK_ENCRYPTED_OUTPUTS, usdc_key), &outputs);
+        let stats = client.cache_stats();
+        assert_eq!(stats.tokens.len(), 2);
+        assert_eq!((stats.tokens[0].entries, stats.tokens[0].bytes), (2, 6));
+        assert_eq!((stats.tokens[1].entries, stats.tokens[1].notes), (2, 2));
+        assert_eq!(stats.total_notes(), 2);
+        client.storage.remove(&format!("{}{}", LSK_ENCRYPTED_OUTPUTS, usdc_key));
+
+        let cleared = client.clear_cache().await;
+        assert_eq!(cleared.keys_removed, 3);
+        assert_eq!(cleared.tokens, vec!["sol".to_string(), "usdc".to_string()]);
+        assert!(client.clear_cache().await.is_empty());
+    }
+
+    /// External prover that is never called
+    struct NoProver;
+
+    impl crate::external_prover::ExternalProver for NoProver {
+        fn prove<'a>(&'a self, _input_json: &'a str) -> crate::external_prover::ProveFuture<'a> {
+            Box::pin(async { Err(PrivacyCashError::InvalidProof("unused".to_string())) })
+        }
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let (client, _cache_dir) = test_client_at("http://127.0.0.1:8899/?api-key=secret");
+        let sol_key = localstorage_key(&client.pubkey);
+        client.storage.set(&format!("{}{}", LSK_TREE_STATE, sol_key), "123:40");
+
+        let diagnostics = {
+            let _guard = crate::http::TEST_HOOKS_LOCK.blocking_lock();
+            assert_eq!(client.diagnostics().proving_mode, "rust");
+            crate::external_prover::set_external_prover(Arc::new(NoProver));
+            let external = client.diagnostics();
+            crate::external_prover::clear_external_prover();
+            external
+        };
+        assert_eq!(diagnostics.proving_mode, "external");
+        assert!(diagnostics.circuit.valid);
+        assert_eq!(diagnostics.tree_states.len(), 1);
+        assert_eq!(diagnostics.tree_states[0].next_index, 40);
+        assert!(diagnostics.supported_tokens.contains(&"usdc".to_string()));
+
+        let json = diagnostics.to_json().unwrap();
+        assert!(!json.contains("secret"));
+        assert!(!json.contains(&client.pubkey.to_string()));
+    }
+
+    #[test]
+    fn test_watch_only_client() {
+        let (owner, cache_dir) = test_client();
+        let pubkey = owner.pubkey();
+        let rpc_url = "http://127.0.0.1:8899";
+
+        let viewing_key = owner.export_viewing_key().unwrap();
+        let watcher =
+            PrivacyCash::watch_only(rpc_url, pubkey, &viewing_key, cache_dir.path()).unwrap();
+
+        assert!(watcher.is_read_only());
+        assert_eq!(
+            watcher.encryption_service.get_utxo_private_key_v2().unwrap(),
+            owner.encryption_service.get_utxo_private_key_v2().unwrap()
+        );
+        assert!(watcher.encryption_service.get_utxo_private_key_v1().is_err());
+        assert_eq!(watcher.zk_pubkey().unwrap(), owner.zk_pubkey().unwrap());
+        assert!(PrivacyCash::watch_only(rpc_url, pubkey, "pcvk1111", None).is_err());
+        assert!(PrivacyCash::watch_only(rpc_url, pubkey, &viewing_key[4..], None).is_err());
+    }
+
+    #[test]
+    fn test_encrypted_output_for_prefers_cache() {
+        let (client, _cache_dir) = test_client();
+
+        let zk_keypair =
+            ZkKeypair::from_hex(&client.encryption_service.get_utxo_private_key_v2().unwrap())
+                .unwrap();
+        let utxo = Utxo::new(1_000u64, zk_keypair, 3, None, None);
+
+        let fresh = client.encrypted_output_for(&utxo).unwrap();
+        assert_ne!(fresh, client.encrypted_output_for(&utxo).unwrap());
+
+        let cached_key = format!(
+            "{}{}",
+            LSK_ENCRYPTED_OUTPUTS,
+            localstorage_key(&client.pubkey)
+        );
+        client.storage.set_outputs(&cached_key, &[hex::encode(&fresh)]);
+        assert_eq!(client.encrypted_output_for(&utxo).unwrap(), fresh);
+    }
+
+    #[test]
+    fn test_read_only_rejects_short_signature() {
+        let result = PrivacyCash::read_only("http://127.0.0.1:8899", Pubkey::new_unique(), &[0u8; 16], None);
+        assert!(matches!(result, Err(PrivacyCashError::InvalidInput(_))));
     }
 }
@@ -0,0 +1,155 @@
+//! Time source for polling and timing
+//!
+//! Confirmation polling, indexing waits and scan timing read the time and
+//! sleep through the process-wide [`Clock`], which defaults to tokio's timer.
+//! Install a [`MockClock`] with [`set_clock`] to make sleeps return at once
+//! while advancing a virtual time, so timeouts can be tested without waiting.
+//! Wall-clock timestamps, e.g. of audit log entries, come from the same clock.
+
+use once_cell::sync::OnceCell;
+use parking_lot::{Mutex, RwLock};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Global clock used by all time-dependent code
+static CLOCK: OnceCell<RwLock<Arc<dyn Clock>>> = OnceCell::new();
+
+/// Future returned by [`Clock::sleep`]
+pub type SleepFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+/// Source of the current time and of delays
+pub trait Clock: Send + Sync {
+    /// Current time
+    fn now(&self) -> Instant;
+
+    /// Wait for `duration`
+    fn sleep(&self, duration: Duration) -> SleepFuture<'_>;
+
+    /// Current wall-clock time
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Default clock backed by the system time and tokio's timer
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> SleepFuture<'_> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// Virtual clock whose sleeps return immediately
+///
+/// Each sleep advances the clock by its duration instead of waiting, and
+/// [`MockClock::advance`] moves it forward by hand.
+#[derive(Debug)]
+pub struct MockClock {
+    start: Instant,
+    start_system: SystemTime,
+    now: Mutex<Instant>,
+}
+
+impl MockClock {
+    /// A clock starting at the current time
+    pub fn new() -> Self {
+        let start = Instant::now();
+        Self {
+            start,
+            start_system: SystemTime::now(),
+            now: Mutex::new(start),
+        }
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock() += duration;
+    }
+
+    /// Virtual time passed since the clock was created
+    pub fn elapsed(&self) -> Duration {
+        *self.now.lock() - self.start
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock()
+    }
+
+    fn sleep(&self, duration: Duration) -> SleepFuture<'_> {
+        self.advance(duration);
+        Box::pin(std::future::ready(()))
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.start_system + self.elapsed()
+    }
+}
+
+/// Get the clock used for polling and timing
+pub fn clock() -> Arc<dyn Clock> {
+    clock_cell().read().clone()
+}
+
+/// Replace the clock used for polling and timing
+pub fn set_clock(clock: Arc<dyn Clock>) {
+    *clock_cell().write() = clock;
+}
+
+/// Restore the default tokio clock
+pub fn reset_clock() {
+    set_clock(Arc::new(TokioClock));
+}
+
+/// Sleep on the current clock
+pub async fn sleep(duration: Duration) {
+    clock().sleep(duration).await
+}
+
+fn clock_cell() -> &'static RwLock<Arc<dyn Clock>> {
+    CLOCK.get_or_init(|| RwLock::new(Arc::new(TokioClock)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::PrivacyCashError;
+    use crate::http::{CannedTransport, TEST_HOOKS_LOCK};
+
+    #[tokio::test]
+    async fn test_confirmation_timeout_on_mock_clock() {
+        // Tests that replace the clock also hold the transport lock
+        let _guard = TEST_HOOKS_LOCK.lock().await;
+        let mock = Arc::new(MockClock::new());
+        set_clock(mock.clone());
+        // The relayer never reports the output as indexed
+        let _transport =
+            CannedTransport::default().route("/utxos/check/", r#"{"exists":false}"#).install();
+
+        let started = std::time::Instant::now();
+        let result = crate::deposit::wait_for_confirmation("sig", &[1, 2, 3], None).await;
+        reset_clock();
+
+        assert!(matches!(
+            result,
+            Err(PrivacyCashError::ConfirmationTimeout { retries: 10, .. })
+        ));
+        assert_eq!(mock.elapsed(), Duration::from_secs(20));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -1,15 +1,28 @@
 //! Configuration fetching from the relayer API
+//!
+//! Fee rates can also be read from the program's on-chain global config,
+//! which the relayer cannot misreport; see [`Config::fetch_with_on_chain`].
 
 use crate::constants::RELAYER_API_URL;
 use crate::error::{PrivacyCashError, Result};
+use crate::http::transport;
+use crate::utils::get_global_config_account;
 use once_cell::sync::OnceCell;
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
 use std::collections::HashMap;
 
+/// Denominator of the on-chain fee rates, which are in basis points
+const FEE_RATE_DENOMINATOR: f64 = 10_000.0;
+
 /// Global cached configuration
 static CONFIG_CACHE: OnceCell<RwLock<Option<Config>>> = OnceCell::new();
 
+/// On-chain fee rates last read by [`Config::fetch_with_on_chain`], re-applied on refresh
+static ON_CHAIN_CACHE: OnceCell<RwLock<Option<OnChainConfig>>> = OnceCell::new();
+
 /// Configuration from the relayer API
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -48,26 +61,79 @@ pub struct SupportedToken {
     pub price_usd: f64,
 }
 
+/// Fee parameters stored in the program's global config account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OnChainConfig {
+    /// Authority allowed to update the config
+    pub authority: Pubkey,
+    /// Deposit fee rate in basis points
+    pub deposit_fee_rate: u16,
+    /// Withdrawal fee rate in basis points
+    pub withdrawal_fee_rate: u16,
+    /// Tolerated difference between the expected and the charged fee, in basis points
+    pub fee_error_margin: u16,
+}
+
+impl OnChainConfig {
+    /// Size of the Anchor account: discriminator, authority, three rates and bump
+    const ACCOUNT_LEN: usize = 8 + 32 + 2 + 2 + 2 + 1;
+
+    /// Read the global config account
+    pub fn fetch(connection: &RpcClient) -> Result<Self> {
+        let data = connection.get_account_data(&get_global_config_account())?;
+        Self::from_account_data(&data)
+    }
+
+    /// Deserialize the global config account data
+    pub fn from_account_data(data: &[u8]) -> Result<Self> {
+        if data.len() < Self::ACCOUNT_LEN {
+            return Err(PrivacyCashError::SerializationError(format!(
+                "Global config account is {} bytes, expected at least {}",
+                data.len(),
+                Self::ACCOUNT_LEN
+            )));
+        }
+
+        let u16_at = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let authority = Pubkey::try_from(&data[8..40])
+            .map_err(|_| PrivacyCashError::SerializationError("Invalid authority".to_string()))?;
+
+        Ok(Self {
+            authority,
+            deposit_fee_rate: u16_at(40),
+            withdrawal_fee_rate: u16_at(42),
+            fee_error_margin: u16_at(44),
+        })
+    }
+
+    /// Deposit fee rate as a decimal (e.g. 0.0035 = 0.35%)
+    pub fn deposit_fee_rate(&self) -> f64 {
+        self.deposit_fee_rate as f64 / FEE_RATE_DENOMINATOR
+    }
+
+    /// Withdrawal fee rate as a decimal
+    pub fn withdraw_fee_rate(&self) -> f64 {
+        self.withdrawal_fee_rate as f64 / FEE_RATE_DENOMINATOR
+    }
+}
+
 impl Config {
     /// Fetch configuration from the relayer API
     pub async fn fetch() -> Result<Self> {
         let url = format!("{}/config", *RELAYER_API_URL);
 
-        let response = reqwest::get(&url)
+        let response = transport().get_json(&url)
             .await
             .map_err(|e| PrivacyCashError::ApiError(format!("Failed to fetch config: {}", e)))?;
 
-        if !response.status().is_success() {
+        if !response.is_success() {
             return Err(PrivacyCashError::ApiError(format!(
                 "Config API returned status: {}",
-                response.status()
+                response.status
             )));
         }
 
-        let config: Config = response
-            .json()
-            .await
-            .map_err(|e| PrivacyCashError::ApiError(format!("Failed to parse config: {}", e)))?;
+        let config: Config = response.json_with_context("Failed to parse config")?;
 
         Ok(config)
     }
@@ -94,12 +160,73 @@ impl Config {
         Ok(config)
     }
 
-    /// Clear the cached configuration
+    /// Fetch the relayer configuration, overriding fee rates with on-chain values
+    ///
+    /// The result replaces the cached configuration, so later fee calculations
+    /// use the on-chain rates until [`Config::clear_cache`] is called. The
+    /// on-chain rates are also kept across [`Config::refresh`]. If the global
+    /// config account cannot be read, the relayer's values are kept and a
+    /// warning is logged.
+    pub async fn fetch_with_on_chain(connection: &RpcClient) -> Result<Self> {
+        let mut config = Self::fetch().await?;
+
+        match OnChainConfig::fetch(connection) {
+            Ok(on_chain) => {
+                config.apply_on_chain(&on_chain);
+                *ON_CHAIN_CACHE.get_or_init(|| RwLock::new(None)).write() = Some(on_chain);
+            }
+            Err(e) => log::warn!("Using relayer fee rates; on-chain config unavailable: {}", e),
+        }
+
+        *CONFIG_CACHE.get_or_init(|| RwLock::new(None)).write() = Some(config.clone());
+
+        Ok(config)
+    }
+
+    /// Replace the fee rates with the on-chain ones
+    ///
+    /// Logs a warning for each rate the relayer misreported.
+    pub fn apply_on_chain(&mut self, on_chain: &OnChainConfig) {
+        let rates = [
+            ("deposit", &mut self.deposit_fee_rate, on_chain.deposit_fee_rate()),
+            ("withdraw", &mut self.withdraw_fee_rate, on_chain.withdraw_fee_rate()),
+        ];
+        for (name, rate, on_chain_rate) in rates {
+            if (*rate - on_chain_rate).abs() > f64::EPSILON {
+                log::warn!(
+                    "Relayer reports {} fee rate {}, on-chain config says {}",
+                    name,
+                    rate,
+                    on_chain_rate
+                );
+            }
+            *rate = on_chain_rate;
+        }
+    }
+
+    /// Fetch the configuration from the relayer and replace the cached copy
+    ///
+    /// On-chain fee rates previously read by [`Config::fetch_with_on_chain`]
+    /// are applied again, so a refresh never reverts to the relayer's rates.
+    pub async fn refresh() -> Result<Self> {
+        let mut config = Self::fetch().await?;
+        let on_chain = ON_CHAIN_CACHE.get().and_then(|cache| *cache.read());
+        if let Some(on_chain) = on_chain {
+            config.apply_on_chain(&on_chain);
+        }
+        *CONFIG_CACHE.get_or_init(|| RwLock::new(None)).write() = Some(config.clone());
+        Ok(config)
+    }
+
+    /// Clear the cached configuration and on-chain fee rates
     pub fn clear_cache() {
         if let Some(cache) = CONFIG_CACHE.get() {
             let mut write_guard = cache.write();
             *write_guard = None;
         }
+        if let Some(cache) = ON_CHAIN_CACHE.get() {
+            *cache.write() = None;
+        }
     }
 
     /// Get withdraw fee rate
@@ -152,6 +279,30 @@ impl Config {
             .ok_or_else(|| PrivacyCashError::ConfigError(format!("Token {} not supported", token_name)))
     }
 
+    /// Minimum withdrawal for a token in base units, if the relayer sets one
+    pub fn minimum_withdrawal_base_units(&self, token_name: &str, units_per_token: u64) -> Option<u64> {
+        self.minimum_withdrawal
+            .get(&token_name.to_lowercase())
+            .map(|min| (min * units_per_token as f64) as u64)
+    }
+
+    /// Fail with `BelowMinimum` if `base_units` is under the token's minimum withdrawal
+    ///
+    /// Tokens without a configured minimum are not checked.
+    pub async fn check_minimum_withdrawal(
+        token_name: &str,
+        units_per_token: u64,
+        base_units: u64,
+    ) -> Result<()> {
+        let config = Self::get_or_fetch().await?;
+        if let Some(min) = config.minimum_withdrawal_base_units(token_name, units_per_token) {
+            if base_units < min {
+                return Err(PrivacyCashError::BelowMinimum { min });
+            }
+        }
+        Ok(())
+    }
+
     /// Get all supported tokens with their details
     pub async fn get_supported_tokens() -> Result<Vec<SupportedToken>> {
         let config = Self::get_or_fetch().await?;
@@ -187,3 +338,60 @@ impl Config {
         Self::get_or_fetch().await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_on_chain_config() {
+        let authority = Pubkey::new_unique();
+        let mut data = vec![0u8; 8];
+        data.extend_from_slice(authority.as_ref());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&25u16.to_le_bytes());
+        data.extend_from_slice(&500u16.to_le_bytes());
+        data.push(255);
+
+        let on_chain = OnChainConfig::from_account_data(&data).unwrap();
+        assert_eq!(on_chain.authority, authority);
+        assert_eq!(on_chain.withdrawal_fee_rate, 25);
+        assert!(OnChainConfig::from_account_data(&data[..40]).is_err());
+
+        let mut config = Config {
+            withdraw_fee_rate: 0.01,
+            withdraw_rent_fee: 0.006,
+            deposit_fee_rate: 0.0,
+            usdc_withdraw_rent_fee: 0.0,
+            rent_fees: HashMap::new(),
+            minimum_withdrawal: HashMap::new(),
+            prices: HashMap::new(),
+        };
+        config.apply_on_chain(&on_chain);
+        assert_eq!(config.withdraw_fee_rate, 0.0025);
+        assert_eq!(config.deposit_fee_rate, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_keeps_on_chain_rates() {
+        let _guard = crate::http::TEST_HOOKS_LOCK.lock().await;
+        let config = r#"{"withdraw_fee_rate":0.01,"withdraw_rent_fee":0.006,
+            "deposit_fee_rate":0}"#;
+        let _transport = crate::http::CannedTransport::default()
+            .route("/config", config)
+            .install();
+        *ON_CHAIN_CACHE.get_or_init(|| RwLock::new(None)).write() = Some(OnChainConfig {
+            authority: Pubkey::new_unique(),
+            deposit_fee_rate: 0,
+            withdrawal_fee_rate: 25,
+            fee_error_margin: 500,
+        });
+        let refreshed = Config::refresh().await;
+        Config::clear_cache();
+        let relayer_only = Config::refresh().await;
+        Config::clear_cache();
+
+        assert_eq!(refreshed.unwrap().withdraw_fee_rate, 0.0025);
+        assert_eq!(relayer_only.unwrap().withdraw_fee_rate, 0.01);
+    }
+}
@@ -0,0 +1,428 @@
+//! Consolidation of many small notes into one
+//!
+//! Each transaction spends two notes into a single output (a zero-amount
+//! deposit), so a wallet with `n` notes needs `n - 1` transactions spread over
+//! several rounds. Progress is saved after every transaction, so a run that is
+//! interrupted can be picked up again with [`resume_consolidation`].
+//!
+//! Within a round the merges spend disjoint notes, so the next merge is proven
+//! while the previous one is relayed and confirmed. Its outputs are built for
+//! the leaf index the previous merge leaves the tree at; if another transaction
+//! lands in between, the proof is discarded and built again.
+
+use crate::blinding::BlindingSource;
+use crate::constants::{LSK_CONSOLIDATION, TX_OUTPUTS};
+use crate::deposit::{prove_deposit, submit_deposit, DepositParams, ProvenDeposit};
+use crate::deposit_spl::{
+    prove_deposit_spl, submit_deposit_spl, DepositSplParams, ProvenDepositSpl,
+};
+use crate::encryption::EncryptionService;
+use crate::error::{PrivacyCashError, Result};
+use crate::get_utxos::{get_utxos, localstorage_key};
+use crate::get_utxos_spl::get_utxos_spl;
+use crate::preview::ApprovalHook;
+use crate::storage::Storage;
+use crate::utxo::Utxo;
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+use spl_associated_token_account::get_associated_token_address;
+use std::time::Duration;
+
+/// Progress of a consolidation, persisted after every transaction
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsolidationProgress {
+    /// Rounds in which every note pair was merged
+    pub rounds_completed: usize,
+    /// Signatures of the merge transactions, in submission order
+    pub signatures: Vec<String>,
+}
+
+/// Consolidation result
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConsolidationResult {
+    /// Progress over the whole run, including any resumed part
+    pub progress: ConsolidationProgress,
+    /// Notes left once consolidation finished (0 or 1)
+    pub notes_remaining: usize,
+}
+
+/// What consolidating a note set would take, see [`plan_consolidation`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsolidationPlan {
+    /// Notes that would be merged
+    pub notes: usize,
+    /// Rounds of pairwise merges until one note is left
+    pub rounds: usize,
+    /// Merge transactions over all rounds (`notes - 1`)
+    pub transactions: usize,
+    /// Expected wall-clock time, one proof per transaction
+    pub estimated_time: Duration,
+}
+
+/// Parameters for consolidation
+pub struct ConsolidateParams<'a> {
+    pub connection: &'a RpcClient,
+    pub keypair: &'a Keypair,
+    pub encryption_service: &'a EncryptionService,
+    pub storage: &'a Storage,
+    /// Token mint to consolidate, or `None` for native SOL
+    pub mint_address: Option<&'a Pubkey>,
+    pub key_base_path: &'a str,
+    pub referrer: Option<&'a str>,
+    /// Called before each merge transaction is relayed; `false` aborts
+    pub approval: Option<&'a ApprovalHook>,
+    /// How the output notes get their blindings
+    pub blinding: BlindingSource,
+    /// Limit on generating each proof
+    pub proof_timeout: Duration,
+}
+
+/// Merge all unspent notes into a single note
+///
+/// Every round rescans the wallet and merges the notes pairwise, until at
+/// most one note is left. If progress from an interrupted run is stored it is
+/// continued rather than started over.
+pub async fn consolidate(params: ConsolidateParams<'_>) -> Result<ConsolidationResult> {
+    let progress = load_progress(params.storage, &progress_key(&params)).unwrap_or_default();
+    run(params, progress).await
+}
+
+/// Continue an interrupted consolidation
+///
+/// Returns `None` when no unfinished consolidation is stored. Notes spent
+/// before the interruption are skipped by the rescan, so no merge is repeated.
+pub async fn resume_consolidation(
+    params: ConsolidateParams<'_>,
+) -> Result<Option<ConsolidationResult>> {
+    match load_progress(params.storage, &progress_key(&params)) {
+        Some(progress) => {
+            log::info!(
+                "Resuming consolidation after {} transaction(s)",
+                progress.signatures.len()
+            );
+            run(params, progress).await.map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Plan the consolidation of `notes` notes without running it
+///
+/// Each round merges the notes pairwise, carrying an odd one over, so it takes
+/// `ceil(log2(notes))` rounds and `notes - 1` transactions. Relaying overlaps
+/// with proving the next merge, so the time is estimated from `proof_time`
+/// per transaction.
+pub fn plan_consolidation(notes: usize, proof_time: Duration) -> ConsolidationPlan {
+    let mut rounds = 0;
+    let mut left = notes;
+    while left > 1 {
+        left -= left / 2;
+        rounds += 1;
+    }
+    let transactions = notes.saturating_sub(1);
+
+    ConsolidationPlan {
+        notes,
+        rounds,
+        transactions,
+        estimated_time: proof_time * transactions as u32,
+    }
+}
+
+/// Load the stored progress of an unfinished consolidation
+pub fn load_progress(storage: &Storage, key: &str) -> Option<ConsolidationProgress> {
+    storage.get(key).and_then(|s| serde_json::from_str(&s).ok())
+}
+
+fn save_progress(storage: &Storage, key: &str, progress: &ConsolidationProgress) {
+    match serde_json::to_string(progress) {
+        Ok(json) => storage.set(key, &json),
+        Err(e) => log::warn!("Failed to save consolidation progress: {}", e),
+    }
+}
+
+/// Storage key of the progress, namespaced like the note cache
+fn progress_key(params: &ConsolidateParams<'_>) -> String {
+    let public_key = params.keypair.pubkey();
+    let owner = match params.mint_address {
+        Some(mint) => get_associated_token_address(&public_key, mint),
+        None => public_key,
+    };
+    format!("{}{}", LSK_CONSOLIDATION, localstorage_key(&owner))
+}
+
+async fn run(
+    params: ConsolidateParams<'_>,
+    mut progress: ConsolidationProgress,
+) -> Result<ConsolidationResult> {
+    let key = progress_key(&params);
+    let ConsolidateParams {
+        connection,
+        keypair,
+        encryption_service,
+        storage,
+        mint_address,
+        key_base_path,
+        referrer,
+        approval,
+        blinding,
+        proof_timeout,
+    } = params;
+
+    let merger = Merger {
+        connection,
+        keypair,
+        encryption_service,
+        storage,
+        mint_address,
+        key_base_path,
+        referrer,
+        approval,
+        blinding,
+        proof_timeout,
+    };
+
+    let public_key = keypair.pubkey();
+    loop {
+        let utxos = match mint_address {
+            Some(mint) => {
+                get_utxos_spl(connection, &public_key, encryption_service, storage, mint, None)
+                    .await?
+            }
+            None => get_utxos(connection, &public_key, encryption_service, storage, None).await?,
+        };
+        let notes = filter_mergeable(utxos);
+
+        if notes.len() <= 1 {
+            storage.remove(&key);
+            log::info!(
+                "Consolidation finished after {} transaction(s)",
+                progress.signatures.len()
+            );
+            return Ok(ConsolidationResult {
+                progress,
+                notes_remaining: notes.len(),
+            });
+        }
+
+        log::info!(
+            "Consolidation round {}: merging {} notes",
+            progress.rounds_completed + 1,
+            notes.len()
+        );
+
+        // An odd note out is merged in the next round
+        let pairs: Vec<&[Utxo]> = notes.chunks_exact(2).collect();
+        let mut proven = merger.prove(pairs[0], None).await?;
+        for (i, pair) in pairs.iter().enumerate() {
+            let ahead = proven.next_index() + TX_OUTPUTS;
+            let submit = merger.submit_or_reprove(pair, proven);
+
+            // Prove the next merge while this one is relayed and confirmed
+            let (signature, next) = match pairs.get(i + 1) {
+                Some(next_pair) => {
+                    let (signature, next) =
+                        tokio::join!(submit, merger.prove(next_pair, Some(ahead)));
+                    (signature?, Some((next_pair, next)))
+                }
+                None => (submit.await?, None),
+            };
+
+            log::info!("Merged 2 notes: {}", signature);
+            progress.signatures.push(signature);
+            save_progress(storage, &key, &progress);
+
+            proven = match next {
+                Some((_, Ok(next))) => next,
+                Some((next_pair, Err(e))) => {
+                    log::debug!("Proving ahead failed ({}), proving again", e);
+                    merger.prove(next_pair, None).await?
+                }
+                None => break,
+            };
+        }
+
+        progress.rounds_completed += 1;
+        save_progress(storage, &key, &progress);
+    }
+}
+
+/// A merge transaction proven but not yet submitted
+enum ProvenMerge {
+    Sol(ProvenDeposit),
+    Spl(ProvenDepositSpl),
+}
+
+impl ProvenMerge {
+    /// Leaf index of the merge's first output
+    fn next_index(&self) -> u64 {
+        match self {
+            Self::Sol(proven) => proven.next_index,
+            Self::Spl(proven) => proven.next_index,
+        }
+    }
+}
+
+/// Builds the zero-amount deposits that merge two notes
+struct Merger<'a> {
+    connection: &'a RpcClient,
+    keypair: &'a Keypair,
+    encryption_service: &'a EncryptionService,
+    storage: &'a Storage,
+    mint_address: Option<&'a Pubkey>,
+    key_base_path: &'a str,
+    referrer: Option<&'a str>,
+    approval: Option<&'a ApprovalHook>,
+    blinding: BlindingSource,
+    proof_timeout: Duration,
+}
+
+impl<'a> Merger<'a> {
+    fn sol_params(&self, pair: &'a [Utxo], next_index: Option<u64>) -> DepositParams<'a> {
+        DepositParams {
+            referrer: self.referrer,
+            input_utxos: Some(pair),
+            approval: self.approval,
+            next_index,
+            blinding: self.blinding,
+            proof_timeout: self.proof_timeout,
+            ..DepositParams::new(
+                self.connection,
+                self.keypair,
+                self.encryption_service,
+                self.storage,
+                self.key_base_path,
+            )
+        }
+    }
+
+    fn spl_params(
+        &self,
+        mint: &'a Pubkey,
+        pair: &'a [Utxo],
+        next_index: Option<u64>,
+    ) -> DepositSplParams<'a> {
+        DepositSplParams {
+            referrer: self.referrer,
+            input_utxos: Some(pair),
+            approval: self.approval,
+            next_index,
+            blinding: self.blinding,
+            proof_timeout: self.proof_timeout,
+            ..DepositSplParams::new(
+                self.connection,
+                self.keypair,
+                self.encryption_service,
+                self.storage,
+                mint,
+                self.key_base_path,
+            )
+        }
+    }
+
+    /// Prove a merge, for the given leaf index if proving ahead
+    async fn prove(&self, pair: &'a [Utxo], next_index: Option<u64>) -> Result<ProvenMerge> {
+        match self.mint_address {
+            Some(mint) => {
+                let params = self.spl_params(mint, pair, next_index);
+                prove_deposit_spl(&params).await.map(ProvenMerge::Spl)
+            }
+            None => prove_deposit(&self.sol_params(pair, next_index)).await.map(ProvenMerge::Sol),
+        }
+    }
+
+    /// Submit a merge, proving it again if its proof was built for a stale index
+    async fn submit_or_reprove(&self, pair: &'a [Utxo], proven: ProvenMerge) -> Result<String> {
+        match self.submit(pair, proven).await {
+            Err(PrivacyCashError::StaleProof { expected, actual }) => {
+                log::info!("Tree moved to {} instead of {}, proving again", actual, expected);
+                let proven = self.prove(pair, None).await?;
+                self.submit(pair, proven).await
+            }
+            result => result,
+        }
+    }
+
+    async fn submit(&self, pair: &'a [Utxo], proven: ProvenMerge) -> Result<String> {
+        let next_index = Some(proven.next_index());
+        match (self.mint_address, proven) {
+            (Some(mint), ProvenMerge::Spl(proven)) => {
+                let params = self.spl_params(mint, pair, next_index);
+                Ok(submit_deposit_spl(&params, proven).await?.signature)
+            }
+            (_, ProvenMerge::Sol(proven)) => {
+                let params = self.sol_params(pair, next_index);
+                Ok(submit_deposit(&params, proven).await?.signature)
+            }
+            (None, ProvenMerge::Spl(_)) => unreachable!("SPL merge without a mint"),
+        }
+    }
+}
+
+/// Keep only notes that still hold value
+pub(crate) fn filter_mergeable(utxos: Vec<Utxo>) -> Vec<Utxo> {
+    utxos.into_iter().filter(|u| !u.is_dummy()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_consolidation_progress() {
+        let connection = RpcClient::new("http://127.0.0.1:8899".to_string());
+        let keypair = Keypair::new();
+        let encryption_service = EncryptionService::new();
+        let storage = Storage::memory();
+        let params = || ConsolidateParams {
+            connection: &connection,
+            keypair: &keypair,
+            encryption_service: &encryption_service,
+            storage: &storage,
+            mint_address: None,
+            key_base_path: "",
+            referrer: None,
+            approval: None,
+            blinding: BlindingSource::Random,
+            proof_timeout: Duration::from_secs(1),
+        };
+
+        // Nothing stored: resuming is a no-op and never touches the network
+        assert!(resume_consolidation(params()).await.unwrap().is_none());
+
+        let key = progress_key(&params());
+        let progress = ConsolidationProgress {
+            rounds_completed: 1,
+            signatures: vec!["sig1".to_string(), "sig2".to_string()],
+        };
+        save_progress(&storage, &key, &progress);
+        assert_eq!(load_progress(&storage, &key), Some(progress));
+
+        let usdc = *crate::constants::USDC_MINT;
+        let spl_key = progress_key(&ConsolidateParams {
+            mint_address: Some(&usdc),
+            ..params()
+        });
+        assert_ne!(spl_key, key);
+        assert_eq!(load_progress(&storage, &spl_key), None);
+    }
+
+    #[test]
+    fn test_plan_consolidation() {
+        let proof = Duration::from_secs(30);
+        assert_eq!(plan_consolidation(0, proof), ConsolidationPlan::default());
+        assert_eq!(plan_consolidation(1, proof).transactions, 0);
+
+        let plan = plan_consolidation(2, proof);
+        assert_eq!((plan.rounds, plan.transactions), (1, 1));
+
+        // 5 -> 3 -> 2 -> 1
+        let plan = plan_consolidation(5, proof);
+        assert_eq!((plan.rounds, plan.transactions), (3, 4));
+        assert_eq!(plan.estimated_time, Duration::from_secs(120));
+
+        assert_eq!(plan_consolidation(8, proof).rounds, 3);
+        assert_eq!(plan_consolidation(9, proof).rounds, 4);
+    }
+}
@@ -107,18 +107,54 @@ pub static SOL_MINT: Lazy<Pubkey> = Lazy::new(|| {
     Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap()
 });
 
+/// Environment variable naming the circuit base path
+pub const CIRCUIT_PATH_ENV: &str = "PRIVACY_CASH_CIRCUIT_PATH";
+
+/// Circuit base path searched for next to the executable and the working directory
+pub const DEFAULT_CIRCUIT_PATH: &str = "circuit/transaction2";
+
+/// Default limit on a single proof generation, in seconds
+pub const DEFAULT_PROOF_TIMEOUT_SECS: u64 = 120;
+
+/// Delay between balance scans while waiting for a deposit to be indexed, in seconds
+pub const INDEX_POLL_INTERVAL_SECS: u64 = 2;
+
+/// Balance scans made while waiting for a deposit to be indexed
+pub const INDEX_POLL_ATTEMPTS: u32 = 15;
+
+/// How long a deposit idempotency key is remembered, in seconds
+pub const IDEMPOTENCY_WINDOW_SECS: u64 = 24 * 60 * 60;
+
+/// Proof time assumed until a proof has been timed on this machine, in seconds
+pub const DEFAULT_PROOF_ESTIMATE_SECS: f64 = 45.0;
+
+/// Weight of the latest proof time in the moving average
+pub const PROOF_TIME_EMA_WEIGHT: f64 = 0.3;
+
+/// Consecutive unmatched derived blindings after which counter recovery stops
+pub const BLINDING_RECOVERY_GAP: u64 = 100;
+
 /// Number of UTXOs to fetch per batch
 pub const FETCH_UTXOS_GROUP_SIZE: u64 = 20_000;
 
 /// Merkle tree depth (26 levels)
 pub const MERKLE_TREE_DEPTH: usize = 26;
 
+/// Number of output commitments each transaction appends to the tree
+pub const TX_OUTPUTS: u64 = 2;
+
 /// Transaction instruction discriminator for native SOL
 pub const TRANSACT_IX_DISCRIMINATOR: [u8; 8] = [217, 149, 130, 143, 221, 52, 252, 119];
 
 /// Transaction instruction discriminator for SPL tokens
 pub const TRANSACT_SPL_IX_DISCRIMINATOR: [u8; 8] = [154, 66, 244, 204, 78, 225, 163, 151];
 
+/// Accounts passed to the native SOL transact instruction
+pub const TRANSACT_ACCOUNTS: usize = 11;
+
+/// Accounts passed to the SPL transact instruction
+pub const TRANSACT_SPL_ACCOUNTS: usize = 16;
+
 /// Sign message for deriving encryption keys
 pub const SIGN_MESSAGE: &str = "Privacy Money account sign in";
 
@@ -128,6 +164,36 @@ pub const LSK_FETCH_OFFSET: &str = "fetch_offset";
 /// LocalStorage key prefix for encrypted outputs
 pub const LSK_ENCRYPTED_OUTPUTS: &str = "encrypted_outputs";
 
+/// LocalStorage key prefix for the last-seen tree root and size
+pub const LSK_TREE_STATE: &str = "tree_state";
+
+/// LocalStorage key prefix for nullifiers known to be spent at `finalized`
+pub const LSK_SPENT_NULLIFIERS: &str = "spent_nullifiers";
+
+/// LocalStorage key prefix for the progress of an unfinished consolidation
+pub const LSK_CONSOLIDATION: &str = "consolidation";
+
+/// LocalStorage key prefix for the number of derived note blindings used
+pub const LSK_BLINDING_COUNTER: &str = "blinding_counter";
+
+/// LocalStorage key prefix for the audit log of completed operations
+pub const LSK_AUDIT_LOG: &str = "audit_log";
+
+/// LocalStorage key prefix for recently used deposit idempotency keys
+pub const LSK_IDEMPOTENCY_KEYS: &str = "idempotency_keys";
+
+/// LocalStorage key of the moving average of proof times, in seconds
+pub const LSK_PROOF_SECONDS: &str = "proof_seconds_ema";
+
+/// Maximum serialized transaction size accepted by Solana (packet data size)
+///
+/// The transact instruction data has 512 fixed bytes (discriminator, proof,
+/// 7 public signals, ext_amount, fee, length prefixes) and the rest of a
+/// deposit transaction adds roughly 400 bytes, so the two encrypted outputs
+/// together must stay under about 320 bytes. A V2 output is 36 bytes of
+/// envelope (version, IV, auth tag) plus the `amount|blinding|index|mint` note.
+pub const MAX_TRANSACTION_SIZE: usize = 1232;
+
 /// Lamports per SOL
 pub const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
 
@@ -0,0 +1,183 @@
+//! Confirming that a withdrawal reached its recipient
+//!
+//! Reads the confirmed withdrawal transaction back from the RPC node and
+//! compares the recipient's balance before and after it, as recorded in the
+//! transaction's metadata. The balances are the ones at the transaction's
+//! slot, so transfers the recipient makes or receives afterwards do not
+//! affect the result.
+
+use crate::error::{PrivacyCashError, Result};
+use crate::withdraw::WithdrawResult;
+use crate::withdraw_spl::WithdrawSplResult;
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status_client_types::option_serializer::OptionSerializer;
+use solana_transaction_status_client_types::{
+    UiTransactionEncoding, UiTransactionStatusMeta, UiTransactionTokenBalance,
+};
+use std::str::FromStr;
+
+/// Whether the recipient of a SOL withdrawal received its net amount
+///
+/// `amount_in_lamports` is already net of the relayer fee, which is paid
+/// from the private balance on top of it, so the recipient's lamports must
+/// grow by exactly that much. Returns `false` if the transaction failed or
+/// did not touch the recipient.
+pub fn verify_withdrawal(connection: &RpcClient, result: &WithdrawResult) -> Result<bool> {
+    let recipient = parse_pubkey(&result.recipient)?;
+    let (account_keys, meta) = fetch_transaction(connection, &result.signature)?;
+    if meta.err.is_some() {
+        return Ok(false);
+    }
+    Ok(lamport_change(&account_keys, &meta, &recipient) == Some(result.amount_in_lamports as i128))
+}
+
+/// Whether the recipient of an SPL withdrawal received its net amount
+///
+/// Checks the recipient's token balance for `mint` across the transaction
+/// that delivered the tokens: the withdrawal itself, or the follow-up
+/// transfer for [`DeliveryMode::ViaSelf`](crate::withdraw_spl::DeliveryMode).
+pub fn verify_withdrawal_spl(
+    connection: &RpcClient,
+    mint: &Pubkey,
+    result: &WithdrawSplResult,
+) -> Result<bool> {
+    let recipient = parse_pubkey(&result.recipient)?;
+    let signature = result.transfer_signature.as_deref().unwrap_or(&result.signature);
+    let (_, meta) = fetch_transaction(connection, signature)?;
+    if meta.err.is_some() {
+        return Ok(false);
+    }
+    Ok(token_change(&meta, &recipient, mint) == Some(result.base_units as i128))
+}
+
+/// Change in `account`'s lamports across the transaction
+pub(crate) fn lamport_change(
+    account_keys: &[Pubkey],
+    meta: &UiTransactionStatusMeta,
+    account: &Pubkey,
+) -> Option<i128> {
+    let index = account_keys.iter().position(|key| key == account)?;
+    let pre = *meta.pre_balances.get(index)?;
+    let post = *meta.post_balances.get(index)?;
+    Some(post as i128 - pre as i128)
+}
+
+/// Change in the `mint` tokens `owner` holds across the transaction
+///
+/// A token account created by the transaction has no pre balance and counts
+/// as starting from zero.
+pub(crate) fn token_change(
+    meta: &UiTransactionStatusMeta,
+    owner: &Pubkey,
+    mint: &Pubkey,
+) -> Option<i128> {
+    let post = token_balance(&meta.post_token_balances, owner, mint)?;
+    let pre = token_balance(&meta.pre_token_balances, owner, mint).unwrap_or(0);
+    Some(post - pre)
+}
+
+/// Sum of the `mint` balances owned by `owner`, if any are listed
+fn token_balance(
+    balances: &OptionSerializer<Vec<UiTransactionTokenBalance>>,
+    owner: &Pubkey,
+    mint: &Pubkey,
+) -> Option<i128> {
+    let OptionSerializer::Some(balances) = balances else {
+        return None;
+    };
+    let (owner, mint) = (owner.to_string(), mint.to_string());
+    let owned: Vec<_> = balances
+        .iter()
+        .filter(|b| b.mint == mint && b.owner.as_ref() == OptionSerializer::Some(&owner))
+        .collect();
+    if owned.is_empty() {
+        return None;
+    }
+    owned.iter().map(|b| b.ui_token_amount.amount.parse::<i128>().ok()).sum()
+}
+
+/// Fetch a confirmed transaction's account keys and status metadata
+///
+/// Keys follow the order of the balance lists: the message's static keys,
+/// then the writable and readonly addresses loaded from lookup tables.
+fn fetch_transaction(
+    connection: &RpcClient,
+    signature: &str,
+) -> Result<(Vec<Pubkey>, UiTransactionStatusMeta)> {
+    let signature = Signature::from_str(signature)
+        .map_err(|e| PrivacyCashError::InvalidInput(format!("Invalid signature: {}", e)))?;
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        commitment: Some(CommitmentConfig::confirmed()),
+        max_supported_transaction_version: Some(0),
+    };
+    let confirmed = connection.get_transaction_with_config(&signature, config)?;
+
+    let transaction = confirmed.transaction.transaction.decode().ok_or_else(|| {
+        PrivacyCashError::InvalidRpcResponse("Transaction could not be decoded".to_string())
+    })?;
+    let meta = confirmed.transaction.meta.ok_or_else(|| {
+        PrivacyCashError::InvalidRpcResponse("Transaction has no status metadata".to_string())
+    })?;
+
+    let mut account_keys = transaction.message.static_account_keys().to_vec();
+    if let OptionSerializer::Some(loaded) = &meta.loaded_addresses {
+        for address in loaded.writable.iter().chain(&loaded.readonly) {
+            account_keys.push(parse_pubkey(address)?);
+        }
+    }
+    Ok((account_keys, meta))
+}
+
+fn parse_pubkey(address: &str) -> Result<Pubkey> {
+    Pubkey::from_str(address)
+        .map_err(|e| PrivacyCashError::InvalidInput(format!("Invalid address {}: {}", address, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_balance_changes() {
+        let (payer, recipient, mint) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let token = |index: u8, owner: &Pubkey, amount: &str| {
+            serde_json::json!({
+                "accountIndex": index,
+                "mint": mint.to_string(),
+                "owner": owner.to_string(),
+                "uiTokenAmount": {
+                    "uiAmount": null,
+                    "decimals": 6,
+                    "amount": amount,
+                    "uiAmountString": "",
+                },
+            })
+        };
+        let meta: UiTransactionStatusMeta = serde_json::from_value(serde_json::json!({
+            "err": null,
+            "status": { "Ok": null },
+            "fee": 5000,
+            "preBalances": [1_000_000, 20],
+            "postBalances": [995_000, 1_000_020],
+            // The recipient's token account is created by the transaction
+            "preTokenBalances": [token(2, &payer, "700")],
+            "postTokenBalances": [token(2, &payer, "700"), token(3, &recipient, "1500")],
+        }))
+        .unwrap();
+
+        let keys = [payer, recipient];
+        assert_eq!(lamport_change(&keys, &meta, &recipient), Some(1_000_000));
+        assert_eq!(lamport_change(&keys, &meta, &payer), Some(-5000));
+        assert_eq!(lamport_change(&keys, &meta, &mint), None);
+
+        assert_eq!(token_change(&meta, &recipient, &mint), Some(1500));
+        assert_eq!(token_change(&meta, &payer, &mint), Some(0));
+        assert_eq!(token_change(&meta, &recipient, &Pubkey::new_unique()), None);
+    }
+}
@@ -1,21 +1,29 @@
 //! Deposit functionality for native SOL
 
+use crate::blinding::BlindingSource;
+use crate::clock;
 use crate::constants::{
-    ALT_ADDRESS, FEE_RECIPIENT, PROGRAM_ID, TRANSACT_IX_DISCRIMINATOR,
+    ALT_ADDRESS, DEFAULT_PROOF_TIMEOUT_SECS, FEE_RECIPIENT, PROGRAM_ID, TRANSACT_ACCOUNTS,
+    TRANSACT_IX_DISCRIMINATOR, TX_OUTPUTS,
 };
-use crate::encryption::EncryptionService;
+use crate::encryption::{ChangeOutput, EncryptionService, OutputTarget};
 use crate::error::{PrivacyCashError, Result};
-use crate::get_utxos::get_utxos;
+use crate::get_utxos::{ensure_unspent_finalized, get_utxos};
+use crate::http::transport;
 use crate::keypair::ZkKeypair;
-use crate::merkle_tree::MerkleTree;
-use crate::prover::{parse_proof_to_bytes, parse_public_signals_to_bytes, CircuitInput};
+use crate::merkle_tree::{MerklePath, MerkleTree};
+use crate::metrics::{metrics, record_relayer_error, DEPOSITS_TOTAL};
+use crate::preview::{require_approval, ApprovalHook, TransactionKind, TxPreview};
+use crate::prover::{
+    parse_proof_to_bytes, parse_public_signals_to_bytes, CircuitArity, CircuitInput,
+};
 use crate::prover_rust::RustProver;
 use crate::storage::Storage;
 use crate::utxo::{Utxo, UtxoVersion};
 use crate::utils::{
-    calculate_public_amount, fetch_merkle_proof, find_cross_check_nullifier_pdas,
-    find_nullifier_pdas, get_mint_address_field, get_program_accounts, query_remote_tree_state,
-    ExtData,
+    calculate_public_amount, check_transaction_size, fetch_merkle_proof,
+    find_cross_check_nullifier_pdas, find_nullifier_pdas, get_mint_address_field,
+    get_program_accounts, query_remote_tree_state, ExtData,
 };
 use num_bigint::BigUint;
 use num_traits::Zero;
@@ -33,27 +41,128 @@ use solana_sdk::{
     transaction::VersionedTransaction,
 };
 use std::str::FromStr;
+use std::time::Duration;
 
 /// Deposit result
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DepositResult {
     /// Transaction signature
     pub signature: String,
+    /// Commitments of the two output notes; empty if the deposit was not
+    /// made by this call
+    #[serde(default)]
+    pub output_commitments: Vec<String>,
+    /// Fee taken by the pool in lamports
+    #[serde(default)]
+    pub fee_in_lamports: u64,
+}
+
+impl std::fmt::Display for DepositResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Deposit {}", self.signature)
+    }
 }
 
+/// Step run with the signature of a signed deposit just before it is relayed
+///
+/// Lets the caller record the transaction so a retry can look it up on-chain;
+/// an error aborts the deposit.
+pub type OnSigned<'a> = dyn Fn(&str) -> Result<()> + Send + Sync + 'a;
+
 /// Parameters for deposit
 pub struct DepositParams<'a> {
     pub connection: &'a RpcClient,
     pub keypair: &'a Keypair,
+    /// Shielded identity that owns the new notes; need not derive from `keypair`
     pub encryption_service: &'a EncryptionService,
     pub storage: &'a Storage,
     pub amount_in_lamports: u64,
     pub key_base_path: &'a str,
     pub referrer: Option<&'a str>,
+    /// Sent to the relayer so a retried deposit with the same key is dropped
+    /// as a duplicate
+    pub idempotency_key: Option<&'a str>,
+    /// Spend these notes instead of scanning for existing UTXOs (at most two)
+    pub input_utxos: Option<&'a [Utxo]>,
+    /// Called with the final transaction details before relaying; `false` aborts
+    pub approval: Option<&'a ApprovalHook>,
+    /// Called with the approved transaction's signature before relaying
+    pub on_signed: Option<&'a OnSigned<'a>>,
+    /// Who each output note is created for and encrypted to; `Sender` keeps it
+    pub output_targets: [OutputTarget<'a>; 2],
+    /// Which output is the sender's change; it must target `Sender`
+    pub change_output: ChangeOutput,
+    /// Leaf index to build the outputs for instead of the relayer's next
+    /// index, to prove ahead of an earlier transaction landing
+    pub next_index: Option<u64>,
+    /// How the output notes get their blindings
+    pub blinding: BlindingSource,
+    /// Limit on generating the proof
+    pub proof_timeout: Duration,
+}
+
+impl<'a> DepositParams<'a> {
+    /// Parameters for a plain deposit of nothing yet
+    ///
+    /// Everything else starts at its default: no amount, referrer, idempotency
+    /// key, approval hook or signed-transaction step, scanned inputs, change
+    /// to the sender, random blindings and the default proof timeout. Set the
+    /// fields that differ with struct update syntax.
+    pub fn new(
+        connection: &'a RpcClient,
+        keypair: &'a Keypair,
+        encryption_service: &'a EncryptionService,
+        storage: &'a Storage,
+        key_base_path: &'a str,
+    ) -> Self {
+        Self {
+            connection,
+            keypair,
+            encryption_service,
+            storage,
+            amount_in_lamports: 0,
+            key_base_path,
+            referrer: None,
+            idempotency_key: None,
+            input_utxos: None,
+            approval: None,
+            on_signed: None,
+            output_targets: Default::default(),
+            change_output: Default::default(),
+            next_index: None,
+            blinding: BlindingSource::default(),
+            proof_timeout: Duration::from_secs(DEFAULT_PROOF_TIMEOUT_SECS),
+        }
+    }
+}
+
+/// A deposit that is proven but not yet submitted, see [`prove_deposit`]
+pub struct ProvenDeposit {
+    /// Leaf index of the first output the proof was built for
+    pub next_index: u64,
+    /// Whether `next_index` was assumed rather than read from the relayer
+    pub ahead: bool,
+    instruction: Instruction,
+    amount_in_lamports: u64,
+    fee_amount: u64,
+    input_nullifiers: Vec<String>,
+    output_commitments: Vec<String>,
+    encrypted_output1: Vec<u8>,
 }
 
 /// Execute a deposit
 pub async fn deposit(params: DepositParams<'_>) -> Result<DepositResult> {
+    let proven = prove_deposit(&params).await?;
+    submit_deposit(&params, proven).await
+}
+
+/// Build and prove a deposit without submitting it
+///
+/// With `params.next_index` set, the outputs are built for that leaf index,
+/// so the proof can be generated while an earlier transaction is still
+/// landing. [`submit_deposit`] then refuses the proof if the tree has moved
+/// elsewhere.
+pub async fn prove_deposit(params: &DepositParams<'_>) -> Result<ProvenDeposit> {
     let DepositParams {
         connection,
         keypair,
@@ -61,8 +170,21 @@ pub async fn deposit(params: DepositParams<'_>) -> Result<DepositResult> {
         storage,
         amount_in_lamports,
         key_base_path,
-        referrer,
-    } = params;
+        referrer: _,
+        idempotency_key: _,
+        input_utxos,
+        approval: _,
+        on_signed: _,
+        output_targets,
+        change_output,
+        next_index,
+        blinding,
+        proof_timeout,
+    } = *params;
+
+    // The transact instruction only takes the two-input circuit
+    CircuitArity::from_key_base_path(key_base_path)?.ensure_supported_on_chain()?;
+    change_output.check_targets(&output_targets)?;
 
     let public_key = keypair.pubkey();
     let fee_amount = 0u64; // No deposit fee
@@ -93,6 +215,8 @@ pub async fn deposit(params: DepositParams<'_>) -> Result<DepositResult> {
 
     // Get tree state
     let tree_state = query_remote_tree_state(None).await?;
+    tree_state.ensure_room_for(TX_OUTPUTS)?;
+    let output_index = next_index.unwrap_or(tree_state.next_index);
 
     log::debug!(
         "Tree state: root={}, nextIndex={}",
@@ -104,20 +228,22 @@ pub async fn deposit(params: DepositParams<'_>) -> Result<DepositResult> {
     let utxo_private_key = encryption_service.get_utxo_private_key_v2()?;
     let utxo_keypair = ZkKeypair::from_hex(&utxo_private_key)?;
 
-    // Fetch existing UTXOs
-    let existing_utxos = get_utxos(connection, &public_key, encryption_service, storage, None).await?;
+    // Fetch existing UTXOs (unless the caller chose the inputs)
+    let existing_utxos = match input_utxos {
+        Some(utxos) => utxos
+            .iter()
+            .map(|utxo| encryption_service.with_version_keypair(utxo))
+            .collect::<Result<Vec<_>>>()?,
+        None => get_utxos(connection, &public_key, encryption_service, storage, None).await?,
+    };
 
     // Build inputs and calculate amounts
     let (inputs, input_merkle_paths, ext_amount, output_amount) = if existing_utxos.is_empty() {
-        // Fresh deposit - use dummy inputs
-        let inputs = vec![
-            Utxo::dummy(utxo_keypair.clone(), None),
-            Utxo::dummy(utxo_keypair.clone(), None),
-        ];
-        let paths = vec![MerkleTree::zero_path(), MerkleTree::zero_path()];
+        // Fresh deposit - use dummy inputs, proven against the fetched root
+        let (inputs, paths) = first_deposit_inputs(&utxo_keypair, None);
 
         let ext_amount = amount_in_lamports as i64;
-        let output_amount = BigUint::from(amount_in_lamports) - BigUint::from(fee_amount);
+        let output_amount = deposit_output_amount(&[], amount_in_lamports, fee_amount)?;
 
         (inputs, paths, ext_amount, output_amount)
     } else {
@@ -141,10 +267,11 @@ pub async fn deposit(params: DepositParams<'_>) -> Result<DepositResult> {
         };
 
         let ext_amount = amount_in_lamports as i64;
-        let output_amount = first_utxo.amount.clone()
-            + second_utxo.amount.clone()
-            + BigUint::from(amount_in_lamports)
-            - BigUint::from(fee_amount);
+        let output_amount = deposit_output_amount(
+            &[first_utxo, &second_utxo],
+            amount_in_lamports,
+            fee_amount,
+        )?;
 
         (
             vec![first_utxo.clone(), second_utxo],
@@ -154,21 +281,31 @@ pub async fn deposit(params: DepositParams<'_>) -> Result<DepositResult> {
         )
     };
 
+    // Fail before proving if an input's spend is already finalized
+    ensure_unspent_finalized(connection, &inputs).await?;
+
     let public_amount = calculate_public_amount(ext_amount, fee_amount);
 
     // Create outputs
+    let [first_owner, second_owner] = [
+        output_targets[0].utxo_keypair(encryption_service)?,
+        output_targets[1].utxo_keypair(encryption_service)?,
+    ];
+    let [first_amount, second_amount] = change_output.arrange(output_amount, BigUint::zero());
     let outputs = vec![
-        Utxo::new(
-            output_amount,
-            utxo_keypair.clone(),
-            tree_state.next_index,
+        Utxo::with_blinding(
+            first_amount,
+            blinding.next(encryption_service, storage)?,
+            first_owner,
+            output_index,
             None,
             Some(UtxoVersion::V2),
         ),
-        Utxo::new(
-            0u64,
-            utxo_keypair.clone(),
-            tree_state.next_index + 1,
+        Utxo::with_blinding(
+            second_amount,
+            blinding.next(encryption_service, storage)?,
+            second_owner,
+            output_index + 1,
             None,
             Some(UtxoVersion::V2),
         ),
@@ -179,8 +316,8 @@ pub async fn deposit(params: DepositParams<'_>) -> Result<DepositResult> {
     let output_commitments = vec![outputs[0].get_commitment()?, outputs[1].get_commitment()?];
 
     // Encrypt outputs
-    let encrypted_output1 = encryption_service.encrypt_utxo(&outputs[0])?;
-    let encrypted_output2 = encryption_service.encrypt_utxo(&outputs[1])?;
+    let encrypted_output1 = output_targets[0].encrypt(encryption_service, &outputs[0])?;
+    let encrypted_output2 = output_targets[1].encrypt(encryption_service, &outputs[1])?;
 
     // Create ExtData
     let recipient = Pubkey::from_str("AWexibGxNFKTa1b5R5MN4PJr9HWnWRwf8EW9g8cLx3dM").unwrap();
@@ -196,6 +333,9 @@ pub async fn deposit(params: DepositParams<'_>) -> Result<DepositResult> {
         mint_address: sol_mint,
     };
 
+    // Reject outputs that cannot fit in a transaction before spending time on a proof
+    check_transaction_size(ext_data.min_transaction_size(TRANSACT_ACCOUNTS))?;
+
     let ext_data_hash = ext_data.hash();
     
     // Debug: log extData values
@@ -234,8 +374,8 @@ pub async fn deposit(params: DepositParams<'_>) -> Result<DepositResult> {
 
     // Generate proof using pure Rust prover (iOS compatible, no Node.js needed)
     log::info!("Generating ZK proof using pure Rust prover...");
-    let prover = RustProver::new(key_base_path);
-    let (proof, public_signals) = prover.prove(&circuit_input).await?;
+    let prover = RustProver::new(key_base_path).with_timeout(proof_timeout);
+    let (proof, public_signals) = prover.prove_recorded(&circuit_input, storage).await?;
 
     // Parse proof to bytes
     let proof_bytes = parse_proof_to_bytes(&proof)?;
@@ -284,6 +424,54 @@ pub async fn deposit(params: DepositParams<'_>) -> Result<DepositResult> {
         data: instruction_data,
     };
 
+    Ok(ProvenDeposit {
+        next_index: output_index,
+        ahead: next_index.is_some(),
+        instruction: deposit_instruction,
+        amount_in_lamports,
+        fee_amount,
+        input_nullifiers,
+        output_commitments,
+        encrypted_output1,
+    })
+}
+
+/// Sign, relay and confirm a proven deposit
+///
+/// A proof built ahead fails with `StaleProof` if the relayer's next index is
+/// no longer the one it was built for; prove it again in that case.
+pub async fn submit_deposit(
+    params: &DepositParams<'_>,
+    proven: ProvenDeposit,
+) -> Result<DepositResult> {
+    let DepositParams {
+        connection,
+        keypair,
+        referrer,
+        idempotency_key,
+        approval,
+        on_signed,
+        ..
+    } = *params;
+    let ProvenDeposit {
+        next_index,
+        ahead,
+        instruction: deposit_instruction,
+        amount_in_lamports,
+        fee_amount,
+        input_nullifiers,
+        output_commitments,
+        encrypted_output1,
+    } = proven;
+    let public_key = keypair.pubkey();
+
+    if ahead {
+        let actual = query_remote_tree_state(None).await?.next_index;
+        if actual != next_index {
+            return Err(PrivacyCashError::StaleProof { expected: next_index, actual });
+        }
+    }
+
     let compute_budget_ix = ComputeBudgetInstruction::set_compute_unit_limit(1_000_000);
 
     // Fetch Address Lookup Table
@@ -312,16 +500,72 @@ pub async fn deposit(params: DepositParams<'_>) -> Result<DepositResult> {
     use base64::Engine;
     let tx_bytes = bincode::serialize(&transaction)
         .map_err(|e| PrivacyCashError::SerializationError(format!("Failed to serialize transaction: {}", e)))?;
+    check_transaction_size(tx_bytes.len())?;
     let serialized = base64::engine::general_purpose::STANDARD.encode(&tx_bytes);
 
+    require_approval(
+        approval,
+        TxPreview {
+            kind: TransactionKind::Deposit,
+            token: "sol".to_string(),
+            recipient: None,
+            amount: amount_in_lamports,
+            fee: fee_amount,
+            input_nullifiers: input_nullifiers.clone(),
+            output_commitments: output_commitments.clone(),
+        },
+    )?;
+    if let Some(on_signed) = on_signed {
+        on_signed(&transaction.signatures[0].to_string())?;
+    }
+
     log::info!("Submitting signed transaction to relayer...");
-    let signature = relay_deposit_to_indexer(&serialized, &public_key, referrer).await?;
+    let signature =
+        relay_deposit_to_indexer(&serialized, &public_key, referrer, idempotency_key).await?;
 
     // Wait for confirmation
     log::info!("Waiting for confirmation...");
-    wait_for_confirmation(&encrypted_output1, None).await?;
+    wait_for_confirmation(&signature, &encrypted_output1, None).await?;
+
+    metrics().inc_counter(DEPOSITS_TOTAL, &[("token", "sol")]);
+    Ok(DepositResult {
+        signature,
+        output_commitments,
+        fee_in_lamports: fee_amount,
+    })
+}
 
-    Ok(DepositResult { signature })
+/// Inputs of a first deposit: two dummy notes with zero Merkle paths
+///
+/// The circuit only enforces Merkle membership for inputs with a non-zero
+/// amount, so the dummies prove against any root. The transaction must still
+/// use the relayer's current root rather than an empty-tree or zero root: the
+/// root is a public signal, and the program rejects roots that are not in its
+/// root history.
+pub fn first_deposit_inputs(
+    keypair: &ZkKeypair,
+    mint_address: Option<&str>,
+) -> (Vec<Utxo>, Vec<MerklePath>) {
+    let inputs = vec![
+        Utxo::dummy(keypair.clone(), mint_address),
+        Utxo::dummy(keypair.clone(), mint_address),
+    ];
+    (inputs, vec![MerkleTree::zero_path(), MerkleTree::zero_path()])
+}
+
+/// Value of a deposit's first output: the inputs plus `amount`, less `fee`
+///
+/// Fails with `InvalidInput` when nothing would be left, since the deposit
+/// would then only create zero-value notes and still cost fees.
+pub(crate) fn deposit_output_amount(inputs: &[&Utxo], amount: u64, fee: u64) -> Result<BigUint> {
+    let total = inputs.iter().map(|utxo| &utxo.amount).sum::<BigUint>() + amount;
+    if total <= BigUint::from(fee) {
+        return Err(PrivacyCashError::InvalidInput(format!(
+            "Deposit of {} base units leaves nothing after a {} fee; the note would be empty",
+            amount, fee
+        )));
+    }
+    Ok(total - fee)
 }
 
 /// Relay deposit to indexer backend
@@ -329,6 +573,7 @@ async fn relay_deposit_to_indexer(
     signed_transaction: &str,
     sender: &Pubkey,
     referrer: Option<&str>,
+    idempotency_key: Option<&str>,
 ) -> Result<String> {
     use crate::constants::RELAYER_API_URL;
 
@@ -340,17 +585,18 @@ async fn relay_deposit_to_indexer(
     if let Some(ref_addr) = referrer {
         body["referralWalletAddress"] = serde_json::Value::String(ref_addr.to_string());
     }
+    if let Some(key) = idempotency_key {
+        body["idempotencyKey"] = serde_json::Value::String(key.to_string());
+    }
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post(format!("{}/deposit", *RELAYER_API_URL))
-        .json(&body)
-        .send()
+    let response = transport()
+        .post_json(&format!("{}/deposit", *RELAYER_API_URL), &body)
         .await
         .map_err(|e| PrivacyCashError::ApiError(format!("Relay failed: {}", e)))?;
 
-    if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_default();
+    if !response.is_success() {
+        record_relayer_error("/deposit", response.status);
+        let error_text = response.body;
         return Err(PrivacyCashError::ApiError(format!(
             "Deposit relay failed: {}",
             error_text
@@ -362,16 +608,17 @@ async fn relay_deposit_to_indexer(
         signature: String,
     }
 
-    let result: Response = response
-        .json()
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("Parse response: {}", e)))?;
+    let result: Response = response.json_with_context("Failed to parse relayer response")?;
 
     Ok(result.signature)
 }
 
 /// Wait for transaction confirmation
-async fn wait_for_confirmation(encrypted_output: &[u8], token_name: Option<&str>) -> Result<()> {
+pub(crate) async fn wait_for_confirmation(
+    signature: &str,
+    encrypted_output: &[u8],
+    token_name: Option<&str>,
+) -> Result<()> {
     use crate::constants::RELAYER_API_URL;
 
     let encrypted_hex = hex::encode(encrypted_output);
@@ -379,17 +626,17 @@ async fn wait_for_confirmation(encrypted_output: &[u8], token_name: Option<&str>
     let max_retries = 10;
 
     loop {
-        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+        clock::sleep(Duration::from_secs(2)).await;
 
         let mut url = format!("{}/utxos/check/{}", *RELAYER_API_URL, encrypted_hex);
         if let Some(token) = token_name {
             url = format!("{}?token={}", url, token);
         }
 
-        let response = reqwest::get(&url).await;
+        let response = transport().get_json(&url).await;
 
         if let Ok(resp) = response {
-            if let Ok(data) = resp.json::<serde_json::Value>().await {
+            if let Ok(data) = resp.json::<serde_json::Value>() {
                 if data.get("exists").and_then(|v| v.as_bool()).unwrap_or(false) {
                     return Ok(());
                 }
@@ -398,7 +645,10 @@ async fn wait_for_confirmation(encrypted_output: &[u8], token_name: Option<&str>
 
         retries += 1;
         if retries >= max_retries {
-            return Err(PrivacyCashError::ConfirmationTimeout { retries });
+            return Err(PrivacyCashError::ConfirmationTimeout {
+                signature: signature.to_string(),
+                retries,
+            });
         }
 
         log::info!("Confirming transaction... (retry {})", retries);
@@ -474,7 +724,8 @@ fn serialize_deposit_instruction(
     data.extend_from_slice(&proof_bytes.proof_c);
 
     // Public signals: root, publicAmount, extDataHash, nullifiers, commitments
-    for signal in signals.iter().take(7) {
+    let signal_count = CircuitArity::TRANSACTION2.public_signal_count();
+    for signal in signals.iter().take(signal_count) {
         data.extend_from_slice(signal);
     }
 
@@ -490,3 +741,87 @@ fn serialize_deposit_instruction(
 
     data
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::MERKLE_TREE_DEPTH;
+    use crate::http::{CannedTransport, TEST_HOOKS_LOCK};
+    use crate::merkle_tree::DEFAULT_ZERO;
+
+    #[tokio::test]
+    async fn test_stale_proof_not_submitted() {
+        // Relayer whose tree has 5 leaves
+        let _guard = TEST_HOOKS_LOCK.lock().await;
+        let _transport = CannedTransport::default()
+            .route("/merkle/root", r#"{"root":"9","nextIndex":5}"#)
+            .install();
+
+        // Unreachable RPC: the stale proof must be refused before any request
+        let connection = RpcClient::new("http://127.0.0.1:1".to_string());
+        let keypair = Keypair::new();
+        let encryption_service = EncryptionService::new();
+        let storage = Storage::memory();
+        let params = DepositParams {
+            next_index: Some(3),
+            ..DepositParams::new(&connection, &keypair, &encryption_service, &storage, "")
+        };
+        let proven = ProvenDeposit {
+            next_index: 3,
+            ahead: true,
+            instruction: Instruction::new_with_bytes(*PROGRAM_ID, &[], vec![]),
+            amount_in_lamports: 0,
+            fee_amount: 0,
+            input_nullifiers: vec![],
+            output_commitments: vec![],
+            encrypted_output1: vec![],
+        };
+
+        let result = submit_deposit(&params, proven).await;
+        assert!(matches!(
+            result,
+            Err(PrivacyCashError::StaleProof { expected: 3, actual: 5 })
+        ));
+    }
+
+    #[test]
+    fn test_first_deposit_inputs() {
+        let keypair = ZkKeypair::generate().unwrap();
+        let (inputs, paths) = first_deposit_inputs(&keypair, None);
+
+        // Zero amounts switch off the circuit's root check for both inputs
+        assert!(inputs.iter().all(Utxo::is_dummy));
+        assert_ne!(inputs[0].get_nullifier().unwrap(), inputs[1].get_nullifier().unwrap());
+
+        // The zero paths do not lead to any real root, so the proof cannot
+        // depend on the root; the relayer's root is what the program accepts
+        let leaves = vec![DEFAULT_ZERO.to_string(), "12345".to_string()];
+        let live = MerkleTree::with_elements(MERKLE_TREE_DEPTH, leaves, DEFAULT_ZERO).unwrap();
+        let empty = MerkleTree::new(MERKLE_TREE_DEPTH).unwrap();
+        for (input, path) in inputs.iter().zip(&paths) {
+            assert_eq!(path.path_elements.len(), MERKLE_TREE_DEPTH);
+            assert_eq!(input.index, 0);
+            let commitment = input.get_commitment().unwrap();
+            assert!(!path.verify(&commitment, &live.root()).unwrap());
+            assert!(!path.verify(&commitment, &empty.root()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_deposit_output_amount() {
+        let keypair = ZkKeypair::generate().unwrap();
+        let note = Utxo::new(7u64, keypair.clone(), 0, None, None);
+        let dummy = Utxo::dummy(keypair, None);
+
+        assert_eq!(deposit_output_amount(&[], 10, 0).unwrap(), BigUint::from(10u8));
+        assert_eq!(deposit_output_amount(&[&note, &dummy], 0, 0).unwrap(), BigUint::from(7u8));
+        assert_eq!(deposit_output_amount(&[&note], 5, 2).unwrap(), BigUint::from(10u8));
+
+        for (inputs, amount, fee) in [(vec![], 0, 0), (vec![], 5, 5), (vec![&dummy], 3, 4)] {
+            assert!(matches!(
+                deposit_output_amount(&inputs, amount, fee),
+                Err(PrivacyCashError::InvalidInput(_))
+            ));
+        }
+    }
+}
@@ -1,24 +1,34 @@
 //! Deposit functionality for SPL tokens
 
+use crate::blinding::BlindingSource;
+use crate::clock;
 use crate::constants::{
-    find_token_by_mint, ALT_ADDRESS, FEE_RECIPIENT, PROGRAM_ID, RELAYER_API_URL,
-    TRANSACT_SPL_IX_DISCRIMINATOR,
+    find_token_by_mint, ALT_ADDRESS, DEFAULT_PROOF_TIMEOUT_SECS, FEE_RECIPIENT, PROGRAM_ID,
+    RELAYER_API_URL, TRANSACT_SPL_ACCOUNTS, TRANSACT_SPL_IX_DISCRIMINATOR, TX_OUTPUTS,
 };
-use crate::encryption::EncryptionService;
+use crate::encryption::{ChangeOutput, EncryptionService, OutputTarget};
 use crate::error::{PrivacyCashError, Result};
+use crate::deposit::{deposit_output_amount, first_deposit_inputs};
+use crate::get_utxos::ensure_unspent_finalized;
 use crate::get_utxos_spl::get_utxos_spl;
+use crate::http::transport;
 use crate::keypair::ZkKeypair;
 use crate::merkle_tree::MerkleTree;
-use crate::prover::{parse_proof_to_bytes, parse_public_signals_to_bytes, CircuitInput};
+use crate::metrics::{metrics, record_relayer_error, DEPOSITS_TOTAL};
+use crate::preview::{require_approval, ApprovalHook, TransactionKind, TxPreview};
+use crate::prover::{
+    parse_proof_to_bytes, parse_public_signals_to_bytes, CircuitArity, CircuitInput,
+};
 use crate::prover_rust::RustProver;
 use crate::storage::Storage;
 use crate::utxo::{Utxo, UtxoVersion};
 use crate::utils::{
-    calculate_public_amount, fetch_merkle_proof, find_cross_check_nullifier_pdas,
-    find_nullifier_pdas, get_mint_address_field, get_program_accounts, get_spl_tree_account,
-    query_remote_tree_state, ExtData,
+    calculate_public_amount, check_transaction_size, fetch_merkle_proof,
+    find_cross_check_nullifier_pdas, find_nullifier_pdas, get_mint_address_field,
+    get_program_accounts, get_spl_tree_account, query_remote_tree_state, ExtData,
 };
 use num_bigint::BigUint;
+use num_traits::Zero;
 use serde::{Deserialize, Serialize};
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::{
@@ -34,27 +44,108 @@ use solana_sdk::{
 };
 use spl_associated_token_account::get_associated_token_address;
 use spl_token;
+use std::time::Duration;
 
 /// SPL Deposit result
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DepositSplResult {
     pub signature: String,
+    /// Fee taken by the pool in base units
+    #[serde(default)]
+    pub fee_base_units: u64,
+}
+
+impl std::fmt::Display for DepositSplResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Deposit {}", self.signature)
+    }
 }
 
 /// Parameters for SPL deposit
 pub struct DepositSplParams<'a> {
     pub connection: &'a RpcClient,
     pub keypair: &'a Keypair,
+    /// Shielded identity that owns the new notes; need not derive from `keypair`
     pub encryption_service: &'a EncryptionService,
     pub storage: &'a Storage,
     pub base_units: u64,
     pub mint_address: &'a Pubkey,
     pub key_base_path: &'a str,
     pub referrer: Option<&'a str>,
+    /// Spend these notes instead of scanning for existing UTXOs (at most two)
+    pub input_utxos: Option<&'a [Utxo]>,
+    /// Called with the final transaction details before relaying; `false` aborts
+    pub approval: Option<&'a ApprovalHook>,
+    /// Who each output note is created for and encrypted to; `Sender` keeps it
+    pub output_targets: [OutputTarget<'a>; 2],
+    /// Which output is the sender's change; it must target `Sender`
+    pub change_output: ChangeOutput,
+    /// Leaf index to build the outputs for instead of the relayer's next
+    /// index, to prove ahead of an earlier transaction landing
+    pub next_index: Option<u64>,
+    /// How the output notes get their blindings
+    pub blinding: BlindingSource,
+    /// Limit on generating the proof
+    pub proof_timeout: Duration,
+}
+
+impl<'a> DepositSplParams<'a> {
+    /// Parameters for a plain deposit of `mint_address` tokens, of nothing yet
+    ///
+    /// The SPL counterpart of [`DepositParams::new`](crate::deposit::DepositParams::new).
+    pub fn new(
+        connection: &'a RpcClient,
+        keypair: &'a Keypair,
+        encryption_service: &'a EncryptionService,
+        storage: &'a Storage,
+        mint_address: &'a Pubkey,
+        key_base_path: &'a str,
+    ) -> Self {
+        Self {
+            connection,
+            keypair,
+            encryption_service,
+            storage,
+            base_units: 0,
+            mint_address,
+            key_base_path,
+            referrer: None,
+            input_utxos: None,
+            approval: None,
+            output_targets: Default::default(),
+            change_output: Default::default(),
+            next_index: None,
+            blinding: BlindingSource::default(),
+            proof_timeout: Duration::from_secs(DEFAULT_PROOF_TIMEOUT_SECS),
+        }
+    }
+}
+
+/// An SPL deposit that is proven but not yet submitted, see [`prove_deposit_spl`]
+pub struct ProvenDepositSpl {
+    /// Leaf index of the first output the proof was built for
+    pub next_index: u64,
+    /// Whether `next_index` was assumed rather than read from the relayer
+    pub ahead: bool,
+    token_name: &'static str,
+    instruction: Instruction,
+    base_units: u64,
+    fee_base_units: u64,
+    input_nullifiers: Vec<String>,
+    output_commitments: Vec<String>,
+    encrypted_output1: Vec<u8>,
 }
 
 /// Execute an SPL token deposit
 pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResult> {
+    let proven = prove_deposit_spl(&params).await?;
+    submit_deposit_spl(&params, proven).await
+}
+
+/// Build and prove an SPL deposit without submitting it
+///
+/// See [`prove_deposit`](crate::deposit::prove_deposit) for `next_index`.
+pub async fn prove_deposit_spl(params: &DepositSplParams<'_>) -> Result<ProvenDepositSpl> {
     let DepositSplParams {
         connection,
         keypair,
@@ -63,8 +154,19 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
         base_units,
         mint_address,
         key_base_path,
-        referrer,
-    } = params;
+        referrer: _,
+        input_utxos,
+        approval: _,
+        output_targets,
+        change_output,
+        next_index,
+        blinding,
+        proof_timeout,
+    } = *params;
+
+    // The transact instruction only takes the two-input circuit
+    CircuitArity::from_key_base_path(key_base_path)?.ensure_supported_on_chain()?;
+    change_output.check_targets(&output_targets)?;
 
     let token = find_token_by_mint(mint_address)
         .ok_or_else(|| PrivacyCashError::TokenNotSupported(mint_address.to_string()))?;
@@ -88,9 +190,18 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
     // Get SPL tree account
     let tree_account = get_spl_tree_account(mint_address);
 
+    // The deposited tokens come from the signer's token account, so it must
+    // already exist and hold them; creating it here would leave it empty
+    ensure_token_account(connection, &public_key, mint_address).map_err(|e| match e {
+        PrivacyCashError::TokenAccountNotFound { owner, mint } => {
+            PrivacyCashError::DepositTokenAccountNotFound { owner, mint }
+        }
+        other => other,
+    })?;
+
     // Check SPL balance
     let account_info = connection.get_token_account_balance(&signer_token_account)?;
-    let balance: u64 = account_info.amount.parse().unwrap_or(0);
+    let balance = parse_token_amount(&account_info.amount)?;
 
     if balance < base_units + fee_base_units {
         return Err(PrivacyCashError::InsufficientTokenBalance {
@@ -114,32 +225,38 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
 
     // Get tree state
     let tree_state = query_remote_tree_state(Some(token.name)).await?;
+    tree_state.ensure_room_for(TX_OUTPUTS)?;
+    let output_index = next_index.unwrap_or(tree_state.next_index);
 
     // Get UTXO keypair
     let utxo_private_key = encryption_service.get_utxo_private_key_v2()?;
     let utxo_keypair = ZkKeypair::from_hex(&utxo_private_key)?;
 
-    // Fetch existing UTXOs
-    let existing_utxos = get_utxos_spl(
-        connection,
-        &public_key,
-        encryption_service,
-        storage,
-        mint_address,
-        None,
-    )
-    .await?;
+    // Fetch existing UTXOs (unless the caller chose the inputs)
+    let existing_utxos = match input_utxos {
+        Some(utxos) => utxos
+            .iter()
+            .map(|utxo| encryption_service.with_version_keypair(utxo))
+            .collect::<Result<Vec<_>>>()?,
+        None => {
+            get_utxos_spl(
+                connection,
+                &public_key,
+                encryption_service,
+                storage,
+                mint_address,
+                None,
+            )
+            .await?
+        }
+    };
 
     // Build inputs
     let (inputs, input_merkle_paths, ext_amount, output_amount) = if existing_utxos.is_empty() {
-        let inputs = vec![
-            Utxo::dummy(utxo_keypair.clone(), Some(&mint_address.to_string())),
-            Utxo::dummy(utxo_keypair.clone(), Some(&mint_address.to_string())),
-        ];
-        let paths = vec![MerkleTree::zero_path(), MerkleTree::zero_path()];
+        let (inputs, paths) = first_deposit_inputs(&utxo_keypair, Some(&mint_address.to_string()));
 
         let ext_amount = base_units as i64;
-        let output_amount = BigUint::from(base_units) - BigUint::from(fee_base_units);
+        let output_amount = deposit_output_amount(&[], base_units, fee_base_units)?;
 
         (inputs, paths, ext_amount, output_amount)
     } else {
@@ -161,10 +278,8 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
         };
 
         let ext_amount = base_units as i64;
-        let output_amount = first_utxo.amount.clone()
-            + second_utxo.amount.clone()
-            + BigUint::from(base_units)
-            - BigUint::from(fee_base_units);
+        let output_amount =
+            deposit_output_amount(&[first_utxo, &second_utxo], base_units, fee_base_units)?;
 
         (
             vec![first_utxo.clone(), second_utxo],
@@ -174,21 +289,31 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
         )
     };
 
+    // Fail before proving if an input's spend is already finalized
+    ensure_unspent_finalized(connection, &inputs).await?;
+
     let public_amount = calculate_public_amount(ext_amount, fee_base_units);
 
     // Create outputs
+    let [first_owner, second_owner] = [
+        output_targets[0].utxo_keypair(encryption_service)?,
+        output_targets[1].utxo_keypair(encryption_service)?,
+    ];
+    let [first_amount, second_amount] = change_output.arrange(output_amount, BigUint::zero());
     let outputs = vec![
-        Utxo::new(
-            output_amount,
-            utxo_keypair.clone(),
-            tree_state.next_index,
+        Utxo::with_blinding(
+            first_amount,
+            blinding.next(encryption_service, storage)?,
+            first_owner,
+            output_index,
             Some(&mint_address.to_string()),
             Some(UtxoVersion::V2),
         ),
-        Utxo::new(
-            0u64,
-            utxo_keypair.clone(),
-            tree_state.next_index + 1,
+        Utxo::with_blinding(
+            second_amount,
+            blinding.next(encryption_service, storage)?,
+            second_owner,
+            output_index + 1,
             Some(&mint_address.to_string()),
             Some(UtxoVersion::V2),
         ),
@@ -199,8 +324,8 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
     let output_commitments = vec![outputs[0].get_commitment()?, outputs[1].get_commitment()?];
 
     // Encrypt outputs
-    let encrypted_output1 = encryption_service.encrypt_utxo(&outputs[0])?;
-    let encrypted_output2 = encryption_service.encrypt_utxo(&outputs[1])?;
+    let encrypted_output1 = output_targets[0].encrypt(encryption_service, &outputs[0])?;
+    let encrypted_output2 = output_targets[1].encrypt(encryption_service, &outputs[1])?;
 
     // For SPL deposits, ExtData uses token accounts (ATAs), not public keys - same as TypeScript SDK
     // recipient_ata = FEE_RECIPIENT's ATA for the token
@@ -215,6 +340,9 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
         mint_address: *mint_address,
     };
 
+    // Reject outputs that cannot fit in a transaction before spending time on a proof
+    check_transaction_size(ext_data.min_transaction_size(TRANSACT_SPL_ACCOUNTS))?;
+
     log::debug!("SPL ExtData recipient (ATA): {}", ext_data.recipient);
     log::debug!("SPL ExtData ext_amount: {}", ext_data.ext_amount);
     log::debug!("SPL ExtData fee: {}", ext_data.fee);
@@ -252,8 +380,8 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
 
     // Generate proof using pure Rust prover (iOS compatible, no Node.js needed)
     log::info!("Generating ZK proof using pure Rust prover...");
-    let prover = RustProver::new(key_base_path);
-    let (proof, public_signals) = prover.prove(&circuit_input).await?;
+    let prover = RustProver::new(key_base_path).with_timeout(proof_timeout);
+    let (proof, public_signals) = prover.prove_recorded(&circuit_input, storage).await?;
 
     let proof_bytes = parse_proof_to_bytes(&proof)?;
     let signals_bytes = parse_public_signals_to_bytes(&public_signals)?;
@@ -304,8 +432,51 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
         data: instruction_data,
     };
 
+    Ok(ProvenDepositSpl {
+        next_index: output_index,
+        ahead: next_index.is_some(),
+        token_name: token.name,
+        instruction: deposit_instruction,
+        base_units,
+        fee_base_units,
+        input_nullifiers,
+        output_commitments,
+        encrypted_output1,
+    })
+}
+
+/// Sign, relay and confirm a proven SPL deposit
+///
+/// See [`submit_deposit`](crate::deposit::submit_deposit) for proofs built ahead.
+pub async fn submit_deposit_spl(
+    params: &DepositSplParams<'_>,
+    proven: ProvenDepositSpl,
+) -> Result<DepositSplResult> {
+    let DepositSplParams { connection, keypair, mint_address, referrer, approval, .. } = *params;
+    let ProvenDepositSpl {
+        next_index,
+        ahead,
+        token_name,
+        instruction: deposit_instruction,
+        base_units,
+        fee_base_units,
+        input_nullifiers,
+        output_commitments,
+        encrypted_output1,
+    } = proven;
+    let public_key = keypair.pubkey();
+
+    if ahead {
+        let actual = query_remote_tree_state(Some(token_name)).await?.next_index;
+        if actual != next_index {
+            return Err(PrivacyCashError::StaleProof { expected: next_index, actual });
+        }
+    }
+
     let compute_budget_ix = ComputeBudgetInstruction::set_compute_unit_limit(1_000_000);
 
+    let instructions = vec![compute_budget_ix, deposit_instruction];
+
     // Fetch Address Lookup Table
     log::info!("Fetching Address Lookup Table...");
     let alt_account = connection.get_account(&ALT_ADDRESS)?;
@@ -319,7 +490,7 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
     
     let message = MessageV0::try_compile(
         &public_key,
-        &[compute_budget_ix, deposit_instruction],
+        &instructions,
         &[alt],
         recent_blockhash,
     ).map_err(|e| PrivacyCashError::TransactionError(format!("Failed to compile message: {}", e)))?;
@@ -332,9 +503,23 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
     use base64::Engine;
     let tx_bytes = bincode::serialize(&transaction)
         .map_err(|e| PrivacyCashError::SerializationError(format!("Failed to serialize transaction: {}", e)))?;
+    check_transaction_size(tx_bytes.len())?;
     let serialized = base64::engine::general_purpose::STANDARD.encode(&tx_bytes);
 
     // Relay to backend
+    require_approval(
+        approval,
+        TxPreview {
+            kind: TransactionKind::Deposit,
+            token: token_name.to_string(),
+            recipient: None,
+            amount: base_units,
+            fee: fee_base_units,
+            input_nullifiers: input_nullifiers.clone(),
+            output_commitments: output_commitments.clone(),
+        },
+    )?;
+
     log::info!("Submitting transaction to relayer...");
     let signature = relay_spl_deposit_to_indexer(
         &serialized,
@@ -346,9 +531,43 @@ pub async fn deposit_spl(params: DepositSplParams<'_>) -> Result<DepositSplResul
 
     // Wait for confirmation
     log::info!("Waiting for confirmation...");
-    wait_for_spl_confirmation(&encrypted_output1, token.name).await?;
+    wait_for_spl_confirmation(&signature, &encrypted_output1, token_name).await?;
 
-    Ok(DepositSplResult { signature })
+    metrics().inc_counter(DEPOSITS_TOTAL, &[("token", token_name)]);
+    Ok(DepositSplResult { signature, fee_base_units })
+}
+
+/// Derive the owner's associated token account and check that it exists
+///
+/// Returns the ATA address, or `TokenAccountNotFound` if the account is
+/// missing.
+pub fn ensure_token_account(
+    connection: &RpcClient,
+    owner: &Pubkey,
+    mint_address: &Pubkey,
+) -> Result<Pubkey> {
+    let ata = get_associated_token_address(owner, mint_address);
+
+    // An RPC failure must not be mistaken for a missing account
+    let account = connection.get_account_with_commitment(&ata, connection.commitment())?.value;
+    if account.is_none() {
+        return Err(PrivacyCashError::TokenAccountNotFound {
+            owner: owner.to_string(),
+            mint: mint_address.to_string(),
+        });
+    }
+    Ok(ata)
+}
+
+/// Parse the base-unit amount of a token balance returned by the RPC
+///
+/// A malformed amount is an `InvalidRpcResponse` rather than a zero balance,
+/// which would surface as a misleading insufficient-balance error.
+pub fn parse_token_amount(amount: &str) -> Result<u64> {
+    amount.parse().map_err(|_| {
+        let message = format!("token balance {:?} is not an amount", amount);
+        PrivacyCashError::InvalidRpcResponse(message)
+    })
 }
 
 /// Serialize SPL instruction data
@@ -365,7 +584,8 @@ fn serialize_spl_instruction(
     data.extend_from_slice(&proof_bytes.proof_b);
     data.extend_from_slice(&proof_bytes.proof_c);
 
-    for signal in signals.iter().take(7) {
+    let signal_count = CircuitArity::TRANSACTION2.public_signal_count();
+    for signal in signals.iter().take(signal_count) {
         data.extend_from_slice(signal);
     }
 
@@ -397,16 +617,14 @@ async fn relay_spl_deposit_to_indexer(
         body["referralWalletAddress"] = serde_json::Value::String(ref_addr.to_string());
     }
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post(format!("{}/deposit/spl", *RELAYER_API_URL))
-        .json(&body)
-        .send()
+    let response = transport()
+        .post_json(&format!("{}/deposit/spl", *RELAYER_API_URL), &body)
         .await
         .map_err(|e| PrivacyCashError::ApiError(format!("SPL deposit relay failed: {}", e)))?;
 
-    if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_default();
+    if !response.is_success() {
+        record_relayer_error("/deposit/spl", response.status);
+        let error_text = response.body;
         return Err(PrivacyCashError::ApiError(format!(
             "SPL deposit failed: {}",
             error_text
@@ -418,32 +636,33 @@ async fn relay_spl_deposit_to_indexer(
         signature: String,
     }
 
-    let result: Response = response
-        .json()
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("Parse response: {}", e)))?;
+    let result: Response = response.json_with_context("Failed to parse relayer response")?;
 
     Ok(result.signature)
 }
 
 /// Wait for SPL confirmation
-async fn wait_for_spl_confirmation(encrypted_output: &[u8], token_name: &str) -> Result<()> {
+async fn wait_for_spl_confirmation(
+    signature: &str,
+    encrypted_output: &[u8],
+    token_name: &str,
+) -> Result<()> {
     let encrypted_hex = hex::encode(encrypted_output);
     let mut retries = 0;
     let max_retries = 10;
 
     loop {
-        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+        clock::sleep(Duration::from_secs(2)).await;
 
         let url = format!(
             "{}/utxos/check/{}?token={}",
             *RELAYER_API_URL, encrypted_hex, token_name
         );
 
-        let response = reqwest::get(&url).await;
+        let response = transport().get_json(&url).await;
 
         if let Ok(resp) = response {
-            if let Ok(data) = resp.json::<serde_json::Value>().await {
+            if let Ok(data) = resp.json::<serde_json::Value>() {
                 if data.get("exists").and_then(|v| v.as_bool()).unwrap_or(false) {
                     return Ok(());
                 }
@@ -452,7 +671,10 @@ async fn wait_for_spl_confirmation(encrypted_output: &[u8], token_name: &str) ->
 
         retries += 1;
         if retries >= max_retries {
-            return Err(PrivacyCashError::ConfirmationTimeout { retries });
+            return Err(PrivacyCashError::ConfirmationTimeout {
+                signature: signature.to_string(),
+                retries,
+            });
         }
 
         log::info!("Confirming SPL transaction... (retry {})", retries);
@@ -487,3 +709,46 @@ fn parse_alt_addresses(data: &[u8]) -> Result<Vec<Pubkey>> {
     
     Ok(addresses)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_token_amount() {
+        assert_eq!(parse_token_amount("0").unwrap(), 0);
+        assert_eq!(parse_token_amount("2500000").unwrap(), 2_500_000);
+        for malformed in ["", "1.5", "-3", "abc"] {
+            assert!(matches!(
+                parse_token_amount(malformed),
+                Err(PrivacyCashError::InvalidRpcResponse(_))
+            ));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_deposit_needs_funded_token_account() {
+        // The mock RPC reports every account as missing
+        let connection = RpcClient::new_mock("succeeds".to_string());
+        let keypair = Keypair::new();
+        let mut encryption_service = EncryptionService::new();
+        encryption_service.derive_encryption_key_from_wallet(&keypair).unwrap();
+        let storage = Storage::memory();
+
+        let mint = *crate::constants::USDC_MINT;
+        let params = DepositSplParams {
+            base_units: 1_000_000,
+            ..DepositSplParams::new(
+                &connection,
+                &keypair,
+                &encryption_service,
+                &storage,
+                &mint,
+                "circuit/transaction2",
+            )
+        };
+        let err = prove_deposit_spl(&params).await.err().unwrap();
+        assert!(matches!(err, PrivacyCashError::DepositTokenAccountNotFound { .. }));
+        assert!(err.to_string().contains("move tokens into it"));
+    }
+}
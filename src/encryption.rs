@@ -2,23 +2,60 @@
 //!
 //! Implements AES-256-GCM encryption with versioned format.
 
-use crate::constants::SIGN_MESSAGE;
+use crate::constants::{FIELD_SIZE, SIGN_MESSAGE};
 use crate::error::{PrivacyCashError, Result};
 use crate::keypair::ZkKeypair;
 use crate::utxo::{Utxo, UtxoVersion};
 use aes_gcm::{
-    aead::{Aead, KeyInit},
+    aead::{Aead, KeyInit, Payload},
     Aes256Gcm, Nonce,
 };
 use hmac::{Hmac, Mac};
+use num_bigint::BigUint;
 use rand::Rng;
 use sha2::Sha256;
 use sha3::{Digest, Keccak256};
 use solana_sdk::signature::{Keypair, Signer};
 
+/// Domain separator of derived note blindings
+const BLINDING_DOMAIN: &[u8] = b"privacy-cash/blinding/v1";
+const AUDIT_DOMAIN: &[u8] = b"privacy-cash/audit/v1";
+
 /// Version identifier for V2 encryption format (8 bytes)
 const ENCRYPTION_VERSION_V2: [u8; 8] = [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02];
 
+/// Prefix of an exported viewing key, followed by the base58 V2 encryption key
+pub const VIEWING_KEY_PREFIX: &str = "pcvk";
+
+/// Length of the legacy V1 key, taken from the start of the signature
+const V1_KEY_LEN: usize = 31;
+
+/// Shortest signature accepted for key derivation; anything shorter cannot be
+/// a real signature and leaves the V2 key with too little entropy
+const MIN_SIGNATURE_LEN: usize = 32;
+
+/// Shortest V2 output that can hold a note: the envelope (version, IV, auth
+/// tag) plus `a|b|c|` and a 32-character mint
+const MIN_V2_NOTE_LEN: usize = 8 + 12 + 16 + 6 + 32;
+
+/// Why an encrypted output did not decrypt to one of our notes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecryptFailure {
+    /// Not authenticated by our key; every other user's note ends up here
+    Foreign,
+    /// V2 envelope too short to hold any note, e.g. truncated in transit
+    Truncated,
+    /// Authenticated by our key, but the note inside does not parse
+    Corrupt,
+}
+
+impl DecryptFailure {
+    /// Whether the output may be a damaged note of ours worth investigating
+    pub fn is_suspicious(self) -> bool {
+        self != Self::Foreign
+    }
+}
+
 /// Encryption key pair for V1 and V2 formats
 #[derive(Clone)]
 pub struct EncryptionKey {
@@ -63,18 +100,43 @@ impl EncryptionService {
     }
 
     /// Derive encryption keys from a wallet keypair
-    pub fn derive_encryption_key_from_wallet(&mut self, keypair: &Keypair) -> EncryptionKey {
+    pub fn derive_encryption_key_from_wallet(
+        &mut self,
+        keypair: &Keypair,
+    ) -> Result<EncryptionKey> {
         // Sign the constant message
         let message = SIGN_MESSAGE.as_bytes();
         let signature = keypair.sign_message(message);
 
-        self.derive_encryption_key_from_signature(&signature.as_ref())
+        self.derive_encryption_key_from_signature(signature.as_ref())
     }
 
     /// Derive encryption keys from a signature
-    pub fn derive_encryption_key_from_signature(&mut self, signature: &[u8]) -> EncryptionKey {
+    ///
+    /// Fails with `InvalidInput` if the signature is shorter than 32 bytes
+    /// (e.g. malformed output of an external signer), and with `DegenerateKey`
+    /// if the 31-byte V1 key is a single repeated byte (e.g. an all-zero
+    /// signature from a broken signer).
+    pub fn derive_encryption_key_from_signature(
+        &mut self,
+        signature: &[u8],
+    ) -> Result<EncryptionKey> {
+        if signature.len() < MIN_SIGNATURE_LEN {
+            return Err(PrivacyCashError::InvalidInput(format!(
+                "Signature must be at least {} bytes, got {}",
+                MIN_SIGNATURE_LEN,
+                signature.len()
+            )));
+        }
+
         // V1: Extract first 31 bytes of signature (legacy method)
-        let encryption_key_v1 = signature[..31].to_vec();
+        let encryption_key_v1 = signature[..V1_KEY_LEN].to_vec();
+        if encryption_key_v1.iter().all(|b| *b == encryption_key_v1[0]) {
+            return Err(PrivacyCashError::DegenerateKey(
+                "Signature bytes are all equal; the signer is not producing real signatures"
+                    .to_string(),
+            ));
+        }
         self.encryption_key_v1 = Some(encryption_key_v1.clone());
 
         // Precompute V1 UTXO private key
@@ -89,14 +151,60 @@ impl EncryptionService {
         let hashed_seed_v2 = Keccak256::digest(&encryption_key_v2);
         self.utxo_private_key_v2 = Some(format!("0x{}", hex::encode(hashed_seed_v2)));
 
-        EncryptionKey {
+        Ok(EncryptionKey {
             v1: encryption_key_v1,
             v2: encryption_key_v2,
+        })
+    }
+
+    /// Create a service from an exported viewing key
+    ///
+    /// Only the V2 key is restored, so V1 notes cannot be decrypted.
+    pub fn from_viewing_key(viewing_key: &str) -> Result<Self> {
+        let encoded = viewing_key.strip_prefix(VIEWING_KEY_PREFIX).ok_or_else(|| {
+            PrivacyCashError::InvalidInput(format!(
+                "Viewing key must start with '{}'",
+                VIEWING_KEY_PREFIX
+            ))
+        })?;
+        let encryption_key_v2 = bs58::decode(encoded)
+            .into_vec()
+            .map_err(|e| PrivacyCashError::InvalidInput(format!("Invalid viewing key: {}", e)))?;
+        if encryption_key_v2.len() != 32 {
+            return Err(PrivacyCashError::InvalidInput(format!(
+                "Expected a 32-byte viewing key, got {} bytes",
+                encryption_key_v2.len()
+            )));
         }
+
+        let hashed_seed_v2 = Keccak256::digest(&encryption_key_v2);
+        Ok(Self {
+            encryption_key_v1: None,
+            encryption_key_v2: Some(encryption_key_v2),
+            utxo_private_key_v1: None,
+            utxo_private_key_v2: Some(format!("0x{}", hex::encode(hashed_seed_v2))),
+        })
+    }
+
+    /// Export the V2 encryption key as a portable viewing key
+    pub fn export_viewing_key(&self) -> Result<String> {
+        let key = self
+            .encryption_key_v2
+            .as_ref()
+            .ok_or_else(|| PrivacyCashError::EncryptionError("V2 encryption key not set".to_string()))?;
+        Ok(format!("{}{}", VIEWING_KEY_PREFIX, bs58::encode(key).into_string()))
     }
 
     /// Encrypt data using V2 format (AES-256-GCM)
     pub fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        self.encrypt_with_aad(data, &[])
+    }
+
+    /// Encrypt data using V2 format, binding the ciphertext to `aad`
+    ///
+    /// `aad` is authenticated but not stored in the envelope, so decryption
+    /// must supply the same bytes. An empty `aad` gives the plain V2 envelope.
+    pub fn encrypt_with_aad(&self, data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
         let key = self
             .encryption_key_v2
             .as_ref()
@@ -115,7 +223,7 @@ impl EncryptionService {
 
         // Encrypt
         let ciphertext = cipher
-            .encrypt(nonce, data)
+            .encrypt(nonce, Payload { msg: data, aad })
             .map_err(|e| PrivacyCashError::EncryptionError(format!("Encryption failed: {}", e)))?;
 
         // V2 format: [version(8)] + [IV(12)] + [ciphertext with auth tag]
@@ -130,20 +238,33 @@ impl EncryptionService {
 
     /// Decrypt data (auto-detects V1 or V2 format)
     pub fn decrypt(&self, encrypted_data: &[u8]) -> Result<Vec<u8>> {
+        self.decrypt_with_aad(encrypted_data, &[])
+    }
+
+    /// Decrypt data encrypted with [`encrypt_with_aad`](Self::encrypt_with_aad)
+    ///
+    /// Fails if `aad` differs from the bytes used at encryption. Legacy V1
+    /// notes carry no associated data and are rejected with a non-empty `aad`.
+    pub fn decrypt_with_aad(&self, encrypted_data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
         if encrypted_data.len() < 8 {
             return Err(PrivacyCashError::DecryptionError("Data too short".to_string()));
         }
 
         // Check if V2 format
         if encrypted_data[..8] == ENCRYPTION_VERSION_V2 {
-            self.decrypt_v2(encrypted_data)
-        } else {
+            self.decrypt_v2(encrypted_data, aad)
+        } else if aad.is_empty() {
             self.decrypt_v1(encrypted_data)
+        } else {
+            Err(PrivacyCashError::DecryptionError(
+                "V1 notes cannot carry associated data".to_string(),
+            ))
         }
     }
 
     /// Decrypt V2 format (AES-256-GCM)
-    fn decrypt_v2(&self, encrypted_data: &[u8]) -> Result<Vec<u8>> {
+    fn decrypt_v2(&self, encrypted_data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        self.check_key_version(UtxoVersion::V2)?;
         let key = self
             .encryption_key_v2
             .as_ref()
@@ -166,12 +287,13 @@ impl EncryptionService {
 
         // Decrypt
         cipher
-            .decrypt(nonce, ciphertext)
+            .decrypt(nonce, Payload { msg: ciphertext, aad })
             .map_err(|_| PrivacyCashError::DecryptionError("Invalid key or corrupted data".to_string()))
     }
 
     /// Decrypt V1 format (AES-128-CTR with HMAC)
     fn decrypt_v1(&self, encrypted_data: &[u8]) -> Result<Vec<u8>> {
+        self.check_key_version(UtxoVersion::V1)?;
         let key = self
             .encryption_key_v1
             .as_ref()
@@ -213,24 +335,87 @@ impl EncryptionService {
         Ok(plaintext)
     }
 
+    /// Fail with `WrongKeyVersion` if only the other version's key was derived
+    fn check_key_version(&self, version: UtxoVersion) -> Result<()> {
+        let (has_key, other_key, other) = match version {
+            UtxoVersion::V1 => (&self.encryption_key_v1, &self.encryption_key_v2, UtxoVersion::V2),
+            UtxoVersion::V2 => (&self.encryption_key_v2, &self.encryption_key_v1, UtxoVersion::V1),
+        };
+
+        if has_key.is_none() && other_key.is_some() {
+            return Err(PrivacyCashError::WrongKeyVersion {
+                note: version,
+                available: other,
+            });
+        }
+
+        Ok(())
+    }
+
     /// Encrypt a UTXO
     pub fn encrypt_utxo(&self, utxo: &Utxo) -> Result<Vec<u8>> {
         let serialized = utxo.serialize_for_encryption();
         self.encrypt(serialized.as_bytes())
     }
 
+    /// Encrypt a UTXO bound to its commitment
+    ///
+    /// The commitment is used as associated data, so the ciphertext only
+    /// decrypts when presented with this note's commitment, e.g. the one the
+    /// relayer lists next to the output. Other clients that decrypt outputs
+    /// without associated data cannot read such notes.
+    pub fn encrypt_utxo_bound(&self, utxo: &Utxo) -> Result<Vec<u8>> {
+        let commitment = utxo.get_commitment()?;
+        let serialized = utxo.serialize_for_encryption();
+        self.encrypt_with_aad(serialized.as_bytes(), commitment.as_bytes())
+    }
+
+    /// Decrypt a UTXO encrypted with [`encrypt_utxo_bound`](Self::encrypt_utxo_bound)
+    ///
+    /// Fails if the ciphertext was bound to another commitment, or if the
+    /// decrypted note does not hash to `commitment`.
+    pub fn decrypt_utxo_bound(&self, encrypted_data: &[u8], commitment: &str) -> Result<Utxo> {
+        let decrypted = self.decrypt_with_aad(encrypted_data, commitment.as_bytes())?;
+        let keypair = ZkKeypair::from_hex(&self.get_utxo_private_key_v2()?)?;
+        let utxo = deserialize_note(decrypted, keypair, UtxoVersion::V2)?;
+        if utxo.get_commitment()? != commitment {
+            return Err(PrivacyCashError::DecryptionError(
+                "Note does not match its commitment".to_string(),
+            ));
+        }
+        Ok(utxo)
+    }
+
     /// Decrypt a UTXO
     pub fn decrypt_utxo(&self, encrypted_data: &[u8]) -> Result<Utxo> {
         let version = self.get_encryption_version(encrypted_data);
         let decrypted = self.decrypt(encrypted_data)?;
 
-        let data_str = String::from_utf8(decrypted)
-            .map_err(|_| PrivacyCashError::DecryptionError("Invalid UTF-8".to_string()))?;
-
         let private_key = self.get_utxo_private_key_with_version(version)?;
         let keypair = ZkKeypair::from_hex(&private_key)?;
 
-        Utxo::deserialize_from_encryption(&data_str, keypair, version)
+        deserialize_note(decrypted, keypair, version)
+    }
+
+    /// Decrypt a UTXO, telling foreign outputs apart from damaged ones
+    ///
+    /// A failed auth tag looks the same for another user's note and for one of
+    /// ours with flipped bits, so both are `Foreign`. Only outputs that cannot
+    /// be a whole note, or that authenticate but do not parse, are flagged.
+    pub fn classify_decrypt_utxo(
+        &self,
+        encrypted_data: &[u8],
+    ) -> std::result::Result<Utxo, DecryptFailure> {
+        let version = self.get_encryption_version(encrypted_data);
+        if version == UtxoVersion::V2 && encrypted_data.len() < MIN_V2_NOTE_LEN {
+            return Err(DecryptFailure::Truncated);
+        }
+
+        let decrypted = self.decrypt(encrypted_data).map_err(|_| DecryptFailure::Foreign)?;
+        self.get_utxo_private_key_with_version(version)
+            .and_then(|private_key| ZkKeypair::from_hex(&private_key))
+            .and_then(|keypair| deserialize_note(decrypted, keypair, version))
+            .map_err(|_| DecryptFailure::Corrupt)
     }
 
     /// Decrypt UTXO from hex string
@@ -261,6 +446,65 @@ impl EncryptionService {
         }
     }
 
+    /// Blinding of the `counter`-th note with a derived blinding
+    ///
+    /// A field element hashed from the V2 UTXO private key and the counter,
+    /// so the owner can recompute it but nobody else can predict it.
+    pub fn derive_blinding(&self, counter: u64) -> Result<BigUint> {
+        let private_key = self.get_utxo_private_key_v2()?;
+        let mut hasher = Keccak256::new();
+        hasher.update(BLINDING_DOMAIN);
+        hasher.update(private_key.as_bytes());
+        hasher.update(counter.to_be_bytes());
+        Ok(BigUint::from_bytes_be(&hasher.finalize()) % &*FIELD_SIZE)
+    }
+
+    /// Key of the audit log's hash chain
+    ///
+    /// Hashed from the V2 UTXO private key, so only the owner can chain new
+    /// entries or re-chain an edited log.
+    pub fn derive_audit_key(&self) -> Result<[u8; 32]> {
+        let private_key = self.get_utxo_private_key_v2()?;
+        let mut hasher = Keccak256::new();
+        hasher.update(AUDIT_DOMAIN);
+        hasher.update(private_key.as_bytes());
+        Ok(hasher.finalize().into())
+    }
+
+    /// UTXO keypair of the given note version
+    pub fn utxo_keypair_for_version(&self, version: UtxoVersion) -> Result<ZkKeypair> {
+        ZkKeypair::from_hex(&self.get_utxo_private_key_with_version(version)?)
+    }
+
+    /// Give one of our notes the keypair of its own version
+    ///
+    /// A note carrying the other version's keypair, e.g. a V1 note built with
+    /// the V2 key, has the wrong commitment and nullifier and cannot be spent.
+    /// Dummy notes and notes whose keypair belongs to neither version are
+    /// returned unchanged.
+    pub fn with_version_keypair(&self, utxo: &Utxo) -> Result<Utxo> {
+        if utxo.is_dummy() {
+            return Ok(utxo.clone());
+        }
+        let own = self.utxo_keypair_for_version(utxo.version)?;
+        if own.pubkey() == utxo.keypair.pubkey() {
+            return Ok(utxo.clone());
+        }
+
+        let other = match utxo.version {
+            UtxoVersion::V1 => UtxoVersion::V2,
+            UtxoVersion::V2 => UtxoVersion::V1,
+        };
+        match self.utxo_keypair_for_version(other) {
+            Ok(keypair) if keypair.pubkey() == utxo.keypair.pubkey() => {
+                let mut rekeyed = utxo.clone();
+                rekeyed.keypair = own;
+                Ok(rekeyed)
+            }
+            _ => Ok(utxo.clone()),
+        }
+    }
+
     /// Derive UTXO private key (V1 by default, or V2 if encrypted data is V2)
     pub fn derive_utxo_private_key(&self, encrypted_data: Option<&[u8]>) -> Result<String> {
         let version = encrypted_data
@@ -295,6 +539,115 @@ impl Default for EncryptionService {
     }
 }
 
+/// Parse a decrypted note, recovering legacy plaintexts that are not UTF-8
+///
+/// The plaintext authenticated under our key, so it is our note even when
+/// some legacy encoder left non-UTF-8 bytes in it, e.g. padding after the
+/// mint. Such a plaintext is decoded lossily with trailing padding trimmed,
+/// and a note that then parses is kept with a warning instead of being
+/// dropped like a foreign one.
+fn deserialize_note(decrypted: Vec<u8>, keypair: ZkKeypair, version: UtxoVersion) -> Result<Utxo> {
+    let bytes = match String::from_utf8(decrypted) {
+        Ok(text) => return Utxo::deserialize_from_encryption(&text, keypair, version),
+        Err(e) => e.into_bytes(),
+    };
+
+    let lossy = String::from_utf8_lossy(&bytes);
+    let text = lossy.trim_end_matches(['\0', char::REPLACEMENT_CHARACTER]);
+    let utxo = Utxo::deserialize_from_encryption(text, keypair, version).map_err(|e| {
+        PrivacyCashError::DecryptionError(format!("Invalid UTF-8 in note: {}", e))
+    })?;
+    log::warn!(
+        "Recovered note {} from a plaintext that is not valid UTF-8",
+        utxo.get_commitment().unwrap_or_else(|_| "with an invalid mint".to_string())
+    );
+    Ok(utxo)
+}
+
+/// Shielded identity a transaction output is created for
+///
+/// The output's note is owned by the target's V2 UTXO key and encrypted with
+/// its encryption key, so only the target can find and spend it. Encryption is
+/// symmetric: a recipient target needs the recipient's keys, e.g. restored with
+/// [`EncryptionService::from_viewing_key`].
+#[derive(Debug, Clone, Copy, Default)]
+pub enum OutputTarget<'a> {
+    /// The transaction's own `encryption_service`
+    #[default]
+    Sender,
+    /// Another shielded identity
+    Recipient(&'a EncryptionService),
+}
+
+impl<'a> OutputTarget<'a> {
+    /// Resolve the target to an identity, given the sender's
+    pub fn service(self, sender: &'a EncryptionService) -> &'a EncryptionService {
+        match self {
+            OutputTarget::Sender => sender,
+            OutputTarget::Recipient(recipient) => recipient,
+        }
+    }
+
+    /// Keypair that owns the output note
+    pub fn utxo_keypair(self, sender: &'a EncryptionService) -> Result<ZkKeypair> {
+        ZkKeypair::from_hex(&self.service(sender).get_utxo_private_key_v2()?)
+    }
+
+    /// Encrypt the output note for the target
+    pub fn encrypt(self, sender: &'a EncryptionService, utxo: &Utxo) -> Result<Vec<u8>> {
+        self.service(sender).encrypt_utxo(utxo)
+    }
+}
+
+/// Which of a transaction's two outputs is the sender's change
+///
+/// The other output carries the transaction's main amount: the deposited
+/// value or a payment to a recipient. A deposit has no change, so its change
+/// output is a zero note; a withdrawal's change is the value left in the
+/// pool and its other output is a zero note. The change always returns to
+/// the sender, which
+/// [`check_targets`](Self::check_targets) enforces before anything is built.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChangeOutput {
+    /// `output[0]` is change and `output[1]` carries the main amount
+    First,
+    /// `output[0]` carries the main amount and `output[1]` is change
+    #[default]
+    Second,
+}
+
+impl ChangeOutput {
+    /// Position of the change output
+    pub fn index(self) -> usize {
+        match self {
+            ChangeOutput::First => 0,
+            ChangeOutput::Second => 1,
+        }
+    }
+
+    /// Place the main and change values in output order
+    pub fn arrange<T>(self, main: T, change: T) -> [T; 2] {
+        match self {
+            ChangeOutput::First => [change, main],
+            ChangeOutput::Second => [main, change],
+        }
+    }
+
+    /// Check that the change output is created for the sender
+    ///
+    /// A change output targeting another identity would hand the sender's
+    /// remaining funds to that identity's key.
+    pub fn check_targets(self, targets: &[OutputTarget<'_>; 2]) -> Result<()> {
+        match targets[self.index()] {
+            OutputTarget::Sender => Ok(()),
+            OutputTarget::Recipient(_) => Err(PrivacyCashError::InvalidInput(format!(
+                "Change output {} must be created for the sender, not a recipient",
+                self.index()
+            ))),
+        }
+    }
+}
+
 /// Constant-time comparison to prevent timing attacks
 fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
     if a.len() != b.len() {
@@ -316,7 +669,7 @@ mod tests {
     fn test_encryption_roundtrip() {
         let keypair = Keypair::new();
         let mut service = EncryptionService::new();
-        service.derive_encryption_key_from_wallet(&keypair);
+        service.derive_encryption_key_from_wallet(&keypair).unwrap();
 
         let data = b"Hello, Privacy Cash!";
         let encrypted = service.encrypt(data).unwrap();
@@ -325,11 +678,35 @@ mod tests {
         assert_eq!(data.as_slice(), decrypted.as_slice());
     }
 
+    #[test]
+    fn test_signature_validation() {
+        let mut service = EncryptionService::new();
+        assert!(matches!(
+            service.derive_encryption_key_from_signature(&[7u8; 30]),
+            Err(PrivacyCashError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            service.derive_encryption_key_from_signature(&[7u8; 31]),
+            Err(PrivacyCashError::InvalidInput(_))
+        ));
+        assert!(service.derive_encryption_key_from_signature(&[]).is_err());
+        assert!(matches!(
+            service.derive_encryption_key_from_signature(&[0u8; 64]),
+            Err(PrivacyCashError::DegenerateKey(_))
+        ));
+        assert!(service.encrypt(b"x").is_err());
+
+        let mut signature = [0u8; 64];
+        signature[0] = 1;
+        let keys = service.derive_encryption_key_from_signature(&signature).unwrap();
+        assert_eq!(keys.v1.len(), 31);
+    }
+
     #[test]
     fn test_utxo_encryption() {
         let keypair = Keypair::new();
         let mut service = EncryptionService::new();
-        service.derive_encryption_key_from_wallet(&keypair);
+        service.derive_encryption_key_from_wallet(&keypair).unwrap();
 
         let zk_keypair = ZkKeypair::from_hex(&service.get_utxo_private_key_v2().unwrap()).unwrap();
         let utxo = Utxo::new(1000u64, zk_keypair, 5, None, Some(UtxoVersion::V2));
@@ -341,4 +718,163 @@ mod tests {
         assert_eq!(utxo.blinding, decrypted.blinding);
         assert_eq!(utxo.index, decrypted.index);
     }
+
+    #[test]
+    fn test_wrong_key_version() {
+        let keypair = Keypair::new();
+        let mut service = EncryptionService::new();
+        service.derive_encryption_key_from_wallet(&keypair).unwrap();
+        let encrypted = service.encrypt(b"note").unwrap();
+
+        // Only the V1 key is available
+        service.encryption_key_v2 = None;
+        service.utxo_private_key_v2 = None;
+
+        match service.decrypt(&encrypted) {
+            Err(PrivacyCashError::WrongKeyVersion { note, available }) => {
+                assert_eq!(note, UtxoVersion::V2);
+                assert_eq!(available, UtxoVersion::V1);
+            }
+            other => panic!("expected WrongKeyVersion, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_output_target() {
+        let mut sender = EncryptionService::new();
+        sender.derive_encryption_key_from_wallet(&Keypair::new()).unwrap();
+        let mut recipient = EncryptionService::new();
+        recipient.derive_encryption_key_from_wallet(&Keypair::new()).unwrap();
+        let restored = EncryptionService::from_viewing_key(&recipient.export_viewing_key().unwrap())
+            .unwrap();
+
+        let own = OutputTarget::default();
+        let own_keypair = own.utxo_keypair(&sender).unwrap();
+        assert_eq!(own_keypair.pubkey(), utxo_keypair_of(&sender).pubkey());
+
+        let target = OutputTarget::Recipient(&restored);
+        let keypair = target.utxo_keypair(&sender).unwrap();
+        assert_eq!(keypair.pubkey(), utxo_keypair_of(&recipient).pubkey());
+
+        let utxo = Utxo::new(500u64, keypair, 3, None, Some(UtxoVersion::V2));
+        let encrypted = target.encrypt(&sender, &utxo).unwrap();
+        assert!(sender.decrypt_utxo(&encrypted).is_err());
+        assert_eq!(recipient.decrypt_utxo(&encrypted).unwrap().amount, utxo.amount);
+    }
+
+    #[test]
+    fn test_legacy_note_utf8_fallback() {
+        let mut service = EncryptionService::new();
+        service.derive_encryption_key_from_wallet(&Keypair::new()).unwrap();
+        let note = format!("1000|5|7|{}", *crate::constants::SOL_MINT);
+
+        // Trailing bytes that are not UTF-8 no longer hide the note
+        let mut padded = note.clone().into_bytes();
+        padded.extend_from_slice(&[0xff, 0x00, 0x00]);
+        let utxo = service.decrypt_utxo(&service.encrypt(&padded).unwrap()).unwrap();
+        assert_eq!(utxo.amount, BigUint::from(1000u32));
+        assert_eq!(utxo.index, 7);
+        let expected = service.decrypt_utxo(&service.encrypt(note.as_bytes()).unwrap()).unwrap();
+        assert_eq!(utxo.get_commitment().unwrap(), expected.get_commitment().unwrap());
+
+        // Damage inside a field still fails, as a corrupt note of ours
+        let mut damaged = note.into_bytes();
+        damaged[1] = 0xff;
+        let encrypted = service.encrypt(&damaged).unwrap();
+        assert!(service.decrypt_utxo(&encrypted).is_err());
+        assert!(matches!(service.classify_decrypt_utxo(&encrypted), Err(DecryptFailure::Corrupt)));
+    }
+
+    #[test]
+    fn test_change_output() {
+        let recipient = EncryptionService::new();
+        let to_recipient = [OutputTarget::Recipient(&recipient), OutputTarget::Sender];
+
+        let change = ChangeOutput::default();
+        assert_eq!(change.index(), 1);
+        assert_eq!(change.arrange("payment", "change"), ["payment", "change"]);
+        assert!(change.check_targets(&to_recipient).is_ok());
+        assert!(change.check_targets(&Default::default()).is_ok());
+
+        // Change placed on the recipient's output would leave the sender
+        let change = ChangeOutput::First;
+        assert_eq!(change.arrange("payment", "change"), ["change", "payment"]);
+        assert!(change.check_targets(&to_recipient).is_err());
+    }
+
+    #[test]
+    fn test_classify_decrypt_failure() {
+        let mut service = EncryptionService::new();
+        service.derive_encryption_key_from_wallet(&Keypair::new()).unwrap();
+        let mut other = EncryptionService::new();
+        other.derive_encryption_key_from_wallet(&Keypair::new()).unwrap();
+
+        let utxo = Utxo::new(1000u64, utxo_keypair_of(&service), 5, None, Some(UtxoVersion::V2));
+        let encrypted = service.encrypt_utxo(&utxo).unwrap();
+        assert_eq!(service.classify_decrypt_utxo(&encrypted).unwrap().amount, utxo.amount);
+
+        let foreign = other.encrypt_utxo(&utxo).unwrap();
+        assert_eq!(service.classify_decrypt_utxo(&foreign).err(), Some(DecryptFailure::Foreign));
+        assert!(!DecryptFailure::Foreign.is_suspicious());
+
+        let truncated = &encrypted[..MIN_V2_NOTE_LEN - 1];
+        assert_eq!(
+            service.classify_decrypt_utxo(truncated).err(),
+            Some(DecryptFailure::Truncated)
+        );
+
+        let garbage = service.encrypt(&[0xff; 64]).unwrap();
+        assert_eq!(service.classify_decrypt_utxo(&garbage).err(), Some(DecryptFailure::Corrupt));
+        assert!(DecryptFailure::Corrupt.is_suspicious());
+    }
+
+    #[test]
+    fn test_commitment_bound_encryption() {
+        let mut service = EncryptionService::new();
+        service.derive_encryption_key_from_wallet(&Keypair::new()).unwrap();
+
+        // Empty associated data is the plain envelope
+        let plain = service.encrypt_with_aad(b"note", &[]).unwrap();
+        assert_eq!(service.decrypt(&plain).unwrap(), b"note");
+        assert!(service.decrypt_with_aad(&plain, b"other").is_err());
+
+        let utxo = Utxo::new(1000u64, utxo_keypair_of(&service), 5, None, Some(UtxoVersion::V2));
+        let other = Utxo::new(1000u64, utxo_keypair_of(&service), 6, None, Some(UtxoVersion::V2));
+        let commitment = utxo.get_commitment().unwrap();
+        let bound = service.encrypt_utxo_bound(&utxo).unwrap();
+
+        assert_eq!(service.decrypt_utxo_bound(&bound, &commitment).unwrap().index, 5);
+        assert!(service.decrypt_utxo(&bound).is_err());
+        assert!(service.decrypt_utxo_bound(&bound, &other.get_commitment().unwrap()).is_err());
+
+        // An unbound ciphertext replayed under another commitment is caught too
+        let unbound = service.encrypt_utxo(&utxo).unwrap();
+        assert!(service.decrypt_utxo_bound(&unbound, &commitment).is_err());
+    }
+
+    #[test]
+    fn test_with_version_keypair() {
+        let mut service = EncryptionService::new();
+        service.derive_encryption_key_from_wallet(&Keypair::new()).unwrap();
+        let v1 = service.utxo_keypair_for_version(UtxoVersion::V1).unwrap();
+        let v2 = service.utxo_keypair_for_version(UtxoVersion::V2).unwrap();
+
+        let legacy = Utxo::new(1000u64, v1.clone(), 5, None, Some(UtxoVersion::V1));
+        let mislabeled = Utxo { keypair: v2.clone(), ..legacy.clone() };
+        let fixed = service.with_version_keypair(&mislabeled).unwrap();
+        assert_eq!(fixed.get_nullifier().unwrap(), legacy.get_nullifier().unwrap());
+        assert_eq!(fixed.get_commitment().unwrap(), legacy.get_commitment().unwrap());
+
+        let current = Utxo::new(1000u64, v2, 6, None, Some(UtxoVersion::V2));
+        let same = service.with_version_keypair(&current).unwrap();
+        assert_eq!(same.keypair.pubkey(), current.keypair.pubkey());
+
+        let foreign = Utxo::new(1000u64, ZkKeypair::generate().unwrap(), 7, None, None);
+        let unchanged = service.with_version_keypair(&foreign).unwrap();
+        assert_eq!(unchanged.keypair.pubkey(), foreign.keypair.pubkey());
+    }
+
+    fn utxo_keypair_of(service: &EncryptionService) -> ZkKeypair {
+        ZkKeypair::from_hex(&service.get_utxo_private_key_v2().unwrap()).unwrap()
+    }
 }
@@ -1,5 +1,6 @@
 //! Error types for Privacy Cash SDK
 
+use crate::utxo::UtxoVersion;
 use thiserror::Error;
 
 /// Result type alias for Privacy Cash operations
@@ -12,6 +13,10 @@ pub enum PrivacyCashError {
     #[error("Invalid keypair: {0}")]
     InvalidKeypair(String),
 
+    /// Key material is degenerate (e.g. zero) and unsafe to use
+    #[error("Degenerate key: {0}")]
+    DegenerateKey(String),
+
     /// Invalid input parameter
     #[error("Invalid input: {0}")]
     InvalidInput(String),
@@ -28,6 +33,17 @@ pub enum PrivacyCashError {
         need: u64,
     },
 
+    /// The recipient's associated token account does not exist
+    #[error(
+        "No token account for mint {mint} owned by {owner}; create it first or withdraw with \
+         DeliveryMode::CreateAta"
+    )]
+    TokenAccountNotFound { owner: String, mint: String },
+
+    /// The depositor has no token account for the mint, so it holds none of the token
+    #[error("No token account for mint {mint} owned by {owner}; move tokens into it first")]
+    DepositTokenAccountNotFound { owner: String, mint: String },
+
     /// No UTXOs available for withdrawal
     #[error("No UTXOs available for withdrawal")]
     NoUtxosAvailable,
@@ -40,6 +56,14 @@ pub enum PrivacyCashError {
     #[error("Withdrawal amount too low, minimum is {minimum}")]
     WithdrawalAmountTooLow { minimum: u64 },
 
+    /// Relayer fee is above the caller's `max_fee`
+    #[error("Relayer fee {fee} exceeds the limit of {max} base units")]
+    FeeExceedsLimit { fee: u64, max: u64 },
+
+    /// Requested amount is below the relayer's minimum withdrawal
+    #[error("Amount is below the minimum withdrawal of {min} base units")]
+    BelowMinimum { min: u64 },
+
     /// Token not supported
     #[error("Token not supported: {0}")]
     TokenNotSupported(String),
@@ -52,10 +76,41 @@ pub enum PrivacyCashError {
     #[error("Decryption error: {0}")]
     DecryptionError(String),
 
+    /// Note was encrypted with a key version that has not been derived
+    #[error("Note is {note:?} but only the {available:?} key is available; derive the {note:?} encryption key to decrypt it")]
+    WrongKeyVersion {
+        note: UtxoVersion,
+        available: UtxoVersion,
+    },
+
+    /// A transaction input is already spent on chain
+    #[error("Input note {0} is already spent")]
+    InputSpent(String),
+
+    /// A proof built ahead targets a leaf index the tree has moved past
+    #[error("Proof built for leaf index {expected}, but the tree's next index is {actual}")]
+    StaleProof { expected: u64, actual: u64 },
+
     /// Proof generation error
     #[error("Proof generation error: {0}")]
     ProofGenerationError(String),
 
+    /// Prover output is not a well-formed Groth16 proof
+    #[error("Invalid proof: {0}")]
+    InvalidProof(String),
+
+    /// Circuit files or circuit input do not match a supported arity
+    #[error("Unsupported circuit: {0}")]
+    UnsupportedCircuit(String),
+
+    /// Proof generation did not finish in time
+    #[error("Proof generation timed out after {seconds}s")]
+    ProofTimeout { seconds: u64 },
+
+    /// The snarkjs subprocess exited unsuccessfully
+    #[error("snarkjs failed: {stderr}")]
+    BridgeError { stderr: String },
+
     /// Merkle proof error
     #[error("Merkle proof error: {0}")]
     MerkleProofError(String),
@@ -68,14 +123,31 @@ pub enum PrivacyCashError {
     #[error("Transaction error: {0}")]
     TransactionError(String),
 
+    /// Serialized transaction exceeds Solana's packet size limit
+    #[error("Transaction too large: {size} bytes exceeds the {limit}-byte limit")]
+    TransactionTooLarge { size: usize, limit: usize },
+
     /// Transaction confirmation timeout
-    #[error("Transaction confirmation timeout after {retries} retries")]
-    ConfirmationTimeout { retries: u32 },
+    ///
+    /// The transaction may still have landed; check `transaction_status`
+    /// before resubmitting.
+    #[error("Transaction {signature} not confirmed after {retries} retries")]
+    ConfirmationTimeout { signature: String, retries: u32 },
+
+    /// A confirmed deposit did not show up in the scanned balance in time
+    ///
+    /// The deposit landed; the indexer has not served the new note yet.
+    #[error("Deposit {signature} not reflected in the balance after {attempts} scans")]
+    IndexingTimeout { signature: String, attempts: u32 },
 
     /// Solana client error
     #[error("Solana client error: {0}")]
     SolanaClientError(#[from] solana_client::client_error::ClientError),
 
+    /// The RPC answered, but with data that cannot be interpreted; retrying may help
+    #[error("Unexpected RPC response: {0}")]
+    InvalidRpcResponse(String),
+
     /// Serialization error
     #[error("Serialization error: {0}")]
     SerializationError(String),
@@ -104,7 +176,23 @@ pub enum PrivacyCashError {
     #[error("Circuit file not found: {0}")]
     CircuitNotFound(String),
 
+    /// Operation requires a keypair but the client is read-only
+    #[error("Operation requires a signing keypair; this client is read-only")]
+    ReadOnlyClient,
+
     /// Operation aborted
     #[error("Operation aborted")]
     Aborted,
+
+    /// The approval hook rejected the transaction before it was relayed
+    #[error("Transaction rejected by the approval hook")]
+    UserRejected,
+
+    /// The on-chain Merkle tree has no room for the transaction's outputs
+    #[error("Merkle tree is full: next index {next_index} of {capacity} leaves")]
+    TreeFull { next_index: u64, capacity: u64 },
+
+    /// An incremental-only scan would have to rescan from index 0
+    #[error("Full rescan required: {0}")]
+    RescanRequired(String),
 }
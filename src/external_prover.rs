@@ -0,0 +1,144 @@
+//! Proving with an external backend
+//!
+//! Every flow proves through [`RustProver`](crate::prover_rust::RustProver).
+//! When an [`ExternalProver`] is installed with [`set_external_prover`], the
+//! bundled prover is skipped: the external one receives the circuit input as
+//! the JSON snarkjs expects (see [`CircuitInput::to_json`]) and returns the
+//! proof and public signals. Before the flow submits them, the signals are
+//! checked against the circuit input and, when the `.zkey` file is present,
+//! the proof is verified against its verifying key. This lets faster
+//! backends such as rapidsnark replace proof generation while the SDK keeps
+//! building and relaying the transactions.
+//!
+//! [`CircuitInput::to_json`]: crate::prover::CircuitInput::to_json
+
+use crate::error::{PrivacyCashError, Result};
+use crate::prover::Proof;
+use once_cell::sync::OnceCell;
+use parking_lot::RwLock;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Global external prover, if one is installed
+static EXTERNAL_PROVER: OnceCell<RwLock<Option<Arc<dyn ExternalProver>>>> = OnceCell::new();
+
+/// Future returned by [`ExternalProver::prove`]
+pub type ProveFuture<'a> = Pin<Box<dyn Future<Output = Result<(Proof, Vec<String>)>> + Send + 'a>>;
+
+/// A proof backend outside the SDK
+pub trait ExternalProver: Send + Sync {
+    /// Prove the circuit input `input_json`, in snarkjs input format
+    ///
+    /// Returns the proof and the public signals as decimal strings, e.g. as
+    /// parsed from snarkjs' `proof.json` and `public.json` with
+    /// [`parse_snarkjs_output`].
+    fn prove<'a>(&'a self, input_json: &'a str) -> ProveFuture<'a>;
+}
+
+/// Get the installed external prover
+pub fn external_prover() -> Option<Arc<dyn ExternalProver>> {
+    prover_cell().read().clone()
+}
+
+/// Prove with `prover` instead of the bundled prover
+pub fn set_external_prover(prover: Arc<dyn ExternalProver>) {
+    *prover_cell().write() = Some(prover);
+}
+
+/// Go back to the bundled prover
+pub fn clear_external_prover() {
+    *prover_cell().write() = None;
+}
+
+/// Parse the `proof.json` and `public.json` a snarkjs-compatible prover writes
+pub fn parse_snarkjs_output(proof_json: &str, public_json: &str) -> Result<(Proof, Vec<String>)> {
+    let proof: Proof = serde_json::from_str(proof_json)
+        .map_err(|e| PrivacyCashError::InvalidProof(format!("Invalid proof JSON: {}", e)))?;
+    let public_signals: Vec<String> = serde_json::from_str(public_json)
+        .map_err(|e| PrivacyCashError::InvalidProof(format!("Invalid public signals: {}", e)))?;
+    Ok((proof, public_signals))
+}
+
+fn prover_cell() -> &'static RwLock<Option<Arc<dyn ExternalProver>>> {
+    EXTERNAL_PROVER.get_or_init(|| RwLock::new(None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::TEST_HOOKS_LOCK;
+    use crate::prover::CircuitInput;
+    use crate::prover_rust::RustProver;
+    use num_bigint::BigUint;
+    use parking_lot::Mutex;
+
+    /// Records the input and answers with a fixed proof and public signals
+    struct RecordingProver {
+        input: Mutex<Option<String>>,
+        public_json: &'static str,
+    }
+
+    impl RecordingProver {
+        fn new(public_json: &'static str) -> Self {
+            Self {
+                input: Mutex::new(None),
+                public_json,
+            }
+        }
+    }
+
+    impl ExternalProver for RecordingProver {
+        fn prove<'a>(&'a self, input_json: &'a str) -> ProveFuture<'a> {
+            *self.input.lock() = Some(input_json.to_string());
+            let proof = r#"{"pi_a":["1","2"],"pi_b":[["1","2"],["3","4"]],"pi_c":["5","6"]}"#;
+            Box::pin(async move { parse_snarkjs_output(proof, self.public_json) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_external_prover_receives_input() {
+        let input = CircuitInput {
+            root: "42".to_string(),
+            input_nullifier: vec!["1".to_string(); 2],
+            output_commitment: vec!["2".to_string(); 2],
+            public_amount: "0".to_string(),
+            ext_data_hash: vec![0; 32],
+            in_amount: vec!["0".to_string(); 2],
+            in_private_key: vec![BigUint::from(1u8); 2],
+            in_blinding: vec!["0".to_string(); 2],
+            in_path_indices: vec![0; 2],
+            in_path_elements: vec![vec![]; 2],
+            out_amount: vec!["0".to_string(); 2],
+            out_blinding: vec!["0".to_string(); 2],
+            out_pubkey: vec![BigUint::from(1u8); 2],
+            mint_address: "0".to_string(),
+        };
+
+        let _guard = TEST_HOOKS_LOCK.lock().await;
+        // Root, publicAmount, extDataHash, two nullifiers, two commitments
+        let external = Arc::new(RecordingProver::new(r#"["42","0","0","1","1","2","2"]"#));
+        set_external_prover(external.clone());
+        // No circuit files exist here, so only the external prover can succeed
+        let prover = RustProver::new("/nonexistent/transaction2");
+        let result = prover.prove(&input).await;
+        set_external_prover(Arc::new(RecordingProver::new(r#"["42","0","0","1","1","2","9"]"#)));
+        let mismatched = prover.prove(&input).await;
+        set_external_prover(Arc::new(RecordingProver::new(r#"["42","0","0"]"#)));
+        let truncated = prover.prove(&input).await;
+        clear_external_prover();
+
+        let (proof, public_signals) = result.unwrap();
+        assert_eq!(proof.pi_c[0], "5");
+        assert_eq!(public_signals.len(), 7);
+        assert!(matches!(mismatched, Err(PrivacyCashError::InvalidProof(_))));
+        assert!(matches!(truncated, Err(PrivacyCashError::UnsupportedCircuit(_))));
+        let sent: serde_json::Value =
+            serde_json::from_str(external.input.lock().as_deref().unwrap()).unwrap();
+        assert_eq!(sent["root"], "42");
+        assert_eq!(sent["inPrivateKey"][0], "1");
+        assert!(external_prover().is_none());
+
+        assert!(parse_snarkjs_output("{}", "[]").is_err());
+    }
+}
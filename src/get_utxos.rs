@@ -1,16 +1,21 @@
 //! UTXO fetching and management for native SOL
 
+use crate::clock::clock;
 use crate::constants::{
-    FETCH_UTXOS_GROUP_SIZE, LSK_ENCRYPTED_OUTPUTS, LSK_FETCH_OFFSET, PROGRAM_ID, RELAYER_API_URL,
+    FETCH_UTXOS_GROUP_SIZE, LSK_ENCRYPTED_OUTPUTS, LSK_FETCH_OFFSET, LSK_SPENT_NULLIFIERS,
+    LSK_TREE_STATE, PROGRAM_ID, RELAYER_API_URL,
 };
 use crate::encryption::EncryptionService;
 use crate::error::{PrivacyCashError, Result};
+use crate::http::transport;
+use crate::metrics::{metrics, record_relayer_error, SCAN_SECONDS};
 use crate::storage::Storage;
-use crate::utxo::{get_balance_from_utxos, Balance, Utxo};
-use num_bigint::BigUint;
+use crate::utils::{query_remote_tree_state, NullifierBytes};
+use crate::utxo::{dedup_by_commitment, get_balance_from_utxos, Balance, Utxo, UtxoDetail};
 use serde::{Deserialize, Serialize};
 use solana_client::rpc_client::RpcClient;
-use solana_sdk::pubkey::Pubkey;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use std::collections::HashSet;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
@@ -54,7 +59,103 @@ pub fn localstorage_key(pubkey: &Pubkey) -> String {
     format!("{}{}", prefix, pubkey)
 }
 
+/// Find the cached ciphertext of the note with the given commitment
+///
+/// Only unspent notes from the last scan are cached, so spent or not yet
+/// scanned notes return `None`.
+pub fn find_cached_output(
+    storage: &Storage,
+    storage_key: &str,
+    encryption_service: &EncryptionService,
+    commitment: &str,
+) -> Result<Option<Vec<u8>>> {
+    let cached_key = format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key);
+    let Some(cached_outputs) = storage.get_outputs(&cached_key) else {
+        return Ok(None);
+    };
+
+    for output in cached_outputs {
+        let Ok(utxo) = encryption_service.decrypt_utxo_from_hex(&output) else {
+            continue;
+        };
+        if utxo.get_commitment()? == commitment {
+            let bytes = hex::decode(&output).map_err(|e| {
+                PrivacyCashError::EncryptionError(format!("Invalid cached output: {}", e))
+            })?;
+            return Ok(Some(bytes));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Where a scan starts and whether it may fall back to a full rescan
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// Start at this output index instead of the cached offset
+    pub start_offset: Option<u64>,
+    /// Fail with `RescanRequired` instead of scanning from index 0
+    pub incremental_only: bool,
+    /// Scan from index 0 without reading or writing the persisted cache
+    pub bypass_cache: bool,
+    /// Treat every note as unspent instead of checking nullifiers over RPC
+    ///
+    /// The result is an upper bound that may include notes already spent, so
+    /// it is only fit for display. Nothing is written back to the cache.
+    pub skip_spent_check: bool,
+}
+
+impl ScanOptions {
+    /// Scan from `offset`, ignoring the cached offset
+    pub fn from_offset(offset: u64) -> Self {
+        Self {
+            start_offset: Some(offset),
+            ..Self::default()
+        }
+    }
+
+    /// Only continue from the cached offset, never rescan the whole tree
+    pub fn incremental() -> Self {
+        Self {
+            incremental_only: true,
+            ..Self::default()
+        }
+    }
+
+    /// Full scan against a scratch cache, leaving the stored one untouched
+    pub fn fresh() -> Self {
+        Self {
+            bypass_cache: true,
+            ..Self::default()
+        }
+    }
+
+    /// Fast read without the RPC spent-check, see `skip_spent_check`
+    pub fn optimistic() -> Self {
+        Self {
+            skip_spent_check: true,
+            ..Self::default()
+        }
+    }
+
+    /// The storage a scan should use: `storage`, or `scratch` when bypassing
+    pub(crate) fn scan_storage<'a>(
+        &self,
+        storage: &'a Storage,
+        scratch: &'a Storage,
+    ) -> &'a Storage {
+        if self.bypass_cache {
+            scratch
+        } else {
+            storage
+        }
+    }
+}
+
 /// Fetch all UTXOs for a user
+///
+/// Pages are pipelined: the next page is fetched and decrypted while the
+/// current page is spent-checked, keeping at most one page in flight.
 pub async fn get_utxos(
     connection: &RpcClient,
     public_key: &Pubkey,
@@ -62,96 +163,312 @@ pub async fn get_utxos(
     storage: &Storage,
     abort_signal: Option<Arc<Mutex<bool>>>,
 ) -> Result<Vec<Utxo>> {
+    get_utxos_with_options(
+        connection,
+        public_key,
+        encryption_service,
+        storage,
+        abort_signal,
+        ScanOptions::default(),
+    )
+    .await
+}
+
+/// Fetch UTXOs for a user with an explicit scan range
+///
+/// Notes below `options.start_offset` are only found if an earlier scan
+/// cached them.
+pub async fn get_utxos_with_options(
+    connection: &RpcClient,
+    public_key: &Pubkey,
+    encryption_service: &EncryptionService,
+    storage: &Storage,
+    abort_signal: Option<Arc<Mutex<bool>>>,
+    options: ScanOptions,
+) -> Result<Vec<Utxo>> {
+    let scratch = Storage::memory();
+    let storage = options.scan_storage(storage, &scratch);
+    let started = clock().now();
     let mut valid_utxos = Vec::new();
     let mut valid_strings = Vec::new();
     let mut history_indexes = Vec::new();
 
     let storage_key = localstorage_key(public_key);
+    let offset_key = format!("{}{}", LSK_FETCH_OFFSET, storage_key);
+    let range_url = |offset: u64| {
+        format!(
+            "{}/utxos/range?start={}&end={}",
+            *RELAYER_API_URL,
+            offset,
+            offset + FETCH_UTXOS_GROUP_SIZE
+        )
+    };
+    let storage_key_ref = storage_key.as_str();
+    let fetch_page = |offset: u64| {
+        let url = range_url(offset);
+        async move {
+            log::debug!("Fetching UTXOs from: {}", url);
+            fetch_user_utxos(&url, encryption_service, storage, storage_key_ref).await
+        }
+    };
 
     // Get starting offset from storage
-    let mut round_start_index: u64 = storage
-        .get(&format!("{}{}", LSK_FETCH_OFFSET, storage_key))
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(0);
+    let mut fetch_offset = load_fetch_offset(storage, &storage_key, None, options).await?;
+    let mut stored_offset = storage.get(&offset_key);
+
+    check_abort(&abort_signal).await?;
+    let mut page = fetch_page(fetch_offset).await?;
 
     loop {
-        // Check for abort
-        if let Some(ref signal) = abort_signal {
-            if *signal.lock().await {
-                return Err(PrivacyCashError::Aborted);
+        let (fetched_utxos, encrypted_outputs, has_more, len) = page;
+        let next_offset = fetch_offset + len;
+
+        // Check which UTXOs are unspent
+        let (non_zero_indices, non_zero_utxos): (Vec<usize>, Vec<Utxo>) = fetched_utxos
+            .into_iter()
+            .enumerate()
+            .filter(|(_, u)| u.amount_u64() > 0)
+            .unzip();
+
+        let spent_check = async {
+            if non_zero_utxos.is_empty() {
+                Ok(Vec::new())
+            } else if options.skip_spent_check {
+                Ok(vec![false; non_zero_utxos.len()])
+            } else {
+                are_utxos_spent_cached(connection, &non_zero_utxos, storage, &storage_key).await
+            }
+        };
+
+        // Fetch and decrypt the next page while this one is spent-checked
+        let (spent_flags, next_page) = if has_more {
+            let next_fetch = async {
+                // Small delay to avoid rate limiting
+                tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+                check_abort(&abort_signal).await?;
+                fetch_page(next_offset).await
+            };
+            let (spent_flags, next_page) = tokio::join!(spent_check, next_fetch);
+            (spent_flags?, Some(next_page))
+        } else {
+            (spent_check.await?, None)
+        };
+
+        let checked = non_zero_indices.into_iter().zip(non_zero_utxos).zip(spent_flags);
+        for ((idx, utxo), is_spent) in checked {
+            history_indexes.push(utxo.index);
+            if !is_spent {
+                log::debug!("Found unspent UTXO: {:?}", encrypted_outputs.get(idx));
+                valid_utxos.push(utxo);
+                if let Some(enc) = encrypted_outputs.get(idx) {
+                    valid_strings.push(enc.clone());
+                }
+            }
+        }
+
+        // Update storage offset; unchecked notes are never cached
+        if !options.skip_spent_check {
+            advance_fetch_offset(storage, &offset_key, &mut stored_offset, next_offset);
+        }
+
+        match next_page {
+            Some(next_page) => {
+                page = next_page?;
+                fetch_offset = next_offset;
             }
+            None => break,
         }
+    }
 
-        let fetch_offset: u64 = storage
-            .get(&format!("{}{}", LSK_FETCH_OFFSET, storage_key))
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(0)
-            .max(round_start_index);
+    // Store valid encrypted outputs, sorted so unchanged caches are not rewritten
+    valid_strings.sort_unstable();
+    valid_strings.dedup();
+
+    if !options.skip_spent_check {
+        storage.set_outputs(&format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key), &valid_strings);
+    }
+
+    let elapsed = clock().now().duration_since(started).as_secs_f64();
+    metrics().observe_histogram(SCAN_SECONDS, elapsed, &[("token", "sol")]);
+    Ok(dedup_by_commitment(valid_utxos))
+}
 
-        let fetch_end = fetch_offset + FETCH_UTXOS_GROUP_SIZE;
+/// Fetch and decrypt our notes among the outputs at indices `[start, end)`
+///
+/// The cache and its fetch offset are neither read nor written, and spends
+/// are not checked, so spent notes are returned too. `token_name` selects an
+/// SPL token's tree, `None` the SOL tree.
+pub async fn scan_range(
+    encryption_service: &EncryptionService,
+    start: u64,
+    end: u64,
+    token_name: Option<&str>,
+) -> Result<Vec<Utxo>> {
+    if end < start {
+        return Err(PrivacyCashError::InvalidInput(format!(
+            "Scan range end {} is before its start {}",
+            end, start
+        )));
+    }
+
+    let token_param = token_name.map(|name| format!("token={}&", name)).unwrap_or_default();
+    let mut utxos = Vec::new();
+    let mut offset = start;
+    while offset < end {
+        let page_end = end.min(offset + FETCH_UTXOS_GROUP_SIZE);
         let url = format!(
-            "{}/utxos/range?start={}&end={}",
-            *RELAYER_API_URL, fetch_offset, fetch_end
+            "{}/utxos/range?{}start={}&end={}",
+            *RELAYER_API_URL, token_param, offset, page_end
         );
+        log::debug!("Fetching UTXO range from: {}", url);
+        let (outputs, has_more) = fetch_encrypted_outputs(&url).await?;
+        let (page, _) = decrypt_outputs(&outputs, encryption_service, token_name).await?;
+        utxos.extend(page);
 
-        log::debug!("Fetching UTXOs from: {}", url);
+        if outputs.is_empty() || !has_more {
+            break;
+        }
+        offset += outputs.len() as u64;
+    }
 
-        let (fetched_utxos, encrypted_outputs, has_more, len) =
-            fetch_user_utxos(&url, encryption_service, storage, &storage_key).await?;
+    Ok(utxos)
+}
 
-        // Check which UTXOs are unspent
-        let non_zero_utxos: Vec<_> = fetched_utxos
-            .iter()
-            .enumerate()
-            .filter(|(_, u)| u.amount_u64() > 0)
-            .collect();
+/// Load the cached fetch offset, resetting it if the relayer's tree no longer
+/// matches the cache
+///
+/// After a relayer reset or cache corruption the stored offset can exceed the
+/// number of outputs the relayer holds, which would otherwise make every scan
+/// request an empty range and report a zero balance. Likewise, a root that
+/// changed without the tree growing (e.g. a relayer data migration) means the
+/// cached outputs and their indices may no longer match the tree. In both
+/// cases the cached unspent outputs are dropped along with the offset so the
+/// rescan does not count them twice. An outputs cache that cannot be decoded
+/// (corrupt, or written by a newer SDK) also forces a rescan, since the notes
+/// below the offset would otherwise be lost.
+///
+/// The last-seen root and size are recorded on every scan. An explicit
+/// `options.start_offset` is returned as is; with `options.incremental_only`
+/// any scan that would start from 0 fails with `RescanRequired` and leaves
+/// the cache untouched.
+pub(crate) async fn load_fetch_offset(
+    storage: &Storage,
+    storage_key: &str,
+    token_name: Option<&str>,
+    options: ScanOptions,
+) -> Result<u64> {
+    if let Some(offset) = options.start_offset {
+        return Ok(offset);
+    }
 
-        if !non_zero_utxos.is_empty() {
-            let spent_flags = are_utxos_spent(
-                connection,
-                &non_zero_utxos.iter().map(|(_, u)| (*u).clone()).collect::<Vec<_>>(),
-            )
-            .await?;
-
-            for ((idx, utxo), is_spent) in non_zero_utxos.into_iter().zip(spent_flags) {
-                history_indexes.push(utxo.index);
-                if !is_spent {
-                    log::debug!("Found unspent UTXO: {:?}", encrypted_outputs.get(idx));
-                    valid_utxos.push(utxo.clone());
-                    if let Some(enc) = encrypted_outputs.get(idx) {
-                        valid_strings.push(enc.clone());
-                    }
-                }
-            }
+    let offset_key = format!("{}{}", LSK_FETCH_OFFSET, storage_key);
+    let mut offset: u64 = storage
+        .get(&offset_key)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0);
+
+    if offset == 0 && options.incremental_only {
+        return Err(PrivacyCashError::RescanRequired("no cached fetch offset".to_string()));
+    }
+
+    // Notes below the offset only survive in the outputs cache, so an offset
+    // without a readable cache would hide them
+    let outputs_key = format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key);
+    if offset > 0 && storage.has_unreadable_outputs(&outputs_key) {
+        if options.incremental_only {
+            return Err(PrivacyCashError::RescanRequired(
+                "cached outputs are in an unsupported format".to_string(),
+            ));
         }
+        log::warn!("Cached outputs are in an unsupported format, rescanning from 0");
+        storage.remove(&offset_key);
+        storage.remove(&outputs_key);
+        offset = 0;
+    }
 
-        // Update storage offset
-        storage.set(
-            &format!("{}{}", LSK_FETCH_OFFSET, storage_key),
-            &(fetch_offset + len).to_string(),
-        );
+    let Ok(state) = query_remote_tree_state(token_name).await else {
+        return Ok(offset);
+    };
 
-        if !has_more {
-            break;
+    let tree_key = format!("{}{}", LSK_TREE_STATE, storage_key);
+    let last_seen = storage.get(&tree_key).and_then(|s| {
+        let (root, next_index) = s.split_once(':')?;
+        Some((root.to_string(), next_index.parse::<u64>().ok()?))
+    });
+
+    if offset == 0 {
+        storage.set(&tree_key, &format!("{}:{}", state.root, state.next_index));
+        return Ok(0);
+    }
+
+    let stale = if offset > state.next_index {
+        log::warn!(
+            "Cached fetch offset {} is past the tree size {}, rescanning from 0",
+            offset,
+            state.next_index
+        );
+        true
+    } else if let Some((root, next_index)) = last_seen {
+        let rewritten = root != state.root && state.next_index <= next_index;
+        if rewritten {
+            log::warn!(
+                "Tree root changed from {} to {} without growth (size {} -> {}), \
+                 rescanning from 0",
+                root,
+                state.root,
+                next_index,
+                state.next_index
+            );
         }
+        rewritten
+    } else {
+        false
+    };
 
-        // Small delay to avoid rate limiting
-        tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+    if stale && options.incremental_only {
+        return Err(PrivacyCashError::RescanRequired(
+            "cached offset no longer matches the relayer's tree".to_string(),
+        ));
     }
+    storage.set(&tree_key, &format!("{}:{}", state.root, state.next_index));
 
-    // Store valid encrypted outputs
-    let unique_strings: Vec<_> = valid_strings
-        .into_iter()
-        .collect::<std::collections::HashSet<_>>()
-        .into_iter()
-        .collect();
+    if stale {
+        storage.remove(&offset_key);
+        storage.remove(&outputs_key);
+        return Ok(0);
+    }
 
-    storage.set(
-        &format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key),
-        &serde_json::to_string(&unique_strings).unwrap_or_default(),
-    );
+    Ok(offset)
+}
 
-    Ok(valid_utxos)
+/// Advance the cached fetch offset unless another scan moved it first
+///
+/// `stored` is the value this scan last read or wrote. If a concurrent scan of
+/// the same wallet changed the offset since, the write is skipped so the two
+/// scans cannot regress each other's progress; `stored` is left as is, so the
+/// rest of this scan leaves the offset to the other one.
+pub(crate) fn advance_fetch_offset(
+    storage: &Storage,
+    offset_key: &str,
+    stored: &mut Option<String>,
+    next_offset: u64,
+) {
+    let next = next_offset.to_string();
+    if storage.cas(offset_key, stored.as_deref(), &next) {
+        *stored = Some(next);
+    } else {
+        log::debug!("{} was moved by a concurrent scan, not overwriting it", offset_key);
+    }
+}
+
+/// Fail with `Aborted` if the abort signal has been raised
+pub(crate) async fn check_abort(abort_signal: &Option<Arc<Mutex<bool>>>) -> Result<()> {
+    if let Some(signal) = abort_signal {
+        if *signal.lock().await {
+            return Err(PrivacyCashError::Aborted);
+        }
+    }
+    Ok(())
 }
 
 /// Fetch UTXOs from API and decrypt
@@ -161,27 +478,51 @@ async fn fetch_user_utxos(
     storage: &Storage,
     storage_key: &str,
 ) -> Result<(Vec<Utxo>, Vec<String>, bool, u64)> {
-    let response = reqwest::get(url)
+    let (encrypted_outputs, has_more) = fetch_encrypted_outputs(url).await?;
+
+    let len = encrypted_outputs.len() as u64;
+
+    // Decrypt outputs
+    let (utxos, decrypted_outputs) =
+        decrypt_outputs(&encrypted_outputs, encryption_service, None).await?;
+
+    // Also check cached outputs if no more to fetch
+    let mut all_utxos = utxos;
+    let mut all_outputs = decrypted_outputs;
+
+    if !has_more {
+        let cached_key = format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key);
+        if let Some(cached_outputs) = storage.get_outputs(&cached_key) {
+            let (cached_utxos, cached_decrypted) =
+                decrypt_outputs(&cached_outputs, encryption_service, None).await?;
+            all_utxos.extend(cached_utxos);
+            all_outputs.extend(cached_decrypted);
+        }
+    }
+
+    Ok((all_utxos, all_outputs, has_more, len))
+}
+
+/// Fetch one range of encrypted outputs from the API
+async fn fetch_encrypted_outputs(url: &str) -> Result<(Vec<String>, bool)> {
+    let response = transport().get_json(url)
         .await
         .map_err(|e| PrivacyCashError::ApiError(format!("Failed to fetch UTXOs: {}", e)))?;
 
-    if !response.status().is_success() {
+    if !response.is_success() {
+        record_relayer_error("/utxos/range", response.status);
         return Err(PrivacyCashError::ApiError(format!(
             "UTXO API returned status: {}",
-            response.status()
+            response.status
         )));
     }
 
-    let data: serde_json::Value = response
-        .json()
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("Failed to parse UTXOs: {}", e)))?;
+    let data: serde_json::Value = response.json_with_context("Failed to parse UTXOs")?;
 
-    let (encrypted_outputs, has_more, total) = if let Some(outputs) = data.get("encrypted_outputs") {
+    if let Some(outputs) = data.get("encrypted_outputs") {
         let outputs: Vec<String> = serde_json::from_value(outputs.clone()).unwrap_or_default();
         let has_more = data.get("hasMore").and_then(|v| v.as_bool()).unwrap_or(false);
-        let total = data.get("total").and_then(|v| v.as_u64()).unwrap_or(0);
-        (outputs, has_more, total)
+        Ok((outputs, has_more))
     } else if data.is_array() {
         let utxos: Vec<ApiUtxo> = serde_json::from_value(data.clone()).unwrap_or_default();
         let outputs: Vec<String> = utxos
@@ -194,34 +535,10 @@ async fn fetch_user_utxos(
                 }
             })
             .collect();
-        let len = outputs.len() as u64;
-        (outputs, false, len)
+        Ok((outputs, false))
     } else {
-        return Err(PrivacyCashError::ApiError("Unexpected API response format".to_string()));
-    };
-
-    let len = encrypted_outputs.len() as u64;
-
-    // Decrypt outputs
-    let (utxos, decrypted_outputs) =
-        decrypt_outputs(&encrypted_outputs, encryption_service, None).await?;
-
-    // Also check cached outputs if no more to fetch
-    let mut all_utxos = utxos;
-    let mut all_outputs = decrypted_outputs;
-
-    if !has_more {
-        if let Some(cached) = storage.get(&format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key)) {
-            if let Ok(cached_outputs) = serde_json::from_str::<Vec<String>>(&cached) {
-                let (cached_utxos, cached_decrypted) =
-                    decrypt_outputs(&cached_outputs, encryption_service, None).await?;
-                all_utxos.extend(cached_utxos);
-                all_outputs.extend(cached_decrypted);
-            }
-        }
+        Err(PrivacyCashError::ApiError("Unexpected API response format".to_string()))
     }
-
-    Ok((all_utxos, all_outputs, has_more, len))
 }
 
 /// Decrypt encrypted outputs
@@ -238,15 +555,9 @@ async fn decrypt_outputs(
             continue;
         }
 
-        match encryption_service.decrypt_utxo_from_hex(encrypted) {
-            Ok(utxo) => {
-                utxos.push(utxo);
-                outputs.push(encrypted.clone());
-            }
-            Err(_) => {
-                // UTXO doesn't belong to this user, skip
-                continue;
-            }
+        if let Some(utxo) = decrypt_own_output(encryption_service, encrypted) {
+            utxos.push(utxo);
+            outputs.push(encrypted.clone());
         }
     }
 
@@ -264,6 +575,33 @@ async fn decrypt_outputs(
     Ok((utxos, outputs))
 }
 
+/// Decrypt an output if it is one of our notes
+///
+/// Foreign outputs are skipped silently. Outputs that look like a damaged
+/// note of ours are skipped with a warning, so a lost note can be tracked down.
+pub(crate) fn decrypt_own_output(
+    encryption_service: &EncryptionService,
+    encrypted: &str,
+) -> Option<Utxo> {
+    let Ok(data) = hex::decode(encrypted) else {
+        log::debug!("Skipping encrypted output that is not hex");
+        return None;
+    };
+    match encryption_service.classify_decrypt_utxo(&data) {
+        Ok(utxo) => Some(utxo),
+        Err(failure) if failure.is_suspicious() => {
+            let excerpt = &encrypted[..encrypted.len().min(64)];
+            log::warn!(
+                "Encrypted output {} may be a damaged note of this wallet ({:?}), skipping it",
+                excerpt,
+                failure
+            );
+            None
+        }
+        Err(_) => None,
+    }
+}
+
 /// Fetch UTXO indices from API
 async fn fetch_utxo_indices(encrypted_outputs: &[String], token_name: Option<&str>) -> Result<Vec<u64>> {
     let mut url = format!("{}/utxos/indices", *RELAYER_API_URL);
@@ -279,52 +617,132 @@ async fn fetch_utxo_indices(encrypted_outputs: &[String], token_name: Option<&st
         })
     };
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post(&url)
-        .json(&body)
-        .send()
+    let response = transport()
+        .post_json(&url, &body)
         .await
         .map_err(|e| PrivacyCashError::ApiError(format!("Failed to fetch indices: {}", e)))?;
 
-    if !response.status().is_success() {
+    if !response.is_success() {
+        record_relayer_error("/utxos/indices", response.status);
         return Err(PrivacyCashError::ApiError(format!(
             "Indices API returned status: {}",
-            response.status()
+            response.status
         )));
     }
 
-    let data: IndicesResponse = response
-        .json()
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("Failed to parse indices: {}", e)))?;
+    let data: IndicesResponse = response.json_with_context("Failed to parse indices")?;
 
-    Ok(data.indices)
+    check_indices_len(encrypted_outputs.len(), data.indices)
 }
 
-/// Check if UTXOs are spent
-async fn are_utxos_spent(connection: &RpcClient, utxos: &[Utxo]) -> Result<Vec<bool>> {
+/// Reject an indices response that does not have one index per output
+///
+/// The indices are matched to the outputs by position, so a shorter or
+/// longer list would leave notes with wrong indices and invalid proofs.
+pub(crate) fn check_indices_len(outputs: usize, indices: Vec<u64>) -> Result<Vec<u64>> {
+    if indices.len() != outputs {
+        return Err(PrivacyCashError::ApiError(format!(
+            "Indices API returned {} indices for {} encrypted outputs",
+            indices.len(),
+            outputs
+        )));
+    }
+    Ok(indices)
+}
+
+/// Check if UTXOs are spent at the connection's commitment level
+pub(crate) async fn are_utxos_spent(connection: &RpcClient, utxos: &[Utxo]) -> Result<Vec<bool>> {
+    are_utxos_spent_with_commitment(connection, utxos, connection.commitment()).await
+}
+
+/// Check if UTXOs are spent, skipping nullifiers cached as spent
+///
+/// A spent nullifier never becomes unspent once finalized, so those are
+/// cached under `storage_key` and not looked up again. Only unknown notes hit
+/// the RPC; notes it reports spent are re-checked at `finalized` before being
+/// cached, so a spend that is later reorged away is never cached.
+pub(crate) async fn are_utxos_spent_cached(
+    connection: &RpcClient,
+    utxos: &[Utxo],
+    storage: &Storage,
+    storage_key: &str,
+) -> Result<Vec<bool>> {
+    let cache_key = format!("{}{}", LSK_SPENT_NULLIFIERS, storage_key);
+    let mut known_spent: HashSet<String> = storage
+        .get(&cache_key)
+        .map(|cached| cached.lines().map(str::to_string).collect())
+        .unwrap_or_default();
+
+    let nullifiers = utxos.iter().map(Utxo::get_nullifier).collect::<Result<Vec<_>>>()?;
+    let mut spent_flags: Vec<bool> = nullifiers.iter().map(|n| known_spent.contains(n)).collect();
+
+    let (unknown_indices, unknown): (Vec<usize>, Vec<Utxo>) = utxos
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !spent_flags[*i])
+        .map(|(i, utxo)| (i, utxo.clone()))
+        .unzip();
+    if unknown.is_empty() {
+        return Ok(spent_flags);
+    }
+
+    let flags = are_utxos_spent(connection, &unknown).await?;
+    let (newly_spent_indices, newly_spent): (Vec<usize>, Vec<Utxo>) = unknown_indices
+        .into_iter()
+        .zip(unknown)
+        .zip(flags)
+        .filter(|(_, spent)| *spent)
+        .map(|(entry, _)| entry)
+        .unzip();
+    if newly_spent.is_empty() {
+        return Ok(spent_flags);
+    }
+
+    let finalized = if connection.commitment().is_finalized() {
+        vec![true; newly_spent.len()]
+    } else {
+        are_utxos_spent_with_commitment(connection, &newly_spent, CommitmentConfig::finalized())
+            .await?
+    };
+    for (i, finalized) in newly_spent_indices.into_iter().zip(finalized) {
+        spent_flags[i] = true;
+        if finalized {
+            known_spent.insert(nullifiers[i].clone());
+        }
+    }
+
+    let mut cached: Vec<&str> = known_spent.iter().map(String::as_str).collect();
+    cached.sort_unstable();
+    storage.set(&cache_key, &cached.join("\n"));
+    Ok(spent_flags)
+}
+
+/// Check if UTXOs are spent, reading nullifier accounts at `commitment`
+///
+/// `confirmed` is fast enough for display; a spend seen only at `confirmed`
+/// or `processed` can still be rolled back, while one seen at `finalized` is
+/// permanent.
+pub async fn are_utxos_spent_with_commitment(
+    connection: &RpcClient,
+    utxos: &[Utxo],
+    commitment: CommitmentConfig,
+) -> Result<Vec<bool>> {
     let mut all_pdas = Vec::new();
 
     for (i, utxo) in utxos.iter().enumerate() {
         let nullifier = utxo.get_nullifier()?;
-        let nullifier_bytes = string_to_nullifier_bytes(&nullifier)?;
-
-        let (nullifier0_pda, _) =
-            Pubkey::find_program_address(&[b"nullifier0", &nullifier_bytes], &PROGRAM_ID);
-        let (nullifier1_pda, _) =
-            Pubkey::find_program_address(&[b"nullifier1", &nullifier_bytes], &PROGRAM_ID);
-
-        all_pdas.push((i, nullifier0_pda));
-        all_pdas.push((i, nullifier1_pda));
+        for pda in NullifierBytes::from_decimal(&nullifier)?.spent_markers() {
+            all_pdas.push((i, pda));
+        }
     }
 
     // Batch fetch account info
     let pubkeys: Vec<Pubkey> = all_pdas.iter().map(|(_, p)| *p).collect();
 
     let accounts = connection
-        .get_multiple_accounts(&pubkeys)
-        .map_err(|e| PrivacyCashError::SolanaClientError(e))?;
+        .get_multiple_accounts_with_commitment(&pubkeys, commitment)
+        .map_err(|e| PrivacyCashError::SolanaClientError(e))?
+        .value;
 
     let mut spent_flags = vec![false; utxos.len()];
 
@@ -337,25 +755,86 @@ async fn are_utxos_spent(connection: &RpcClient, utxos: &[Utxo]) -> Result<Vec<b
     Ok(spent_flags)
 }
 
+/// Fail with `InputSpent` if any real input has a finalized spend
+///
+/// Run before proving, since inputs can come from the caller or from a scan
+/// that is already out of date. A note whose spend is finalized can never be
+/// spent again, so this fails fast instead of after proving, when the
+/// program would reject the transaction anyway. A spend that is not yet
+/// finalized is not seen here; the program's nullifier check still rejects
+/// the transaction in that case. Dummy inputs are skipped.
+pub async fn ensure_unspent_finalized(connection: &RpcClient, inputs: &[Utxo]) -> Result<()> {
+    let real: Vec<Utxo> = inputs.iter().filter(|u| !u.is_dummy()).cloned().collect();
+    if real.is_empty() {
+        return Ok(());
+    }
+
+    let spent = are_utxos_spent_with_commitment(connection, &real, CommitmentConfig::finalized())
+        .await?;
+    match real.iter().zip(spent).find(|(_, spent)| *spent) {
+        Some((utxo, _)) => Err(PrivacyCashError::InputSpent(utxo.get_commitment()?)),
+        None => Ok(()),
+    }
+}
+
 /// Check if a single UTXO is spent
 pub async fn is_utxo_spent(connection: &RpcClient, utxo: &Utxo) -> Result<bool> {
     let result = are_utxos_spent(connection, &[utxo.clone()]).await?;
     Ok(result.first().copied().unwrap_or(false))
 }
 
-/// Convert nullifier string to bytes
-fn string_to_nullifier_bytes(nullifier: &str) -> Result<[u8; 32]> {
-    let n = BigUint::parse_bytes(nullifier.as_bytes(), 10)
-        .ok_or_else(|| PrivacyCashError::SerializationError("Invalid nullifier".to_string()))?;
+/// Scan the full history and report every owned note with its spend status
+///
+/// Unlike `get_utxos`, this always starts from offset 0 and does not touch the
+/// cache, since spent notes are never cached. Spend status is resolved with a
+/// single batched nullifier lookup once the scan completes.
+pub async fn get_utxo_details(
+    connection: &RpcClient,
+    encryption_service: &EncryptionService,
+) -> Result<Vec<UtxoDetail>> {
+    let mut utxos = Vec::new();
+    let mut fetch_offset: u64 = 0;
+
+    loop {
+        let url = format!(
+            "{}/utxos/range?start={}&end={}",
+            *RELAYER_API_URL,
+            fetch_offset,
+            fetch_offset + FETCH_UTXOS_GROUP_SIZE
+        );
+
+        let (encrypted_outputs, has_more) = fetch_encrypted_outputs(&url).await?;
+        let (fetched_utxos, _) = decrypt_outputs(&encrypted_outputs, encryption_service, None).await?;
+        utxos.extend(fetched_utxos.into_iter().filter(|u| !u.is_dummy()));
+
+        fetch_offset += encrypted_outputs.len() as u64;
+        if !has_more {
+            break;
+        }
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+    }
+
+    if utxos.is_empty() {
+        return Ok(Vec::new());
+    }
 
-    let bytes = n.to_bytes_le();
-    let mut result = [0u8; 32];
-    let len = bytes.len().min(32);
-    result[..len].copy_from_slice(&bytes[..len]);
+    let spent_flags = are_utxos_spent(connection, &utxos).await?;
 
-    // Reverse for on-chain format
-    result.reverse();
-    Ok(result)
+    utxos
+        .iter()
+        .zip(spent_flags)
+        .map(|(utxo, spent)| {
+            Ok(UtxoDetail {
+                amount: utxo.amount_u64(),
+                index: utxo.index,
+                commitment: utxo.get_commitment()?,
+                mint_address: utxo.mint_address.clone(),
+                version: utxo.version,
+                spent,
+            })
+        })
+        .collect()
 }
 
 /// Get private balance from UTXOs
@@ -368,3 +847,216 @@ pub async fn get_private_balance(
     let utxos = get_utxos(connection, public_key, encryption_service, storage, None).await?;
     Ok(get_balance_from_utxos(&utxos))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{CannedTransport, TEST_HOOKS_LOCK};
+
+    const EMPTY_RANGE: &str = r#"{"count":0,"encrypted_outputs":[],"hasMore":false}"#;
+
+    /// Reports a five-leaf tree holding no notes of ours
+    fn small_tree() -> CannedTransport {
+        CannedTransport::default()
+            .route("/utxos/range", EMPTY_RANGE)
+            .route("/merkle/root", r#"{"root":"9","nextIndex":5}"#)
+    }
+
+    /// Serves one encrypted output at index 0
+    fn one_output(output: &str) -> CannedTransport {
+        let range = serde_json::json!({"encrypted_outputs": [output], "hasMore": false});
+        CannedTransport::default()
+            .route("/utxos/range", range.to_string())
+            .route("/merkle/root", r#"{"root":"9","nextIndex":1}"#)
+            .route("/utxos/indices", r#"{"indices":[0]}"#)
+    }
+
+    #[tokio::test]
+    async fn test_indices_length_mismatch() {
+        let _guard = TEST_HOOKS_LOCK.lock().await;
+        let _transport =
+            CannedTransport::default().route("/utxos/indices", r#"{"indices":[4,5,6]}"#).install();
+        let outputs = |n: usize| vec!["aa".to_string(); n];
+        let two = fetch_utxo_indices(&outputs(2), None).await;
+        let three = fetch_utxo_indices(&outputs(3), None).await;
+        let four = fetch_utxo_indices(&outputs(4), Some("usdc")).await;
+
+        assert!(matches!(
+            two,
+            Err(PrivacyCashError::ApiError(ref e)) if e.contains("3 indices for 2")
+        ));
+        assert_eq!(three.unwrap(), vec![4, 5, 6]);
+        assert!(four.is_err());
+    }
+
+    #[test]
+    fn test_concurrent_offset_advance() {
+        let storage = Storage::memory();
+        let mut first = storage.get("offset");
+        let mut second = storage.get("offset");
+
+        advance_fetch_offset(&storage, "offset", &mut first, 20_000);
+        advance_fetch_offset(&storage, "offset", &mut first, 40_000);
+        // The slower scan must not move the offset back
+        advance_fetch_offset(&storage, "offset", &mut second, 20_000);
+        assert_eq!(storage.get("offset").as_deref(), Some("40000"));
+        assert_eq!(second, None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_finalized_check_skips_dummies() {
+        // Unreachable RPC: only dummy inputs, so no request may be made
+        let connection = RpcClient::new("http://127.0.0.1:1".to_string());
+        let keypair = crate::keypair::ZkKeypair::generate().unwrap();
+        let dummies = [Utxo::dummy(keypair.clone(), None), Utxo::dummy(keypair.clone(), None)];
+        assert!(ensure_unspent_finalized(&connection, &dummies).await.is_ok());
+
+        let note = Utxo::new(5u64, keypair, 0, None, None);
+        assert!(ensure_unspent_finalized(&connection, &[note]).await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_cached_spent_nullifiers_skip_rpc() {
+        // Unreachable RPC: notes cached as spent must not be looked up
+        let connection = RpcClient::new("http://127.0.0.1:1".to_string());
+        let storage = Storage::memory();
+        let keypair = crate::keypair::ZkKeypair::generate().unwrap();
+        let notes: Vec<Utxo> =
+            (0..2).map(|i| Utxo::new(5u64, keypair.clone(), i, None, None)).collect();
+        let cache_key = format!("{}{}", LSK_SPENT_NULLIFIERS, "key");
+        let nullifiers: Vec<String> = notes.iter().map(|n| n.get_nullifier().unwrap()).collect();
+        storage.set(&cache_key, &nullifiers.join("\n"));
+
+        let spent = are_utxos_spent_cached(&connection, &notes, &storage, "key").await.unwrap();
+        assert_eq!(spent, vec![true, true]);
+
+        storage.set(&cache_key, &nullifiers[0]);
+        assert!(are_utxos_spent_cached(&connection, &notes, &storage, "key").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_incremental_only_scan() {
+        let storage = Storage::memory();
+        let key = localstorage_key(&Pubkey::new_unique());
+        let offset_key = format!("{}{}", LSK_FETCH_OFFSET, key);
+
+        let explicit = load_fetch_offset(&storage, &key, None, ScanOptions::from_offset(7)).await;
+        assert_eq!(explicit.unwrap(), 7);
+        let fresh = load_fetch_offset(&storage, &key, None, ScanOptions::incremental()).await;
+        assert!(matches!(fresh, Err(PrivacyCashError::RescanRequired(_))));
+
+        storage.set(&offset_key, "10");
+        let _guard = TEST_HOOKS_LOCK.lock().await;
+        let _transport = small_tree().install();
+        let stale = load_fetch_offset(&storage, &key, None, ScanOptions::incremental()).await;
+        let reset = load_fetch_offset(&storage, &key, None, ScanOptions::default()).await;
+
+        assert!(matches!(stale, Err(PrivacyCashError::RescanRequired(_))));
+        assert_eq!(reset.unwrap(), 0);
+        assert_eq!(storage.get(&offset_key), None);
+    }
+
+    #[tokio::test]
+    async fn test_fresh_scan_leaves_cache() {
+        let connection = RpcClient::new("http://127.0.0.1:8899".to_string());
+        let public_key = Pubkey::new_unique();
+        let key = localstorage_key(&public_key);
+        let offset_key = format!("{}{}", LSK_FETCH_OFFSET, key);
+        let storage = Storage::memory();
+        storage.set(&offset_key, "4");
+
+        let _guard = TEST_HOOKS_LOCK.lock().await;
+        let _transport = small_tree().install();
+        let utxos = get_utxos_with_options(
+            &connection,
+            &public_key,
+            &EncryptionService::new(),
+            &storage,
+            None,
+            ScanOptions::fresh(),
+        )
+        .await;
+
+        assert!(utxos.unwrap().is_empty());
+        assert_eq!(storage.get(&offset_key).as_deref(), Some("4"));
+        assert_eq!(storage.get(&format!("{}{}", LSK_TREE_STATE, key)), None);
+        assert_eq!(storage.get_outputs(&format!("{}{}", LSK_ENCRYPTED_OUTPUTS, key)), None);
+    }
+
+    #[tokio::test]
+    async fn test_unreadable_outputs_force_rescan() {
+        let storage = Storage::memory();
+        let key = localstorage_key(&Pubkey::new_unique());
+        let offset_key = format!("{}{}", LSK_FETCH_OFFSET, key);
+        let outputs_key = format!("{}{}", LSK_ENCRYPTED_OUTPUTS, key);
+        storage.set(&offset_key, "4");
+        storage.set(&outputs_key, r#"{"version":1,"outputs":["ab"]}"#);
+
+        let _guard = TEST_HOOKS_LOCK.lock().await;
+        let _transport = small_tree().install();
+        let readable = load_fetch_offset(&storage, &key, None, ScanOptions::default()).await;
+        storage.set(&outputs_key, r#"{"version":99,"outputs":["ab"]}"#);
+        let newer = load_fetch_offset(&storage, &key, None, ScanOptions::incremental()).await;
+        let reset = load_fetch_offset(&storage, &key, None, ScanOptions::default()).await;
+
+        assert_eq!(readable.unwrap(), 4);
+        assert!(matches!(newer, Err(PrivacyCashError::RescanRequired(_))));
+        assert_eq!(reset.unwrap(), 0);
+        assert_eq!(storage.get(&offset_key), None);
+        assert_eq!(storage.get(&outputs_key), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_optimistic_scan_skips_spent_check() {
+        // Unreachable RPC: the optimistic scan must not check nullifiers
+        let connection = RpcClient::new("http://127.0.0.1:1".to_string());
+        let public_key = Pubkey::new_unique();
+        let mut service = EncryptionService::new();
+        service.derive_encryption_key_from_wallet(&solana_sdk::signature::Keypair::new()).unwrap();
+        let owner_key = service.get_utxo_private_key_v2().unwrap();
+        let owner = crate::keypair::ZkKeypair::from_hex(&owner_key).unwrap();
+        let note = Utxo::new(5u64, owner, 0, None, Some(crate::utxo::UtxoVersion::V2));
+        let output = hex::encode(service.encrypt_utxo(&note).unwrap());
+        let storage = Storage::memory();
+        let key = localstorage_key(&public_key);
+
+        let _guard = TEST_HOOKS_LOCK.lock().await;
+        let _transport = one_output(&output).install();
+        let scan = |options| {
+            get_utxos_with_options(&connection, &public_key, &service, &storage, None, options)
+        };
+        let optimistic = scan(ScanOptions::optimistic()).await;
+        let checked = scan(ScanOptions::default()).await;
+
+        let utxos = optimistic.unwrap();
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].amount_u64(), 5);
+        assert!(checked.is_err());
+        assert_eq!(storage.get(&format!("{}{}", LSK_FETCH_OFFSET, key)), None);
+        assert_eq!(storage.get_outputs(&format!("{}{}", LSK_ENCRYPTED_OUTPUTS, key)), None);
+    }
+
+    #[tokio::test]
+    async fn test_scan_range() {
+        let mut service = EncryptionService::new();
+        service.derive_encryption_key_from_wallet(&solana_sdk::signature::Keypair::new()).unwrap();
+        let owner_key = service.get_utxo_private_key_v2().unwrap();
+        let owner = crate::keypair::ZkKeypair::from_hex(&owner_key).unwrap();
+        let note = Utxo::new(9u64, owner, 0, None, Some(crate::utxo::UtxoVersion::V2));
+        let output = hex::encode(service.encrypt_utxo(&note).unwrap());
+
+        let _guard = TEST_HOOKS_LOCK.lock().await;
+        let _transport = one_output(&output).install();
+        let found = scan_range(&service, 0, 10, None).await;
+        let empty = scan_range(&service, 5, 5, None).await;
+
+        let found = found.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].get_commitment().unwrap(), note.get_commitment().unwrap());
+        assert!(empty.unwrap().is_empty());
+        assert!(matches!(
+            scan_range(&service, 10, 5, None).await,
+            Err(PrivacyCashError::InvalidInput(_))
+        ));
+    }
+}
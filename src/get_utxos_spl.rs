@@ -1,15 +1,20 @@
 //! UTXO fetching and management for SPL tokens
 
+use crate::clock::clock;
 use crate::constants::{
     find_token_by_mint, FETCH_UTXOS_GROUP_SIZE, LSK_ENCRYPTED_OUTPUTS, LSK_FETCH_OFFSET,
-    PROGRAM_ID, RELAYER_API_URL,
+    RELAYER_API_URL,
 };
 use crate::encryption::EncryptionService;
 use crate::error::{PrivacyCashError, Result};
-use crate::get_utxos::localstorage_key;
+use crate::get_utxos::{
+    advance_fetch_offset, are_utxos_spent_cached, check_abort, check_indices_len,
+    decrypt_own_output, load_fetch_offset, localstorage_key, ScanOptions,
+};
+use crate::http::transport;
+use crate::metrics::{metrics, record_relayer_error, SCAN_SECONDS};
 use crate::storage::Storage;
-use crate::utxo::{get_balance_from_utxos_spl, SplBalance, Utxo};
-use num_bigint::BigUint;
+use crate::utxo::{dedup_by_commitment, get_balance_from_utxos_spl, SplBalance, Utxo};
 use serde::Deserialize;
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::pubkey::Pubkey;
@@ -18,6 +23,8 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 
 /// Fetch all SPL token UTXOs for a user
+///
+/// Pages are pipelined the same way as [`get_utxos`](crate::get_utxos::get_utxos).
 pub async fn get_utxos_spl(
     connection: &RpcClient,
     public_key: &Pubkey,
@@ -26,10 +33,35 @@ pub async fn get_utxos_spl(
     mint_address: &Pubkey,
     abort_signal: Option<Arc<Mutex<bool>>>,
 ) -> Result<Vec<Utxo>> {
+    get_utxos_spl_with_options(
+        connection,
+        public_key,
+        encryption_service,
+        storage,
+        mint_address,
+        abort_signal,
+        ScanOptions::default(),
+    )
+    .await
+}
+
+/// Fetch SPL token UTXOs for a user with an explicit scan range
+pub async fn get_utxos_spl_with_options(
+    connection: &RpcClient,
+    public_key: &Pubkey,
+    encryption_service: &EncryptionService,
+    storage: &Storage,
+    mint_address: &Pubkey,
+    abort_signal: Option<Arc<Mutex<bool>>>,
+    options: ScanOptions,
+) -> Result<Vec<Utxo>> {
+    let scratch = Storage::memory();
+    let storage = options.scan_storage(storage, &scratch);
     let token = find_token_by_mint(mint_address)
         .ok_or_else(|| PrivacyCashError::TokenNotSupported(mint_address.to_string()))?;
 
     log::debug!("Fetching UTXOs for token: {}", token.name);
+    let started = clock().now();
 
     // Get associated token address
     let ata = get_associated_token_address(public_key, mint_address);
@@ -38,98 +70,106 @@ pub async fn get_utxos_spl(
     let mut valid_utxos = Vec::new();
     let mut valid_strings = Vec::new();
 
-    // Get starting offset from storage
-    let round_start_index: u64 = storage
-        .get(&format!("{}{}", LSK_FETCH_OFFSET, storage_key))
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(0);
-
-    loop {
-        // Check for abort
-        if let Some(ref signal) = abort_signal {
-            if *signal.lock().await {
-                return Err(PrivacyCashError::Aborted);
-            }
+    let offset_key = format!("{}{}", LSK_FETCH_OFFSET, storage_key);
+    let range_url = |offset: u64| {
+        format!(
+            "{}/utxos/range?token={}&start={}&end={}",
+            *RELAYER_API_URL,
+            token.name,
+            offset,
+            offset + FETCH_UTXOS_GROUP_SIZE
+        )
+    };
+    let storage_key_ref = storage_key.as_str();
+    let fetch_page = |offset: u64| {
+        let url = range_url(offset);
+        async move {
+            log::debug!("Fetching SPL UTXOs from: {}", url);
+            fetch_user_utxos_spl(&url, encryption_service, storage, storage_key_ref, token.name)
+                .await
         }
+    };
 
-        let fetch_offset: u64 = storage
-            .get(&format!("{}{}", LSK_FETCH_OFFSET, storage_key))
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(0)
-            .max(round_start_index);
+    let mint_str = mint_address.to_string();
 
-        let fetch_end = fetch_offset + FETCH_UTXOS_GROUP_SIZE;
-        let url = format!(
-            "{}/utxos/range?token={}&start={}&end={}",
-            *RELAYER_API_URL, token.name, fetch_offset, fetch_end
-        );
+    // Get starting offset from storage
+    let mut fetch_offset =
+        load_fetch_offset(storage, &storage_key, Some(token.name), options).await?;
+    let mut stored_offset = storage.get(&offset_key);
 
-        log::debug!("Fetching SPL UTXOs from: {}", url);
+    check_abort(&abort_signal).await?;
+    let mut page = fetch_page(fetch_offset).await?;
 
-        let (fetched_utxos, encrypted_outputs, has_more, len) =
-            fetch_user_utxos_spl(&url, encryption_service, storage, &storage_key, token.name)
-                .await?;
+    loop {
+        let (fetched_utxos, encrypted_outputs, has_more, len) = page;
+        let next_offset = fetch_offset + len;
 
-        // Check which UTXOs are unspent
-        let non_zero_utxos: Vec<_> = fetched_utxos
-            .iter()
+        // Only spent-check non-zero notes of the requested mint
+        let (non_zero_indices, non_zero_utxos): (Vec<usize>, Vec<Utxo>) = fetched_utxos
+            .into_iter()
             .enumerate()
-            .filter(|(_, u)| u.amount_u64() > 0)
-            .collect();
-
-        if !non_zero_utxos.is_empty() {
-            let spent_flags = are_utxos_spent_spl(
-                connection,
-                &non_zero_utxos
-                    .iter()
-                    .map(|(_, u)| (*u).clone())
-                    .collect::<Vec<_>>(),
-            )
-            .await?;
+            .filter(|(_, u)| u.amount_u64() > 0 && u.mint_address == mint_str)
+            .unzip();
+
+        let spent_check = async {
+            if non_zero_utxos.is_empty() {
+                Ok(Vec::new())
+            } else if options.skip_spent_check {
+                Ok(vec![false; non_zero_utxos.len()])
+            } else {
+                are_utxos_spent_cached(connection, &non_zero_utxos, storage, &storage_key).await
+            }
+        };
 
-            for ((idx, utxo), is_spent) in non_zero_utxos.into_iter().zip(spent_flags) {
-                if !is_spent {
-                    log::debug!("Found unspent SPL UTXO: {:?}", encrypted_outputs.get(idx));
-                    valid_utxos.push(utxo.clone());
-                    if let Some(enc) = encrypted_outputs.get(idx) {
-                        valid_strings.push(enc.clone());
-                    }
+        // Fetch and decrypt the next page while this one is spent-checked
+        let (spent_flags, next_page) = if has_more {
+            let next_fetch = async {
+                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                check_abort(&abort_signal).await?;
+                fetch_page(next_offset).await
+            };
+            let (spent_flags, next_page) = tokio::join!(spent_check, next_fetch);
+            (spent_flags?, Some(next_page))
+        } else {
+            (spent_check.await?, None)
+        };
+
+        let checked = non_zero_indices.into_iter().zip(non_zero_utxos).zip(spent_flags);
+        for ((idx, utxo), is_spent) in checked {
+            if !is_spent {
+                log::debug!("Found unspent SPL UTXO: {:?}", encrypted_outputs.get(idx));
+                valid_utxos.push(utxo);
+                if let Some(enc) = encrypted_outputs.get(idx) {
+                    valid_strings.push(enc.clone());
                 }
             }
         }
 
-        // Update storage offset
-        storage.set(
-            &format!("{}{}", LSK_FETCH_OFFSET, storage_key),
-            &(fetch_offset + len).to_string(),
-        );
+        // Update storage offset; unchecked notes are never cached
+        if !options.skip_spent_check {
+            advance_fetch_offset(storage, &offset_key, &mut stored_offset, next_offset);
+        }
 
-        if !has_more {
-            break;
+        match next_page {
+            Some(next_page) => {
+                page = next_page?;
+                fetch_offset = next_offset;
+            }
+            None => break,
         }
+    }
 
-        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+    // Store valid encrypted outputs, sorted so unchanged caches are not rewritten
+    valid_strings.sort_unstable();
+    valid_strings.dedup();
+
+    if !options.skip_spent_check {
+        storage.set_outputs(&format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key), &valid_strings);
     }
 
-    // Store valid encrypted outputs
-    let unique_strings: Vec<_> = valid_strings
-        .into_iter()
-        .collect::<std::collections::HashSet<_>>()
-        .into_iter()
-        .collect();
-
-    storage.set(
-        &format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key),
-        &serde_json::to_string(&unique_strings).unwrap_or_default(),
-    );
-
-    // Filter UTXOs to only include those matching the mint address
-    let filtered_utxos: Vec<_> = valid_utxos
-        .into_iter()
-        .filter(|u| u.mint_address == mint_address.to_string())
-        .collect();
-
-    Ok(filtered_utxos)
+    let elapsed = clock().now().duration_since(started).as_secs_f64();
+    metrics().observe_histogram(SCAN_SECONDS, elapsed, &[("token", token.name)]);
+    Ok(dedup_by_commitment(valid_utxos))
 }
 
 /// Fetch SPL UTXOs from API and decrypt
@@ -140,21 +180,19 @@ async fn fetch_user_utxos_spl(
     storage_key: &str,
     token_name: &str,
 ) -> Result<(Vec<Utxo>, Vec<String>, bool, u64)> {
-    let response = reqwest::get(url)
+    let response = transport().get_json(url)
         .await
         .map_err(|e| PrivacyCashError::ApiError(format!("Failed to fetch SPL UTXOs: {}", e)))?;
 
-    if !response.status().is_success() {
+    if !response.is_success() {
+        record_relayer_error("/utxos/range", response.status);
         return Err(PrivacyCashError::ApiError(format!(
             "SPL UTXO API returned status: {}",
-            response.status()
+            response.status
         )));
     }
 
-    let data: serde_json::Value = response
-        .json()
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("Failed to parse SPL UTXOs: {}", e)))?;
+    let data: serde_json::Value = response.json_with_context("Failed to parse SPL UTXOs")?;
 
     let (encrypted_outputs, has_more, _total) =
         if let Some(outputs) = data.get("encrypted_outputs") {
@@ -201,13 +239,12 @@ async fn fetch_user_utxos_spl(
     let mut all_outputs = decrypted_outputs;
 
     if !has_more {
-        if let Some(cached) = storage.get(&format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key)) {
-            if let Ok(cached_outputs) = serde_json::from_str::<Vec<String>>(&cached) {
-                let (cached_utxos, cached_decrypted) =
-                    decrypt_outputs_spl(&cached_outputs, encryption_service, token_name).await?;
-                all_utxos.extend(cached_utxos);
-                all_outputs.extend(cached_decrypted);
-            }
+        let cached_key = format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key);
+        if let Some(cached_outputs) = storage.get_outputs(&cached_key) {
+            let (cached_utxos, cached_decrypted) =
+                decrypt_outputs_spl(&cached_outputs, encryption_service, token_name).await?;
+            all_utxos.extend(cached_utxos);
+            all_outputs.extend(cached_decrypted);
         }
     }
 
@@ -228,14 +265,9 @@ async fn decrypt_outputs_spl(
             continue;
         }
 
-        match encryption_service.decrypt_utxo_from_hex(encrypted) {
-            Ok(utxo) => {
-                utxos.push(utxo);
-                outputs.push(encrypted.clone());
-            }
-            Err(_) => {
-                continue;
-            }
+        if let Some(utxo) = decrypt_own_output(encryption_service, encrypted) {
+            utxos.push(utxo);
+            outputs.push(encrypted.clone());
         }
     }
 
@@ -262,18 +294,16 @@ async fn fetch_utxo_indices_spl(encrypted_outputs: &[String], token_name: &str)
         "token": token_name
     });
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post(&url)
-        .json(&body)
-        .send()
+    let response = transport()
+        .post_json(&url, &body)
         .await
         .map_err(|e| PrivacyCashError::ApiError(format!("Failed to fetch SPL indices: {}", e)))?;
 
-    if !response.status().is_success() {
+    if !response.is_success() {
+        record_relayer_error("/utxos/indices", response.status);
         return Err(PrivacyCashError::ApiError(format!(
             "SPL indices API returned status: {}",
-            response.status()
+            response.status
         )));
     }
 
@@ -282,59 +312,9 @@ async fn fetch_utxo_indices_spl(encrypted_outputs: &[String], token_name: &str)
         indices: Vec<u64>,
     }
 
-    let data: IndicesResponse = response
-        .json()
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("Failed to parse SPL indices: {}", e)))?;
-
-    Ok(data.indices)
-}
-
-/// Check if SPL UTXOs are spent
-async fn are_utxos_spent_spl(connection: &RpcClient, utxos: &[Utxo]) -> Result<Vec<bool>> {
-    let mut all_pdas = Vec::new();
-
-    for (i, utxo) in utxos.iter().enumerate() {
-        let nullifier = utxo.get_nullifier()?;
-        let nullifier_bytes = string_to_nullifier_bytes(&nullifier)?;
-
-        let (nullifier0_pda, _) =
-            Pubkey::find_program_address(&[b"nullifier0", &nullifier_bytes], &PROGRAM_ID);
-        let (nullifier1_pda, _) =
-            Pubkey::find_program_address(&[b"nullifier1", &nullifier_bytes], &PROGRAM_ID);
-
-        all_pdas.push((i, nullifier0_pda));
-        all_pdas.push((i, nullifier1_pda));
-    }
-
-    let pubkeys: Vec<Pubkey> = all_pdas.iter().map(|(_, p)| *p).collect();
-
-    let accounts = connection
-        .get_multiple_accounts(&pubkeys)
-        .map_err(|e| PrivacyCashError::SolanaClientError(e))?;
-
-    let mut spent_flags = vec![false; utxos.len()];
-
-    for ((utxo_idx, _), account) in all_pdas.iter().zip(accounts.iter()) {
-        if account.is_some() {
-            spent_flags[*utxo_idx] = true;
-        }
-    }
-
-    Ok(spent_flags)
-}
+    let data: IndicesResponse = response.json_with_context("Failed to parse SPL indices")?;
 
-/// Convert nullifier string to bytes
-fn string_to_nullifier_bytes(nullifier: &str) -> Result<[u8; 32]> {
-    let n = BigUint::parse_bytes(nullifier.as_bytes(), 10)
-        .ok_or_else(|| PrivacyCashError::SerializationError("Invalid nullifier".to_string()))?;
-
-    let bytes = n.to_bytes_le();
-    let mut result = [0u8; 32];
-    let len = bytes.len().min(32);
-    result[..len].copy_from_slice(&bytes[..len]);
-    result.reverse();
-    Ok(result)
+    check_indices_len(encrypted_outputs.len(), data.indices)
 }
 
 /// Get SPL private balance
@@ -352,5 +332,5 @@ pub async fn get_private_balance_spl(
         get_utxos_spl(connection, public_key, encryption_service, storage, mint_address, None)
             .await?;
 
-    Ok(get_balance_from_utxos_spl(&utxos, token.units_per_token))
+    Ok(get_balance_from_utxos_spl(&utxos, token.units_per_token).with_symbol(token.name))
 }
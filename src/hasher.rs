@@ -0,0 +1,142 @@
+//! Pluggable Poseidon hashing
+//!
+//! Keypair derivation, commitments, nullifiers and Merkle paths all hash
+//! through the process-wide [`PoseidonBackend`], which defaults to the native
+//! circom-compatible implementation in [`crate::poseidon`]. Install another
+//! backend with [`set_hasher`] to benchmark or cross-check an alternative
+//! implementation, such as an FFI binding.
+
+use crate::error::{PrivacyCashError, Result};
+use crate::poseidon::{Poseidon, PoseidonHasher};
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use num_bigint::BigUint;
+use once_cell::sync::OnceCell;
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+/// Poseidon(1, 2) as computed by circomlib
+pub(crate) const POSEIDON_1_2: &str =
+    "7853200120776062878684798364095072458815029376092732009249414926327459813530";
+
+/// Global backend used by all Poseidon hashing
+static HASHER: OnceCell<RwLock<Arc<dyn PoseidonBackend>>> = OnceCell::new();
+
+/// Poseidon hash over BN254 field elements
+pub trait PoseidonBackend: Send + Sync {
+    /// Hash 1 to 12 field elements
+    fn hash(&self, inputs: &[BigUint]) -> Result<BigUint>;
+}
+
+/// Native circom-compatible Poseidon with BN254 parameters
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NativePoseidon;
+
+impl PoseidonBackend for NativePoseidon {
+    fn hash(&self, inputs: &[BigUint]) -> Result<BigUint> {
+        let num_inputs = inputs.len();
+        if num_inputs == 0 || num_inputs > 12 {
+            return Err(PrivacyCashError::InvalidKeypair(
+                format!("Invalid number of inputs: {}. Must be 1-12.", num_inputs)
+            ));
+        }
+
+        // Convert BigUint inputs to Fr field elements
+        let fr_inputs: Vec<Fr> = inputs
+            .iter()
+            .map(|input| {
+                let bytes = input.to_bytes_be();
+                let mut padded = [0u8; 32];
+                let start = 32usize.saturating_sub(bytes.len());
+                let len = bytes.len().min(32);
+                padded[start..start + len].copy_from_slice(&bytes[..len]);
+                Fr::from_be_bytes_mod_order(&padded)
+            })
+            .collect();
+
+        // Create Poseidon hasher and compute hash
+        let mut poseidon = Poseidon::<Fr>::new_circom(num_inputs)
+            .map_err(|e| PrivacyCashError::InvalidKeypair(format!("Poseidon error: {:?}", e)))?;
+
+        let hash = poseidon.hash(&fr_inputs)
+            .map_err(|e| PrivacyCashError::InvalidKeypair(format!("Poseidon hash error: {:?}", e)))?;
+
+        // Convert Fr back to BigUint
+        let result_bytes = hash.into_bigint().to_bytes_be();
+        Ok(BigUint::from_bytes_be(&result_bytes))
+    }
+}
+
+fn hasher_cell() -> &'static RwLock<Arc<dyn PoseidonBackend>> {
+    HASHER.get_or_init(|| RwLock::new(Arc::new(NativePoseidon)))
+}
+
+/// Get the backend used for Poseidon hashing
+pub fn hasher() -> Arc<dyn PoseidonBackend> {
+    hasher_cell().read().clone()
+}
+
+/// Replace the backend used for Poseidon hashing
+///
+/// The backend must produce the same outputs as the circuit's Poseidon, or
+/// commitments and proofs will not verify. A backend that does not is still
+/// installed, e.g. for benchmarks, but a warning is logged.
+pub fn set_hasher(hasher: Arc<dyn PoseidonBackend>) {
+    if !matches_circomlib(hasher.as_ref()) {
+        log::warn!(
+            "Installed Poseidon backend does not match circomlib; the relayer and program \
+             will reject every transaction built with it"
+        );
+    }
+    *hasher_cell().write() = hasher;
+}
+
+/// Whether the active backend hashes like the circuit
+///
+/// Checks Poseidon(1, 2) against circomlib's value. When this is false,
+/// commitments and proofs are built but fail on the relayer or on chain.
+pub fn is_circuit_compatible() -> bool {
+    matches_circomlib(hasher().as_ref())
+}
+
+fn matches_circomlib(hasher: &dyn PoseidonBackend) -> bool {
+    hasher
+        .hash(&[BigUint::from(1u8), BigUint::from(2u8)])
+        .is_ok_and(|hash| hash.to_string() == POSEIDON_1_2)
+}
+
+/// Restore the native Poseidon backend
+pub fn reset_hasher() {
+    set_hasher(Arc::new(NativePoseidon));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_native_poseidon_backend() {
+        let inputs = [BigUint::from(1u8), BigUint::from(2u8)];
+        assert_eq!(
+            NativePoseidon.hash(&inputs).unwrap(),
+            hasher().hash(&inputs).unwrap()
+        );
+        assert!(NativePoseidon.hash(&[]).is_err());
+        assert!(NativePoseidon.hash(&vec![BigUint::from(1u8); 13]).is_err());
+    }
+
+    /// Sum of the inputs, nothing like Poseidon
+    struct SumHasher;
+
+    impl PoseidonBackend for SumHasher {
+        fn hash(&self, inputs: &[BigUint]) -> Result<BigUint> {
+            Ok(inputs.iter().sum())
+        }
+    }
+
+    #[test]
+    fn test_circuit_compatibility() {
+        assert!(matches_circomlib(&NativePoseidon));
+        assert!(!matches_circomlib(&SumHasher));
+    }
+}
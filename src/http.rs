@@ -0,0 +1,448 @@
+//! HTTP transport for relayer API calls
+//!
+//! Every relayer request goes through the process-wide [`HttpTransport`],
+//! which defaults to a `reqwest` implementation. Install another transport
+//! with [`set_transport`] to proxy requests, or with [`scoped_transport`] to
+//! serve canned responses for the length of a test.
+
+use crate::error::{PrivacyCashError, Result};
+use once_cell::sync::OnceCell;
+use parking_lot::RwLock;
+use reqwest::header::HeaderMap;
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Longest body excerpt included in parse errors
+const BODY_SNIPPET_LEN: usize = 200;
+
+/// Global transport used by all relayer calls
+static TRANSPORT: OnceCell<RwLock<Arc<dyn HttpTransport>>> = OnceCell::new();
+
+/// Future returned by transport methods
+pub type HttpFuture<'a> = Pin<Box<dyn Future<Output = Result<HttpResponse>> + Send + 'a>>;
+
+/// Raw HTTP response from the relayer
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    /// HTTP status code
+    pub status: u16,
+    /// Response body
+    pub body: String,
+}
+
+impl HttpResponse {
+    /// Whether the status code is 2xx
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Parse the body as JSON
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T> {
+        Ok(serde_json::from_str(&self.body)?)
+    }
+
+    /// Parse the body as JSON, reporting the status and a body excerpt on failure
+    ///
+    /// `what` names the payload, e.g. "Failed to parse tree state".
+    pub fn json_with_context<T: DeserializeOwned>(&self, what: &str) -> Result<T> {
+        serde_json::from_str(&self.body).map_err(|e| {
+            PrivacyCashError::ApiError(format!(
+                "{} (status {}): {}; body: {}",
+                what,
+                self.status,
+                e,
+                self.body_snippet()
+            ))
+        })
+    }
+
+    /// The body truncated to a short excerpt for error messages
+    pub fn body_snippet(&self) -> String {
+        match self.body.char_indices().nth(BODY_SNIPPET_LEN) {
+            Some((end, _)) => format!("{}...", &self.body[..end]),
+            None => self.body.clone(),
+        }
+    }
+}
+
+/// Transport for relayer requests
+pub trait HttpTransport: Send + Sync {
+    /// Send a GET request
+    fn get_json<'a>(&'a self, url: &'a str) -> HttpFuture<'a>;
+
+    /// Send a POST request with a JSON body
+    fn post_json<'a>(&'a self, url: &'a str, body: &'a serde_json::Value) -> HttpFuture<'a>;
+}
+
+/// Default transport backed by `reqwest`
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+    headers: HeaderMap,
+}
+
+impl ReqwestTransport {
+    /// Create a transport with a custom `reqwest` client
+    pub fn new(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Send these headers with every relayer request
+    ///
+    /// For relayers that require an API key or bearer token:
+    ///
+    /// ```rust,no_run
+    /// use privacy_cash::http::{set_transport, ReqwestTransport};
+    /// use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+    /// use std::sync::Arc;
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer my-token"));
+    /// set_transport(Arc::new(ReqwestTransport::default().relayer_headers(headers)));
+    /// ```
+    ///
+    /// The values are marked sensitive, so they never show up in `Debug`
+    /// output or logs.
+    pub fn relayer_headers(mut self, mut headers: HeaderMap) -> Self {
+        for value in headers.values_mut() {
+            value.set_sensitive(true);
+        }
+        self.headers = headers;
+        self
+    }
+}
+
+impl HttpTransport for ReqwestTransport {
+    fn get_json<'a>(&'a self, url: &'a str) -> HttpFuture<'a> {
+        Box::pin(async move {
+            let request = self.client.get(url).headers(self.headers.clone());
+            let response = request.send().await?;
+            let status = response.status().as_u16();
+            let body = response.text().await?;
+            Ok(HttpResponse { status, body })
+        })
+    }
+
+    fn post_json<'a>(&'a self, url: &'a str, body: &'a serde_json::Value) -> HttpFuture<'a> {
+        Box::pin(async move {
+            let request = self.client.post(url).headers(self.headers.clone()).json(body);
+            let response = request.send().await?;
+            let status = response.status().as_u16();
+            let body = response.text().await?;
+            Ok(HttpResponse { status, body })
+        })
+    }
+}
+
+/// Transport that caps the number of relayer requests in flight
+///
+/// Wrap the shared transport in this when many clients run in one process,
+/// e.g. a server scanning hundreds of wallets, so they queue for a permit
+/// instead of flooding the relayer:
+///
+/// ```rust,no_run
+/// use privacy_cash::http::{set_transport, transport, LimitedTransport};
+/// use std::sync::Arc;
+///
+/// set_transport(Arc::new(LimitedTransport::new(transport(), 8)));
+/// ```
+pub struct LimitedTransport {
+    inner: Arc<dyn HttpTransport>,
+    permits: tokio::sync::Semaphore,
+}
+
+impl LimitedTransport {
+    /// Wrap `inner`, allowing at most `max_in_flight` concurrent requests
+    pub fn new(inner: Arc<dyn HttpTransport>, max_in_flight: usize) -> Self {
+        Self {
+            inner,
+            permits: tokio::sync::Semaphore::new(max_in_flight.max(1)),
+        }
+    }
+}
+
+impl HttpTransport for LimitedTransport {
+    fn get_json<'a>(&'a self, url: &'a str) -> HttpFuture<'a> {
+        Box::pin(async move {
+            let _permit = self.permits.acquire().await.expect("semaphore is never closed");
+            self.inner.get_json(url).await
+        })
+    }
+
+    fn post_json<'a>(&'a self, url: &'a str, body: &'a serde_json::Value) -> HttpFuture<'a> {
+        Box::pin(async move {
+            let _permit = self.permits.acquire().await.expect("semaphore is never closed");
+            self.inner.post_json(url, body).await
+        })
+    }
+}
+
+fn transport_cell() -> &'static RwLock<Arc<dyn HttpTransport>> {
+    TRANSPORT.get_or_init(|| RwLock::new(Arc::new(ReqwestTransport::default())))
+}
+
+/// Get the transport used for relayer calls
+pub fn transport() -> Arc<dyn HttpTransport> {
+    transport_cell().read().clone()
+}
+
+/// Replace the transport used for relayer calls
+pub fn set_transport(transport: Arc<dyn HttpTransport>) {
+    *transport_cell().write() = transport;
+}
+
+/// Use the default `reqwest` transport, sending `headers` with every request
+///
+/// Replaces any transport installed with [`set_transport`].
+pub fn set_relayer_headers(headers: HeaderMap) {
+    set_transport(Arc::new(ReqwestTransport::default().relayer_headers(headers)));
+}
+
+/// Restore the default `reqwest` transport
+pub fn reset_transport() {
+    set_transport(Arc::new(ReqwestTransport::default()));
+}
+
+/// Replace the transport used for relayer calls until the guard is dropped
+///
+/// The previous transport comes back even if the scope unwinds.
+pub fn scoped_transport(transport: Arc<dyn HttpTransport>) -> TransportGuard {
+    let previous = std::mem::replace(&mut *transport_cell().write(), transport);
+    TransportGuard { previous }
+}
+
+/// Restores the previous transport when dropped, see [`scoped_transport`]
+#[must_use = "the previous transport is restored when the guard is dropped"]
+pub struct TransportGuard {
+    previous: Arc<dyn HttpTransport>,
+}
+
+impl Drop for TransportGuard {
+    fn drop(&mut self) {
+        set_transport(self.previous.clone());
+    }
+}
+
+/// Serializes tests that replace a global hook: the transport or the external prover
+#[cfg(test)]
+pub(crate) static TEST_HOOKS_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Canned relayer responses for tests, keyed by URL
+///
+/// A request gets the response of the first route whose key its URL
+/// contains, or a 404 if none does. GET and POST requests share the routes.
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct CannedTransport {
+    routes: Vec<(String, HttpResponse)>,
+}
+
+#[cfg(test)]
+impl CannedTransport {
+    /// Answer URLs containing `key` with `body`
+    pub(crate) fn route(self, key: &str, body: impl Into<String>) -> Self {
+        self.route_status(key, 200, body)
+    }
+
+    /// Answer URLs containing `key` with `status` and `body`
+    pub(crate) fn route_status(mut self, key: &str, status: u16, body: impl Into<String>) -> Self {
+        let body = body.into();
+        self.routes.push((key.to_string(), HttpResponse { status, body }));
+        self
+    }
+
+    /// Serve these routes until the guard is dropped
+    pub(crate) fn install(self) -> TransportGuard {
+        scoped_transport(Arc::new(self))
+    }
+
+    fn respond(&self, url: &str) -> HttpResponse {
+        self.routes
+            .iter()
+            .find(|(key, _)| url.contains(key.as_str()))
+            .map(|(_, response)| response.clone())
+            .unwrap_or(HttpResponse {
+                status: 404,
+                body: String::new(),
+            })
+    }
+}
+
+#[cfg(test)]
+impl HttpTransport for CannedTransport {
+    fn get_json<'a>(&'a self, url: &'a str) -> HttpFuture<'a> {
+        Box::pin(async move { Ok(self.respond(url)) })
+    }
+
+    fn post_json<'a>(&'a self, url: &'a str, _body: &'a serde_json::Value) -> HttpFuture<'a> {
+        Box::pin(async move { Ok(self.respond(url)) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::query_remote_tree_state;
+    use crate::PrivacyCash;
+    use solana_sdk::signature::{Keypair, Signer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Serves an empty pool and records the peak number of requests in flight
+    struct EmptyPoolTransport {
+        pool: CannedTransport,
+        in_flight: AtomicUsize,
+        peak: AtomicUsize,
+        requests: AtomicUsize,
+    }
+
+    impl Default for EmptyPoolTransport {
+        fn default() -> Self {
+            let range = r#"{"count":0,"encrypted_outputs":[],"hasMore":false}"#;
+            Self {
+                pool: CannedTransport::default()
+                    .route("/merkle/root", r#"{"root":"0","nextIndex":0}"#)
+                    .route("/utxos/range", range),
+                in_flight: AtomicUsize::new(0),
+                peak: AtomicUsize::new(0),
+                requests: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    impl EmptyPoolTransport {
+        async fn respond(&self, url: &str) -> Result<HttpResponse> {
+            let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(now, Ordering::SeqCst);
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(self.pool.respond(url))
+        }
+    }
+
+    impl HttpTransport for EmptyPoolTransport {
+        fn get_json<'a>(&'a self, url: &'a str) -> HttpFuture<'a> {
+            Box::pin(self.respond(url))
+        }
+
+        fn post_json<'a>(&'a self, url: &'a str, _body: &'a serde_json::Value) -> HttpFuture<'a> {
+            Box::pin(self.respond(url))
+        }
+    }
+
+    #[test]
+    fn test_relayer_headers_redacted() {
+        use reqwest::header::{HeaderValue, AUTHORIZATION};
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer my-token"));
+        let transport = ReqwestTransport::default().relayer_headers(headers);
+
+        let debug = format!("{:?}", transport);
+        assert!(debug.contains("authorization"));
+        assert!(!debug.contains("my-token"));
+    }
+
+    #[test]
+    fn test_parse_error_context() {
+        let response = HttpResponse {
+            status: 200,
+            body: format!("<html>{}</html>", "x".repeat(500)),
+        };
+        let err = response
+            .json_with_context::<serde_json::Value>("Failed to parse tree state")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Failed to parse tree state (status 200)"));
+        assert!(err.contains("body: <html>xxx"));
+        assert!(err.ends_with("..."));
+    }
+
+    #[tokio::test]
+    async fn test_canned_transport() {
+        let _guard = TEST_HOOKS_LOCK.lock().await;
+        let state = {
+            let _transport = CannedTransport::default()
+                .route("/merkle/root", r#"{"root":"123","nextIndex":42}"#)
+                .install();
+            query_remote_tree_state(None).await
+        };
+
+        let state = state.unwrap();
+        assert_eq!(state.root, "123");
+        assert_eq!(state.next_index, 42);
+    }
+
+    #[tokio::test]
+    async fn test_transport_guard_restores_previous() {
+        let _guard = TEST_HOOKS_LOCK.lock().await;
+        let tree = |root: &str| format!(r#"{{"root":"{}","nextIndex":1}}"#, root);
+        let _outer = CannedTransport::default().route("/merkle/root", tree("1")).install();
+        {
+            let _inner = CannedTransport::default().route("/merkle/root", tree("2")).install();
+            assert_eq!(query_remote_tree_state(None).await.unwrap().root, "2");
+        }
+        assert_eq!(query_remote_tree_state(None).await.unwrap().root, "1");
+    }
+
+    #[tokio::test]
+    async fn test_scan_wallets_under_budget() {
+        let _guard = TEST_HOOKS_LOCK.lock().await;
+        crate::prover::install_test_circuit();
+        let canned = Arc::new(EmptyPoolTransport::default());
+        let transport = scoped_transport(Arc::new(LimitedTransport::new(canned.clone(), 2)));
+
+        let clients: Vec<_> = (0..6)
+            .map(|_| {
+                let keypair = Keypair::new();
+                let cache_dir = std::env::temp_dir()
+                    .join(format!("privacy-cash-test-{}", keypair.pubkey()));
+                PrivacyCash::with_options("http://127.0.0.1:1", keypair, Some(cache_dir), None)
+                    .unwrap()
+            })
+            .collect();
+        let summaries = PrivacyCash::scan_wallets(&clients, clients.len()).await;
+        drop(transport);
+        for client in &clients {
+            let _ = std::fs::remove_dir_all(
+                std::env::temp_dir().join(format!("privacy-cash-test-{}", client.pubkey())),
+            );
+        }
+
+        assert_eq!(summaries.len(), clients.len());
+        for summary in summaries {
+            assert!(summary.unwrap().tokens.iter().all(|t| t.balance == 0));
+        }
+        assert!(canned.requests.load(Ordering::SeqCst) > clients.len());
+        assert!(canned.peak.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[tokio::test]
+    async fn test_relayer_headers_sent() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/config", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0u8; 4096];
+            let len = socket.read(&mut request).await.unwrap();
+            let reply = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\n{}";
+            socket.write_all(reply.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&request[..len]).to_lowercase()
+        });
+
+        let mut headers = HeaderMap::new();
+        headers.insert(reqwest::header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        let transport = ReqwestTransport::default().relayer_headers(headers);
+        let response = transport.get_json(&url).await.unwrap();
+
+        assert!(response.is_success());
+        assert!(server.await.unwrap().contains("authorization: bearer secret"));
+    }
+}
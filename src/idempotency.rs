@@ -0,0 +1,208 @@
+//! Idempotency keys for retried deposits
+//!
+//! A service that retries a deposit after a timeout cannot tell whether the
+//! first attempt landed. Passing the same idempotency key on every attempt
+//! lets the relayer drop the duplicate, and the SDK also records the key per
+//! wallet in [`Storage`] as a fallback for relayers that ignore it: a key
+//! whose deposit completed returns the original signature without depositing
+//! again, and a key whose earlier attempt never finished is logged as a
+//! possible duplicate before retrying. Each key remembers the amount it was
+//! first used with, so reusing it for a different deposit is an error, and
+//! the signature of the last transaction it signed, so a retry can look that
+//! transaction up on-chain first. Keys are forgotten after
+//! [`IDEMPOTENCY_WINDOW_SECS`].
+
+use crate::constants::{IDEMPOTENCY_WINDOW_SECS, LSK_IDEMPOTENCY_KEYS};
+use crate::error::{PrivacyCashError, Result};
+use crate::get_utxos::localstorage_key;
+use crate::storage::Storage;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What is known about an idempotency key before an attempt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyState {
+    /// Not seen within the window
+    New,
+    /// An earlier attempt started but never completed; it may still have
+    /// landed. Holds the signature of its signed transaction, if it got that
+    /// far.
+    Pending(Option<String>),
+    /// An earlier attempt completed with this signature
+    Completed(String),
+}
+
+/// Stored attempt of one key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyRecord {
+    /// Unix time in seconds of the first attempt
+    started: u64,
+    /// Confirmed deposit signature
+    signature: Option<String>,
+    /// Lamports of the first attempt; `None` for keys stored before amounts
+    #[serde(default)]
+    amount: Option<u64>,
+    /// Signature of the last transaction signed for the key, before relaying
+    #[serde(default)]
+    submitted: Option<String>,
+}
+
+/// Storage key of a wallet's idempotency keys
+pub fn idempotency_storage_key(public_key: &Pubkey) -> String {
+    format!("{}{}", LSK_IDEMPOTENCY_KEYS, localstorage_key(public_key))
+}
+
+/// Record an attempt to deposit `amount` for `idempotency_key`, returning
+/// what was known before
+///
+/// A new key is stored as pending; known keys are left as they are. Keys
+/// older than the window are dropped first. Fails with `InvalidInput` if the
+/// key was first used for a different amount.
+pub fn begin(
+    storage: &Storage,
+    key: &str,
+    idempotency_key: &str,
+    amount: u64,
+) -> Result<KeyState> {
+    begin_at(storage, key, idempotency_key, amount, unix_now())
+}
+
+/// Remember `signature` as the transaction signed for `idempotency_key`
+pub fn record_submitted(
+    storage: &Storage,
+    key: &str,
+    idempotency_key: &str,
+    signature: &str,
+) -> Result<()> {
+    update(storage, key, |records| {
+        if let Some(record) = records.get_mut(idempotency_key) {
+            record.submitted = Some(signature.to_string());
+        }
+    })
+}
+
+/// Mark the deposit for `idempotency_key` as completed with `signature`
+pub fn complete(
+    storage: &Storage,
+    key: &str,
+    idempotency_key: &str,
+    signature: &str,
+) -> Result<()> {
+    update(storage, key, |records| {
+        let record = records.entry(idempotency_key.to_string()).or_insert_with(|| KeyRecord {
+            started: unix_now(),
+            signature: None,
+            amount: None,
+            submitted: None,
+        });
+        record.signature = Some(signature.to_string());
+    })
+}
+
+fn begin_at(
+    storage: &Storage,
+    key: &str,
+    idempotency_key: &str,
+    amount: u64,
+    now: u64,
+) -> Result<KeyState> {
+    update(storage, key, |records| {
+        records.retain(|_, r| now.saturating_sub(r.started) < IDEMPOTENCY_WINDOW_SECS);
+        let Some(record) = records.get(idempotency_key) else {
+            records.insert(
+                idempotency_key.to_string(),
+                KeyRecord {
+                    started: now,
+                    signature: None,
+                    amount: Some(amount),
+                    submitted: None,
+                },
+            );
+            return Ok(KeyState::New);
+        };
+        match record.amount {
+            Some(first) if first != amount => Err(PrivacyCashError::InvalidInput(format!(
+                "Idempotency key {} was used for a deposit of {} lamports, not {}",
+                idempotency_key, first, amount
+            ))),
+            _ => Ok(match &record.signature {
+                Some(signature) => KeyState::Completed(signature.clone()),
+                None => KeyState::Pending(record.submitted.clone()),
+            }),
+        }
+    })?
+}
+
+/// Apply `f` to the stored records with a compare-and-swap loop
+fn update<T>(
+    storage: &Storage,
+    key: &str,
+    mut f: impl FnMut(&mut BTreeMap<String, KeyRecord>) -> T,
+) -> Result<T> {
+    loop {
+        let current = storage.get(key);
+        let mut records: BTreeMap<String, KeyRecord> = match current.as_deref() {
+            Some(json) => serde_json::from_str(json).map_err(|e| {
+                PrivacyCashError::StorageError(format!("Unreadable idempotency keys: {}", e))
+            })?,
+            None => BTreeMap::new(),
+        };
+        let result = f(&mut records);
+        let updated = serde_json::to_string(&records)?;
+        if storage.cas(key, current.as_deref(), &updated) {
+            return Ok(result);
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idempotency_keys() {
+        let storage = Storage::memory();
+        let key = idempotency_storage_key(&Pubkey::new_unique());
+        let now = 1_700_000_000;
+
+        assert_eq!(begin_at(&storage, &key, "order-1", 100, now).unwrap(), KeyState::New);
+        // A retry before the first attempt reported back may be a duplicate
+        assert_eq!(
+            begin_at(&storage, &key, "order-1", 100, now + 30).unwrap(),
+            KeyState::Pending(None)
+        );
+        assert_eq!(begin_at(&storage, &key, "order-2", 100, now + 30).unwrap(), KeyState::New);
+
+        // Once signed, a retry learns which transaction to look up
+        record_submitted(&storage, &key, "order-1", "signed1").unwrap();
+        assert_eq!(
+            begin_at(&storage, &key, "order-1", 100, now + 40).unwrap(),
+            KeyState::Pending(Some("signed1".to_string()))
+        );
+
+        // The same key for a different amount is a different deposit
+        assert!(matches!(
+            begin_at(&storage, &key, "order-1", 200, now + 50),
+            Err(PrivacyCashError::InvalidInput(_))
+        ));
+
+        complete(&storage, &key, "order-1", "sig1").unwrap();
+        assert_eq!(
+            begin_at(&storage, &key, "order-1", 100, now + 60).unwrap(),
+            KeyState::Completed("sig1".to_string())
+        );
+
+        // Outside the window the key is free again
+        let later = now + IDEMPOTENCY_WINDOW_SECS;
+        assert_eq!(begin_at(&storage, &key, "order-1", 200, later).unwrap(), KeyState::New);
+    }
+}
@@ -3,14 +3,13 @@
 //! Implements a Poseidon-based keypair system for UTXO ownership.
 //! Based on Tornado Cash Nova's approach.
 //!
-//! Uses native Poseidon implementation compatible with circom circuits.
+//! Hashing goes through the pluggable backend in [`crate::hasher`].
 
 use crate::constants::FIELD_SIZE;
 use crate::error::{PrivacyCashError, Result};
-use crate::poseidon::{Poseidon, PoseidonHasher};
-use ark_bn254::Fr;
-use ark_ff::{BigInteger, PrimeField};
+use crate::hasher::hasher;
 use num_bigint::BigUint;
+use num_traits::Zero;
 
 /// ZK Keypair for UTXO ownership
 ///
@@ -40,23 +39,42 @@ impl ZkKeypair {
     pub fn from_hex(privkey_hex: &str) -> Result<Self> {
         let hex_str = privkey_hex.strip_prefix("0x").unwrap_or(privkey_hex);
 
+        if hex_str.is_empty() {
+            return Err(PrivacyCashError::InvalidKeypair("Empty hex string".to_string()));
+        }
+        if hex_str.len() % 2 != 0 {
+            return Err(PrivacyCashError::InvalidKeypair(format!(
+                "Odd-length hex string ({} digits)",
+                hex_str.len()
+            )));
+        }
+
         let raw_decimal = BigUint::parse_bytes(hex_str.as_bytes(), 16)
             .ok_or_else(|| PrivacyCashError::InvalidKeypair("Invalid hex string".to_string()))?;
 
-        // Reduce modulo field size
-        let privkey = raw_decimal % &*FIELD_SIZE;
-
-        // Compute public key using native Poseidon hash
-        let pubkey = Self::poseidon_hash(&[privkey.clone()])?;
-
-        Ok(Self { privkey, pubkey })
+        Self::from_raw(raw_decimal)
     }
 
     /// Create a new keypair from raw bytes
     pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
-        let raw_decimal = BigUint::from_bytes_be(bytes);
-        let privkey = raw_decimal % &*FIELD_SIZE;
+        Self::from_raw(BigUint::from_bytes_be(bytes))
+    }
+
+    /// Reduce a raw private key into the field and derive the public key
+    fn from_raw(raw: BigUint) -> Result<Self> {
+        // Reduce modulo field size
+        let privkey = raw % &*FIELD_SIZE;
+
+        // A zero key would make every note owned by it forgeable
+        if privkey.is_zero() {
+            return Err(PrivacyCashError::DegenerateKey(
+                "Private key reduces to zero".to_string(),
+            ));
+        }
+
+        // Compute public key using native Poseidon hash
         let pubkey = Self::poseidon_hash(&[privkey.clone()])?;
+
         Ok(Self { privkey, pubkey })
     }
 
@@ -108,40 +126,12 @@ impl ZkKeypair {
         Ok(result.to_string())
     }
 
-    /// Compute Poseidon hash of multiple inputs using native implementation
+    /// Compute Poseidon hash of multiple inputs
     ///
-    /// This uses the circom-compatible Poseidon hash with BN254 curve parameters.
+    /// Delegates to the installed [`crate::hasher::PoseidonBackend`], which is
+    /// the circom-compatible BN254 implementation unless replaced.
     pub fn poseidon_hash(inputs: &[BigUint]) -> Result<BigUint> {
-        let num_inputs = inputs.len();
-        if num_inputs == 0 || num_inputs > 12 {
-            return Err(PrivacyCashError::InvalidKeypair(
-                format!("Invalid number of inputs: {}. Must be 1-12.", num_inputs)
-            ));
-        }
-
-        // Convert BigUint inputs to Fr field elements
-        let fr_inputs: Vec<Fr> = inputs
-            .iter()
-            .map(|input| {
-                let bytes = input.to_bytes_be();
-                let mut padded = [0u8; 32];
-                let start = 32usize.saturating_sub(bytes.len());
-                let len = bytes.len().min(32);
-                padded[start..start + len].copy_from_slice(&bytes[..len]);
-                Fr::from_be_bytes_mod_order(&padded)
-            })
-            .collect();
-
-        // Create Poseidon hasher and compute hash
-        let mut poseidon = Poseidon::<Fr>::new_circom(num_inputs)
-            .map_err(|e| PrivacyCashError::InvalidKeypair(format!("Poseidon error: {:?}", e)))?;
-        
-        let hash = poseidon.hash(&fr_inputs)
-            .map_err(|e| PrivacyCashError::InvalidKeypair(format!("Poseidon hash error: {:?}", e)))?;
-
-        // Convert Fr back to BigUint
-        let result_bytes = hash.into_bigint().to_bytes_be();
-        Ok(BigUint::from_bytes_be(&result_bytes))
+        hasher().hash(inputs)
     }
 
     /// Compute Poseidon hash from string inputs (for compatibility with JS SDK)
@@ -162,7 +152,6 @@ impl ZkKeypair {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use num_traits::Zero;
 
     #[test]
     fn test_keypair_generation() {
@@ -179,6 +168,21 @@ mod tests {
         assert!(!keypair.pubkey().is_zero());
     }
 
+    #[test]
+    fn test_keypair_from_hex_rejects_malformed() {
+        assert!(matches!(ZkKeypair::from_hex(""), Err(PrivacyCashError::InvalidKeypair(_))));
+        assert!(matches!(ZkKeypair::from_hex("0x"), Err(PrivacyCashError::InvalidKeypair(_))));
+        assert!(matches!(ZkKeypair::from_hex("0xabc"), Err(PrivacyCashError::InvalidKeypair(_))));
+        assert!(matches!(ZkKeypair::from_hex("0x0000"), Err(PrivacyCashError::DegenerateKey(_))));
+
+        // A multiple of the field size also reduces to zero
+        let field_size_hex = format!("0x{:064x}", *FIELD_SIZE);
+        assert!(matches!(
+            ZkKeypair::from_hex(&field_size_hex),
+            Err(PrivacyCashError::DegenerateKey(_))
+        ));
+    }
+
     #[test]
     fn test_poseidon_hash_consistency() {
         // Test that poseidon hash produces consistent output
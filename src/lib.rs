@@ -35,33 +35,58 @@
 //! }
 //! ```
 
+pub mod amount;
+pub mod audit;
+pub mod blinding;
+pub mod cheque;
 pub mod client;
+pub mod clock;
 pub mod config;
+pub mod consolidate;
 pub mod constants;
+pub mod delivery;
 pub mod deposit;
 pub mod deposit_spl;
+pub mod diagnostics;
 pub mod encryption;
 pub mod error;
+pub mod external_prover;
 pub mod get_utxos;
 pub mod get_utxos_spl;
+pub mod hasher;
+pub mod http;
+pub mod idempotency;
 pub mod keypair;
 pub mod merkle_tree;
+pub mod metrics;
+pub mod migrate;
+pub mod network_state;
+pub mod pool_stats;
 pub mod poseidon;
+pub mod prelude;
+pub mod preview;
 pub mod prover;
 pub mod prover_rust;
+pub mod reconcile;
+pub mod scan;
+pub mod self_test;
 pub mod storage;
+pub mod tokens;
+pub mod tree_audit;
 pub mod utxo;
 pub mod utils;
+pub mod wallet_lock;
 pub mod withdraw;
 pub mod withdraw_spl;
 
 // Re-export main types
+pub use amount::{Lamports, Rounding, TokenAmount};
 pub use client::PrivacyCash;
 pub use config::{Config, SupportedToken};
 pub use constants::*;
 pub use error::{PrivacyCashError, Result};
 pub use keypair::ZkKeypair;
-pub use utxo::{Utxo, Balance, SplBalance};
+pub use utxo::{Utxo, Balance, SplBalance, UtxoDetail};
 
 // Re-export Solana types for convenience
 pub use solana_sdk::{
@@ -155,7 +180,7 @@ pub async fn send_privately(
     
     match token_lower.as_str() {
         "sol" => {
-            let lamports = (amount * 1_000_000_000.0) as u64;
+            let lamports = amount::base_units_from_f64(amount, amount::SOL_DECIMALS)?;
             
             // Step 1: Deposit
             log::info!("Step 1/3: Depositing {} SOL...", amount);
@@ -164,7 +189,7 @@ pub async fn send_privately(
             
             // Step 2: Wait for indexer
             log::info!("Step 2/3: Waiting for indexer (5 seconds)...");
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+            clock::sleep(std::time::Duration::from_secs(5)).await;
             
             // Step 3: Withdraw ALL to recipient
             log::info!("Step 3/3: Withdrawing to recipient...");
@@ -182,7 +207,7 @@ pub async fn send_privately(
             })
         }
         "usdc" => {
-            let base_units = (amount * 1_000_000.0) as u64;
+            let base_units = amount::base_units_from_f64(amount, 6)?;
             
             // Step 1: Deposit
             log::info!("Step 1/3: Depositing {} USDC...", amount);
@@ -191,7 +216,7 @@ pub async fn send_privately(
             
             // Step 2: Wait for indexer
             log::info!("Step 2/3: Waiting for indexer (5 seconds)...");
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+            clock::sleep(std::time::Duration::from_secs(5)).await;
             
             // Step 3: Withdraw ALL to recipient
             log::info!("Step 3/3: Withdrawing to recipient...");
@@ -209,7 +234,7 @@ pub async fn send_privately(
             })
         }
         "usdt" => {
-            let base_units = (amount * 1_000_000.0) as u64;
+            let base_units = amount::base_units_from_f64(amount, 6)?;
             
             // Step 1: Deposit
             log::info!("Step 1/3: Depositing {} USDT...", amount);
@@ -218,7 +243,7 @@ pub async fn send_privately(
             
             // Step 2: Wait for indexer
             log::info!("Step 2/3: Waiting for indexer (5 seconds)...");
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+            clock::sleep(std::time::Duration::from_secs(5)).await;
             
             // Step 3: Withdraw ALL to recipient
             log::info!("Step 3/3: Withdrawing to recipient...");
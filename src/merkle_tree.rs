@@ -4,6 +4,7 @@ use crate::constants::MERKLE_TREE_DEPTH;
 use crate::error::{PrivacyCashError, Result};
 use crate::keypair::ZkKeypair;
 use num_bigint::BigUint;
+use std::collections::{BTreeSet, HashMap};
 
 /// Default zero element for empty leaves
 pub const DEFAULT_ZERO: &str = "0";
@@ -40,15 +41,7 @@ impl MerkleTree {
             return Err(PrivacyCashError::MerkleProofError("Tree is full".to_string()));
         }
 
-        // Initialize zeros for each level
-        let mut zeros = Vec::with_capacity(levels + 1);
-        zeros.push(zero_element.to_string());
-
-        for i in 1..=levels {
-            let prev = &zeros[i - 1];
-            let hash = ZkKeypair::poseidon_hash_strings(&[prev, prev])?;
-            zeros.push(hash);
-        }
+        let zeros = compute_zeros(levels, zero_element)?;
 
         // Initialize layers
         let mut layers: Vec<Vec<String>> = Vec::with_capacity(levels + 1);
@@ -233,6 +226,179 @@ impl MerkleTree {
     }
 }
 
+/// Precompute the root of an empty subtree at each level
+fn compute_zeros(levels: usize, zero_element: &str) -> Result<Vec<String>> {
+    let mut zeros = Vec::with_capacity(levels + 1);
+    zeros.push(zero_element.to_string());
+
+    for i in 1..=levels {
+        let prev = &zeros[i - 1];
+        let hash = ZkKeypair::poseidon_hash_strings(&[prev, prev])?;
+        zeros.push(hash);
+    }
+
+    Ok(zeros)
+}
+
+/// Merkle tree that stores only populated nodes
+///
+/// Produces the same `root()` and `path()` as [`MerkleTree`], but empty
+/// subtrees are read from the precomputed zeros instead of being stored, so
+/// memory grows with the number of populated leaves rather than with the
+/// highest index. Suited to full-depth trees with scattered known leaves.
+pub struct SparseMerkleTree {
+    /// Number of levels in the tree
+    pub levels: usize,
+
+    /// Maximum number of leaves (2^levels)
+    pub capacity: usize,
+
+    /// Zero element for empty leaves
+    pub zero_element: String,
+
+    /// Precomputed zero values for each level
+    zeros: Vec<String>,
+
+    /// Populated nodes per level (nodes[0] = leaves)
+    nodes: Vec<HashMap<usize, String>>,
+
+    /// One past the highest populated leaf index
+    next_index: usize,
+}
+
+impl SparseMerkleTree {
+    /// Create a new sparse Merkle tree
+    pub fn new(levels: usize) -> Result<Self> {
+        Self::with_elements(levels, vec![], DEFAULT_ZERO)
+    }
+
+    /// Create a new sparse Merkle tree with initial elements
+    pub fn with_elements(levels: usize, elements: Vec<String>, zero_element: &str) -> Result<Self> {
+        let mut tree = Self {
+            levels,
+            capacity: 1usize << levels,
+            zero_element: zero_element.to_string(),
+            zeros: compute_zeros(levels, zero_element)?,
+            nodes: vec![HashMap::new(); levels + 1],
+            next_index: 0,
+        };
+
+        tree.bulk_insert(elements)?;
+
+        Ok(tree)
+    }
+
+    /// Get a node, falling back to the empty-subtree value
+    fn node(&self, level: usize, index: usize) -> &str {
+        self.nodes[level].get(&index).unwrap_or(&self.zeros[level])
+    }
+
+    /// Recompute the ancestors of the given leaf indices
+    fn recompute(&mut self, leaves: BTreeSet<usize>) -> Result<()> {
+        let mut dirty = leaves;
+
+        for level in 1..=self.levels {
+            dirty = dirty.into_iter().map(|index| index >> 1).collect();
+
+            for &index in &dirty {
+                let hash = ZkKeypair::poseidon_hash_strings(&[
+                    self.node(level - 1, index * 2),
+                    self.node(level - 1, index * 2 + 1),
+                ])?;
+                self.nodes[level].insert(index, hash);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the tree root
+    pub fn root(&self) -> String {
+        self.node(self.levels, 0).to_string()
+    }
+
+    /// Insert a new element into the tree
+    pub fn insert(&mut self, element: String) -> Result<()> {
+        if self.next_index >= self.capacity {
+            return Err(PrivacyCashError::MerkleProofError("Tree is full".to_string()));
+        }
+
+        self.update(self.next_index, element)
+    }
+
+    /// Update an element at a specific index
+    pub fn update(&mut self, index: usize, element: String) -> Result<()> {
+        if index >= self.capacity {
+            return Err(PrivacyCashError::MerkleProofError(format!(
+                "Index {} out of bounds",
+                index
+            )));
+        }
+
+        self.nodes[0].insert(index, element);
+        self.next_index = self.next_index.max(index + 1);
+        self.recompute(BTreeSet::from([index]))
+    }
+
+    /// Bulk insert multiple elements
+    pub fn bulk_insert(&mut self, elements: Vec<String>) -> Result<()> {
+        if self.next_index + elements.len() > self.capacity {
+            return Err(PrivacyCashError::MerkleProofError("Tree is full".to_string()));
+        }
+
+        let start = self.next_index;
+        self.next_index += elements.len();
+        for (offset, element) in elements.into_iter().enumerate() {
+            self.nodes[0].insert(start + offset, element);
+        }
+        self.recompute((start..self.next_index).collect())
+    }
+
+    /// Get Merkle path for a leaf at given index
+    pub fn path(&self, index: usize) -> Result<MerklePath> {
+        if index >= self.next_index {
+            return Err(PrivacyCashError::MerkleProofError(format!(
+                "Index {} out of bounds",
+                index
+            )));
+        }
+
+        let mut path_elements = Vec::with_capacity(self.levels);
+        let mut path_indices = Vec::with_capacity(self.levels);
+        let mut current_index = index;
+
+        for level in 0..self.levels {
+            path_indices.push(current_index % 2);
+            path_elements.push(self.node(level, current_index ^ 1).to_string());
+            current_index >>= 1;
+        }
+
+        Ok(MerklePath {
+            path_elements,
+            path_indices,
+        })
+    }
+
+    /// Find index of an element
+    pub fn index_of(&self, element: &str) -> Option<usize> {
+        self.nodes[0]
+            .iter()
+            .filter(|(_, e)| e.as_str() == element)
+            .map(|(index, _)| *index)
+            .min()
+    }
+
+    /// Get the next available index
+    pub fn next_index(&self) -> usize {
+        self.next_index
+    }
+
+    /// Number of populated leaves
+    pub fn populated_leaves(&self) -> usize {
+        self.nodes[0].len()
+    }
+}
+
 /// Merkle path proof
 #[derive(Debug, Clone)]
 pub struct MerklePath {
@@ -301,4 +467,28 @@ mod tests {
         let result = tree.insert("5".to_string());
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_sparse_tree_matches_dense() {
+        let leaves: Vec<String> = (1..=5).map(|i| i.to_string()).collect();
+        let dense = MerkleTree::with_elements(4, leaves.clone(), DEFAULT_ZERO).unwrap();
+        let mut sparse =
+            SparseMerkleTree::with_elements(4, leaves[..3].to_vec(), DEFAULT_ZERO).unwrap();
+        sparse.insert("4".to_string()).unwrap();
+        sparse.bulk_insert(vec!["5".to_string()]).unwrap();
+
+        assert_eq!(sparse.root(), dense.root());
+        for index in 0..5 {
+            let (a, b) = (sparse.path(index).unwrap(), dense.path(index).unwrap());
+            assert_eq!(a.path_elements, b.path_elements);
+            assert_eq!(a.path_indices, b.path_indices);
+        }
+        assert!(sparse.path(5).is_err());
+
+        // A single high leaf at full depth only stores one node per level
+        let mut deep = SparseMerkleTree::new(MERKLE_TREE_DEPTH).unwrap();
+        deep.update(1 << 20, "123".to_string()).unwrap();
+        assert_eq!(deep.populated_leaves(), 1);
+        assert!(deep.path(1 << 20).unwrap().verify("123", &deep.root()).unwrap());
+    }
 }
@@ -0,0 +1,98 @@
+//! Metrics hook for operations and errors
+//!
+//! Deposits, withdrawals, proof generation, UTXO scans and relayer failures
+//! report to the process-wide [`MetricsSink`], which does nothing by default.
+//! Install a sink with [`set_metrics`] to forward them to Prometheus or any
+//! other backend.
+
+use once_cell::sync::OnceCell;
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+/// Global sink used by all instrumentation points
+static METRICS: OnceCell<RwLock<Arc<dyn MetricsSink>>> = OnceCell::new();
+
+/// Completed deposits, labelled by `token`
+pub const DEPOSITS_TOTAL: &str = "privacy_cash_deposits_total";
+/// Completed withdrawals, labelled by `token`
+pub const WITHDRAWALS_TOTAL: &str = "privacy_cash_withdrawals_total";
+/// Proof generation time in seconds
+pub const PROOF_SECONDS: &str = "privacy_cash_proof_seconds";
+/// UTXO scan time in seconds, labelled by `token`
+pub const SCAN_SECONDS: &str = "privacy_cash_scan_seconds";
+/// Failed relayer requests, labelled by `endpoint` and `status`
+pub const RELAYER_ERRORS_TOTAL: &str = "privacy_cash_relayer_errors_total";
+
+/// Receiver for SDK metrics
+///
+/// Both methods default to doing nothing, so a sink only implements the kinds
+/// it records.
+pub trait MetricsSink: Send + Sync {
+    /// Increment a counter by one
+    fn inc_counter(&self, _name: &str, _labels: &[(&str, &str)]) {}
+
+    /// Record one observation in a histogram
+    fn observe_histogram(&self, _name: &str, _value: f64, _labels: &[(&str, &str)]) {}
+}
+
+/// Sink that discards all metrics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopMetrics;
+
+impl MetricsSink for NoopMetrics {}
+
+fn metrics_cell() -> &'static RwLock<Arc<dyn MetricsSink>> {
+    METRICS.get_or_init(|| RwLock::new(Arc::new(NoopMetrics)))
+}
+
+/// Get the sink used for metrics
+pub fn metrics() -> Arc<dyn MetricsSink> {
+    metrics_cell().read().clone()
+}
+
+/// Replace the sink used for metrics
+pub fn set_metrics(sink: Arc<dyn MetricsSink>) {
+    *metrics_cell().write() = sink;
+}
+
+/// Restore the no-op sink
+pub fn reset_metrics() {
+    set_metrics(Arc::new(NoopMetrics));
+}
+
+/// Count a failed relayer request
+pub(crate) fn record_relayer_error(endpoint: &str, status: u16) {
+    let status = status.to_string();
+    metrics().inc_counter(RELAYER_ERRORS_TOTAL, &[("endpoint", endpoint), ("status", &status)]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    #[derive(Default)]
+    struct RecordingSink {
+        counters: Mutex<Vec<String>>,
+    }
+
+    impl MetricsSink for RecordingSink {
+        fn inc_counter(&self, name: &str, labels: &[(&str, &str)]) {
+            let labels: Vec<String> = labels.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+            self.counters.lock().push(format!("{}{{{}}}", name, labels.join(",")));
+        }
+    }
+
+    #[test]
+    fn test_metrics_sink() {
+        let sink = Arc::new(RecordingSink::default());
+        set_metrics(sink.clone());
+        record_relayer_error("/withdraw", 502);
+        reset_metrics();
+
+        assert!(sink
+            .counters
+            .lock()
+            .contains(&"privacy_cash_relayer_errors_total{endpoint=/withdraw,status=502}".to_string()));
+    }
+}
@@ -0,0 +1,162 @@
+//! Migration of legacy V1 notes to the V2 encryption format
+//!
+//! V1 notes (AES-128-CTR, keyed from the first 31 signature bytes) are still
+//! spendable, but every new output is V2. Migrating re-spends the V1 notes in
+//! zero-amount deposits so their value ends up in V2 change outputs.
+
+use crate::blinding::BlindingSource;
+use crate::deposit::{deposit, DepositParams};
+use crate::deposit_spl::{deposit_spl, DepositSplParams};
+use crate::encryption::EncryptionService;
+use crate::error::Result;
+use crate::get_utxos::get_utxos;
+use crate::get_utxos_spl::get_utxos_spl;
+use crate::preview::ApprovalHook;
+use crate::storage::Storage;
+use crate::utxo::{Utxo, UtxoVersion};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+use std::time::Duration;
+
+/// Migration result
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MigrationResult {
+    /// Number of V1 notes that were re-spent into V2 outputs
+    pub notes_migrated: usize,
+    /// Signatures of the migration transactions, in submission order
+    pub signatures: Vec<String>,
+}
+
+/// Parameters for migration
+pub struct MigrateParams<'a> {
+    pub connection: &'a RpcClient,
+    pub keypair: &'a Keypair,
+    pub encryption_service: &'a EncryptionService,
+    pub storage: &'a Storage,
+    /// Token mint to migrate, or `None` for native SOL
+    pub mint_address: Option<&'a Pubkey>,
+    pub key_base_path: &'a str,
+    pub referrer: Option<&'a str>,
+    /// Called before each migration transaction is relayed; `false` aborts
+    pub approval: Option<&'a ApprovalHook>,
+    /// How the output notes get their blindings
+    pub blinding: BlindingSource,
+    /// Limit on generating each proof
+    pub proof_timeout: Duration,
+}
+
+/// Re-spend all V1 notes so their value is held in V2 notes
+///
+/// Notes are consumed two at a time (the circuit has two inputs), each pair
+/// producing a single V2 output.
+pub async fn migrate_v1_to_v2(params: MigrateParams<'_>) -> Result<MigrationResult> {
+    let MigrateParams {
+        connection,
+        keypair,
+        encryption_service,
+        storage,
+        mint_address,
+        key_base_path,
+        referrer,
+        approval,
+        blinding,
+        proof_timeout,
+    } = params;
+
+    // V1 notes can only be found and spent with the V1 key
+    encryption_service.get_utxo_private_key_v1()?;
+
+    let public_key = keypair.pubkey();
+    let utxos = match mint_address {
+        Some(mint) => {
+            get_utxos_spl(connection, &public_key, encryption_service, storage, mint, None)
+                .await?
+        }
+        None => get_utxos(connection, &public_key, encryption_service, storage, None).await?,
+    };
+
+    let v1_notes = filter_v1_notes(utxos);
+    if v1_notes.is_empty() {
+        log::info!("No V1 notes to migrate");
+        return Ok(MigrationResult::default());
+    }
+
+    log::info!("Migrating {} V1 notes to V2", v1_notes.len());
+
+    let mut result = MigrationResult::default();
+    for pair in v1_notes.chunks(2) {
+        let signature = match mint_address {
+            Some(mint) => {
+                deposit_spl(DepositSplParams {
+                    referrer,
+                    input_utxos: Some(pair),
+                    approval,
+                    blinding,
+                    proof_timeout,
+                    ..DepositSplParams::new(
+                        connection,
+                        keypair,
+                        encryption_service,
+                        storage,
+                        mint,
+                        key_base_path,
+                    )
+                })
+                .await?
+                .signature
+            }
+            None => {
+                deposit(DepositParams {
+                    referrer,
+                    input_utxos: Some(pair),
+                    approval,
+                    blinding,
+                    proof_timeout,
+                    ..DepositParams::new(
+                        connection,
+                        keypair,
+                        encryption_service,
+                        storage,
+                        key_base_path,
+                    )
+                })
+                .await?
+                .signature
+            }
+        };
+
+        log::info!("Migrated {} note(s): {}", pair.len(), signature);
+        result.notes_migrated += pair.len();
+        result.signatures.push(signature);
+    }
+
+    Ok(result)
+}
+
+/// Keep only V1 notes that still hold value
+fn filter_v1_notes(utxos: Vec<Utxo>) -> Vec<Utxo> {
+    utxos
+        .into_iter()
+        .filter(|u| u.version == UtxoVersion::V1 && !u.is_dummy())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keypair::ZkKeypair;
+    use num_bigint::BigUint;
+
+    #[test]
+    fn test_filter_v1_notes() {
+        let keypair = ZkKeypair::generate().unwrap();
+        let v1 = Utxo::new(5u64, keypair.clone(), 0, None, Some(UtxoVersion::V1));
+        let v1_empty = Utxo::new(0u64, keypair.clone(), 1, None, Some(UtxoVersion::V1));
+        let v2 = Utxo::new(7u64, keypair, 2, None, Some(UtxoVersion::V2));
+
+        let notes = filter_v1_notes(vec![v1, v1_empty, v2]);
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].amount, BigUint::from(5u64));
+    }
+}
@@ -0,0 +1,80 @@
+//! Pre-fetching of relayer state ahead of a transaction
+//!
+//! Refreshes the cached [`Config`] and queries the Merkle tree state of every
+//! supported token, so a UI can warm up on screen load instead of paying the
+//! fetch latency when the user submits.
+
+use crate::config::Config;
+use crate::constants::get_supported_tokens;
+use crate::error::Result;
+use crate::utils::{query_remote_tree_state, TreeState};
+use futures::future::join_all;
+use std::collections::HashMap;
+
+/// Relayer state fetched by [`refresh_network_state`]
+#[derive(Debug, Clone)]
+pub struct NetworkState {
+    /// Freshly fetched configuration, now also cached
+    pub config: Config,
+    /// Tree state per token name (e.g. "sol", "usdc"); tokens whose tree
+    /// could not be fetched are left out
+    pub tree_states: HashMap<String, TreeState>,
+}
+
+/// Refresh the config cache and fetch every supported token's tree state
+///
+/// Transactions still query the tree state themselves, since the root and
+/// next index must be current when the proof is built; fetching it here
+/// warms the relayer connection and gives the caller a current snapshot.
+pub async fn refresh_network_state() -> Result<NetworkState> {
+    let tokens = get_supported_tokens();
+    let trees = join_all(tokens.iter().map(|token| {
+        let token_name = (token.name != "sol").then_some(token.name);
+        query_remote_tree_state(token_name)
+    }));
+    let (config, trees) = futures::join!(Config::refresh(), trees);
+
+    let mut tree_states = HashMap::new();
+    for (token, tree) in tokens.iter().zip(trees) {
+        match tree {
+            Ok(state) => {
+                tree_states.insert(token.name.to_string(), state);
+            }
+            Err(e) => log::warn!("Failed to fetch {} tree state: {}", token.name, e),
+        }
+    }
+
+    Ok(NetworkState {
+        config: config?,
+        tree_states,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{CannedTransport, TEST_HOOKS_LOCK};
+
+    #[tokio::test]
+    async fn test_refresh_network_state() {
+        let _guard = TEST_HOOKS_LOCK.lock().await;
+        // Serves a config and tree states, failing for USDT
+        let config = r#"{"withdraw_fee_rate":0.0025,"withdraw_rent_fee":0.006,
+            "deposit_fee_rate":0}"#;
+        let _transport = CannedTransport::default()
+            .route("/config", config)
+            .route_status("token=usdt", 500, "")
+            .route("/merkle/root", r#"{"root":"7","nextIndex":12}"#)
+            .install();
+        let state = refresh_network_state().await;
+        let cached = Config::get().await;
+        Config::clear_cache();
+
+        let state = state.unwrap();
+        assert_eq!(state.config.withdraw_fee_rate, 0.0025);
+        assert_eq!(cached.unwrap().withdraw_fee_rate, 0.0025);
+        assert_eq!(state.tree_states["sol"].next_index, 12);
+        assert!(state.tree_states.contains_key("usdc"));
+        assert!(!state.tree_states.contains_key("usdt"));
+    }
+}
@@ -0,0 +1,79 @@
+//! Aggregate statistics of a shielded pool
+//!
+//! Summarizes the relayer's tree state for display: how many commitments the
+//! pool holds, its total value when the relayer reports it, and how well the
+//! wallet's own notes are hidden. The anonymity set of a note is the number
+//! of commitments inserted since it, the note included: a withdrawal could
+//! have been funded by any of them, and a note withdrawn right after it was
+//! deposited stands out.
+
+use crate::error::Result;
+use crate::utils::query_remote_tree_state;
+use crate::utxo::Utxo;
+use serde::{Deserialize, Serialize};
+
+/// Statistics of one token's pool
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolStats {
+    /// Token name, e.g. "sol" or "usdc"
+    pub token: String,
+    /// Commitments in the tree, the tree's next index
+    pub commitments: u64,
+    /// Base units held by the pool, if the relayer reports it
+    pub total_value: Option<u64>,
+    /// Anonymity set of the wallet's least hidden note, its newest one;
+    /// `None` without unspent notes
+    pub anonymity_set: Option<u64>,
+}
+
+/// Anonymity set of the least hidden of `notes` in a tree of `next_index` leaves
+///
+/// Counts the commitments from the newest note's leaf to the end of the tree.
+pub fn anonymity_set(next_index: u64, notes: &[Utxo]) -> Option<u64> {
+    let newest = notes.iter().map(|note| note.index).max()?;
+    Some(next_index.saturating_sub(newest))
+}
+
+/// Fetch the pool statistics of a token, given the wallet's unspent notes
+///
+/// `token_name` is `None` for SOL, as for [`query_remote_tree_state`].
+pub async fn pool_stats(token_name: Option<&str>, notes: &[Utxo]) -> Result<PoolStats> {
+    let state = query_remote_tree_state(token_name).await?;
+    Ok(PoolStats {
+        token: token_name.unwrap_or("sol").to_string(),
+        commitments: state.next_index,
+        total_value: state.total_value,
+        anonymity_set: anonymity_set(state.next_index, notes),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{CannedTransport, TEST_HOOKS_LOCK};
+    use crate::keypair::ZkKeypair;
+
+    #[tokio::test]
+    async fn test_pool_stats() {
+        let keypair = ZkKeypair::generate().unwrap();
+        let notes: Vec<Utxo> = [3, 30]
+            .into_iter()
+            .map(|index| Utxo::new(100u64, keypair.clone(), index, None, None))
+            .collect();
+
+        let _guard = TEST_HOOKS_LOCK.lock().await;
+        let _transport = CannedTransport::default()
+            .route("/merkle/root", r#"{"root":"7","nextIndex":40,"totalValue":9000}"#)
+            .install();
+        let stats = pool_stats(None, &notes).await;
+        let empty = pool_stats(Some("usdc"), &[]).await;
+
+        let stats = stats.unwrap();
+        assert_eq!(stats.token, "sol");
+        assert_eq!(stats.commitments, 40);
+        assert_eq!(stats.total_value, Some(9000));
+        // The note at leaf 30 hides among leaves 30..40
+        assert_eq!(stats.anonymity_set, Some(10));
+        assert_eq!(empty.unwrap().anonymity_set, None);
+    }
+}
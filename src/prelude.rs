@@ -0,0 +1,28 @@
+//! Commonly used types in one import
+//!
+//! ```rust,no_run
+//! use privacy_cash::prelude::*;
+//!
+//! # async fn example() -> Result<()> {
+//! let client = PrivacyCash::new("https://api.mainnet-beta.solana.com", Keypair::new())?;
+//! println!("Wallet: {}", client.pubkey());
+//! let balance: Balance = client.get_private_balance().await?;
+//! let usdc: SplBalance = client.get_private_balance_spl(&USDC_MINT).await?;
+//! println!("{} / {}", balance, usdc.base_units);
+//! # Ok(())
+//! # }
+//! ```
+
+pub use crate::amount::{Lamports, TokenAmount};
+pub use crate::client::PrivacyCash;
+pub use crate::constants::{LAMPORTS_PER_SOL, SOL_MINT, USDC_MINT, USDT_MINT};
+pub use crate::deposit::DepositResult;
+pub use crate::deposit_spl::DepositSplResult;
+pub use crate::error::{PrivacyCashError, Result};
+pub use crate::utxo::{Balance, SplBalance};
+pub use crate::withdraw::WithdrawResult;
+pub use crate::withdraw_spl::WithdrawSplResult;
+pub use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
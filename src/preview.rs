@@ -0,0 +1,267 @@
+//! Dry-run note arithmetic for deposits and withdrawals
+//!
+//! Runs the input selection and fee and amount calculations of `deposit`,
+//! `deposit_spl`, `withdraw` and `withdraw_spl` without proving or relaying.
+
+use crate::constants::find_token_by_mint;
+use crate::encryption::EncryptionService;
+use crate::error::{PrivacyCashError, Result};
+use crate::get_utxos::get_utxos;
+use crate::get_utxos_spl::get_utxos_spl;
+use crate::storage::Storage;
+use crate::utxo::Utxo;
+use crate::withdraw::{largest_first, withdraw_amount, withdraw_fee, withdraw_fee_config};
+use crate::withdraw_spl::{spl_withdraw_amount, spl_withdraw_fee, spl_withdraw_fee_config};
+use num_bigint::BigUint;
+use num_traits::{ToPrimitive, Zero};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+
+/// Kind of transaction to preview
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionKind {
+    Deposit,
+    Withdraw,
+}
+
+/// Expected note arithmetic of a transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionPreview {
+    /// Number of existing notes spent as inputs
+    pub inputs_used: usize,
+    /// Total value of the spent notes
+    pub input_amount: u64,
+    /// Amount entering (deposit) or leaving (withdrawal) the pool
+    pub output_amount: u64,
+    /// Value of the new shielded change note
+    pub change_amount: u64,
+    /// Relayer fee in base units
+    pub fee: u64,
+    /// Whether a withdrawal would be reduced to the available balance
+    pub is_partial: bool,
+}
+
+/// Final details of a proven transaction, passed to an [`ApprovalHook`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxPreview {
+    /// Deposit or withdrawal
+    pub kind: TransactionKind,
+    /// Token name, e.g. "sol" or "usdc"
+    pub token: String,
+    /// Account receiving the withdrawn funds (`None` for deposits)
+    pub recipient: Option<String>,
+    /// Amount entering (deposit) or leaving (withdrawal) the pool in base units
+    pub amount: u64,
+    /// Relayer fee in base units
+    pub fee: u64,
+    /// Nullifiers of the two spent notes
+    pub input_nullifiers: Vec<String>,
+    /// Commitments of the two new notes
+    pub output_commitments: Vec<String>,
+}
+
+/// Callback that approves (`true`) or rejects (`false`) a transaction
+///
+/// Called after the proof and transaction are built and before anything is
+/// sent to the relayer, so the preview matches what will be submitted.
+pub type ApprovalHook = dyn Fn(&TxPreview) -> bool + Send + Sync;
+
+/// Step run on an approved transaction just before it is relayed
+///
+/// Lets the client collect a service fee only for transactions that will be
+/// submitted; an error aborts the transaction.
+pub type BeforeRelay<'a> = dyn Fn(&TxPreview) -> Result<()> + Send + Sync + 'a;
+
+/// Fail with `UserRejected` unless the hook (if any) approves the transaction
+pub(crate) fn require_approval(hook: Option<&ApprovalHook>, preview: TxPreview) -> Result<()> {
+    match hook {
+        Some(hook) if !hook(&preview) => {
+            log::info!("{:?} of {} {} rejected", preview.kind, preview.amount, preview.token);
+            Err(PrivacyCashError::UserRejected)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// [`require_approval`], then run `before_relay` (if any) on the approved transaction
+pub(crate) fn approve_for_relay(
+    hook: Option<&ApprovalHook>,
+    before_relay: Option<&BeforeRelay<'_>>,
+    preview: TxPreview,
+) -> Result<()> {
+    require_approval(hook, preview.clone())?;
+    match before_relay {
+        Some(before_relay) => before_relay(&preview),
+        None => Ok(()),
+    }
+}
+
+/// Preview a transaction without proving or relaying it
+///
+/// `mint_address` selects the token, or `None` for native SOL.
+pub async fn preview_transaction(
+    connection: &RpcClient,
+    public_key: &Pubkey,
+    encryption_service: &EncryptionService,
+    storage: &Storage,
+    mint_address: Option<&Pubkey>,
+    amount: u64,
+    kind: TransactionKind,
+) -> Result<TransactionPreview> {
+    let utxos = match mint_address {
+        Some(mint) => {
+            get_utxos_spl(connection, public_key, encryption_service, storage, mint, None).await?
+        }
+        None => get_utxos(connection, public_key, encryption_service, storage, None).await?,
+    };
+
+    match (kind, mint_address) {
+        (TransactionKind::Deposit, _) => Ok(preview_deposit(&utxos, amount)),
+        (TransactionKind::Withdraw, None) => {
+            let (fee_rate, rent_lamports) = withdraw_fee_config().await?;
+            preview_withdraw(utxos, amount, withdraw_fee(amount, fee_rate, rent_lamports))
+        }
+        (TransactionKind::Withdraw, Some(mint)) => {
+            let token = find_token_by_mint(mint)
+                .ok_or_else(|| PrivacyCashError::TokenNotSupported(mint.to_string()))?;
+            let (fee_rate, rent_base_units) = spl_withdraw_fee_config(&token).await?;
+            let fee = spl_withdraw_fee(amount, fee_rate, rent_base_units);
+            preview_withdraw_spl(utxos, amount, fee)
+        }
+    }
+}
+
+/// Deposit: the first two notes are merged with the deposited amount
+fn preview_deposit(utxos: &[Utxo], amount: u64) -> TransactionPreview {
+    let inputs: Vec<&Utxo> = utxos.iter().take(2).collect();
+    let input_amount: u64 = inputs.iter().map(|u| u.amount_u64()).sum();
+
+    TransactionPreview {
+        inputs_used: inputs.len(),
+        input_amount,
+        output_amount: amount,
+        change_amount: input_amount + amount,
+        fee: 0,
+        is_partial: false,
+    }
+}
+
+/// Pick the one or two largest notes, as the withdrawal flows do
+fn select_withdraw_inputs(utxos: Vec<Utxo>) -> Result<(usize, BigUint)> {
+    let utxos = largest_first(utxos)?;
+    let inputs = &utxos[..utxos.len().min(2)];
+    let total: BigUint = inputs.iter().map(|u| u.amount.clone()).sum();
+
+    if total.is_zero() {
+        return Err(PrivacyCashError::NoUtxosAvailable);
+    }
+
+    Ok((inputs.len(), total))
+}
+
+/// SOL withdrawal: the fee is paid on top of the requested amount
+fn preview_withdraw(utxos: Vec<Utxo>, amount: u64, fee: u64) -> Result<TransactionPreview> {
+    let (inputs_used, total) = select_withdraw_inputs(utxos)?;
+    let input_amount = total.to_u64().unwrap_or(u64::MAX);
+    let (output_amount, is_partial) = withdraw_amount(&total, amount, fee)?;
+
+    Ok(TransactionPreview {
+        inputs_used,
+        input_amount,
+        output_amount,
+        change_amount: input_amount - output_amount - fee,
+        fee,
+        is_partial,
+    })
+}
+
+/// SPL withdrawal: the fee is deducted from the requested amount
+fn preview_withdraw_spl(utxos: Vec<Utxo>, amount: u64, fee: u64) -> Result<TransactionPreview> {
+    let output_amount = amount.saturating_sub(fee);
+    if output_amount == 0 {
+        return Err(PrivacyCashError::WithdrawalAmountTooLow { minimum: fee });
+    }
+
+    let (inputs_used, total) = select_withdraw_inputs(utxos)?;
+    let input_amount = total.to_u64().unwrap_or(u64::MAX);
+    let (output_amount, is_partial) = spl_withdraw_amount(&total, output_amount, fee);
+
+    Ok(TransactionPreview {
+        inputs_used,
+        input_amount,
+        output_amount,
+        change_amount: input_amount.saturating_sub(output_amount + fee),
+        fee,
+        is_partial,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keypair::ZkKeypair;
+
+    fn notes(amounts: &[u64]) -> Vec<Utxo> {
+        let keypair = ZkKeypair::generate().unwrap();
+        amounts
+            .iter()
+            .enumerate()
+            .map(|(i, a)| Utxo::new(*a, keypair.clone(), i as u64, None, None))
+            .collect()
+    }
+
+    #[test]
+    fn test_preview_deposit() {
+        let preview = preview_deposit(&notes(&[100, 200, 300]), 50);
+        assert_eq!(preview.inputs_used, 2);
+        assert_eq!(preview.change_amount, 350);
+        assert_eq!(preview.fee, 0);
+    }
+
+    #[test]
+    fn test_preview_withdraw() {
+        let preview = preview_withdraw(notes(&[100, 500, 300]), 400, 10).unwrap();
+        assert_eq!(preview.input_amount, 800);
+        assert_eq!(preview.change_amount, 390);
+        assert!(!preview.is_partial);
+
+        let partial = preview_withdraw(notes(&[100]), 400, 10).unwrap();
+        assert!(partial.is_partial);
+        assert_eq!(partial.output_amount, 90);
+        assert_eq!(partial.change_amount, 0);
+    }
+
+    #[test]
+    fn test_require_approval() {
+        let tx = TxPreview {
+            kind: TransactionKind::Withdraw,
+            token: "sol".to_string(),
+            recipient: Some(Pubkey::new_unique().to_string()),
+            amount: 1_000,
+            fee: 10,
+            input_nullifiers: vec!["1".to_string(), "2".to_string()],
+            output_commitments: vec!["3".to_string(), "4".to_string()],
+        };
+
+        let small_fees = |tx: &TxPreview| tx.fee < 100;
+        assert!(require_approval(None, tx.clone()).is_ok());
+        assert!(require_approval(Some(&small_fees), tx.clone()).is_ok());
+        assert!(matches!(
+            require_approval(Some(&|_: &TxPreview| false), tx.clone()),
+            Err(PrivacyCashError::UserRejected)
+        ));
+
+        // A rejected transaction never reaches the step that collects fees
+        let collected = std::sync::atomic::AtomicBool::new(false);
+        let collect = |_: &TxPreview| {
+            collected.store(true, std::sync::atomic::Ordering::SeqCst);
+            Ok(())
+        };
+        let rejected = approve_for_relay(Some(&|_: &TxPreview| false), Some(&collect), tx.clone());
+        assert!(matches!(rejected, Err(PrivacyCashError::UserRejected)));
+        assert!(!collected.load(std::sync::atomic::Ordering::SeqCst));
+        approve_for_relay(Some(&small_fees), Some(&collect), tx).unwrap();
+        assert!(collected.load(std::sync::atomic::Ordering::SeqCst));
+    }
+}
@@ -2,13 +2,16 @@
 //!
 //! Uses snarkjs WASM for proof generation, compatible with the TypeScript SDK.
 
+use crate::constants::DEFAULT_PROOF_TIMEOUT_SECS;
 use crate::error::{PrivacyCashError, Result};
 use crate::utils::biguint_to_bytes_le;
+use ark_ff::PrimeField;
 use num_bigint::BigUint;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
-use std::process::Command;
+use std::time::Duration;
+use tokio::process::Command;
 
 /// Groth16 proof structure (compatible with snarkjs)
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,13 +34,278 @@ fn default_curve() -> String {
 }
 
 /// Parsed proof in bytes for on-chain submission
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ProofBytes {
     pub proof_a: Vec<u8>,
     pub proof_b: Vec<u8>,
     pub proof_c: Vec<u8>,
 }
 
+impl ProofBytes {
+    /// Length of `proof_a`, a BN254 G1 point
+    pub const PROOF_A_LEN: usize = 64;
+    /// Length of `proof_b`, a BN254 G2 point
+    pub const PROOF_B_LEN: usize = 128;
+    /// Length of `proof_c`, a BN254 G1 point
+    pub const PROOF_C_LEN: usize = 64;
+    /// Length of the three components concatenated
+    pub const LEN: usize = Self::PROOF_A_LEN + Self::PROOF_B_LEN + Self::PROOF_C_LEN;
+
+    /// Fail with `InvalidProof` unless each component has its Groth16 length
+    pub fn validate(&self) -> Result<()> {
+        let components = [
+            ("proof_a", self.proof_a.len(), Self::PROOF_A_LEN),
+            ("proof_b", self.proof_b.len(), Self::PROOF_B_LEN),
+            ("proof_c", self.proof_c.len(), Self::PROOF_C_LEN),
+        ];
+        for (name, len, expected) in components {
+            if len != expected {
+                return Err(PrivacyCashError::InvalidProof(format!(
+                    "{} is {} bytes, expected {}",
+                    name, len, expected
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Hex encoding of `proof_a || proof_b || proof_c`
+    pub fn to_hex(&self) -> String {
+        hex::encode([&self.proof_a[..], &self.proof_b[..], &self.proof_c[..]].concat())
+    }
+
+    /// Parse the output of [`ProofBytes::to_hex`]
+    pub fn from_hex(hex_str: &str) -> Result<Self> {
+        let bytes = hex::decode(hex_str.trim())
+            .map_err(|e| PrivacyCashError::InvalidProof(format!("Invalid hex: {}", e)))?;
+        if bytes.len() != Self::LEN {
+            return Err(PrivacyCashError::InvalidProof(format!(
+                "Proof is {} bytes, expected {}",
+                bytes.len(),
+                Self::LEN
+            )));
+        }
+
+        let (proof_a, rest) = bytes.split_at(Self::PROOF_A_LEN);
+        let (proof_b, proof_c) = rest.split_at(Self::PROOF_B_LEN);
+        Ok(Self {
+            proof_a: proof_a.to_vec(),
+            proof_b: proof_b.to_vec(),
+            proof_c: proof_c.to_vec(),
+        })
+    }
+}
+
+/// Number of inputs and outputs a transaction circuit takes
+///
+/// Circuits follow the `transaction<N>` naming of the circuit files: `N`
+/// inputs and two outputs. The flows only assemble and submit two-input
+/// transactions; the arity exists so that other circuit files are rejected
+/// with `UnsupportedCircuit` before any input is built, rather than failing
+/// inside the prover or on-chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CircuitArity {
+    /// Notes spent per transaction
+    pub inputs: usize,
+    /// Notes created per transaction
+    pub outputs: usize,
+}
+
+impl CircuitArity {
+    /// The deployed two-input, two-output circuit
+    pub const TRANSACTION2: Self = Self {
+        inputs: 2,
+        outputs: 2,
+    };
+
+    /// Read the arity from the name of the circuit files
+    ///
+    /// `key_base_path` is the path of the `.wasm` and `.zkey` files without
+    /// extension. Paths that are not named `transaction<N>`, including a bare
+    /// `transaction`, are assumed to be the deployed two-input circuit.
+    pub fn from_key_base_path(key_base_path: &str) -> Result<Self> {
+        let name = Path::new(key_base_path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        let digits = match name.strip_prefix("transaction") {
+            Some(digits) if digits.starts_with(|c: char| c.is_ascii_digit()) => digits,
+            _ => return Ok(Self::TRANSACTION2),
+        };
+
+        match digits.parse::<usize>() {
+            Ok(inputs) if inputs >= 1 => Ok(Self {
+                inputs,
+                outputs: Self::TRANSACTION2.outputs,
+            }),
+            _ => Err(PrivacyCashError::UnsupportedCircuit(format!(
+                "cannot read the input count from '{}'",
+                name
+            ))),
+        }
+    }
+
+    /// Public signals the circuit outputs
+    ///
+    /// root, publicAmount and extDataHash, then one nullifier per input and
+    /// one commitment per output.
+    pub fn public_signal_count(&self) -> usize {
+        3 + self.inputs + self.outputs
+    }
+
+    /// Fail if the prover returned fewer public signals than the circuit has
+    pub fn check_public_signals(&self, public_signals: &[String]) -> Result<()> {
+        if public_signals.len() < self.public_signal_count() {
+            return Err(PrivacyCashError::UnsupportedCircuit(format!(
+                "expected {} public signals, prover returned {}",
+                self.public_signal_count(),
+                public_signals.len()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Fail unless the program's transact instruction accepts this arity
+    ///
+    /// The instruction carries exactly two nullifier PDAs and two encrypted
+    /// outputs, so larger circuits can prove but cannot be submitted yet.
+    pub fn ensure_supported_on_chain(&self) -> Result<()> {
+        if *self != Self::TRANSACTION2 {
+            return Err(PrivacyCashError::UnsupportedCircuit(format!(
+                "the program only accepts {}x{} transactions, not {}x{} (inputs x outputs)",
+                Self::TRANSACTION2.inputs,
+                Self::TRANSACTION2.outputs,
+                self.inputs,
+                self.outputs
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Where [`resolve_circuit_path`] found the circuit files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CircuitSource {
+    /// Path passed by the caller
+    Explicit,
+    /// The `PRIVACY_CASH_CIRCUIT_PATH` environment variable
+    Environment,
+    /// `circuit/transaction2` next to the running executable
+    Executable,
+    /// `circuit/transaction2` under the working directory
+    WorkingDirectory,
+}
+
+/// Circuit base path chosen by [`resolve_circuit_path`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedCircuit {
+    /// Base path of the `.wasm` and `.zkey` files
+    pub path: String,
+    /// Which location the path came from
+    pub source: CircuitSource,
+}
+
+/// Locate the circuit files
+///
+/// Locations are tried in order: `explicit`, the `PRIVACY_CASH_CIRCUIT_PATH`
+/// environment variable, `circuit/transaction2` next to the executable, then
+/// `circuit/transaction2` under the working directory. A path given explicitly
+/// or through the environment must be valid; it is never silently replaced by
+/// a default. Fails with `CircuitNotFound` naming every location tried.
+pub fn resolve_circuit_path(explicit: Option<&str>) -> Result<ResolvedCircuit> {
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+    let cwd = std::env::current_dir().ok();
+    resolve_from(
+        explicit,
+        std::env::var(crate::constants::CIRCUIT_PATH_ENV).ok().as_deref(),
+        exe_dir.as_deref(),
+        cwd.as_deref(),
+    )
+}
+
+fn resolve_from(
+    explicit: Option<&str>,
+    env: Option<&str>,
+    exe_dir: Option<&Path>,
+    cwd: Option<&Path>,
+) -> Result<ResolvedCircuit> {
+    use crate::constants::{CIRCUIT_PATH_ENV, DEFAULT_CIRCUIT_PATH};
+
+    let configured = explicit
+        .map(|path| (path, CircuitSource::Explicit, "circuit path"))
+        .or_else(|| env.map(|path| (path, CircuitSource::Environment, CIRCUIT_PATH_ENV)));
+    if let Some((path, source, origin)) = configured {
+        return match validate_circuit_files(path) {
+            Ok(()) => Ok(ResolvedCircuit { path: path.to_string(), source }),
+            Err(reason) => Err(PrivacyCashError::CircuitNotFound(format!(
+                "{} {} is unusable: {}",
+                origin, path, reason
+            ))),
+        };
+    }
+
+    let defaults = [
+        (exe_dir, CircuitSource::Executable),
+        (cwd, CircuitSource::WorkingDirectory),
+    ];
+    let mut tried = Vec::new();
+    for (dir, source) in defaults {
+        let Some(dir) = dir else { continue };
+        let path = dir.join(DEFAULT_CIRCUIT_PATH).to_string_lossy().to_string();
+        match validate_circuit_files(&path) {
+            Ok(()) => return Ok(ResolvedCircuit { path, source }),
+            Err(reason) => tried.push(format!("{} ({})", path, reason)),
+        }
+    }
+
+    Err(PrivacyCashError::CircuitNotFound(format!(
+        "tried {}. Download the circuit files from the Privacy Cash SDK, or pass a circuit \
+         path or set {}",
+        tried.join(", "),
+        CIRCUIT_PATH_ENV
+    )))
+}
+
+/// Check that `<base>.wasm` and `<base>.zkey` exist and have the right headers
+pub fn validate_circuit_files(base: &str) -> std::result::Result<(), String> {
+    for (extension, magic) in [("wasm", b"\0asm"), ("zkey", b"zkey")] {
+        let path = format!("{}.{}", base, extension);
+        let mut header = [0u8; 4];
+        let read = std::fs::File::open(&path).and_then(|mut file| {
+            use std::io::Read;
+            file.read_exact(&mut header)
+        });
+        match read {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(format!("{} not found", path));
+            }
+            Err(e) if e.kind() != std::io::ErrorKind::UnexpectedEof => {
+                return Err(format!("cannot read {}: {}", path, e));
+            }
+            Ok(()) if &header == magic => {}
+            _ => return Err(format!("{} is not a {} file", path, extension)),
+        }
+    }
+    Ok(())
+}
+
+/// Write placeholder circuit files next to the test executable
+///
+/// Lets tests construct spending clients without the real circuit files.
+#[cfg(test)]
+pub(crate) fn install_test_circuit() {
+    static INSTALL: std::sync::Once = std::sync::Once::new();
+    INSTALL.call_once(|| {
+        let exe = std::env::current_exe().unwrap();
+        let base = exe.parent().unwrap().join(crate::constants::DEFAULT_CIRCUIT_PATH);
+        std::fs::create_dir_all(base.parent().unwrap()).unwrap();
+        std::fs::write(base.with_extension("wasm"), b"\0asm").unwrap();
+        std::fs::write(base.with_extension("zkey"), b"zkey").unwrap();
+    });
+}
+
 /// Circuit input for proof generation
 #[derive(Debug, Clone, Serialize)]
 pub struct CircuitInput {
@@ -78,6 +346,62 @@ pub struct CircuitInput {
 }
 
 impl CircuitInput {
+    /// Check that every per-input and per-output field matches `arity`
+    pub fn check_arity(&self, arity: CircuitArity) -> Result<()> {
+        let inputs = [
+            ("inputNullifier", self.input_nullifier.len()),
+            ("inAmount", self.in_amount.len()),
+            ("inPrivateKey", self.in_private_key.len()),
+            ("inBlinding", self.in_blinding.len()),
+            ("inPathIndices", self.in_path_indices.len()),
+            ("inPathElements", self.in_path_elements.len()),
+        ];
+        let outputs = [
+            ("outputCommitment", self.output_commitment.len()),
+            ("outAmount", self.out_amount.len()),
+            ("outBlinding", self.out_blinding.len()),
+            ("outPubkey", self.out_pubkey.len()),
+        ];
+
+        let expected = inputs
+            .iter()
+            .map(|(field, len)| (field, len, arity.inputs))
+            .chain(outputs.iter().map(|(field, len)| (field, len, arity.outputs)));
+        for (field, len, want) in expected {
+            if *len != want {
+                return Err(PrivacyCashError::UnsupportedCircuit(format!(
+                    "{} has {} entries but the circuit takes {}",
+                    field, len, want
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fail with `InvalidProof` unless `public_signals` were proven for this input
+    ///
+    /// Compares the root, nullifiers and output commitments against the
+    /// signals, which carry them around publicAmount and extDataHash (see
+    /// [`CircuitArity::public_signal_count`]).
+    pub fn check_public_signals(&self, public_signals: &[String]) -> Result<()> {
+        let parse = |s: &str| BigUint::parse_bytes(s.as_bytes(), 10);
+        let nullifiers = self.input_nullifier.iter().map(|n| ("inputNullifier", n));
+        let commitments = self.output_commitment.iter().map(|c| ("outputCommitment", c));
+        let expected = std::iter::once((0, ("root", &self.root)))
+            .chain((3..).zip(nullifiers.chain(commitments)));
+
+        for (index, (field, want)) in expected {
+            let got = public_signals.get(index).and_then(|s| parse(s));
+            if got.is_none() || got != parse(want) {
+                return Err(PrivacyCashError::InvalidProof(format!(
+                    "public signal {} does not match the {} of the circuit input",
+                    index, field
+                )));
+            }
+        }
+        Ok(())
+    }
+
     /// Convert to JSON for snarkjs
     pub fn to_json(&self) -> Result<String> {
         // Convert BigUint fields to strings for JSON serialization
@@ -145,6 +469,12 @@ impl CircuitInput {
         serde_json::to_string(&input_map)
             .map_err(|e| PrivacyCashError::SerializationError(e.to_string()))
     }
+
+    /// Write the snarkjs input JSON to `path`, e.g. for an external prover
+    pub fn write_json(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
 }
 
 /// Prover for generating ZK proofs
@@ -157,6 +487,8 @@ impl CircuitInput {
 pub struct Prover {
     /// Base path for circuit files (.wasm and .zkey)
     key_base_path: String,
+    /// Limit on a single proof generation
+    timeout: Duration,
 }
 
 impl Prover {
@@ -164,15 +496,37 @@ impl Prover {
     pub fn new(key_base_path: &str) -> Self {
         Self {
             key_base_path: key_base_path.to_string(),
+            timeout: Duration::from_secs(DEFAULT_PROOF_TIMEOUT_SECS),
         }
     }
 
+    /// Set the proof generation timeout (default 120s)
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
     /// Generate a ZK proof using snarkjs CLI
     ///
     /// This method shells out to snarkjs which must be installed globally.
     /// For production use, consider using the TypeScript SDK for proof generation
     /// or implementing a native WASM-based prover.
+    ///
+    /// Returns `ProofTimeout` if both snarkjs steps together exceed the
+    /// timeout; the running snarkjs process is killed. A snarkjs step that
+    /// exits unsuccessfully returns `BridgeError` with its stderr.
     pub async fn prove(&self, input: &CircuitInput) -> Result<(Proof, Vec<String>)> {
+        tokio::time::timeout(self.timeout, self.prove_with_snarkjs(input))
+            .await
+            .map_err(|_| PrivacyCashError::ProofTimeout {
+                seconds: self.timeout.as_secs(),
+            })?
+    }
+
+    async fn prove_with_snarkjs(&self, input: &CircuitInput) -> Result<(Proof, Vec<String>)> {
+        let arity = CircuitArity::from_key_base_path(&self.key_base_path)?;
+        input.check_arity(arity)?;
+
         let wasm_path = format!("{}.wasm", self.key_base_path);
         let zkey_path = format!("{}.zkey", self.key_base_path);
 
@@ -204,56 +558,26 @@ impl Prover {
 
         // Generate witness using snarkjs
         log::debug!("Generating witness...");
-        let witness_output = Command::new("snarkjs")
-            .args([
-                "wtns",
-                "calculate",
-                &wasm_path,
-                input_path.to_str().unwrap(),
-                witness_path.to_str().unwrap(),
-            ])
-            .output()
-            .map_err(|e| {
-                PrivacyCashError::ProofGenerationError(format!(
-                    "Failed to run snarkjs witness calculation. Is snarkjs installed? (npm install -g snarkjs): {}",
-                    e
-                ))
-            })?;
-
-        if !witness_output.status.success() {
-            let stderr = String::from_utf8_lossy(&witness_output.stderr);
-            return Err(PrivacyCashError::ProofGenerationError(format!(
-                "Witness calculation failed: {}",
-                stderr
-            )));
-        }
+        run_snarkjs(&[
+            "wtns",
+            "calculate",
+            &wasm_path,
+            input_path.to_str().unwrap(),
+            witness_path.to_str().unwrap(),
+        ])
+        .await?;
 
         // Generate proof
         log::debug!("Generating proof...");
-        let proof_output = Command::new("snarkjs")
-            .args([
-                "groth16",
-                "prove",
-                &zkey_path,
-                witness_path.to_str().unwrap(),
-                proof_path.to_str().unwrap(),
-                public_path.to_str().unwrap(),
-            ])
-            .output()
-            .map_err(|e| {
-                PrivacyCashError::ProofGenerationError(format!(
-                    "Failed to run snarkjs proof generation: {}",
-                    e
-                ))
-            })?;
-
-        if !proof_output.status.success() {
-            let stderr = String::from_utf8_lossy(&proof_output.stderr);
-            return Err(PrivacyCashError::ProofGenerationError(format!(
-                "Proof generation failed: {}",
-                stderr
-            )));
-        }
+        run_snarkjs(&[
+            "groth16",
+            "prove",
+            &zkey_path,
+            witness_path.to_str().unwrap(),
+            proof_path.to_str().unwrap(),
+            public_path.to_str().unwrap(),
+        ])
+        .await?;
 
         // Read proof and public signals
         let proof_json = std::fs::read_to_string(&proof_path)
@@ -275,13 +599,14 @@ impl Prover {
         let _ = std::fs::remove_file(&proof_path);
         let _ = std::fs::remove_file(&public_path);
 
+        arity.check_public_signals(&public_signals)?;
         log::debug!("Proof generated successfully");
         Ok((proof, public_signals))
     }
 
     /// Check if snarkjs is available
     pub fn check_snarkjs_available() -> bool {
-        Command::new("snarkjs")
+        std::process::Command::new("snarkjs")
             .arg("--version")
             .output()
             .map(|o| o.status.success())
@@ -289,27 +614,74 @@ impl Prover {
     }
 }
 
+/// Run one snarkjs step to completion
+async fn run_snarkjs(args: &[&str]) -> Result<()> {
+    run_subprocess("snarkjs", args).await
+}
+
+/// Run `program`, killing it if the returned future is dropped
+///
+/// The child is owned by the future, so a timeout, a cancelled task or a
+/// panic elsewhere in the flow kills the process instead of leaking it.
+async fn run_subprocess(program: &str, args: &[&str]) -> Result<()> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| {
+            PrivacyCashError::ProofGenerationError(format!(
+                "Failed to run {}. Is snarkjs installed? (npm install -g snarkjs): {}",
+                program, e
+            ))
+        })?;
+
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    Err(PrivacyCashError::BridgeError {
+        stderr: if stderr.is_empty() {
+            format!("{} exited with {}", program, output.status)
+        } else {
+            stderr
+        },
+    })
+}
+
 /// Parse proof to bytes array for on-chain submission
 /// 
 /// Matches the TypeScript SDK's parseProofToBytesArray function:
 /// - pi_a, pi_c: Each coordinate is LE bytes then reversed to BE
 /// - pi_b: Each coordinate is LE bytes, then the entire 64-byte chunk is reversed
 pub fn parse_proof_to_bytes(proof: &Proof) -> Result<ProofBytes> {
+    let shape_ok = proof.pi_a.len() >= 2
+        && proof.pi_c.len() >= 2
+        && proof.pi_b.len() >= 2
+        && proof.pi_b[..2].iter().all(|coords| coords.len() >= 2);
+    if !shape_ok {
+        return Err(PrivacyCashError::InvalidProof("Missing proof coordinates".to_string()));
+    }
+
+    // Coordinates are BN254 base field elements, so anything larger would be
+    // silently truncated to 32 bytes below
+    let modulus = BigUint::from(ark_bn254::Fq::MODULUS);
+
     // For pi_a and pi_c: convert to LE then reverse to BE
     let parse_coord_be = |s: &str| -> Result<Vec<u8>> {
         let n = BigUint::parse_bytes(s.as_bytes(), 10)
             .ok_or_else(|| PrivacyCashError::SerializationError("Invalid coordinate".to_string()))?;
+        if n >= modulus {
+            return Err(PrivacyCashError::InvalidProof(format!(
+                "Coordinate {} is outside the BN254 base field",
+                s
+            )));
+        }
         let bytes = biguint_to_bytes_le(&n);
         // Reverse for big-endian format
         Ok(bytes.iter().rev().cloned().collect())
     };
-    
-    // For pi_b: convert to LE, keep as LE (no reverse per element)
-    let parse_coord_le = |s: &str| -> Result<Vec<u8>> {
-        let n = BigUint::parse_bytes(s.as_bytes(), 10)
-            .ok_or_else(|| PrivacyCashError::SerializationError("Invalid coordinate".to_string()))?;
-        Ok(biguint_to_bytes_le(&n).to_vec())
-    };
 
     // Proof A: [x, y] flattened, each coord is BE
     let mut proof_a = Vec::new();
@@ -338,11 +710,14 @@ pub fn parse_proof_to_bytes(proof: &Proof) -> Result<ProofBytes> {
     proof_c.extend(parse_coord_be(&proof.pi_c[0])?);
     proof_c.extend(parse_coord_be(&proof.pi_c[1])?);
 
-    Ok(ProofBytes {
+    let proof_bytes = ProofBytes {
         proof_a,
         proof_b,
         proof_c,
-    })
+    };
+    proof_bytes.validate()?;
+
+    Ok(proof_bytes)
 }
 
 /// Parse public signals to bytes arrays
@@ -377,6 +752,79 @@ pub fn parse_public_signals_to_bytes(signals: &[String]) -> Result<Vec<[u8; 32]>
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_resolve_circuit_path() {
+        let root =
+            std::env::temp_dir().join(format!("privacy-cash-circuit-{}", std::process::id()));
+        let exe_dir = root.join("bin");
+        let cwd = root.join("work");
+        let base = cwd.join(crate::constants::DEFAULT_CIRCUIT_PATH);
+        std::fs::create_dir_all(base.parent().unwrap()).unwrap();
+        std::fs::write(base.with_extension("wasm"), b"\0asm").unwrap();
+        std::fs::write(base.with_extension("zkey"), b"zkey").unwrap();
+        let base = base.to_string_lossy().to_string();
+
+        let found = resolve_from(None, None, Some(&exe_dir), Some(&cwd)).unwrap();
+        assert_eq!((found.path, found.source), (base.clone(), CircuitSource::WorkingDirectory));
+        let from_env = resolve_from(None, Some(&base), Some(&exe_dir), None).unwrap();
+        assert_eq!(from_env.source, CircuitSource::Environment);
+
+        // A configured path is never replaced by a default
+        let missing = root.join("missing").to_string_lossy().to_string();
+        let err = resolve_from(Some(&missing), Some(&base), None, Some(&cwd)).unwrap_err();
+        assert!(matches!(err, PrivacyCashError::CircuitNotFound(_)));
+        assert!(resolve_from(None, None, Some(&exe_dir), None).is_err());
+
+        std::fs::write(format!("{}.zkey", base), b"{}").unwrap();
+        assert!(validate_circuit_files(&base).unwrap_err().contains("not a zkey file"));
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_circuit_arity() {
+        let arity = CircuitArity::from_key_base_path("./circuit/transaction2").unwrap();
+        assert_eq!(arity, CircuitArity::TRANSACTION2);
+        assert_eq!(arity.public_signal_count(), 7);
+        assert!(arity.ensure_supported_on_chain().is_ok());
+        assert_eq!(CircuitArity::from_key_base_path("/keys/custom").unwrap(), arity);
+
+        let wide = CircuitArity::from_key_base_path("/keys/transaction16").unwrap();
+        assert_eq!((wide.inputs, wide.outputs), (16, 2));
+        assert_eq!(wide.public_signal_count(), 21);
+        assert!(matches!(
+            wide.ensure_supported_on_chain(),
+            Err(PrivacyCashError::UnsupportedCircuit(_))
+        ));
+        assert_eq!(CircuitArity::from_key_base_path("/keys/transaction").unwrap(), arity);
+        assert_eq!(CircuitArity::from_key_base_path("transaction_v2").unwrap(), arity);
+        assert!(CircuitArity::from_key_base_path("transaction16x").is_err());
+        assert!(CircuitArity::from_key_base_path("transaction0").is_err());
+
+        let signals = vec!["0".to_string(); 7];
+        assert!(arity.check_public_signals(&signals).is_ok());
+        assert!(wide.check_public_signals(&signals).is_err());
+
+        let input = CircuitInput {
+            root: "0".to_string(),
+            input_nullifier: vec!["1".to_string(); 2],
+            output_commitment: vec!["2".to_string(); 2],
+            public_amount: "0".to_string(),
+            ext_data_hash: vec![0; 32],
+            in_amount: vec!["0".to_string(); 2],
+            in_private_key: vec![BigUint::from(1u8); 2],
+            in_blinding: vec!["0".to_string(); 2],
+            in_path_indices: vec![0; 2],
+            in_path_elements: vec![vec![]; 2],
+            out_amount: vec!["0".to_string(); 2],
+            out_blinding: vec!["0".to_string(); 2],
+            out_pubkey: vec![BigUint::from(1u8); 2],
+            mint_address: "0".to_string(),
+        };
+        assert!(input.check_arity(arity).is_ok());
+        assert!(input.check_arity(wide).is_err());
+    }
+
     #[test]
     fn test_parse_public_signals() {
         let signals = vec!["123".to_string(), "456".to_string()];
@@ -385,6 +833,59 @@ mod tests {
         assert_eq!(bytes[0].len(), 32);
     }
 
+    #[test]
+    fn test_proof_bytes_validation() {
+        let coords = |n: usize| (1..=n).map(|i| i.to_string()).collect::<Vec<_>>();
+        let proof = Proof {
+            pi_a: coords(3),
+            pi_b: vec![coords(2), coords(2), coords(2)],
+            pi_c: coords(3),
+            protocol: default_protocol(),
+            curve: default_curve(),
+        };
+        let bytes = parse_proof_to_bytes(&proof).unwrap();
+        assert_eq!(bytes.proof_b.len(), ProofBytes::PROOF_B_LEN);
+        assert_eq!(ProofBytes::from_hex(&bytes.to_hex()).unwrap(), bytes);
+        assert!(ProofBytes::from_hex(&bytes.to_hex()[2..]).is_err());
+
+        let mut short = proof.clone();
+        short.pi_b.truncate(1);
+        assert!(matches!(parse_proof_to_bytes(&short), Err(PrivacyCashError::InvalidProof(_))));
+
+        let mut oversized = proof;
+        oversized.pi_c[0] = BigUint::from(ark_bn254::Fq::MODULUS).to_string();
+        assert!(matches!(
+            parse_proof_to_bytes(&oversized),
+            Err(PrivacyCashError::InvalidProof(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_subprocess_errors_and_cleanup() {
+        let err = run_subprocess("sh", &["-c", "echo 'bad witness' >&2; exit 3"])
+            .await
+            .unwrap_err();
+        let stderr = match err {
+            PrivacyCashError::BridgeError { stderr } => stderr,
+            other => panic!("unexpected error: {}", other),
+        };
+        assert_eq!(stderr, "bad witness");
+        let err = run_subprocess("sh", &["-c", "exit 2"]).await.unwrap_err();
+        assert!(err.to_string().contains("exit status: 2"));
+        assert!(run_subprocess("sh", &["-c", "true"]).await.is_ok());
+
+        // A timed out step takes its process down with it
+        let marker = std::env::temp_dir()
+            .join(format!("privacy-cash-subprocess-{}", std::process::id()));
+        let script = format!("sleep 1; touch {}", marker.display());
+        let args = ["-c", script.as_str()];
+        let step = run_subprocess("sh", &args);
+        assert!(tokio::time::timeout(Duration::from_millis(100), step).await.is_err());
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(!marker.exists());
+    }
+
     #[test]
     fn test_check_snarkjs() {
         // This will only pass if snarkjs is installed
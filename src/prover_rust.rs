@@ -5,8 +5,15 @@
 //! 
 //! This is the iOS-compatible prover that doesn't require Node.js/snarkjs.
 
+use crate::constants::{
+    DEFAULT_PROOF_ESTIMATE_SECS, DEFAULT_PROOF_TIMEOUT_SECS, LSK_PROOF_SECONDS,
+    PROOF_TIME_EMA_WEIGHT,
+};
 use crate::error::{PrivacyCashError, Result};
-use crate::prover::{CircuitInput, Proof};
+use crate::external_prover::{external_prover, ExternalProver};
+use crate::metrics::{metrics, PROOF_SECONDS};
+use crate::prover::{CircuitArity, CircuitInput, Proof};
+use crate::storage::Storage;
 use ark_bn254::{Bn254, Fr};
 use ark_circom::{read_zkey, CircomReduction, WitnessCalculator};
 use ark_groth16::Groth16;
@@ -15,6 +22,7 @@ use num_bigint::BigUint;
 use std::collections::HashMap;
 use std::fs::File;
 use std::path::Path;
+use std::time::Duration;
 
 type GrothBn = Groth16<Bn254, CircomReduction>;
 
@@ -32,9 +40,12 @@ pub struct RustProofResult {
 /// This prover uses ark-circom for native proof generation,
 /// making it compatible with iOS and other platforms that
 /// cannot run Node.js/snarkjs.
+#[derive(Clone)]
 pub struct RustProver {
     /// Base path for circuit files (.wasm and .zkey)
     key_base_path: String,
+    /// Limit on a single proof generation
+    timeout: Duration,
 }
 
 impl RustProver {
@@ -42,14 +53,110 @@ impl RustProver {
     pub fn new(key_base_path: &str) -> Self {
         Self {
             key_base_path: key_base_path.to_string(),
+            timeout: Duration::from_secs(DEFAULT_PROOF_TIMEOUT_SECS),
         }
     }
 
+    /// Set the proof generation timeout (default 120s)
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
     /// Generate a ZK proof using pure Rust (ark-circom)
     ///
     /// This method provides the same interface as the snarkjs-based Prover,
-    /// but uses native Rust code for proof generation.
+    /// but uses native Rust code for proof generation. Proving runs on a
+    /// blocking thread; if it exceeds the timeout, `ProofTimeout` is returned
+    /// and the abandoned computation is left to finish in the background.
     pub async fn prove(&self, input: &CircuitInput) -> Result<(Proof, Vec<String>)> {
+        if let Some(external) = external_prover() {
+            return self.prove_external(external.as_ref(), input).await;
+        }
+
+        let prover = self.clone();
+        let input = input.clone();
+        let started = std::time::Instant::now();
+        let task = tokio::task::spawn_blocking(move || prover.prove_blocking(&input));
+
+        let result = match tokio::time::timeout(self.timeout, task).await {
+            Ok(joined) => joined.map_err(|e| {
+                PrivacyCashError::ProofGenerationError(format!("Prover task failed: {}", e))
+            })?,
+            Err(_) => Err(PrivacyCashError::ProofTimeout {
+                seconds: self.timeout.as_secs(),
+            }),
+        };
+
+        if result.is_ok() {
+            metrics().observe_histogram(PROOF_SECONDS, started.elapsed().as_secs_f64(), &[]);
+        }
+        result
+    }
+
+    /// Hand the circuit input to the installed external prover
+    async fn prove_external(
+        &self,
+        external: &dyn ExternalProver,
+        input: &CircuitInput,
+    ) -> Result<(Proof, Vec<String>)> {
+        let arity = CircuitArity::from_key_base_path(&self.key_base_path)?;
+        input.check_arity(arity)?;
+        let input_json = input.to_json()?;
+
+        log::info!("Generating ZK proof with the external prover...");
+        let (proof, public_signals) =
+            match tokio::time::timeout(self.timeout, external.prove(&input_json)).await {
+                Ok(result) => result?,
+                Err(_) => {
+                    return Err(PrivacyCashError::ProofTimeout {
+                        seconds: self.timeout.as_secs(),
+                    })
+                }
+            };
+
+        // The proof comes from outside the SDK, so check it before it is submitted
+        arity.check_public_signals(&public_signals)?;
+        input.check_public_signals(&public_signals)?;
+        let zkey_path = format!("{}.zkey", self.key_base_path);
+        if Path::new(&zkey_path).exists() {
+            let (proof, public_signals) = (proof.clone(), public_signals.clone());
+            let verify = move || verify_with_zkey(&zkey_path, &proof, &public_signals);
+            tokio::task::spawn_blocking(verify)
+                .await
+                .map_err(|e| {
+                    PrivacyCashError::ProofGenerationError(format!("Verifier task failed: {}", e))
+                })??;
+        } else {
+            log::warn!("No zkey at {}; external proof not verified locally", zkey_path);
+        }
+
+        Ok((proof, public_signals))
+    }
+
+    /// Generate a proof and fold its duration into the stored estimate
+    ///
+    /// See [`estimated_proof_time`]. Proofs from an external prover are not
+    /// timed, since the estimate describes the bundled prover on this machine.
+    pub async fn prove_recorded(
+        &self,
+        input: &CircuitInput,
+        storage: &Storage,
+    ) -> Result<(Proof, Vec<String>)> {
+        let bundled = external_prover().is_none();
+        let started = std::time::Instant::now();
+        let result = self.prove(input).await?;
+        if bundled {
+            record_proof_time(storage, started.elapsed());
+        }
+        Ok(result)
+    }
+
+    /// Generate a proof on the current thread
+    fn prove_blocking(&self, input: &CircuitInput) -> Result<(Proof, Vec<String>)> {
+        let arity = CircuitArity::from_key_base_path(&self.key_base_path)?;
+        input.check_arity(arity)?;
+
         let wasm_path = format!("{}.wasm", self.key_base_path);
         let zkey_path = format!("{}.zkey", self.key_base_path);
 
@@ -143,6 +250,7 @@ impl RustProver {
             .map(|fr| fr_to_decimal_string(*fr))
             .collect();
         
+        arity.check_public_signals(&public_signals)?;
         log::info!("  ✅ Proof complete with {} public signals", public_signals.len());
         
         // Debug: Log proof details
@@ -272,6 +380,101 @@ impl RustProver {
     }
 }
 
+/// Expected duration of the next proof on this machine
+///
+/// An exponential moving average of the proofs timed by
+/// [`RustProver::prove_recorded`], or `DEFAULT_PROOF_ESTIMATE_SECS` before
+/// the first one.
+pub fn estimated_proof_time(storage: &Storage) -> Duration {
+    let seconds = storage
+        .get(LSK_PROOF_SECONDS)
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|s| s.is_finite() && *s > 0.0)
+        .unwrap_or(DEFAULT_PROOF_ESTIMATE_SECS);
+    Duration::from_secs_f64(seconds)
+}
+
+/// Fold a measured proof time into the stored average
+pub fn record_proof_time(storage: &Storage, elapsed: Duration) {
+    let sample = elapsed.as_secs_f64();
+    let average = match storage.get(LSK_PROOF_SECONDS) {
+        Some(_) => {
+            let previous = estimated_proof_time(storage).as_secs_f64();
+            PROOF_TIME_EMA_WEIGHT * sample + (1.0 - PROOF_TIME_EMA_WEIGHT) * previous
+        }
+        None => sample,
+    };
+    storage.set(LSK_PROOF_SECONDS, &format!("{:.3}", average));
+}
+
+/// Check a snarkjs-format proof against the verifying key in `zkey_path`
+fn verify_with_zkey(zkey_path: &str, proof: &Proof, public_signals: &[String]) -> Result<()> {
+    use ark_crypto_primitives::snark::SNARK;
+
+    let mut zkey_file = File::open(zkey_path)?;
+    let (params, _) = read_zkey(&mut zkey_file).map_err(|e| {
+        PrivacyCashError::ProofGenerationError(format!("Failed to read zkey: {}", e))
+    })?;
+    let pvk = GrothBn::process_vk(&params.vk).map_err(|e| {
+        PrivacyCashError::ProofGenerationError(format!("Failed to process VK: {}", e))
+    })?;
+
+    let proof = proof_from_snarkjs(proof)?;
+    let public_inputs = public_signals
+        .iter()
+        .map(|s| field_element::<Fr>(s))
+        .collect::<Result<Vec<_>>>()?;
+    let verified = GrothBn::verify_with_processed_vk(&pvk, &public_inputs, &proof)
+        .map_err(|e| PrivacyCashError::InvalidProof(format!("Proof verification failed: {}", e)))?;
+    if !verified {
+        return Err(PrivacyCashError::InvalidProof(
+            "External proof does not verify against the zkey".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Convert a snarkjs-format proof back to ark-groth16, rejecting points off the curve
+fn proof_from_snarkjs(proof: &Proof) -> Result<ark_groth16::Proof<Bn254>> {
+    use ark_bn254::{Fq, Fq2, G1Affine, G2Affine};
+
+    let coordinate = |values: &[String], i: usize| -> Result<Fq> {
+        let value = values.get(i).ok_or_else(|| {
+            PrivacyCashError::InvalidProof("Proof point is missing a coordinate".to_string())
+        })?;
+        field_element(value)
+    };
+    let g1 = |values: &[String]| -> Result<G1Affine> {
+        let point = G1Affine::new_unchecked(coordinate(values, 0)?, coordinate(values, 1)?);
+        if !point.is_on_curve() || !point.is_in_correct_subgroup_assuming_on_curve() {
+            return Err(PrivacyCashError::InvalidProof("G1 point is not on the curve".to_string()));
+        }
+        Ok(point)
+    };
+    // snarkjs orders each Fp2 element as [c1, c0]
+    let fq2 = |i: usize| -> Result<Fq2> {
+        let values = proof.pi_b.get(i).map(Vec::as_slice).unwrap_or_default();
+        Ok(Fq2::new(coordinate(values, 1)?, coordinate(values, 0)?))
+    };
+
+    let b = G2Affine::new_unchecked(fq2(0)?, fq2(1)?);
+    if !b.is_on_curve() || !b.is_in_correct_subgroup_assuming_on_curve() {
+        return Err(PrivacyCashError::InvalidProof("G2 point is not on the curve".to_string()));
+    }
+    Ok(ark_groth16::Proof {
+        a: g1(&proof.pi_a)?,
+        b,
+        c: g1(&proof.pi_c)?,
+    })
+}
+
+/// Parse a decimal string to a field element
+fn field_element<F: ark_ff::PrimeField>(s: &str) -> Result<F> {
+    let n = BigUint::parse_bytes(s.as_bytes(), 10)
+        .ok_or_else(|| PrivacyCashError::InvalidProof(format!("Invalid number: {}", s)))?;
+    Ok(F::from_be_bytes_mod_order(&n.to_bytes_be()))
+}
+
 /// Parse a decimal string to BigInt
 fn parse_bigint(s: &str) -> Result<num_bigint::BigInt> {
     num_bigint::BigInt::parse_bytes(s.as_bytes(), 10)
@@ -302,6 +505,41 @@ mod tests {
         assert_eq!(result.unwrap(), num_bigint::BigInt::from(1234567890u64));
     }
     
+    #[test]
+    fn test_proof_time_estimate() {
+        let storage = Storage::memory();
+        let default = Duration::from_secs_f64(DEFAULT_PROOF_ESTIMATE_SECS);
+        assert_eq!(estimated_proof_time(&storage), default);
+
+        // The first sample replaces the default, later ones are averaged in
+        record_proof_time(&storage, Duration::from_secs(10));
+        assert_eq!(estimated_proof_time(&storage), Duration::from_secs(10));
+        record_proof_time(&storage, Duration::from_secs(20));
+        assert_eq!(estimated_proof_time(&storage), Duration::from_secs(13));
+
+        storage.set(LSK_PROOF_SECONDS, "garbage");
+        assert_eq!(estimated_proof_time(&storage), default);
+    }
+
+    #[test]
+    fn test_proof_from_snarkjs() {
+        use ark_ec::AffineRepr;
+
+        let proof = ark_groth16::Proof::<Bn254> {
+            a: ark_bn254::G1Affine::generator(),
+            b: ark_bn254::G2Affine::generator(),
+            c: ark_bn254::G1Affine::generator(),
+        };
+        let formatted = RustProver::new("unused").format_proof_for_snarkjs(&proof).unwrap();
+        assert_eq!(proof_from_snarkjs(&formatted).unwrap(), proof);
+
+        let mut off_curve = formatted.clone();
+        off_curve.pi_a[1] = "3".to_string();
+        assert!(matches!(proof_from_snarkjs(&off_curve), Err(PrivacyCashError::InvalidProof(_))));
+        off_curve.pi_c.clear();
+        assert!(proof_from_snarkjs(&off_curve).is_err());
+    }
+
     #[test]
     fn test_biguint_to_bigint() {
         let bu = BigUint::from(12345u64);
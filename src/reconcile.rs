@@ -0,0 +1,185 @@
+//! Comparison of the cached note set with a fresh relayer scan
+//!
+//! The cache should hold exactly the unspent notes a full scan finds. Notes
+//! only in the cache are stale entries (usually notes spent since the last
+//! scan), notes only in the scan were missed, and a note cached twice inflates
+//! the balance. Nothing is written: the cache is read as is and the scan
+//! bypasses it.
+
+use crate::constants::LSK_ENCRYPTED_OUTPUTS;
+use crate::encryption::EncryptionService;
+use crate::error::Result;
+use crate::get_utxos::{
+    are_utxos_spent_with_commitment, get_utxos_with_options, localstorage_key, ScanOptions,
+};
+use crate::get_utxos_spl::get_utxos_spl_with_options;
+use crate::storage::Storage;
+use crate::utxo::{Utxo, UtxoDetail};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use spl_associated_token_account::get_associated_token_address;
+use std::collections::HashSet;
+
+/// Where the cached note set and a fresh scan disagree
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Reconciliation {
+    /// Notes present in both
+    pub matched: usize,
+    /// Cached notes the scan did not return; `spent` tells whether they were
+    /// spent since they were cached
+    pub only_cached: Vec<UtxoDetail>,
+    /// Unspent notes the scan found that are missing from the cache
+    pub only_fresh: Vec<UtxoDetail>,
+    /// Notes cached more than once, each listed once per extra copy
+    pub cached_duplicates: Vec<UtxoDetail>,
+}
+
+impl Reconciliation {
+    /// Whether the cache matches the scan exactly
+    pub fn is_consistent(&self) -> bool {
+        self.only_cached.is_empty()
+            && self.only_fresh.is_empty()
+            && self.cached_duplicates.is_empty()
+    }
+}
+
+/// Compare the cached notes of a token with a fresh scan
+///
+/// `mint_address` selects an SPL token; `None` reconciles SOL. Cached notes
+/// missing from the scan are checked for spends at `confirmed`.
+pub async fn reconcile(
+    connection: &RpcClient,
+    public_key: &Pubkey,
+    encryption_service: &EncryptionService,
+    storage: &Storage,
+    mint_address: Option<&Pubkey>,
+) -> Result<Reconciliation> {
+    let (owner, fresh) = match mint_address {
+        Some(mint) => {
+            let fresh = get_utxos_spl_with_options(
+                connection,
+                public_key,
+                encryption_service,
+                storage,
+                mint,
+                None,
+                ScanOptions::fresh(),
+            )
+            .await?;
+            (get_associated_token_address(public_key, mint), fresh)
+        }
+        None => {
+            let fresh = get_utxos_with_options(
+                connection,
+                public_key,
+                encryption_service,
+                storage,
+                None,
+                ScanOptions::fresh(),
+            )
+            .await?;
+            (*public_key, fresh)
+        }
+    };
+
+    let cached = cached_notes(storage, &localstorage_key(&owner), encryption_service);
+    let (mut report, stale) = diff_notes(&cached, &fresh)?;
+    if !stale.is_empty() {
+        let spent =
+            are_utxos_spent_with_commitment(connection, &stale, CommitmentConfig::confirmed())
+                .await?;
+        for (detail, spent) in report.only_cached.iter_mut().zip(spent) {
+            detail.spent = spent;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Decrypt the notes in a token's outputs cache, keeping duplicates
+fn cached_notes(
+    storage: &Storage,
+    storage_key: &str,
+    encryption_service: &EncryptionService,
+) -> Vec<Utxo> {
+    let key = format!("{}{}", LSK_ENCRYPTED_OUTPUTS, storage_key);
+    storage
+        .get_outputs(&key)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|output| encryption_service.decrypt_utxo_from_hex(output).ok())
+        .collect()
+}
+
+/// Diff two note sets by commitment
+///
+/// Also returns the notes behind `only_cached`, in the same order, so their
+/// spend status can be looked up.
+fn diff_notes(cached: &[Utxo], fresh: &[Utxo]) -> Result<(Reconciliation, Vec<Utxo>)> {
+    let fresh_commitments: HashSet<String> =
+        fresh.iter().map(Utxo::get_commitment).collect::<Result<_>>()?;
+
+    let mut report = Reconciliation::default();
+    let mut stale = Vec::new();
+    let mut seen = HashSet::new();
+    for utxo in cached {
+        let commitment = utxo.get_commitment()?;
+        if !seen.insert(commitment.clone()) {
+            report.cached_duplicates.push(detail(utxo, commitment, false));
+        } else if fresh_commitments.contains(&commitment) {
+            report.matched += 1;
+        } else {
+            report.only_cached.push(detail(utxo, commitment, false));
+            stale.push(utxo.clone());
+        }
+    }
+
+    for utxo in fresh {
+        let commitment = utxo.get_commitment()?;
+        if !seen.contains(&commitment) {
+            report.only_fresh.push(detail(utxo, commitment, false));
+        }
+    }
+
+    Ok((report, stale))
+}
+
+fn detail(utxo: &Utxo, commitment: String, spent: bool) -> UtxoDetail {
+    UtxoDetail {
+        amount: utxo.amount_u64(),
+        index: utxo.index,
+        commitment,
+        mint_address: utxo.mint_address.clone(),
+        version: utxo.version,
+        spent,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keypair::ZkKeypair;
+
+    #[test]
+    fn test_diff_notes() {
+        let keypair = ZkKeypair::generate().unwrap();
+        let note = |amount: u64, index: u64| Utxo::new(amount, keypair.clone(), index, None, None);
+        let (kept, stale, missed) = (note(10, 0), note(20, 1), note(30, 2));
+
+        let stale_commitment = stale.get_commitment().unwrap();
+        let cached = [kept.clone(), stale, kept.clone()];
+        let (report, stale) = diff_notes(&cached, &[kept.clone(), missed]).unwrap();
+        assert_eq!(report.matched, 1);
+        assert_eq!(report.only_cached.len(), 1);
+        assert_eq!(report.only_cached[0].amount, 20);
+        assert_eq!(stale[0].get_commitment().unwrap(), stale_commitment);
+        assert_eq!(report.only_fresh.len(), 1);
+        assert_eq!(report.only_fresh[0].index, 2);
+        assert_eq!(report.cached_duplicates.len(), 1);
+        assert!(!report.is_consistent());
+
+        let (report, _) = diff_notes(&[kept.clone()], &[kept]).unwrap();
+        assert!(report.is_consistent());
+    }
+}
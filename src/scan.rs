@@ -0,0 +1,182 @@
+//! Wallet-wide scanning across all supported tokens
+
+use crate::config::Config;
+use crate::constants::{find_token_by_name, get_supported_tokens, TokenInfo};
+use crate::encryption::EncryptionService;
+use crate::error::Result;
+use crate::get_utxos::get_utxos;
+use crate::get_utxos_spl::get_utxos_spl;
+use crate::storage::Storage;
+use crate::utxo::{value_usd, Utxo};
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+
+/// Maximum number of tokens scanned at the same time
+const SCAN_CONCURRENCY: usize = 3;
+
+/// Scan result for a single token
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TokenSummary {
+    /// Token name (e.g. "sol", "usdc")
+    pub token: String,
+    /// Token mint address
+    pub mint: String,
+    /// Private balance in base units
+    pub balance: u64,
+    /// Number of unspent notes
+    pub utxo_count: usize,
+    /// Number of notes below the token's minimum withdrawal
+    pub dust_count: usize,
+    /// Total value of dust notes in base units
+    pub dust_amount: u64,
+    /// Scan error, if this token could not be scanned
+    pub error: Option<String>,
+}
+
+/// Scan result for the whole wallet
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WalletSummary {
+    /// One entry per supported token, in `get_supported_tokens()` order
+    pub tokens: Vec<TokenSummary>,
+}
+
+impl WalletSummary {
+    /// Get the summary for a token by name
+    pub fn token(&self, name: &str) -> Option<&TokenSummary> {
+        self.tokens.iter().find(|t| t.token == name)
+    }
+
+    /// Whether any token failed to scan
+    pub fn has_errors(&self) -> bool {
+        self.tokens.iter().any(|t| t.error.is_some())
+    }
+
+    /// Total value in USD, given prices per whole token keyed by token name
+    ///
+    /// Tokens without a usable (positive, finite) price are left out, as are
+    /// tokens that failed to scan. Prices are typically `Config::prices`.
+    pub fn total_value_usd(&self, prices: &HashMap<String, f64>) -> f64 {
+        self.tokens
+            .iter()
+            .filter_map(|summary| {
+                let token = find_token_by_name(&summary.token)?;
+                let price = *prices.get(&summary.token)?;
+                let decimals = token.units_per_token.checked_ilog10().unwrap_or(0);
+                value_usd(summary.balance, decimals, price)
+            })
+            .sum()
+    }
+}
+
+/// Scan SOL and every supported SPL token
+///
+/// Tokens are scanned concurrently (bounded). A failure for one token is
+/// recorded in its `TokenSummary::error` instead of aborting the scan.
+pub async fn scan_all(
+    connection: &RpcClient,
+    public_key: &Pubkey,
+    encryption_service: &EncryptionService,
+    storage: &Storage,
+) -> Result<WalletSummary> {
+    let tokens = get_supported_tokens();
+
+    let mut results: Vec<(usize, TokenSummary)> = stream::iter(tokens.iter().enumerate())
+        .map(|(i, token)| async move {
+            let scanned =
+                scan_token(connection, public_key, encryption_service, storage, token).await;
+            let summary = match scanned {
+                Ok(summary) => summary,
+                Err(e) => {
+                    log::warn!("Failed to scan {}: {}", token.name, e);
+                    TokenSummary {
+                        token: token.name.to_string(),
+                        mint: token.mint.to_string(),
+                        error: Some(e.to_string()),
+                        ..Default::default()
+                    }
+                }
+            };
+            (i, summary)
+        })
+        .buffer_unordered(SCAN_CONCURRENCY)
+        .collect()
+        .await;
+
+    results.sort_by_key(|(i, _)| *i);
+    Ok(WalletSummary {
+        tokens: results.into_iter().map(|(_, summary)| summary).collect(),
+    })
+}
+
+/// Scan a single token and summarize its notes
+async fn scan_token(
+    connection: &RpcClient,
+    public_key: &Pubkey,
+    encryption_service: &EncryptionService,
+    storage: &Storage,
+    token: &TokenInfo,
+) -> Result<TokenSummary> {
+    let utxos = if token.name == "sol" {
+        get_utxos(connection, public_key, encryption_service, storage, None).await?
+    } else {
+        get_utxos_spl(connection, public_key, encryption_service, storage, &token.mint, None)
+            .await?
+    };
+
+    // Dust is only reported when the relayer config is reachable
+    let dust_threshold = Config::get_or_fetch()
+        .await
+        .ok()
+        .and_then(|config| config.minimum_withdrawal_base_units(token.name, token.units_per_token))
+        .unwrap_or(0);
+
+    Ok(summarize(token, &utxos, dust_threshold))
+}
+
+fn summarize(token: &TokenInfo, utxos: &[Utxo], dust_threshold: u64) -> TokenSummary {
+    let dust: Vec<u64> = utxos
+        .iter()
+        .map(|u| u.amount_u64())
+        .filter(|amount| *amount < dust_threshold)
+        .collect();
+
+    TokenSummary {
+        token: token.name.to_string(),
+        mint: token.mint.to_string(),
+        balance: utxos.iter().map(|u| u.amount_u64()).sum(),
+        utxo_count: utxos.len(),
+        dust_count: dust.len(),
+        dust_amount: dust.iter().sum(),
+        error: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keypair::ZkKeypair;
+
+    #[test]
+    fn test_summarize() {
+        let token = find_token_by_name("usdc").unwrap();
+        let keypair = ZkKeypair::generate().unwrap();
+        let utxos: Vec<Utxo> = [5u64, 2_000_000, 10]
+            .iter()
+            .enumerate()
+            .map(|(i, a)| Utxo::new(*a, keypair.clone(), i as u64, None, None))
+            .collect();
+
+        let summary = summarize(&token, &utxos, 1_000);
+        assert_eq!(summary.balance, 2_000_015);
+        assert_eq!(summary.utxo_count, 3);
+        assert_eq!(summary.dust_count, 2);
+        assert_eq!(summary.dust_amount, 15);
+
+        let wallet = WalletSummary { tokens: vec![summary] };
+        let prices = HashMap::from([("usdc".to_string(), 1.0), ("sol".to_string(), 0.0)]);
+        assert_eq!(wallet.total_value_usd(&prices), 2.000015);
+    }
+}
@@ -0,0 +1,229 @@
+//! Offline smoke test of the cryptographic pipeline
+//!
+//! Runs every local step a transaction depends on — key derivation, Poseidon,
+//! commitments and nullifiers, the Merkle tree, note encryption and proof
+//! generation — without touching the network, and reports each step
+//! separately so a broken setup is caught before any funds move.
+
+use crate::constants::{MERKLE_TREE_DEPTH, SOL_MINT};
+use crate::deposit::first_deposit_inputs;
+use crate::encryption::EncryptionService;
+use crate::error::{PrivacyCashError, Result};
+use crate::hasher::POSEIDON_1_2;
+use crate::keypair::ZkKeypair;
+use crate::merkle_tree::{MerkleTree, DEFAULT_ZERO};
+use crate::prover::CircuitInput;
+use crate::prover_rust::RustProver;
+use crate::utils::{calculate_public_amount, get_mint_address_field, ExtData};
+use crate::utxo::{Utxo, UtxoVersion};
+use solana_sdk::pubkey::Pubkey;
+
+/// Amount of the throwaway note used by the self-test
+const TEST_AMOUNT: u64 = 1_000_000;
+
+/// Outcome of one self-test step
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestStep {
+    /// Step name, e.g. "poseidon" or "proof"
+    pub name: &'static str,
+    /// Why the step failed, `None` if it passed
+    pub error: Option<String>,
+}
+
+impl SelfTestStep {
+    /// Whether the step passed
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Per-step results of [`self_test`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelfTestReport {
+    /// Steps in the order they ran
+    pub steps: Vec<SelfTestStep>,
+}
+
+impl SelfTestReport {
+    /// Whether every step passed
+    pub fn all_passed(&self) -> bool {
+        self.steps.iter().all(SelfTestStep::passed)
+    }
+
+    /// Steps that failed
+    pub fn failures(&self) -> impl Iterator<Item = &SelfTestStep> {
+        self.steps.iter().filter(|step| !step.passed())
+    }
+
+    fn record<T>(&mut self, name: &'static str, result: Result<T>) -> Option<T> {
+        let (value, error) = match result {
+            Ok(value) => (Some(value), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        self.steps.push(SelfTestStep { name, error });
+        value
+    }
+
+    fn skip(&mut self, name: &'static str, after: &str) {
+        self.steps.push(SelfTestStep {
+            name,
+            error: Some(format!("skipped: {} failed", after)),
+        });
+    }
+}
+
+/// Run the offline self-test
+///
+/// `circuit_path` is the base path of the `.wasm` and `.zkey` files, as used
+/// by the prover. Steps that depend on a failed step are reported as skipped.
+/// The proof step takes as long as a real proof.
+pub async fn self_test(
+    encryption_service: &EncryptionService,
+    circuit_path: &str,
+) -> SelfTestReport {
+    let mut report = SelfTestReport::default();
+
+    let keypair = report.record("keys", utxo_keypair(encryption_service));
+    report.record("poseidon", check_poseidon());
+
+    let Some(keypair) = keypair else {
+        for step in ["note", "merkle_tree", "encryption", "proof"] {
+            report.skip(step, "keys");
+        }
+        return report;
+    };
+
+    let note = Utxo::new(TEST_AMOUNT, keypair.clone(), 0, None, Some(UtxoVersion::V2));
+    let Some(commitment) = report.record("note", check_note(&note)) else {
+        for step in ["merkle_tree", "encryption", "proof"] {
+            report.skip(step, "note");
+        }
+        return report;
+    };
+
+    report.record("merkle_tree", check_merkle_tree(&commitment));
+    report.record("encryption", check_encryption(encryption_service, &note, &commitment));
+    report.record("proof", check_proof(&keypair, circuit_path).await);
+
+    report
+}
+
+fn utxo_keypair(encryption_service: &EncryptionService) -> Result<ZkKeypair> {
+    ZkKeypair::from_hex(&encryption_service.get_utxo_private_key_v2()?)
+}
+
+fn failed(step: &str, reason: &str) -> PrivacyCashError {
+    PrivacyCashError::InvalidInput(format!("{} check failed: {}", step, reason))
+}
+
+fn check_poseidon() -> Result<()> {
+    let hash = ZkKeypair::poseidon_hash_strings(&["1", "2"])?;
+    if hash != POSEIDON_1_2 {
+        return Err(failed("Poseidon", "hash does not match circomlib"));
+    }
+    Ok(())
+}
+
+/// Compute the note's commitment and nullifier, returning the commitment
+fn check_note(note: &Utxo) -> Result<String> {
+    let commitment = note.get_commitment()?;
+    let nullifier = note.get_nullifier()?;
+    if commitment == nullifier || commitment != note.get_commitment()? {
+        return Err(failed("note", "commitment is not deterministic or equals the nullifier"));
+    }
+    Ok(commitment)
+}
+
+fn check_merkle_tree(commitment: &str) -> Result<()> {
+    let leaves = vec![DEFAULT_ZERO.to_string(), commitment.to_string()];
+    let tree = MerkleTree::with_elements(MERKLE_TREE_DEPTH, leaves, DEFAULT_ZERO)?;
+    if !tree.path(1)?.verify(commitment, &tree.root())? {
+        return Err(failed("Merkle tree", "path does not hash to the root"));
+    }
+    Ok(())
+}
+
+fn check_encryption(
+    encryption_service: &EncryptionService,
+    note: &Utxo,
+    commitment: &str,
+) -> Result<()> {
+    let encrypted = encryption_service.encrypt_utxo(note)?;
+    let decrypted = encryption_service.decrypt_utxo(&encrypted)?;
+    if decrypted.get_commitment()? != commitment || decrypted.index != note.index {
+        return Err(failed("encryption", "decrypted note differs from the original"));
+    }
+    Ok(())
+}
+
+/// Prove a first deposit: two dummy inputs, one funded output
+///
+/// Like a real first deposit into a pool that already holds notes, the proof
+/// uses a non-empty tree's root, which the dummy inputs' zero paths do not
+/// lead to.
+async fn check_proof(keypair: &ZkKeypair, circuit_path: &str) -> Result<()> {
+    let sol_mint = *SOL_MINT;
+    let (inputs, paths) = first_deposit_inputs(keypair, None);
+    let outputs = [
+        Utxo::new(TEST_AMOUNT, keypair.clone(), 2, None, Some(UtxoVersion::V2)),
+        Utxo::new(0u64, keypair.clone(), 3, None, Some(UtxoVersion::V2)),
+    ];
+    let leaves = vec![DEFAULT_ZERO.to_string(), POSEIDON_1_2.to_string()];
+    let tree = MerkleTree::with_elements(MERKLE_TREE_DEPTH, leaves, DEFAULT_ZERO)?;
+
+    let ext_data = ExtData {
+        recipient: Pubkey::default(),
+        ext_amount: TEST_AMOUNT as i64,
+        encrypted_output1: vec![],
+        encrypted_output2: vec![],
+        fee: 0,
+        fee_recipient: Pubkey::default(),
+        mint_address: sol_mint,
+    };
+
+    let circuit_input = CircuitInput {
+        root: tree.root(),
+        input_nullifier: vec![inputs[0].get_nullifier()?, inputs[1].get_nullifier()?],
+        output_commitment: vec![outputs[0].get_commitment()?, outputs[1].get_commitment()?],
+        public_amount: calculate_public_amount(ext_data.ext_amount, ext_data.fee).to_string(),
+        ext_data_hash: ext_data.hash().to_vec(),
+
+        in_amount: inputs.iter().map(|u| u.amount.to_string()).collect(),
+        in_private_key: inputs.iter().map(|u| u.keypair.privkey().clone()).collect(),
+        in_blinding: inputs.iter().map(|u| u.blinding.to_string()).collect(),
+        in_path_indices: inputs.iter().map(|u| u.index).collect(),
+        in_path_elements: paths.iter().map(|p| p.path_elements.clone()).collect(),
+
+        out_amount: outputs.iter().map(|u| u.amount.to_string()).collect(),
+        out_blinding: outputs.iter().map(|u| u.blinding.to_string()).collect(),
+        out_pubkey: outputs.iter().map(|u| u.keypair.pubkey().clone()).collect(),
+
+        mint_address: get_mint_address_field(&sol_mint),
+    };
+
+    // The prover verifies the proof against the zkey's verifying key
+    RustProver::new(circuit_path).prove(&circuit_input).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Keypair;
+
+    #[tokio::test]
+    async fn test_self_test_without_circuit() {
+        let mut encryption_service = EncryptionService::new();
+        encryption_service.derive_encryption_key_from_wallet(&Keypair::new()).unwrap();
+
+        let report = self_test(&encryption_service, "/nonexistent/transaction2").await;
+        let failures: Vec<_> = report.failures().map(|step| step.name).collect();
+        assert_eq!(failures, vec!["proof"]);
+        assert!(report.steps.last().unwrap().error.as_ref().unwrap().contains("not found"));
+
+        let report = self_test(&EncryptionService::new(), "/nonexistent/transaction2").await;
+        assert!(!report.all_passed());
+        assert!(report.steps.iter().find(|step| step.name == "poseidon").unwrap().passed());
+        assert_eq!(report.failures().count(), 5);
+    }
+}
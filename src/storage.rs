@@ -2,9 +2,21 @@
 
 use crate::error::{PrivacyCashError, Result};
 use parking_lot::RwLock;
+use serde::Serialize;
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Magic prefix of the binary encrypted-outputs cache
+const BINARY_CACHE_MAGIC: &[u8; 4] = b"PCO1";
+
+/// Newest encrypted-outputs cache schema this SDK reads
+///
+/// Binary caches carry the version as the last byte of their `PCO<n>` magic,
+/// JSON caches as `{"version": n, "outputs": [...]}`. The bare JSON array
+/// written by every SDK release so far is version 1.
+pub const OUTPUTS_CACHE_VERSION: u32 = 1;
 
 /// Storage backend trait
 pub trait StorageBackend: Send + Sync {
@@ -12,12 +24,119 @@ pub trait StorageBackend: Send + Sync {
     fn set(&self, key: &str, value: &str);
     fn remove(&self, key: &str);
     fn clear(&self);
+
+    /// Get a raw value; defaults to the UTF-8 bytes of `get`
+    fn get_bytes(&self, key: &str) -> Option<Vec<u8>> {
+        self.get(key).map(String::into_bytes)
+    }
+
+    /// Store a raw value; defaults to `set`, dropping values that are not UTF-8
+    fn set_bytes(&self, key: &str, value: &[u8]) {
+        if let Ok(value) = std::str::from_utf8(value) {
+            self.set(key, value);
+        }
+    }
+
+    /// Size of a stored value in bytes; defaults to the length of `get_bytes`
+    fn size_of(&self, key: &str) -> Option<u64> {
+        self.get_bytes(key).map(|value| value.len() as u64)
+    }
+
+    /// Compare-and-set: store `new` only if the current value is `expected`
+    ///
+    /// `expected` of `None` means the key must be absent. Returns whether the
+    /// value was stored. The default reads then writes and is not atomic;
+    /// backends shared between concurrent scans should override it.
+    fn cas(&self, key: &str, expected: Option<&str>, new: &str) -> bool {
+        if self.get(key).as_deref() != expected {
+            return false;
+        }
+        self.set(key, new);
+        true
+    }
+}
+
+/// On-disk format of the encrypted-outputs cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CacheFormat {
+    /// JSON array of hex strings
+    #[default]
+    Json,
+    /// `PCO1` magic, then each output as a u32 LE length and its raw bytes
+    Binary,
+}
+
+/// Encode encrypted outputs (hex strings) in the given cache format
+///
+/// Falls back to JSON if any output is not valid hex.
+pub fn encode_outputs(outputs: &[String], format: CacheFormat) -> Vec<u8> {
+    if format == CacheFormat::Binary {
+        let raw: std::result::Result<Vec<Vec<u8>>, _> = outputs.iter().map(hex::decode).collect();
+        if let Ok(raw) = raw {
+            let mut data = BINARY_CACHE_MAGIC.to_vec();
+            for output in raw {
+                data.extend_from_slice(&(output.len() as u32).to_le_bytes());
+                data.extend_from_slice(&output);
+            }
+            return data;
+        }
+    }
+
+    serde_json::to_vec(outputs).unwrap_or_default()
+}
+
+/// Versioned JSON encrypted-outputs cache
+#[derive(serde::Deserialize)]
+struct VersionedOutputs {
+    version: u32,
+    outputs: Vec<String>,
+}
+
+/// Decode an encrypted-outputs cache, detecting its format and version
+///
+/// Reads the bare JSON array, the versioned JSON object and the binary
+/// format. Returns `None` for corrupt data and for schema versions newer than
+/// [`OUTPUTS_CACHE_VERSION`].
+pub fn decode_outputs(data: &[u8]) -> Option<Vec<String>> {
+    if let Some(version) = data.strip_prefix(b"PCO").and_then(|rest| rest.first()) {
+        return match version {
+            b'1' => decode_binary_outputs(&data[BINARY_CACHE_MAGIC.len()..]),
+            _ => {
+                log::debug!("Unknown binary outputs cache version {}", *version as char);
+                None
+            }
+        };
+    }
+
+    match serde_json::from_slice::<serde_json::Value>(data).ok()? {
+        serde_json::Value::Array(_) => serde_json::from_slice(data).ok(),
+        serde_json::Value::Object(_) => {
+            let versioned: VersionedOutputs = serde_json::from_slice(data).ok()?;
+            if versioned.version > OUTPUTS_CACHE_VERSION {
+                log::debug!("Outputs cache version {} is newer than supported", versioned.version);
+                return None;
+            }
+            Some(versioned.outputs)
+        }
+        _ => None,
+    }
+}
+
+fn decode_binary_outputs(mut rest: &[u8]) -> Option<Vec<String>> {
+    let mut outputs = Vec::new();
+    while !rest.is_empty() {
+        let len = u32::from_le_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+        let output = rest.get(4..4 + len)?;
+        outputs.push(hex::encode(output));
+        rest = &rest[4 + len..];
+    }
+    Some(outputs)
 }
 
 /// File-based storage implementation
 pub struct FileStorage {
     cache_dir: PathBuf,
-    cache: RwLock<HashMap<String, String>>,
+    cache: RwLock<HashMap<String, Vec<u8>>>,
 }
 
 impl FileStorage {
@@ -64,7 +183,7 @@ impl FileStorage {
             let path = entry.path();
             if path.is_file() {
                 if let Some(key) = path.file_name().and_then(|n| n.to_str()) {
-                    if let Ok(value) = fs::read_to_string(&path) {
+                    if let Ok(value) = fs::read(&path) {
                         cache.insert(key.to_string(), value);
                     }
                 }
@@ -84,15 +203,23 @@ impl FileStorage {
 
 impl StorageBackend for FileStorage {
     fn get(&self, key: &str) -> Option<String> {
+        self.get_bytes(key).and_then(|value| String::from_utf8(value).ok())
+    }
+
+    fn set(&self, key: &str, value: &str) {
+        self.set_bytes(key, value.as_bytes());
+    }
+
+    fn get_bytes(&self, key: &str) -> Option<Vec<u8>> {
         let cache = self.cache.read();
         cache.get(key).cloned()
     }
 
-    fn set(&self, key: &str, value: &str) {
+    fn set_bytes(&self, key: &str, value: &[u8]) {
         // Update in-memory cache
         {
             let mut cache = self.cache.write();
-            cache.insert(key.to_string(), value.to_string());
+            cache.insert(key.to_string(), value.to_vec());
         }
 
         // Persist to disk (ignore errors)
@@ -100,6 +227,24 @@ impl StorageBackend for FileStorage {
         let _ = fs::write(path, value);
     }
 
+    fn cas(&self, key: &str, expected: Option<&str>, new: &str) -> bool {
+        let mut cache = self.cache.write();
+        if cache.get(key).map(Vec::as_slice) != expected.map(str::as_bytes) {
+            return false;
+        }
+        cache.insert(key.to_string(), new.as_bytes().to_vec());
+
+        // Write while holding the lock so disk order matches memory order
+        let _ = fs::write(self.key_path(key), new);
+        true
+    }
+
+    fn size_of(&self, key: &str) -> Option<u64> {
+        let in_memory = self.cache.read().get(key).map(|value| value.len() as u64)?;
+        // The file is what uses disk; fall back if it could not be written
+        Some(fs::metadata(self.key_path(key)).map(|m| m.len()).unwrap_or(in_memory))
+    }
+
     fn remove(&self, key: &str) {
         // Remove from in-memory cache
         {
@@ -129,7 +274,7 @@ impl StorageBackend for FileStorage {
 
 /// In-memory storage (for testing or ephemeral use)
 pub struct MemoryStorage {
-    data: RwLock<HashMap<String, String>>,
+    data: RwLock<HashMap<String, Vec<u8>>>,
 }
 
 impl MemoryStorage {
@@ -148,11 +293,28 @@ impl Default for MemoryStorage {
 
 impl StorageBackend for MemoryStorage {
     fn get(&self, key: &str) -> Option<String> {
-        self.data.read().get(key).cloned()
+        self.get_bytes(key).and_then(|value| String::from_utf8(value).ok())
     }
 
     fn set(&self, key: &str, value: &str) {
-        self.data.write().insert(key.to_string(), value.to_string());
+        self.set_bytes(key, value.as_bytes());
+    }
+
+    fn get_bytes(&self, key: &str) -> Option<Vec<u8>> {
+        self.data.read().get(key).cloned()
+    }
+
+    fn set_bytes(&self, key: &str, value: &[u8]) {
+        self.data.write().insert(key.to_string(), value.to_vec());
+    }
+
+    fn cas(&self, key: &str, expected: Option<&str>, new: &str) -> bool {
+        let mut data = self.data.write();
+        if data.get(key).map(Vec::as_slice) != expected.map(str::as_bytes) {
+            return false;
+        }
+        data.insert(key.to_string(), new.as_bytes().to_vec());
+        true
     }
 
     fn remove(&self, key: &str) {
@@ -164,30 +326,85 @@ impl StorageBackend for MemoryStorage {
     }
 }
 
+/// What [`PrivacyCash::clear_cache`](crate::PrivacyCash::clear_cache) removed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheClearSummary {
+    /// Number of cache entries removed
+    pub keys_removed: usize,
+    /// Tokens that had at least one cache entry, e.g. "sol", "usdc"
+    pub tokens: Vec<String>,
+}
+
+impl CacheClearSummary {
+    /// Whether nothing was cached
+    pub fn is_empty(&self) -> bool {
+        self.keys_removed == 0
+    }
+}
+
+/// Cache usage of one token, see [`CacheStats`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TokenCacheStats {
+    /// Token name, e.g. "sol", "usdc"
+    pub token: String,
+    /// Number of cache entries present
+    pub entries: usize,
+    /// Total size of those entries in bytes
+    pub bytes: u64,
+    /// Number of encrypted notes in the outputs cache
+    pub notes: usize,
+}
+
+/// What [`PrivacyCash::cache_stats`](crate::PrivacyCash::cache_stats) found
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    /// Tokens with at least one cache entry
+    pub tokens: Vec<TokenCacheStats>,
+}
+
+impl CacheStats {
+    /// Total cache size in bytes
+    pub fn total_bytes(&self) -> u64 {
+        self.tokens.iter().map(|t| t.bytes).sum()
+    }
+
+    /// Total number of cached notes
+    pub fn total_notes(&self) -> usize {
+        self.tokens.iter().map(|t| t.notes).sum()
+    }
+}
+
 /// Storage wrapper for the SDK
+///
+/// Clones share the same backend.
+#[derive(Clone)]
 pub struct Storage {
-    backend: Box<dyn StorageBackend>,
+    backend: Arc<dyn StorageBackend>,
+    cache_format: CacheFormat,
 }
 
 impl Storage {
     /// Create storage with file backend
     pub fn file(cache_dir: PathBuf) -> Result<Self> {
         Ok(Self {
-            backend: Box::new(FileStorage::new(cache_dir)?),
+            backend: Arc::new(FileStorage::new(cache_dir)?),
+            cache_format: CacheFormat::default(),
         })
     }
 
     /// Create storage with default file backend
     pub fn default_file() -> Result<Self> {
         Ok(Self {
-            backend: Box::new(FileStorage::default_cache()?),
+            backend: Arc::new(FileStorage::default_cache()?),
+            cache_format: CacheFormat::default(),
         })
     }
 
     /// Create storage with memory backend
     pub fn memory() -> Self {
         Self {
-            backend: Box::new(MemoryStorage::new()),
+            backend: Arc::new(MemoryStorage::new()),
+            cache_format: CacheFormat::default(),
         }
     }
 
@@ -203,9 +420,57 @@ impl Storage {
         self.backend.remove(key);
     }
 
+    /// Store `new` only if the current value is `expected`; see [`StorageBackend::cas`]
+    pub fn cas(&self, key: &str, expected: Option<&str>, new: &str) -> bool {
+        self.backend.cas(key, expected, new)
+    }
+
+    /// Size of a stored value in bytes, `None` if absent
+    pub fn size_of(&self, key: &str) -> Option<u64> {
+        self.backend.size_of(key)
+    }
+
+    /// Remove a key, returning whether it was present
+    pub fn remove_existing(&self, key: &str) -> bool {
+        let existed = self.backend.get_bytes(key).is_some();
+        if existed {
+            self.backend.remove(key);
+        }
+        existed
+    }
+
     pub fn clear(&self) {
         self.backend.clear();
     }
+
+    /// Set the format used when writing encrypted-outputs caches
+    ///
+    /// Reads detect the format, so existing JSON caches keep working.
+    pub fn set_cache_format(&mut self, format: CacheFormat) {
+        self.cache_format = format;
+    }
+
+    /// Read a cached list of encrypted outputs in any supported format
+    pub fn get_outputs(&self, key: &str) -> Option<Vec<String>> {
+        self.backend.get_bytes(key).and_then(|data| decode_outputs(&data))
+    }
+
+    /// Whether an encrypted-outputs cache is stored but cannot be decoded
+    ///
+    /// True for corrupt caches and caches written by a newer SDK.
+    pub fn has_unreadable_outputs(&self, key: &str) -> bool {
+        self.backend.get_bytes(key).is_some_and(|data| decode_outputs(&data).is_none())
+    }
+
+    /// Write a list of encrypted outputs in the configured cache format
+    ///
+    /// Skips the write when the stored bytes are already identical.
+    pub fn set_outputs(&self, key: &str, outputs: &[String]) {
+        let data = encode_outputs(outputs, self.cache_format);
+        if self.backend.get_bytes(key).as_deref() != Some(data.as_slice()) {
+            self.backend.set_bytes(key, &data);
+        }
+    }
 }
 
 impl std::fmt::Debug for Storage {
@@ -213,3 +478,55 @@ impl std::fmt::Debug for Storage {
         f.debug_struct("Storage").finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cas() {
+        let dir = std::env::temp_dir().join(format!("privacy-cash-cas-{}", std::process::id()));
+        let backends: Vec<Box<dyn StorageBackend>> =
+            vec![Box::new(MemoryStorage::new()), Box::new(FileStorage::new(dir.clone()).unwrap())];
+
+        for backend in backends {
+            assert!(!backend.cas("offset", Some("0"), "20"));
+            assert!(backend.cas("offset", None, "20"));
+            assert!(!backend.cas("offset", None, "40"));
+            assert!(!backend.cas("offset", Some("10"), "40"));
+            assert!(backend.cas("offset", Some("20"), "40"));
+            assert_eq!(backend.get("offset").as_deref(), Some("40"));
+        }
+        assert_eq!(fs::read_to_string(dir.join("offset")).unwrap(), "40");
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_outputs_cache_formats() {
+        let outputs = vec!["deadbeef".to_string(), "00ff".to_string()];
+
+        let json = encode_outputs(&outputs, CacheFormat::Json);
+        let binary = encode_outputs(&outputs, CacheFormat::Binary);
+        assert!(binary.len() < json.len());
+        assert_eq!(decode_outputs(&json).unwrap(), outputs);
+        assert_eq!(decode_outputs(&binary).unwrap(), outputs);
+        assert!(decode_outputs(&binary[..binary.len() - 1]).is_none());
+
+        let mut storage = Storage::memory();
+        storage.set_cache_format(CacheFormat::Binary);
+        storage.set_outputs("outputs", &outputs);
+        assert_eq!(storage.get_outputs("outputs").unwrap(), outputs);
+
+        let versioned = br#"{"version":1,"outputs":["deadbeef","00ff"]}"#;
+        assert_eq!(decode_outputs(versioned).unwrap(), outputs);
+        assert!(decode_outputs(br#"{"version":2,"outputs":[]}"#).is_none());
+        assert!(decode_outputs(b"PCO2").is_none());
+        assert!(!storage.has_unreadable_outputs("outputs"));
+        storage.set("outputs", "\"deadbeef\"");
+        assert!(storage.has_unreadable_outputs("outputs"));
+
+        // Non-hex outputs cannot be packed and stay JSON
+        let text = vec!["not hex".to_string()];
+        assert_eq!(encode_outputs(&text, CacheFormat::Binary), serde_json::to_vec(&text).unwrap());
+    }
+}
@@ -0,0 +1,34 @@
+//! Supported token registry
+//!
+//! Typed mint addresses for every token the pool supports, so code can write
+//! `privacy_cash::tokens::USDT_MINT` instead of copying mint strings.
+//!
+//! ```rust
+//! use privacy_cash::tokens;
+//!
+//! for token in tokens::all() {
+//!     println!("{}: {}", token.name, token.mint);
+//! }
+//! assert_eq!(tokens::find_by_mint(&tokens::USDT_MINT).unwrap().name, "usdt");
+//! ```
+
+pub use crate::constants::{
+    TokenInfo, ORE_MINT, SOL_MINT, STORE_MINT, USDC_MINT, USDT_MINT, ZEC_MINT,
+};
+use crate::constants::{find_token_by_mint, find_token_by_name, get_supported_tokens};
+use solana_sdk::pubkey::Pubkey;
+
+/// Iterate over all supported tokens, starting with native SOL
+pub fn all() -> impl Iterator<Item = TokenInfo> {
+    get_supported_tokens().into_iter()
+}
+
+/// Find a supported token by mint address
+pub fn find_by_mint(mint: &Pubkey) -> Option<TokenInfo> {
+    find_token_by_mint(mint)
+}
+
+/// Find a supported token by name (case-insensitive)
+pub fn find_by_name(name: &str) -> Option<TokenInfo> {
+    find_token_by_name(name)
+}
@@ -0,0 +1,173 @@
+//! Independent check of the relayer's Merkle root
+//!
+//! Rebuilds the commitment tree locally from the leaves the relayer serves
+//! and compares the computed root with the root the relayer reports. A
+//! mismatch means the relayer is serving an inconsistent view of the pool.
+
+use crate::constants::{FETCH_UTXOS_GROUP_SIZE, MERKLE_TREE_DEPTH, RELAYER_API_URL};
+use crate::error::{PrivacyCashError, Result};
+use crate::http::transport;
+use crate::merkle_tree::{SparseMerkleTree, DEFAULT_ZERO};
+use crate::metrics::record_relayer_error;
+use crate::utils::query_remote_tree_state;
+use serde::{Deserialize, Serialize};
+
+/// Leaf entry in the relayer's range response
+#[derive(Debug, Deserialize)]
+struct ApiLeaf {
+    commitment: String,
+    index: u64,
+}
+
+/// Result of comparing the locally built tree with the relayer's
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeConsistency {
+    /// Root computed from the relayer's leaves
+    pub local_root: String,
+    /// Root reported by the relayer
+    pub remote_root: String,
+    /// Number of leaves the tree was built from
+    pub leaf_count: u64,
+}
+
+impl TreeConsistency {
+    /// Whether the computed root matches the reported one
+    pub fn is_consistent(&self) -> bool {
+        self.local_root == self.remote_root
+    }
+}
+
+/// Rebuild the tree from the relayer's leaves and compare roots
+///
+/// `token_name` selects an SPL tree (e.g. "usdc"); `None` checks the SOL tree.
+/// Leaves are fetched up to the `nextIndex` reported alongside the root, so
+/// deposits landing during the check do not cause a false mismatch.
+pub async fn verify_tree_consistency(token_name: Option<&str>) -> Result<TreeConsistency> {
+    let state = query_remote_tree_state(token_name).await?;
+    let leaves = fetch_leaves(token_name, state.next_index).await?;
+    let leaf_count = leaves.len() as u64;
+
+    let tree = SparseMerkleTree::with_elements(MERKLE_TREE_DEPTH, leaves, DEFAULT_ZERO)?;
+    let report = TreeConsistency {
+        local_root: tree.root(),
+        remote_root: state.root,
+        leaf_count,
+    };
+
+    if !report.is_consistent() {
+        log::warn!(
+            "Relayer root {} does not match root {} computed from {} leaves",
+            report.remote_root,
+            report.local_root,
+            leaf_count
+        );
+    }
+
+    Ok(report)
+}
+
+/// Fetch the first `count` leaf commitments in index order
+async fn fetch_leaves(token_name: Option<&str>, count: u64) -> Result<Vec<String>> {
+    let mut leaves: Vec<String> = Vec::with_capacity(count as usize);
+
+    while (leaves.len() as u64) < count {
+        let start = leaves.len() as u64;
+        let end = start + FETCH_UTXOS_GROUP_SIZE;
+        let url = match token_name {
+            Some(token) => format!(
+                "{}/utxos/range?token={}&start={}&end={}",
+                *RELAYER_API_URL, token, start, end
+            ),
+            None => format!("{}/utxos/range?start={}&end={}", *RELAYER_API_URL, start, end),
+        };
+
+        log::debug!("Fetching tree leaves from: {}", url);
+
+        let response = transport()
+            .get_json(&url)
+            .await
+            .map_err(|e| PrivacyCashError::ApiError(format!("Failed to fetch leaves: {}", e)))?;
+
+        if !response.is_success() {
+            record_relayer_error("/utxos/range", response.status);
+            return Err(PrivacyCashError::ApiError(format!(
+                "UTXO API returned status: {}",
+                response.status
+            )));
+        }
+
+        let page: Vec<ApiLeaf> = response.json().map_err(|_| {
+            PrivacyCashError::ApiError(
+                "Relayer does not expose leaf commitments; cannot rebuild the tree".to_string(),
+            )
+        })?;
+        if page.is_empty() {
+            break;
+        }
+
+        for leaf in page {
+            let expected = leaves.len() as u64;
+            if leaf.index != expected {
+                return Err(PrivacyCashError::ApiError(format!(
+                    "Relayer returned leaf {} where {} was expected",
+                    leaf.index, expected
+                )));
+            }
+            leaves.push(leaf.commitment);
+        }
+    }
+
+    if (leaves.len() as u64) < count {
+        return Err(PrivacyCashError::ApiError(format!(
+            "Relayer returned {} leaves but reports {}",
+            leaves.len(),
+            count
+        )));
+    }
+    leaves.truncate(count as usize);
+
+    Ok(leaves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{CannedTransport, TEST_HOOKS_LOCK};
+
+    /// Reports three leaves but serves four, as if a deposit landed mid-check
+    fn leaves_with_root(root: &str) -> CannedTransport {
+        let leaves: Vec<_> = (0..4)
+            .map(|i| {
+                let commitment = (i + 1).to_string();
+                serde_json::json!({ "commitment": commitment, "index": i })
+            })
+            .collect();
+        CannedTransport::default()
+            .route("/merkle/root", format!(r#"{{"root":"{}","nextIndex":3}}"#, root))
+            .route("/utxos/range?start=0&", serde_json::to_string(&leaves).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_verify_tree_consistency() {
+        let leaves = vec!["1".to_string(), "2".to_string(), "3".to_string()];
+        let expected = SparseMerkleTree::with_elements(MERKLE_TREE_DEPTH, leaves, DEFAULT_ZERO)
+            .unwrap()
+            .root();
+
+        let _guard = TEST_HOOKS_LOCK.lock().await;
+        let honest = {
+            let _transport = leaves_with_root(&expected).install();
+            verify_tree_consistency(None).await
+        };
+        let lying = {
+            let _transport = leaves_with_root("42").install();
+            verify_tree_consistency(None).await
+        };
+
+        let honest = honest.unwrap();
+        assert!(honest.is_consistent());
+        assert_eq!(honest.leaf_count, 3);
+        assert_eq!(honest.local_root, expected);
+        assert!(!lying.unwrap().is_consistent());
+    }
+}
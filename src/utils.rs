@@ -1,15 +1,22 @@
 //! Utility functions for Privacy Cash SDK
 
-use crate::constants::{PROGRAM_ID, RELAYER_API_URL, FIELD_SIZE};
+use crate::constants::{
+    find_token_by_mint, MAX_TRANSACTION_SIZE, MERKLE_TREE_DEPTH, PROGRAM_ID, RELAYER_API_URL,
+    FIELD_SIZE,
+};
 #[allow(unused_imports)]
 use crate::error::{PrivacyCashError, Result};
+use crate::http::transport;
 use crate::merkle_tree::MerklePath;
+use crate::utxo::Utxo;
 use borsh::BorshSerialize;
 use num_bigint::BigUint;
 use num_traits::ToPrimitive;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use solana_sdk::pubkey::Pubkey;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use std::str::FromStr;
 
 /// External data for proof
 #[derive(Debug, Clone)]
@@ -36,8 +43,12 @@ struct ExtDataForHash {
 }
 
 impl ExtData {
-    /// Calculate the hash of external data (SHA-256)
-    pub fn hash(&self) -> [u8; 32] {
+    /// Borsh serialization of the external data as the program hashes it
+    ///
+    /// Field order: `recipient` (32), `ext_amount` (i64 LE), `encrypted_output1`
+    /// (u32 LE length + bytes), `encrypted_output2` (u32 LE length + bytes),
+    /// `fee` (u64 LE), `fee_recipient` (32), `mint_address` (32).
+    pub fn to_bytes(&self) -> Vec<u8> {
         let data_for_hash = ExtDataForHash {
             recipient: self.recipient.to_bytes(),
             ext_amount: self.ext_amount,
@@ -48,9 +59,53 @@ impl ExtData {
             mint_address: self.mint_address.to_bytes(),
         };
 
-        let serialized = borsh::to_vec(&data_for_hash).unwrap();
-        Sha256::digest(&serialized).into()
+        borsh::to_vec(&data_for_hash).unwrap()
+    }
+
+    /// Calculate the hash of external data (SHA-256 of [`ExtData::to_bytes`])
+    pub fn hash(&self) -> [u8; 32] {
+        Sha256::digest(self.to_bytes()).into()
+    }
+
+    /// Size of the serialized transact instruction data carrying this ExtData
+    ///
+    /// Discriminator (8), proof (256), 7 public signals (224), ext_amount and
+    /// fee (16), plus both encrypted outputs with their u32 length prefixes.
+    pub fn transact_instruction_size(&self) -> usize {
+        8 + 256 + 7 * 32 + 16 + 4 + self.encrypted_output1.len() + 4 + self.encrypted_output2.len()
+    }
+
+    /// Smallest size of the signed transaction carrying this ExtData
+    ///
+    /// Adds to the instruction data what no lookup table can shrink: the
+    /// signature, message header and blockhash, the static keys (fee payer,
+    /// four nullifier PDAs, the program and the compute-budget program), the
+    /// compute-budget instruction, and a one-byte lookup index for each other
+    /// of the instruction's `accounts`. Above the packet limit, the
+    /// transaction cannot fit whatever the lookup table holds.
+    pub fn min_transaction_size(&self, accounts: usize) -> usize {
+        const STATIC_KEYS: usize = 7;
+        let data_len = self.transact_instruction_size();
+        let signature = 1 + 64;
+        let message_head = 1 + 3 + 1 + 32 * STATIC_KEYS + 32 + 1;
+        let compute_budget_ix = 1 + 1 + 1 + 5;
+        let data_len_prefix = if data_len < 0x80 { 1 } else { 2 };
+        let transact_ix = 1 + 1 + accounts + data_len_prefix + data_len;
+        // Payer and nullifier PDAs are static; the rest come from the table
+        let lookup = 1 + 32 + 1 + 1 + accounts.saturating_sub(STATIC_KEYS - 2);
+        signature + message_head + compute_budget_ix + transact_ix + lookup
+    }
+}
+
+/// Fail with `TransactionTooLarge` if `size` exceeds Solana's packet limit
+pub fn check_transaction_size(size: usize) -> Result<()> {
+    if size > MAX_TRANSACTION_SIZE {
+        return Err(PrivacyCashError::TransactionTooLarge {
+            size,
+            limit: MAX_TRANSACTION_SIZE,
+        });
     }
+    Ok(())
 }
 
 /// Tree state from API
@@ -59,6 +114,37 @@ pub struct TreeState {
     pub root: String,
     #[serde(rename = "nextIndex")]
     pub next_index: u64,
+    /// Number of leaves the tree can hold, if the relayer reports it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacity: Option<u64>,
+    /// Base units held by the pool, if the relayer reports it
+    #[serde(rename = "totalValue", default, skip_serializing_if = "Option::is_none")]
+    pub total_value: Option<u64>,
+}
+
+impl TreeState {
+    /// Number of leaves the tree can hold
+    ///
+    /// Falls back to `2^MERKLE_TREE_DEPTH` when the relayer does not report it.
+    pub fn capacity(&self) -> u64 {
+        self.capacity.unwrap_or(1u64 << MERKLE_TREE_DEPTH)
+    }
+
+    /// Number of leaves that can still be inserted
+    pub fn remaining(&self) -> u64 {
+        self.capacity().saturating_sub(self.next_index)
+    }
+
+    /// Fail with `TreeFull` unless `outputs` more leaves fit in the tree
+    pub fn ensure_room_for(&self, outputs: u64) -> Result<()> {
+        if self.remaining() < outputs {
+            return Err(PrivacyCashError::TreeFull {
+                next_index: self.next_index,
+                capacity: self.capacity(),
+            });
+        }
+        Ok(())
+    }
 }
 
 /// Fetch Merkle tree state from relayer API
@@ -70,21 +156,18 @@ pub async fn query_remote_tree_state(token_name: Option<&str>) -> Result<TreeSta
 
     log::debug!("Fetching Merkle root from: {}", url);
 
-    let response = reqwest::get(&url)
+    let response = transport().get_json(&url)
         .await
         .map_err(|e| PrivacyCashError::ApiError(format!("Failed to fetch tree state: {}", e)))?;
 
-    if !response.status().is_success() {
+    if !response.is_success() {
         return Err(PrivacyCashError::ApiError(format!(
             "Tree state API returned status: {}",
-            response.status()
+            response.status
         )));
     }
 
-    let state: TreeState = response
-        .json()
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("Failed to parse tree state: {}", e)))?;
+    let state: TreeState = response.json_with_context("Failed to parse tree state")?;
 
     log::debug!("Fetched root: {}, nextIndex: {}", state.root, state.next_index);
 
@@ -111,6 +194,16 @@ impl From<MerkleProofResponse> for MerklePath {
 
 /// Fetch Merkle proof for a commitment
 pub async fn fetch_merkle_proof(commitment: &str, token_name: Option<&str>) -> Result<MerklePath> {
+    request_merkle_proof(commitment, token_name).await?.ok_or_else(|| {
+        PrivacyCashError::MerkleProofError("Merkle proof API returned status: 404".to_string())
+    })
+}
+
+/// Fetch the Merkle proof for a commitment, `None` if the relayer does not know it
+async fn request_merkle_proof(
+    commitment: &str,
+    token_name: Option<&str>,
+) -> Result<Option<MerklePath>> {
     let mut url = format!("{}/merkle/proof/{}", *RELAYER_API_URL, commitment);
     if let Some(token) = token_name {
         url = format!("{}?token={}", url, token);
@@ -118,25 +211,96 @@ pub async fn fetch_merkle_proof(commitment: &str, token_name: Option<&str>) -> R
 
     log::debug!("Fetching Merkle proof for: {}", commitment);
 
-    let response = reqwest::get(&url)
+    let response = transport().get_json(&url)
         .await
         .map_err(|e| PrivacyCashError::ApiError(format!("Failed to fetch Merkle proof: {}", e)))?;
 
-    if !response.status().is_success() {
+    if response.status == 404 {
+        return Ok(None);
+    }
+    if !response.is_success() {
         return Err(PrivacyCashError::MerkleProofError(format!(
             "Merkle proof API returned status: {}",
-            response.status()
+            response.status
         )));
     }
 
-    let proof: MerkleProofResponse = response
-        .json()
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("Failed to parse Merkle proof: {}", e)))?;
+    let proof: MerkleProofResponse = response.json_with_context("Failed to parse Merkle proof")?;
 
     log::debug!("Fetched proof with {} elements", proof.path_elements.len());
 
-    Ok(proof.into())
+    Ok(Some(proof.into()))
+}
+
+/// Check that a note's commitment is present in the relayer's Merkle tree
+///
+/// Fetches the inclusion proof for the commitment and verifies it against the
+/// current root. Returns `false` if the relayer has no proof for the commitment
+/// or the proof does not hash to the root.
+pub async fn verify_note(utxo: &Utxo) -> Result<bool> {
+    let commitment = utxo.get_commitment()?;
+    let token_name = Pubkey::from_str(&utxo.mint_address)
+        .ok()
+        .and_then(|mint| find_token_by_mint(&mint))
+        .filter(|token| token.name != "sol")
+        .map(|token| token.name);
+
+    is_commitment_included(&commitment, token_name).await
+}
+
+/// Check that a commitment is a leaf of the relayer's Merkle tree
+///
+/// `token_name` is `None` for the SOL tree. A commitment the relayer answers
+/// with a 404 for, or whose proof does not hash to the current root, is not
+/// included; other relayer statuses and network errors are returned as errors.
+pub async fn is_commitment_included(commitment: &str, token_name: Option<&str>) -> Result<bool> {
+    // A deposit landing between the two requests moves the root and leaves the
+    // proof stale, so fetch both again once
+    for _ in 0..2 {
+        let Some(proof) = request_merkle_proof(commitment, token_name).await? else {
+            log::debug!("No Merkle proof for commitment {}", commitment);
+            return Ok(false);
+        };
+        let state = query_remote_tree_state(token_name).await?;
+        if proof.verify(commitment, &state.root)? {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Status of a previously submitted transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionStatus {
+    /// Confirmed and succeeded
+    Confirmed,
+    /// Landed but failed, with the on-chain error
+    Failed(String),
+    /// Not found: still in flight, dropped, or too old for the RPC node's history
+    NotFound,
+}
+
+/// Look up the status of a transaction signature
+///
+/// Call this after a `ConfirmationTimeout` before resubmitting: a transaction
+/// that landed has already spent its input nullifiers, so a resubmission
+/// would fail.
+pub fn transaction_status(connection: &RpcClient, signature: &str) -> Result<TransactionStatus> {
+    let signature = Signature::from_str(signature)
+        .map_err(|e| PrivacyCashError::InvalidInput(format!("Invalid signature: {}", e)))?;
+
+    let status = connection.get_signature_status_with_commitment_and_history(
+        &signature,
+        CommitmentConfig::confirmed(),
+        true,
+    )?;
+
+    Ok(match status {
+        Some(Ok(())) => TransactionStatus::Confirmed,
+        Some(Err(e)) => TransactionStatus::Failed(e.to_string()),
+        None => TransactionStatus::NotFound,
+    })
 }
 
 /// Derive program PDAs
@@ -145,9 +309,13 @@ pub fn get_program_accounts() -> (Pubkey, Pubkey, Pubkey) {
 
     let (tree_token_account, _) = Pubkey::find_program_address(&[b"tree_token"], &PROGRAM_ID);
 
-    let (global_config_account, _) = Pubkey::find_program_address(&[b"global_config"], &PROGRAM_ID);
+    (tree_account, tree_token_account, get_global_config_account())
+}
 
-    (tree_account, tree_token_account, global_config_account)
+/// Get the program's global config PDA, which holds the on-chain fee rates
+pub fn get_global_config_account() -> Pubkey {
+    let (global_config_account, _) = Pubkey::find_program_address(&[b"global_config"], &PROGRAM_ID);
+    global_config_account
 }
 
 /// Get SPL tree account PDA
@@ -157,6 +325,51 @@ pub fn get_spl_tree_account(mint: &Pubkey) -> Pubkey {
     tree_account
 }
 
+/// A nullifier in the byte order the program seeds its PDAs with
+///
+/// On chain a nullifier is the 32-byte big-endian encoding of the field
+/// element, the same order as the proof's public inputs. PDAs derived from
+/// any other order never exist, so a spent note would look unspent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NullifierBytes([u8; 32]);
+
+impl NullifierBytes {
+    /// Wrap bytes that are already big-endian
+    pub const fn from_be_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Encode a decimal nullifier as returned by `Utxo::get_nullifier`
+    pub fn from_decimal(nullifier: &str) -> Result<Self> {
+        let invalid = || PrivacyCashError::SerializationError("Invalid nullifier".to_string());
+        let n = BigUint::parse_bytes(nullifier.as_bytes(), 10).ok_or_else(invalid)?;
+        if n >= *FIELD_SIZE {
+            return Err(invalid());
+        }
+
+        let be = n.to_bytes_be();
+        let mut bytes = [0u8; 32];
+        bytes[32 - be.len()..].copy_from_slice(&be);
+        Ok(Self(bytes))
+    }
+
+    /// The big-endian bytes used as PDA seed
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Decimal form of the nullifier
+    pub fn to_decimal(&self) -> String {
+        BigUint::from_bytes_be(&self.0).to_string()
+    }
+
+    /// The `nullifier0` and `nullifier1` PDAs, either of which marks it spent
+    pub fn spent_markers(&self) -> [Pubkey; 2] {
+        [b"nullifier0".as_slice(), b"nullifier1".as_slice()]
+            .map(|prefix| Pubkey::find_program_address(&[prefix, &self.0], &PROGRAM_ID).0)
+    }
+}
+
 /// Find nullifier PDAs for proof validation
 pub fn find_nullifier_pdas(nullifiers: &[[u8; 32]]) -> (Pubkey, Pubkey) {
     let (nullifier0_pda, _) =
@@ -250,6 +463,42 @@ pub fn string_to_circuit_bytes(s: &str) -> Result<[u8; 32]> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::constants::{TRANSACT_ACCOUNTS, TRANSACT_SPL_ACCOUNTS};
+    use crate::http::{CannedTransport, TEST_HOOKS_LOCK};
+    use crate::merkle_tree::MerkleTree;
+
+    #[test]
+    fn test_nullifier_bytes() {
+        let one = NullifierBytes::from_decimal("1").unwrap();
+        let mut expected = [0u8; 32];
+        expected[31] = 1;
+        assert_eq!(one.as_bytes(), &expected);
+
+        let nullifier =
+            "11954255677048767585730959529592939615262310191150853775895456173962480955685";
+        let bytes = NullifierBytes::from_decimal(nullifier).unwrap();
+        assert_eq!(bytes.to_decimal(), nullifier);
+        let signal =
+            crate::prover::parse_public_signals_to_bytes(&[nullifier.to_string()]).unwrap();
+        assert_eq!(bytes, NullifierBytes::from_be_bytes(signal[0]));
+
+        // Same seeds the transaction builders derive from the proof's public inputs
+        let (nullifier0_pda, _) = find_nullifier_pdas(&[signal[0], [0u8; 32]]);
+        let (_, nullifier1_pda) = find_nullifier_pdas(&[[0u8; 32], signal[0]]);
+        assert_eq!(bytes.spent_markers(), [nullifier0_pda, nullifier1_pda]);
+        // Pinned so a byte-order change cannot slip through unnoticed
+        assert_eq!(
+            nullifier0_pda.to_string(),
+            "3jRkxxR1FK91C64ZZYKDmGzgHTRmCGSbE2V7qTKrmosg"
+        );
+        assert_eq!(
+            nullifier1_pda.to_string(),
+            "4J9umVKd2x6zxHz13NCPjd7FoCvqEw5MnSwExoFxEeTN"
+        );
+
+        assert!(NullifierBytes::from_decimal("0x12").is_err());
+        assert!(NullifierBytes::from_decimal(&FIELD_SIZE.to_string()).is_err());
+    }
 
     #[test]
     fn test_public_amount_positive() {
@@ -266,6 +515,60 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_ext_data_to_bytes_layout() {
+        let ext_data = ExtData {
+            recipient: Pubkey::new_unique(),
+            ext_amount: -5,
+            encrypted_output1: vec![1u8; 3],
+            encrypted_output2: vec![2u8; 2],
+            fee: 7,
+            fee_recipient: Pubkey::new_unique(),
+            mint_address: Pubkey::new_unique(),
+        };
+
+        let bytes = ext_data.to_bytes();
+        assert_eq!(bytes.len(), 32 + 8 + 4 + 3 + 4 + 2 + 8 + 32 + 32);
+        assert_eq!(&bytes[..32], ext_data.recipient.as_ref());
+        assert_eq!(&bytes[32..40], &(-5i64).to_le_bytes());
+        assert_eq!(&bytes[40..47], &[3, 0, 0, 0, 1, 1, 1]);
+        assert_eq!(&bytes[47..53], &[2, 0, 0, 0, 2, 2]);
+        assert_eq!(&bytes[53..61], &7u64.to_le_bytes());
+        assert_eq!(&bytes[93..], ext_data.mint_address.as_ref());
+        assert_eq!(ext_data.hash(), <[u8; 32]>::from(Sha256::digest(&bytes)));
+    }
+
+    #[test]
+    fn test_transaction_size_guard() {
+        let ext_data = ExtData {
+            recipient: Pubkey::default(),
+            ext_amount: 0,
+            encrypted_output1: vec![0u8; 120],
+            encrypted_output2: vec![0u8; 120],
+            fee: 0,
+            fee_recipient: Pubkey::default(),
+            mint_address: Pubkey::default(),
+        };
+        assert_eq!(ext_data.transact_instruction_size(), 512 + 240);
+        assert_eq!(ext_data.min_transaction_size(TRANSACT_ACCOUNTS), 1143);
+        assert!(check_transaction_size(ext_data.min_transaction_size(TRANSACT_ACCOUNTS)).is_ok());
+
+        // Fits as bare instruction data, but not in a transaction
+        let large = ExtData {
+            encrypted_output1: vec![0u8; 200],
+            encrypted_output2: vec![0u8; 200],
+            ..ext_data
+        };
+        assert!(large.transact_instruction_size() <= MAX_TRANSACTION_SIZE);
+        let size = large.min_transaction_size(TRANSACT_SPL_ACCOUNTS);
+        assert!(check_transaction_size(size).is_err());
+        assert!(check_transaction_size(MAX_TRANSACTION_SIZE).is_ok());
+        assert!(matches!(
+            check_transaction_size(MAX_TRANSACTION_SIZE + 1),
+            Err(PrivacyCashError::TransactionTooLarge { .. })
+        ));
+    }
+
     #[test]
     fn test_program_accounts() {
         let (tree, token, config) = get_program_accounts();
@@ -273,4 +576,47 @@ mod tests {
         assert_ne!(token, Pubkey::default());
         assert_ne!(config, Pubkey::default());
     }
+
+    #[test]
+    fn test_tree_capacity() {
+        let state: TreeState = serde_json::from_str(r#"{"root":"1","nextIndex":67108862}"#).unwrap();
+        assert_eq!(state.capacity(), 1 << MERKLE_TREE_DEPTH);
+        assert!(state.ensure_room_for(2).is_ok());
+        assert!(matches!(
+            state.ensure_room_for(3),
+            Err(PrivacyCashError::TreeFull { next_index: 67108862, .. })
+        ));
+
+        let state: TreeState =
+            serde_json::from_str(r#"{"root":"1","nextIndex":15,"capacity":16}"#).unwrap();
+        assert_eq!(state.remaining(), 1);
+        assert!(state.ensure_room_for(2).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_is_commitment_included() {
+        let mut tree = MerkleTree::new(MERKLE_TREE_DEPTH).unwrap();
+        tree.insert("123".to_string()).unwrap();
+        let path = tree.path(0).unwrap();
+        let proof = serde_json::json!({
+            "pathElements": path.path_elements,
+            "pathIndices": path.path_indices,
+        });
+
+        let _guard = TEST_HOOKS_LOCK.lock().await;
+        // A one-leaf tree whose proof endpoint only knows the commitment "123"
+        let _transport = CannedTransport::default()
+            .route("/merkle/proof/123", proof.to_string())
+            .route_status("/merkle/proof/789", 503, "")
+            .route("/merkle/root", format!(r#"{{"root":"{}","nextIndex":1}}"#, tree.root()))
+            .install();
+        let included = is_commitment_included("123", None).await;
+        let unknown = is_commitment_included("456", None).await;
+        let outage = is_commitment_included("789", None).await;
+
+        assert!(included.unwrap());
+        assert!(!unknown.unwrap());
+        // A relayer outage is an error, not "not included"
+        assert!(matches!(outage, Err(PrivacyCashError::MerkleProofError(_))));
+    }
 }
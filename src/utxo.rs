@@ -2,6 +2,7 @@
 //!
 //! Based on Tornado Cash Nova's UTXO model.
 
+use crate::amount::{Lamports, TokenAmount};
 use crate::constants::{FIELD_SIZE, SOL_MINT};
 use crate::error::{PrivacyCashError, Result};
 use crate::keypair::ZkKeypair;
@@ -10,6 +11,7 @@ use num_traits::Zero;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
 use std::str::FromStr;
 
 /// UTXO version
@@ -67,12 +69,9 @@ impl Utxo {
         mint_address: Option<&str>,
         version: Option<UtxoVersion>,
     ) -> Self {
-        let mut rng = rand::thread_rng();
-        let blinding = BigUint::from(rng.gen::<u64>() % 1_000_000_000);
-
         Self {
             amount: amount.into(),
-            blinding,
+            blinding: Self::random_blinding(),
             keypair,
             index,
             mint_address: mint_address
@@ -82,6 +81,11 @@ impl Utxo {
         }
     }
 
+    /// Random blinding as used by [`Utxo::new`]
+    pub fn random_blinding() -> BigUint {
+        BigUint::from(rand::thread_rng().gen::<u64>() % 1_000_000_000)
+    }
+
     /// Create a new UTXO with specific blinding factor
     pub fn with_blinding(
         amount: impl Into<BigUint>,
@@ -104,10 +108,23 @@ impl Utxo {
     }
 
     /// Create a dummy (zero-value) UTXO
+    ///
+    /// The blinding stays random: a dummy's nullifier is still published
+    /// on-chain, so identical dummies would collide with each other and with
+    /// dummies spent in earlier transactions.
     pub fn dummy(keypair: ZkKeypair, mint_address: Option<&str>) -> Self {
         Self::new(0u64, keypair, 0, mint_address, Some(UtxoVersion::V2))
     }
 
+    /// Create a dummy UTXO with a zero blinding
+    ///
+    /// The commitment is deterministic for a given keypair and mint, which is
+    /// useful for reproducible tests and fixtures. Do not use it as a
+    /// transaction input; see [`Utxo::dummy`].
+    pub fn dummy_deterministic(keypair: ZkKeypair, mint_address: Option<&str>) -> Self {
+        Self::with_blinding(0u64, 0u64, keypair, 0, mint_address, Some(UtxoVersion::V2))
+    }
+
     /// Get the amount as u64
     pub fn amount_u64(&self) -> u64 {
         use num_traits::ToPrimitive;
@@ -226,6 +243,23 @@ impl Utxo {
     }
 }
 
+/// Per-note summary including spend status
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UtxoDetail {
+    /// Amount in base units
+    pub amount: u64,
+    /// Leaf index in the Merkle tree
+    pub index: u64,
+    /// Note commitment (decimal string)
+    pub commitment: String,
+    /// Mint address the note is denominated in
+    pub mint_address: String,
+    /// Encryption version of the note
+    pub version: UtxoVersion,
+    /// Whether the note's nullifier has been published on-chain
+    pub spent: bool,
+}
+
 /// Balance result structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Balance {
@@ -245,6 +279,14 @@ pub struct SplBalance {
     /// Legacy: same as base_units
     #[deprecated(note = "Use base_units instead")]
     pub lamports: u64,
+
+    /// Number of decimals (log10 of units per token)
+    #[serde(default)]
+    pub decimals: u32,
+
+    /// Token symbol, when known
+    #[serde(default)]
+    pub symbol: Option<String>,
 }
 
 impl SplBalance {
@@ -254,6 +296,8 @@ impl SplBalance {
             base_units,
             amount: base_units as f64 / units_per_token as f64,
             lamports: base_units,
+            decimals: units_per_token.checked_ilog10().unwrap_or(0),
+            symbol: None,
         }
     }
 
@@ -263,8 +307,83 @@ impl SplBalance {
             base_units: 0,
             amount: 0.0,
             lamports: 0,
+            decimals: 0,
+            symbol: None,
         }
     }
+
+    /// Attach a token symbol (e.g. "usdc") for display
+    pub fn with_symbol(mut self, symbol: &str) -> Self {
+        self.symbol = Some(symbol.to_uppercase());
+        self
+    }
+
+    /// Exact balance with its decimals
+    pub fn token_amount(&self) -> TokenAmount {
+        TokenAmount::new(self.base_units, self.decimals)
+    }
+
+    /// Value in USD at `price_usd` per whole token
+    ///
+    /// Returns `None` for a zero, negative or non-finite price.
+    pub fn value_usd(&self, price_usd: f64) -> Option<f64> {
+        value_usd(self.base_units, self.decimals, price_usd)
+    }
+}
+
+impl Balance {
+    /// Balance as an exact lamport amount
+    pub fn amount(&self) -> Lamports {
+        Lamports(self.lamports)
+    }
+
+    /// Value in USD at `price_usd` per SOL
+    ///
+    /// Returns `None` for a zero, negative or non-finite price.
+    pub fn value_usd(&self, price_usd: f64) -> Option<f64> {
+        value_usd(self.lamports, 9, price_usd)
+    }
+}
+
+impl std::fmt::Display for Balance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.amount())
+    }
+}
+
+impl std::fmt::Display for SplBalance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let amount = format_base_units(self.base_units, self.decimals);
+        match &self.symbol {
+            Some(symbol) => write!(f, "{} {}", amount, symbol),
+            None => write!(f, "{}", amount),
+        }
+    }
+}
+
+/// Value of `base_units` in USD, or `None` if the price is unusable
+pub fn value_usd(base_units: u64, decimals: u32, price_usd: f64) -> Option<f64> {
+    if !price_usd.is_finite() || price_usd <= 0.0 {
+        return None;
+    }
+    Some(base_units as f64 / 10f64.powi(decimals as i32) * price_usd)
+}
+
+/// Format base units as a decimal token amount, trimming trailing zeros
+pub fn format_base_units(base_units: u64, decimals: u32) -> String {
+    if decimals == 0 {
+        return base_units.to_string();
+    }
+
+    let divisor = 10u64.pow(decimals);
+    let whole = base_units / divisor;
+    let fraction = base_units % divisor;
+    if fraction == 0 {
+        return whole.to_string();
+    }
+
+    let fraction = format!("{:0width$}", fraction, width = decimals as usize);
+    format!("{}.{}", whole, fraction.trim_end_matches('0'))
 }
 
 /// Calculate total balance from UTXOs
@@ -273,6 +392,40 @@ pub fn get_balance_from_utxos(utxos: &[Utxo]) -> Balance {
     Balance { lamports: total }
 }
 
+/// Drop repeated copies of the same note (same commitment and leaf index)
+///
+/// A commitment found at two different leaf indices is two separately
+/// spendable notes, since the nullifier covers the index, so both are kept
+/// with a warning: it means two deposits reused a blinding, which links them.
+pub fn dedup_by_commitment(utxos: Vec<Utxo>) -> Vec<Utxo> {
+    let mut seen: HashMap<String, Vec<u64>> = HashMap::new();
+    let mut unique = Vec::with_capacity(utxos.len());
+
+    for utxo in utxos {
+        let Ok(commitment) = utxo.get_commitment() else {
+            unique.push(utxo);
+            continue;
+        };
+
+        let indices = seen.entry(commitment).or_default();
+        if indices.contains(&utxo.index) {
+            log::debug!("Dropping duplicate note at index {}", utxo.index);
+            continue;
+        }
+        if let Some(first) = indices.first() {
+            log::warn!(
+                "Notes at indices {} and {} share a commitment; their deposits are linkable",
+                first,
+                utxo.index
+            );
+        }
+        indices.push(utxo.index);
+        unique.push(utxo);
+    }
+
+    unique
+}
+
 /// Calculate total SPL balance from UTXOs
 pub fn get_balance_from_utxos_spl(utxos: &[Utxo], units_per_token: u64) -> SplBalance {
     if utxos.is_empty() {
@@ -306,6 +459,37 @@ mod tests {
         assert_eq!(utxo.amount_u64(), 0);
     }
 
+    #[test]
+    fn test_balance_display() {
+        assert_eq!(Balance { lamports: 1_500_000_000 }.to_string(), "1.5 SOL");
+        assert_eq!(Balance { lamports: 0 }.to_string(), "0 SOL");
+        assert_eq!(
+            SplBalance::new(2_050_000, 1_000_000).with_symbol("usdc").to_string(),
+            "2.05 USDC"
+        );
+        assert_eq!(SplBalance::new(7, 1_000_000).to_string(), "0.000007");
+    }
+
+    #[test]
+    fn test_value_usd() {
+        let balance = Balance { lamports: 1_500_000_000 };
+        assert_eq!(balance.value_usd(100.0), Some(150.0));
+        assert_eq!(balance.value_usd(0.0), None);
+        assert_eq!(balance.value_usd(f64::NAN), None);
+        assert_eq!(SplBalance::new(2_500_000, 1_000_000).value_usd(1.0), Some(2.5));
+    }
+
+    #[test]
+    fn test_dummy_utxo_deterministic() {
+        let keypair = ZkKeypair::generate().unwrap();
+        let mint = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+        let first = Utxo::dummy_deterministic(keypair.clone(), Some(mint));
+        let second = Utxo::dummy_deterministic(keypair, Some(mint));
+
+        assert!(first.is_dummy());
+        assert_eq!(first.get_commitment().unwrap(), second.get_commitment().unwrap());
+    }
+
     #[test]
     fn test_commitment_calculation() {
         let keypair = ZkKeypair::generate().unwrap();
@@ -333,4 +517,17 @@ mod tests {
         assert_eq!(utxo.index, deserialized.index);
         assert_eq!(utxo.mint_address, deserialized.mint_address);
     }
+
+    #[test]
+    fn test_dedup_by_commitment() {
+        let keypair = ZkKeypair::generate().unwrap();
+        let note = Utxo::with_blinding(1000u64, 7u64, keypair.clone(), 3, None, None);
+        let mut same_commitment = note.clone();
+        same_commitment.index = 4;
+        let other = Utxo::with_blinding(1000u64, 8u64, keypair, 3, None, None);
+
+        let unique = dedup_by_commitment(vec![note.clone(), note, same_commitment, other]);
+        assert_eq!(unique.len(), 3);
+        assert_eq!(get_balance_from_utxos(&unique).lamports, 3000);
+    }
 }
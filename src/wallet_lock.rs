@@ -0,0 +1,140 @@
+//! Per-wallet serialization of operations that sign transactions
+//!
+//! Two deposits started at once would both read the same tree `next_index`
+//! and build outputs at the same leaf, and two withdrawals could pick the same
+//! input notes. Every client for a wallet, clones and separately built clients
+//! alike, shares one [`WalletLock`], so such calls queue and run one at a time.
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+
+/// Lock of every wallet with a live client in this process
+///
+/// Holds weak references, so a wallet's lock is freed with its last client;
+/// dead entries are pruned whenever a lock is looked up.
+static WALLET_LOCKS: Lazy<Mutex<HashMap<Pubkey, Weak<WalletLock>>>> = Lazy::new(Default::default);
+
+/// What a wallet's signing operations are doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum OperationState {
+    /// No operation is running
+    Idle,
+    /// One operation is running and `queued` more are waiting for it
+    Running { queued: usize },
+}
+
+/// Queue for the signing operations of one wallet
+#[derive(Debug, Default)]
+pub struct WalletLock {
+    lock: tokio::sync::Mutex<()>,
+    /// Operations running or waiting to run
+    pending: AtomicUsize,
+}
+
+/// Held while an operation runs; the next queued one starts when it drops
+#[derive(Debug)]
+pub struct OperationGuard<'a> {
+    _lock: tokio::sync::MutexGuard<'a, ()>,
+    _pending: PendingOperation<'a>,
+}
+
+/// Counts an operation as pending until dropped, including while it waits
+#[derive(Debug)]
+struct PendingOperation<'a>(&'a AtomicUsize);
+
+impl Drop for PendingOperation<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl WalletLock {
+    /// The lock shared by every client of `pubkey`
+    pub fn for_wallet(pubkey: &Pubkey) -> Arc<Self> {
+        let mut locks = WALLET_LOCKS.lock();
+        if let Some(lock) = locks.get(pubkey).and_then(Weak::upgrade) {
+            return lock;
+        }
+        locks.retain(|_, lock| lock.strong_count() > 0);
+        let lock = Arc::new(Self::default());
+        locks.insert(*pubkey, Arc::downgrade(&lock));
+        lock
+    }
+
+    /// Wait for earlier operations to finish, then hold the lock
+    ///
+    /// `operation` names the call in the log line written when it has to
+    /// queue.
+    pub async fn acquire(&self, operation: &str) -> OperationGuard<'_> {
+        let ahead = self.pending.fetch_add(1, Ordering::SeqCst);
+        let pending = PendingOperation(&self.pending);
+        if ahead > 0 {
+            log::info!("{} queued behind {} operation(s) on this wallet", operation, ahead);
+        }
+        OperationGuard {
+            _lock: self.lock.lock().await,
+            _pending: pending,
+        }
+    }
+
+    /// Whether an operation is running and how many are queued behind it
+    pub fn state(&self) -> OperationState {
+        match self.pending.load(Ordering::SeqCst) {
+            0 => OperationState::Idle,
+            pending => OperationState::Running { queued: pending - 1 },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_operations_queue() {
+        let wallet = Pubkey::new_unique();
+        let lock = WalletLock::for_wallet(&wallet);
+        assert!(Arc::ptr_eq(&lock, &WalletLock::for_wallet(&wallet)));
+        assert_eq!(lock.state(), OperationState::Idle);
+
+        let first = lock.acquire("deposit").await;
+        assert_eq!(lock.state(), OperationState::Running { queued: 0 });
+
+        let waiting = lock.clone();
+        let second = tokio::spawn(async move {
+            let _guard = waiting.acquire("withdraw").await;
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(lock.state(), OperationState::Running { queued: 1 });
+        assert!(!second.is_finished());
+
+        drop(first);
+        second.await.unwrap();
+        assert_eq!(lock.state(), OperationState::Idle);
+
+        // A caller that gives up while queued leaves the queue
+        let _held = lock.acquire("deposit").await;
+        let gave_up = tokio::time::timeout(Duration::from_millis(10), lock.acquire("withdraw"));
+        assert!(gave_up.await.is_err());
+        assert_eq!(lock.state(), OperationState::Running { queued: 0 });
+    }
+
+    #[test]
+    fn test_unused_locks_are_freed() {
+        let wallet = Pubkey::new_unique();
+        let lock = WalletLock::for_wallet(&wallet);
+        let weak = Arc::downgrade(&lock);
+        drop(lock);
+        assert!(weak.upgrade().is_none());
+
+        // Looking up any wallet prunes the dead entry
+        let _other = WalletLock::for_wallet(&Pubkey::new_unique());
+        assert!(!WALLET_LOCKS.lock().contains_key(&wallet));
+    }
+}
@@ -1,23 +1,39 @@
 //! Withdrawal functionality for native SOL
-
+//!
+//! # Account lifecycle
+//!
+//! A withdrawal creates only the two nullifier PDAs (`nullifier0`/`nullifier1`
+//! seeds). They are owned by the program and must live forever: their existence
+//! is what marks a note as spent, so closing them would allow double spends.
+//! The relayer submits and pays for the transaction, so the signer funds no
+//! rent here and there is nothing for the SDK to reclaim.
+
+use crate::amount::Lamports;
+use crate::blinding::BlindingSource;
+use crate::clock;
 use crate::config::Config;
 use crate::constants::{
     ALT_ADDRESS, FEE_RECIPIENT, LAMPORTS_PER_SOL, PROGRAM_ID,
-    RELAYER_API_URL, TRANSACT_IX_DISCRIMINATOR,
+    RELAYER_API_URL, TRANSACT_ACCOUNTS, TRANSACT_IX_DISCRIMINATOR, TX_OUTPUTS,
 };
-use crate::encryption::EncryptionService;
+use crate::encryption::{ChangeOutput, EncryptionService, OutputTarget};
 use crate::error::{PrivacyCashError, Result};
-use crate::get_utxos::get_utxos;
+use crate::get_utxos::{ensure_unspent_finalized, get_utxos};
+use crate::http::transport;
 use crate::keypair::ZkKeypair;
 use crate::merkle_tree::MerkleTree;
-use crate::prover::{parse_proof_to_bytes, parse_public_signals_to_bytes, CircuitInput};
+use crate::metrics::{metrics, record_relayer_error, WITHDRAWALS_TOTAL};
+use crate::preview::{approve_for_relay, ApprovalHook, BeforeRelay, TransactionKind, TxPreview};
+use crate::prover::{
+    parse_proof_to_bytes, parse_public_signals_to_bytes, CircuitArity, CircuitInput,
+};
 use crate::prover_rust::RustProver;
 use crate::storage::Storage;
 use crate::utxo::{Utxo, UtxoVersion};
 use crate::utils::{
-    calculate_public_amount, fetch_merkle_proof, find_cross_check_nullifier_pdas,
-    find_nullifier_pdas, get_mint_address_field, get_program_accounts, query_remote_tree_state,
-    ExtData,
+    calculate_public_amount, check_transaction_size, fetch_merkle_proof,
+    find_cross_check_nullifier_pdas, find_nullifier_pdas, get_mint_address_field,
+    get_program_accounts, query_remote_tree_state, ExtData,
 };
 use num_bigint::BigUint;
 use num_traits::Zero;
@@ -25,6 +41,7 @@ use serde::{Deserialize, Serialize};
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
 use std::str::FromStr;
+use std::time::Duration;
 
 /// Withdrawal result
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,6 +62,166 @@ pub struct WithdrawResult {
     pub is_partial: bool,
 }
 
+impl WithdrawResult {
+    /// Amount withdrawn (after fees) as exact lamports
+    pub fn amount(&self) -> Lamports {
+        Lamports(self.amount_in_lamports)
+    }
+
+    /// Fee charged as exact lamports
+    pub fn fee(&self) -> Lamports {
+        Lamports(self.fee_in_lamports)
+    }
+}
+
+impl std::fmt::Display for WithdrawResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Withdrew {} to {} (fee {}{}) {}",
+            self.amount(),
+            self.recipient,
+            self.fee(),
+            if self.is_partial { ", partial" } else { "" },
+            self.signature
+        )
+    }
+}
+
+/// Withdrawal sized so the recipient receives an exact amount
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetWithdrawResult<T> {
+    /// Amount that left the private balance: the net amount plus the relayer fee
+    pub gross: u64,
+    /// Amount the recipient received
+    pub net: u64,
+    /// The underlying withdrawal
+    pub withdrawal: T,
+}
+
+/// Outcome of a withdrawal that reports balance problems instead of erroring
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum WithdrawOutcome {
+    /// The withdrawal was sent in full
+    Ok(WithdrawResult),
+
+    /// The private balance cannot cover the amount plus fees
+    Insufficient {
+        /// Total private balance in lamports
+        have: u64,
+        /// Largest amount a single withdrawal can send after fees
+        max_single_tx: u64,
+    },
+
+    /// The balance covers the amount, but it is spread over more notes than
+    /// one transaction can spend (two inputs)
+    NeedsConsolidation {
+        /// Total private balance in lamports
+        have: u64,
+        /// Largest amount a single withdrawal can send after fees
+        max_single_tx: u64,
+        /// Number of unspent notes holding the balance
+        notes: usize,
+    },
+}
+
+/// Fail with `FeeExceedsLimit` if `fee` is above `max_fee`
+pub fn check_fee_limit(fee: u64, max_fee: Option<u64>) -> Result<()> {
+    match max_fee {
+        Some(max) if fee > max => Err(PrivacyCashError::FeeExceedsLimit { fee, max }),
+        _ => Ok(()),
+    }
+}
+
+/// Check whether `amount` can be withdrawn in one transaction
+///
+/// `fee_rate` and `rent_fee` are the relayer's withdrawal fee rate and fixed
+/// fee in lamports. Returns `None` when the two largest notes cover the amount
+/// plus fees, otherwise the outcome to report instead of withdrawing.
+/// `rent_lamports` is the fixed rent fee in lamports, as for [`withdraw_fee`].
+pub(crate) fn check_withdrawable(
+    note_amounts: &[u64],
+    amount: u64,
+    fee_rate: f64,
+    rent_lamports: f64,
+) -> Option<WithdrawOutcome> {
+    let mut amounts: Vec<u64> = note_amounts.iter().copied().filter(|a| *a > 0).collect();
+    amounts.sort_unstable_by(|a, b| b.cmp(a));
+
+    let have: u64 = amounts.iter().sum();
+    let spendable: u64 = amounts.iter().take(2).sum();
+    let total = |x: u64| x.saturating_add(withdraw_fee(x, fee_rate, rent_lamports));
+    let required = total(amount);
+    if spendable >= required {
+        return None;
+    }
+
+    // Largest x with x + withdraw_fee(x) <= spendable: estimate, then step
+    // to the exact value since the fee is rounded down to whole lamports
+    let rent = rent_lamports as u64;
+    let mut max_single_tx = (spendable.saturating_sub(rent) as f64 / (1.0 + fee_rate)) as u64;
+    while max_single_tx > 0 && total(max_single_tx) > spendable {
+        max_single_tx -= 1;
+    }
+    while total(max_single_tx + 1) <= spendable {
+        max_single_tx += 1;
+    }
+    Some(if have >= required {
+        WithdrawOutcome::NeedsConsolidation {
+            have,
+            max_single_tx,
+            notes: amounts.len(),
+        }
+    } else {
+        WithdrawOutcome::Insufficient { have, max_single_tx }
+    })
+}
+
+/// Relayer fee on a SOL withdrawal of `lamports`
+///
+/// The fee is taken from the private balance on top of the amount sent.
+/// `rent_lamports` is the fixed rent fee in lamports.
+pub fn withdraw_fee(lamports: u64, fee_rate: f64, rent_lamports: f64) -> u64 {
+    (lamports as f64 * fee_rate + rent_lamports) as u64
+}
+
+/// Withdrawal fee rate and rent fee in lamports from the relayer config, as
+/// taken by [`withdraw_fee`]
+pub(crate) async fn withdraw_fee_config() -> Result<(f64, f64)> {
+    let fee_rate = Config::get_withdraw_fee_rate().await?;
+    let rent_fee = Config::get_withdraw_rent_fee().await?;
+    Ok((fee_rate, LAMPORTS_PER_SOL as f64 * rent_fee))
+}
+
+/// Sort notes largest first; a withdrawal spends the first one or two
+pub(crate) fn largest_first(mut utxos: Vec<Utxo>) -> Result<Vec<Utxo>> {
+    if utxos.is_empty() {
+        return Err(PrivacyCashError::NoUtxosAvailable);
+    }
+    utxos.sort_by(|a, b| b.amount.cmp(&a.amount));
+    Ok(utxos)
+}
+
+/// Lamports a SOL withdrawal sends from inputs worth `total_input`
+///
+/// The fee is paid on top of `amount`. When the inputs cannot cover both,
+/// the withdrawal is partial and sends everything but the fee. Returns the
+/// amount sent and whether it is partial.
+pub(crate) fn withdraw_amount(
+    total_input: &BigUint,
+    amount: u64,
+    fee: u64,
+) -> Result<(u64, bool)> {
+    if *total_input >= BigUint::from(amount) + fee {
+        return Ok((amount, false));
+    }
+    let total = total_input.to_u64().unwrap_or(u64::MAX);
+    if total <= fee {
+        return Err(PrivacyCashError::InsufficientBalance { have: total, need: fee });
+    }
+    Ok((total - fee, true))
+}
+
 /// Parameters for withdrawal
 pub struct WithdrawParams<'a> {
     pub connection: &'a RpcClient,
@@ -55,6 +232,48 @@ pub struct WithdrawParams<'a> {
     pub recipient: &'a Pubkey,
     pub key_base_path: &'a str,
     pub referrer: Option<&'a str>,
+    /// Called with the final transaction details before relaying; `false` aborts
+    pub approval: Option<&'a ApprovalHook>,
+    /// Run after approval, just before relaying; an error aborts
+    pub before_relay: Option<&'a BeforeRelay<'a>>,
+    /// Abort before proving if the relayer fee would exceed this many lamports
+    pub max_fee: Option<u64>,
+    /// Spend exactly these notes (one or two) instead of the two largest;
+    /// never withdraws partially
+    pub input_utxos: Option<&'a [Utxo]>,
+    /// Who each output note is created for and encrypted to; `Sender` keeps it
+    pub output_targets: [OutputTarget<'a>; 2],
+    /// Which output is the sender's change; it must target `Sender`
+    pub change_output: ChangeOutput,
+    /// How the output notes get their blindings
+    pub blinding: BlindingSource,
+    /// Limit on generating the proof
+    pub proof_timeout: Duration,
+}
+
+/// Validate caller-chosen inputs: one or two distinct notes
+pub(crate) fn check_chosen_inputs(utxos: &[Utxo]) -> Result<Vec<Utxo>> {
+    if utxos.is_empty() || utxos.len() > 2 {
+        return Err(PrivacyCashError::InvalidInput(format!(
+            "Expected one or two input notes, got {}",
+            utxos.len()
+        )));
+    }
+    if utxos.len() == 2 && utxos[0].get_commitment()? == utxos[1].get_commitment()? {
+        return Err(PrivacyCashError::InvalidInput(
+            "The same note cannot be spent twice in one transaction".to_string(),
+        ));
+    }
+    Ok(utxos.to_vec())
+}
+
+/// Output amounts of a withdrawal: what stays in the pool is the sender's
+/// change, and the other output is a zero note
+pub(crate) fn withdraw_output_amounts(
+    change_output: ChangeOutput,
+    change_amount: BigUint,
+) -> [BigUint; 2] {
+    change_output.arrange(BigUint::zero(), change_amount)
 }
 
 /// Execute a withdrawal
@@ -64,27 +283,38 @@ pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
         keypair,
         encryption_service,
         storage,
-        mut amount_in_lamports,
+        amount_in_lamports,
         recipient,
         key_base_path,
         referrer,
+        approval,
+        before_relay,
+        max_fee,
+        input_utxos,
+        output_targets,
+        change_output,
+        blinding,
+        proof_timeout,
     } = params;
 
+    // The transact instruction only takes the two-input circuit
+    CircuitArity::from_key_base_path(key_base_path)?.ensure_supported_on_chain()?;
+    change_output.check_targets(&output_targets)?;
+
     let public_key = keypair.pubkey();
 
-    // Get fee configuration
-    let withdraw_fee_rate = Config::get_withdraw_fee_rate().await?;
-    let withdraw_rent_fee = Config::get_withdraw_rent_fee().await?;
+    // Fail before proving if the relayer would reject the amount
+    Config::check_minimum_withdrawal("sol", LAMPORTS_PER_SOL, amount_in_lamports).await?;
 
-    let fee_in_lamports =
-        (amount_in_lamports as f64 * withdraw_fee_rate + LAMPORTS_PER_SOL as f64 * withdraw_rent_fee)
-            as u64;
+    // Get fee configuration
+    let (withdraw_fee_rate, rent_lamports) = withdraw_fee_config().await?;
+    let fee_in_lamports = withdraw_fee(amount_in_lamports, withdraw_fee_rate, rent_lamports);
+    check_fee_limit(fee_in_lamports, max_fee)?;
 
     // Note: We do NOT subtract fee from amount here.
     // The user requests X lamports to withdraw, and the fee is taken from their balance.
     // ext_amount = -amount_in_lamports (the amount leaving the pool)
     // change = total_input - amount_in_lamports - fee
-    let mut is_partial = false;
 
     log::info!(
         "Starting withdrawal of {} lamports (fee: {})",
@@ -96,33 +326,31 @@ pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
 
     // Get tree state
     let tree_state = query_remote_tree_state(None).await?;
+    tree_state.ensure_room_for(TX_OUTPUTS)?;
 
-    // Get UTXO keypairs
+    // Dummy inputs use the V1 keypair; outputs take their target's V2 keypair
     let utxo_private_key_v1 = encryption_service.get_utxo_private_key_v1()?;
     let utxo_keypair_v1 = ZkKeypair::from_hex(&utxo_private_key_v1)?;
 
-    let utxo_private_key_v2 = encryption_service.get_utxo_private_key_v2()?;
-    let utxo_keypair_v2 = ZkKeypair::from_hex(&utxo_private_key_v2)?;
-
-    // Fetch existing UTXOs
-    let mut unspent_utxos =
-        get_utxos(connection, &public_key, encryption_service, storage, None).await?;
-
-    if unspent_utxos.is_empty() {
-        return Err(PrivacyCashError::NoUtxosAvailable);
-    }
-
-    // Sort by amount descending
-    unspent_utxos.sort_by(|a, b| b.amount.cmp(&a.amount));
+    let unspent_utxos = match input_utxos {
+        Some(utxos) => check_chosen_inputs(utxos)?,
+        None => largest_first(
+            get_utxos(connection, &public_key, encryption_service, storage, None).await?,
+        )?,
+    };
 
-    let first_input = unspent_utxos[0].clone();
+    // Each input must carry the key of its own version to spend
+    let first_input = encryption_service.with_version_keypair(&unspent_utxos[0])?;
     let second_input = if unspent_utxos.len() > 1 {
-        unspent_utxos[1].clone()
+        encryption_service.with_version_keypair(&unspent_utxos[1])?
     } else {
         Utxo::dummy(utxo_keypair_v1.clone(), None)
     };
 
     let inputs = vec![first_input.clone(), second_input.clone()];
+
+    // Fail before proving if an input's spend is already finalized
+    ensure_unspent_finalized(connection, &inputs).await?;
     let total_input_amount = first_input.amount.clone() + second_input.amount.clone();
 
     if total_input_amount.is_zero() {
@@ -131,25 +359,15 @@ pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
 
     // Check if partial withdrawal
     let required = BigUint::from(amount_in_lamports + fee_in_lamports);
-    if total_input_amount < required {
-        is_partial = true;
-        // In partial withdrawal, we withdraw everything minus the fee
-        let total_as_u64 = total_input_amount
-            .to_u64_digits()
-            .first()
-            .copied()
-            .unwrap_or(0);
-        
-        // If balance is less than fee, we can't withdraw anything
-        if total_as_u64 <= fee_in_lamports {
-            return Err(PrivacyCashError::InsufficientBalance {
-                have: total_as_u64,
-                need: fee_in_lamports,
-            });
-        }
-        
-        amount_in_lamports = total_as_u64.saturating_sub(fee_in_lamports);
+    if total_input_amount < required && input_utxos.is_some() {
+        return Err(PrivacyCashError::InsufficientBalance {
+            have: total_input_amount.to_u64().unwrap_or(u64::MAX),
+            need: amount_in_lamports.saturating_add(fee_in_lamports),
+        });
     }
+    // In a partial withdrawal, we withdraw everything minus the fee
+    let (amount_in_lamports, is_partial) =
+        withdraw_amount(&total_input_amount, amount_in_lamports, fee_in_lamports)?;
 
     // Calculate change
     let change_amount = total_input_amount.clone()
@@ -179,18 +397,25 @@ pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
         },
     ];
 
-    // Create outputs with V2 keypair
+    // Create outputs
+    let [first_owner, second_owner] = [
+        output_targets[0].utxo_keypair(encryption_service)?,
+        output_targets[1].utxo_keypair(encryption_service)?,
+    ];
+    let [first_amount, second_amount] = withdraw_output_amounts(change_output, change_amount);
     let outputs = vec![
-        Utxo::new(
-            change_amount,
-            utxo_keypair_v2.clone(),
+        Utxo::with_blinding(
+            first_amount,
+            blinding.next(encryption_service, storage)?,
+            first_owner,
             tree_state.next_index,
             None,
             Some(UtxoVersion::V2),
         ),
-        Utxo::new(
-            0u64,
-            utxo_keypair_v2.clone(),
+        Utxo::with_blinding(
+            second_amount,
+            blinding.next(encryption_service, storage)?,
+            second_owner,
             tree_state.next_index + 1,
             None,
             Some(UtxoVersion::V2),
@@ -206,8 +431,8 @@ pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
     let output_commitments = vec![outputs[0].get_commitment()?, outputs[1].get_commitment()?];
 
     // Encrypt outputs
-    let encrypted_output1 = encryption_service.encrypt_utxo(&outputs[0])?;
-    let encrypted_output2 = encryption_service.encrypt_utxo(&outputs[1])?;
+    let encrypted_output1 = output_targets[0].encrypt(encryption_service, &outputs[0])?;
+    let encrypted_output2 = output_targets[1].encrypt(encryption_service, &outputs[1])?;
 
     // Create ExtData
     let sol_mint = Pubkey::from_str("11111111111111111111111111111112").unwrap();
@@ -222,6 +447,9 @@ pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
         mint_address: sol_mint,
     };
 
+    // Reject outputs that cannot fit in a transaction before spending time on a proof
+    check_transaction_size(ext_data.min_transaction_size(TRANSACT_ACCOUNTS))?;
+
     let ext_data_hash = ext_data.hash();
 
     // Build circuit input
@@ -250,8 +478,8 @@ pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
 
     // Generate proof using pure Rust prover (iOS compatible, no Node.js needed)
     log::info!("Generating ZK proof using pure Rust prover...");
-    let prover = RustProver::new(key_base_path);
-    let (proof, public_signals) = prover.prove(&circuit_input).await?;
+    let prover = RustProver::new(key_base_path).with_timeout(proof_timeout);
+    let (proof, public_signals) = prover.prove_recorded(&circuit_input, storage).await?;
 
     // Parse proof to bytes
     let proof_bytes = parse_proof_to_bytes(&proof)?;
@@ -292,14 +520,29 @@ pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
     
     log::debug!("Withdraw params: {:?}", withdraw_params);
 
+    approve_for_relay(
+        approval,
+        before_relay,
+        TxPreview {
+            kind: TransactionKind::Withdraw,
+            token: "sol".to_string(),
+            recipient: Some(recipient.to_string()),
+            amount: amount_in_lamports,
+            fee: fee_in_lamports,
+            input_nullifiers: input_nullifiers.clone(),
+            output_commitments: output_commitments.clone(),
+        },
+    )?;
+
     // Submit to backend
     log::info!("Submitting withdrawal to relayer...");
     let signature = submit_withdraw_to_indexer(withdraw_params).await?;
 
     // Wait for confirmation
     log::info!("Waiting for confirmation...");
-    wait_for_confirmation(&encrypted_output1, None).await?;
+    wait_for_confirmation(&signature, &encrypted_output1, None).await?;
 
+    metrics().inc_counter(WITHDRAWALS_TOTAL, &[("token", "sol")]);
     Ok(WithdrawResult {
         signature,
         recipient: recipient.to_string(),
@@ -311,16 +554,14 @@ pub async fn withdraw(params: WithdrawParams<'_>) -> Result<WithdrawResult> {
 
 /// Submit withdrawal to indexer backend
 async fn submit_withdraw_to_indexer(params: serde_json::Value) -> Result<String> {
-    let client = reqwest::Client::new();
-    let response = client
-        .post(format!("{}/withdraw", *RELAYER_API_URL))
-        .json(&params)
-        .send()
+    let response = transport()
+        .post_json(&format!("{}/withdraw", *RELAYER_API_URL), &params)
         .await
         .map_err(|e| PrivacyCashError::ApiError(format!("Withdraw submit failed: {}", e)))?;
 
-    if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_default();
+    if !response.is_success() {
+        record_relayer_error("/withdraw", response.status);
+        let error_text = response.body;
         return Err(PrivacyCashError::ApiError(format!(
             "Withdraw failed: {}",
             error_text
@@ -332,32 +573,33 @@ async fn submit_withdraw_to_indexer(params: serde_json::Value) -> Result<String>
         signature: String,
     }
 
-    let result: Response = response
-        .json()
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("Parse response: {}", e)))?;
+    let result: Response = response.json_with_context("Failed to parse relayer response")?;
 
     Ok(result.signature)
 }
 
 /// Wait for transaction confirmation
-async fn wait_for_confirmation(encrypted_output: &[u8], token_name: Option<&str>) -> Result<()> {
+async fn wait_for_confirmation(
+    signature: &str,
+    encrypted_output: &[u8],
+    token_name: Option<&str>,
+) -> Result<()> {
     let encrypted_hex = hex::encode(encrypted_output);
     let mut retries = 0;
     let max_retries = 10;
 
     loop {
-        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+        clock::sleep(Duration::from_secs(2)).await;
 
         let mut url = format!("{}/utxos/check/{}", *RELAYER_API_URL, encrypted_hex);
         if let Some(token) = token_name {
             url = format!("{}?token={}", url, token);
         }
 
-        let response = reqwest::get(&url).await;
+        let response = transport().get_json(&url).await;
 
         if let Ok(resp) = response {
-            if let Ok(data) = resp.json::<serde_json::Value>().await {
+            if let Ok(data) = resp.json::<serde_json::Value>() {
                 if data.get("exists").and_then(|v| v.as_bool()).unwrap_or(false) {
                     return Ok(());
                 }
@@ -366,7 +608,10 @@ async fn wait_for_confirmation(encrypted_output: &[u8], token_name: Option<&str>
 
         retries += 1;
         if retries >= max_retries {
-            return Err(PrivacyCashError::ConfirmationTimeout { retries });
+            return Err(PrivacyCashError::ConfirmationTimeout {
+                signature: signature.to_string(),
+                retries,
+            });
         }
 
         log::info!("Confirming transaction... (retry {})", retries);
@@ -390,7 +635,8 @@ fn serialize_withdraw_proof(
     data.extend_from_slice(&proof_bytes.proof_c);
 
     // Public signals
-    for signal in signals.iter().take(7) {
+    let signal_count = CircuitArity::TRANSACTION2.public_signal_count();
+    for signal in signals.iter().take(signal_count) {
         data.extend_from_slice(signal);
     }
 
@@ -418,3 +664,73 @@ impl BigUintExt for BigUint {
         self.to_u64().unwrap_or(0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_withdraw_change_stays_with_sender() {
+        let recipient = EncryptionService::new();
+        let targets = [OutputTarget::Recipient(&recipient), OutputTarget::Sender];
+        let change_output = ChangeOutput::default();
+        change_output.check_targets(&targets).unwrap();
+
+        // The remaining balance lands on the sender's output, not the recipient's
+        let amounts = withdraw_output_amounts(change_output, BigUint::from(700u64));
+        assert_eq!(amounts, [BigUint::zero(), BigUint::from(700u64)]);
+        let amounts = withdraw_output_amounts(ChangeOutput::First, BigUint::from(700u64));
+        assert_eq!(amounts, [BigUint::from(700u64), BigUint::zero()]);
+    }
+
+    #[test]
+    fn test_check_withdrawable() {
+        // Two largest notes cover amount + 1% + 10 rent
+        assert!(check_withdrawable(&[600, 500, 100], 1_000, 0.01, 10.0).is_none());
+
+        // 882 + withdraw_fee(882) is exactly 900, since the fee rounds down
+        match check_withdrawable(&[500, 400, 300], 1_000, 0.01, 10.0) {
+            Some(WithdrawOutcome::NeedsConsolidation { have, max_single_tx, notes }) => {
+                assert_eq!((have, notes), (1_200, 3));
+                assert_eq!(max_single_tx, 882);
+                assert_eq!(max_single_tx + withdraw_fee(max_single_tx, 0.01, 10.0), 900);
+            }
+            other => panic!("unexpected outcome: {:?}", other),
+        }
+
+        assert!(matches!(
+            check_withdrawable(&[500, 0], 1_000, 0.01, 10.0),
+            Some(WithdrawOutcome::Insufficient { have: 500, max_single_tx: 486 })
+        ));
+        assert!(matches!(
+            check_withdrawable(&[5], 1_000, 0.01, 10.0),
+            Some(WithdrawOutcome::Insufficient { have: 5, max_single_tx: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_check_chosen_inputs() {
+        let keypair = ZkKeypair::generate().unwrap();
+        let note = Utxo::new(5u64, keypair.clone(), 0, None, None);
+        let other = Utxo::new(7u64, keypair, 1, None, None);
+
+        assert_eq!(check_chosen_inputs(&[note.clone()]).unwrap().len(), 1);
+        assert_eq!(check_chosen_inputs(&[note.clone(), other.clone()]).unwrap().len(), 2);
+        assert!(check_chosen_inputs(&[]).is_err());
+        assert!(check_chosen_inputs(&[note.clone(), other, note.clone()]).is_err());
+        assert!(matches!(
+            check_chosen_inputs(&[note.clone(), note]),
+            Err(PrivacyCashError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_fee_limit() {
+        assert!(check_fee_limit(5_000, None).is_ok());
+        assert!(check_fee_limit(5_000, Some(5_000)).is_ok());
+        assert!(matches!(
+            check_fee_limit(5_001, Some(5_000)),
+            Err(PrivacyCashError::FeeExceedsLimit { fee: 5_001, max: 5_000 })
+        ));
+    }
+}
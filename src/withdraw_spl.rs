@@ -1,30 +1,63 @@
 //! Withdrawal functionality for SPL tokens
-
+//!
+//! Besides the nullifier PDAs (see [`crate::withdraw`]), an SPL withdrawal may
+//! create the recipient's associated token account. That account holds the
+//! withdrawn tokens and belongs to the recipient, so it is not closed here.
+//! With [`DeliveryMode::ViaSelf`] the signer pays for that account instead.
+
+use crate::blinding::BlindingSource;
+use crate::clock;
 use crate::config::Config;
 use crate::constants::{
-    find_token_by_mint, ALT_ADDRESS, FEE_RECIPIENT, PROGRAM_ID, RELAYER_API_URL,
-    TRANSACT_SPL_IX_DISCRIMINATOR,
+    find_token_by_mint, TokenInfo, ALT_ADDRESS, FEE_RECIPIENT, PROGRAM_ID, RELAYER_API_URL,
+    TRANSACT_SPL_ACCOUNTS, TRANSACT_SPL_IX_DISCRIMINATOR, TX_OUTPUTS,
 };
-use crate::encryption::EncryptionService;
+use crate::deposit_spl::ensure_token_account;
+use crate::encryption::{ChangeOutput, EncryptionService, OutputTarget};
 use crate::error::{PrivacyCashError, Result};
+use crate::get_utxos::ensure_unspent_finalized;
 use crate::get_utxos_spl::get_utxos_spl;
+use crate::http::transport;
 use crate::keypair::ZkKeypair;
 use crate::merkle_tree::MerkleTree;
-use crate::prover::{parse_proof_to_bytes, parse_public_signals_to_bytes, CircuitInput};
+use crate::metrics::{metrics, record_relayer_error, WITHDRAWALS_TOTAL};
+use crate::preview::{approve_for_relay, ApprovalHook, BeforeRelay, TransactionKind, TxPreview};
+use crate::prover::{
+    parse_proof_to_bytes, parse_public_signals_to_bytes, CircuitArity, CircuitInput,
+};
 use crate::prover_rust::RustProver;
 use crate::storage::Storage;
 use crate::utxo::{Utxo, UtxoVersion};
 use crate::utils::{
-    calculate_public_amount, fetch_merkle_proof, find_cross_check_nullifier_pdas,
-    find_nullifier_pdas, get_mint_address_field, get_program_accounts, get_spl_tree_account,
-    query_remote_tree_state, ExtData,
+    calculate_public_amount, check_transaction_size, fetch_merkle_proof,
+    find_cross_check_nullifier_pdas, find_nullifier_pdas, get_mint_address_field,
+    get_program_accounts, get_spl_tree_account, query_remote_tree_state, ExtData,
 };
+use crate::withdraw::{check_fee_limit, largest_first, withdraw_output_amounts};
 use num_bigint::BigUint;
 use num_traits::{ToPrimitive, Zero};
 use serde::{Deserialize, Serialize};
 use solana_client::rpc_client::RpcClient;
-use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer, transaction::Transaction};
 use spl_associated_token_account::get_associated_token_address;
+use std::time::Duration;
+
+/// How withdrawn tokens reach the recipient
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeliveryMode {
+    /// Withdraw to the recipient's token account, failing if it does not exist
+    DirectAta,
+    /// Withdraw to the recipient's token account, created by the program if missing
+    #[default]
+    CreateAta,
+    /// Withdraw to the signer's own token account, then transfer to the recipient
+    /// in a follow-up transaction that creates the recipient's account if needed
+    ///
+    /// The follow-up is an ordinary public transfer from the signer to the
+    /// recipient, so it links the two on-chain. Use it only when that link is
+    /// acceptable.
+    ViaSelf,
+}
 
 /// SPL Withdrawal result
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,6 +67,92 @@ pub struct WithdrawSplResult {
     pub base_units: u64,
     pub fee_base_units: u64,
     pub is_partial: bool,
+    /// Follow-up transfer to the recipient ([`DeliveryMode::ViaSelf`] only)
+    #[serde(default)]
+    pub transfer_signature: Option<String>,
+    /// Why the follow-up transfer failed; the withdrawn tokens are then still
+    /// in the signer's token account
+    #[serde(default)]
+    pub transfer_error: Option<String>,
+}
+
+impl std::fmt::Display for WithdrawSplResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Withdrew {} base units to {} (fee {}{}) {}",
+            self.base_units,
+            self.recipient,
+            self.fee_base_units,
+            if self.is_partial { ", partial" } else { "" },
+            self.signature
+        )
+    }
+}
+
+/// Relayer fee on an SPL withdrawal of `base_units`
+///
+/// The fee is taken out of the withdrawn amount, so the recipient receives
+/// `base_units` minus this. `rent_base_units` is the fixed rent fee in base
+/// units.
+pub fn spl_withdraw_fee(base_units: u64, fee_rate: f64, rent_base_units: f64) -> u64 {
+    (base_units as f64 * fee_rate + rent_base_units) as u64
+}
+
+/// Withdrawal fee rate and rent fee in base units of `token` from the relayer
+/// config, as taken by [`spl_withdraw_fee`]
+pub(crate) async fn spl_withdraw_fee_config(token: &TokenInfo) -> Result<(f64, f64)> {
+    let fee_rate = Config::get_withdraw_fee_rate().await?;
+    let rent_fee = Config::get_token_rent_fee(token.name).await?;
+    Ok((fee_rate, token.units_per_token as f64 * rent_fee))
+}
+
+/// Base units an SPL withdrawal sends from inputs worth `total_input`
+///
+/// `base_units` is the amount after the fee. When the inputs cannot cover it
+/// plus the fee, the withdrawal is partial and sends everything but the fee.
+/// Returns the amount sent and whether it is partial.
+pub(crate) fn spl_withdraw_amount(
+    total_input: &BigUint,
+    base_units: u64,
+    fee: u64,
+) -> (u64, bool) {
+    if *total_input >= BigUint::from(base_units) + fee {
+        return (base_units, false);
+    }
+    (total_input.to_u64().unwrap_or(0).saturating_sub(fee), true)
+}
+
+/// Smallest withdrawal amount from which the recipient receives exactly `net`
+///
+/// Inverts [`spl_withdraw_fee`]: starts from `(net + rent) / (1 - rate)` and
+/// steps to the exact amount, since the fee is rounded down to whole units.
+pub fn gross_for_net(net: u64, fee_rate: f64, rent_base_units: f64) -> Result<u64> {
+    if !(0.0..1.0).contains(&fee_rate) {
+        return Err(PrivacyCashError::InvalidInput(format!(
+            "Cannot invert a withdrawal fee rate of {}",
+            fee_rate
+        )));
+    }
+    let received =
+        |gross: u64| gross.saturating_sub(spl_withdraw_fee(gross, fee_rate, rent_base_units));
+    let too_large = || {
+        PrivacyCashError::InvalidInput(format!("No withdrawal amount nets {} base units", net))
+    };
+
+    let estimate = ((net as f64 + rent_base_units) / (1.0 - fee_rate)).ceil();
+    let mut gross = if estimate < u64::MAX as f64 { estimate as u64 } else { u64::MAX };
+    while received(gross) < net {
+        gross = gross.checked_add(1).ok_or_else(too_large)?;
+    }
+    while gross > 0 && received(gross - 1) >= net {
+        gross -= 1;
+    }
+
+    if received(gross) != net {
+        return Err(too_large());
+    }
+    Ok(gross)
 }
 
 /// Parameters for SPL withdrawal
@@ -47,6 +166,21 @@ pub struct WithdrawSplParams<'a> {
     pub recipient: &'a Pubkey,
     pub key_base_path: &'a str,
     pub referrer: Option<&'a str>,
+    pub delivery_mode: DeliveryMode,
+    /// Called with the final transaction details before relaying; `false` aborts
+    pub approval: Option<&'a ApprovalHook>,
+    /// Run after approval, just before relaying; an error aborts
+    pub before_relay: Option<&'a BeforeRelay<'a>>,
+    /// Abort before proving if the relayer fee would exceed this many base units
+    pub max_fee: Option<u64>,
+    /// Who each output note is created for and encrypted to; `Sender` keeps it
+    pub output_targets: [OutputTarget<'a>; 2],
+    /// Which output is the sender's change; it must target `Sender`
+    pub change_output: ChangeOutput,
+    /// How the output notes get their blindings
+    pub blinding: BlindingSource,
+    /// Limit on generating the proof
+    pub proof_timeout: Duration,
 }
 
 /// Execute an SPL token withdrawal
@@ -61,8 +195,20 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
         recipient,
         key_base_path,
         referrer,
+        delivery_mode,
+        approval,
+        before_relay,
+        max_fee,
+        output_targets,
+        change_output,
+        blinding,
+        proof_timeout,
     } = params;
 
+    // The transact instruction only takes the two-input circuit
+    CircuitArity::from_key_base_path(key_base_path)?.ensure_supported_on_chain()?;
+    change_output.check_targets(&output_targets)?;
+
     let token = find_token_by_mint(mint_address)
         .ok_or_else(|| PrivacyCashError::TokenNotSupported(mint_address.to_string()))?;
 
@@ -74,16 +220,24 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
 
     let public_key = keypair.pubkey();
 
-    // Get fee configuration
-    let withdraw_fee_rate = Config::get_withdraw_fee_rate().await?;
-    let token_rent_fee = Config::get_token_rent_fee(token.name).await?;
+    // Relayed withdrawals land in the signer's account when delivering via self
+    let final_recipient = recipient;
+    let via_self = delivery_mode == DeliveryMode::ViaSelf && *recipient != public_key;
+    let recipient = if via_self { &public_key } else { recipient };
+
+    if delivery_mode == DeliveryMode::DirectAta {
+        ensure_token_account(connection, recipient, mint_address)?;
+    }
 
-    let fee_base_units =
-        (base_units as f64 * withdraw_fee_rate + token.units_per_token as f64 * token_rent_fee)
-            as u64;
+    // Fail before proving if the relayer would reject the amount
+    Config::check_minimum_withdrawal(token.name, token.units_per_token, base_units).await?;
+
+    // Get fee configuration
+    let (withdraw_fee_rate, rent_base_units) = spl_withdraw_fee_config(&token).await?;
+    let fee_base_units = spl_withdraw_fee(base_units, withdraw_fee_rate, rent_base_units);
+    check_fee_limit(fee_base_units, max_fee)?;
 
     base_units = base_units.saturating_sub(fee_base_units);
-    let mut is_partial = false;
 
     if base_units == 0 {
         return Err(PrivacyCashError::WithdrawalAmountTooLow {
@@ -105,40 +259,30 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
 
     // Get tree state
     let tree_state = query_remote_tree_state(Some(token.name)).await?;
+    tree_state.ensure_room_for(TX_OUTPUTS)?;
 
-    // Get UTXO keypairs
+    // Dummy inputs use the V1 keypair; outputs take their target's V2 keypair
     let utxo_private_key_v1 = encryption_service.get_utxo_private_key_v1()?;
     let utxo_keypair_v1 = ZkKeypair::from_hex(&utxo_private_key_v1)?;
 
-    let utxo_private_key_v2 = encryption_service.get_utxo_private_key_v2()?;
-    let utxo_keypair_v2 = ZkKeypair::from_hex(&utxo_private_key_v2)?;
-
-    // Fetch existing UTXOs
-    let mut unspent_utxos = get_utxos_spl(
-        connection,
-        &public_key,
-        encryption_service,
-        storage,
-        mint_address,
-        None,
-    )
-    .await?;
+    // Fetch existing UTXOs, largest first
+    let unspent_utxos = largest_first(
+        get_utxos_spl(connection, &public_key, encryption_service, storage, mint_address, None)
+            .await?,
+    )?;
 
-    if unspent_utxos.is_empty() {
-        return Err(PrivacyCashError::NoUtxosAvailable);
-    }
-
-    // Sort by amount descending
-    unspent_utxos.sort_by(|a, b| b.amount.cmp(&a.amount));
-
-    let first_input = unspent_utxos[0].clone();
+    // Each input must carry the key of its own version to spend
+    let first_input = encryption_service.with_version_keypair(&unspent_utxos[0])?;
     let second_input = if unspent_utxos.len() > 1 {
-        unspent_utxos[1].clone()
+        encryption_service.with_version_keypair(&unspent_utxos[1])?
     } else {
         Utxo::dummy(utxo_keypair_v1.clone(), Some(&mint_address.to_string()))
     };
 
     let inputs = vec![first_input.clone(), second_input.clone()];
+
+    // Fail before proving if an input's spend is already finalized
+    ensure_unspent_finalized(connection, &inputs).await?;
     let total_input_amount = first_input.amount.clone() + second_input.amount.clone();
 
     if total_input_amount.is_zero() {
@@ -146,14 +290,8 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
     }
 
     // Check if partial withdrawal
-    let required = BigUint::from(base_units + fee_base_units);
-    if total_input_amount < required {
-        is_partial = true;
-        base_units = total_input_amount
-            .to_u64()
-            .unwrap_or(0)
-            .saturating_sub(fee_base_units);
-    }
+    let (base_units, is_partial) =
+        spl_withdraw_amount(&total_input_amount, base_units, fee_base_units);
 
     let change_amount =
         total_input_amount.clone() - BigUint::from(base_units) - BigUint::from(fee_base_units);
@@ -181,18 +319,25 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
         },
     ];
 
-    // Create outputs with V2 keypair
+    // Create outputs
+    let [first_owner, second_owner] = [
+        output_targets[0].utxo_keypair(encryption_service)?,
+        output_targets[1].utxo_keypair(encryption_service)?,
+    ];
+    let [first_amount, second_amount] = withdraw_output_amounts(change_output, change_amount);
     let outputs = vec![
-        Utxo::new(
-            change_amount,
-            utxo_keypair_v2.clone(),
+        Utxo::with_blinding(
+            first_amount,
+            blinding.next(encryption_service, storage)?,
+            first_owner,
             tree_state.next_index,
             Some(&mint_address.to_string()),
             Some(UtxoVersion::V2),
         ),
-        Utxo::new(
-            0u64,
-            utxo_keypair_v2.clone(),
+        Utxo::with_blinding(
+            second_amount,
+            blinding.next(encryption_service, storage)?,
+            second_owner,
             tree_state.next_index + 1,
             Some(&mint_address.to_string()),
             Some(UtxoVersion::V2),
@@ -205,8 +350,8 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
     let input_nullifiers = vec![inputs[0].get_nullifier()?, inputs[1].get_nullifier()?];
     let output_commitments = vec![outputs[0].get_commitment()?, outputs[1].get_commitment()?];
 
-    let encrypted_output1 = encryption_service.encrypt_utxo(&outputs[0])?;
-    let encrypted_output2 = encryption_service.encrypt_utxo(&outputs[1])?;
+    let encrypted_output1 = output_targets[0].encrypt(encryption_service, &outputs[0])?;
+    let encrypted_output2 = output_targets[1].encrypt(encryption_service, &outputs[1])?;
 
     let ext_data = ExtData {
         recipient: recipient_ata,
@@ -218,6 +363,9 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
         mint_address: *mint_address,
     };
 
+    // Reject outputs that cannot fit in a transaction before spending time on a proof
+    check_transaction_size(ext_data.min_transaction_size(TRANSACT_SPL_ACCOUNTS))?;
+
     let ext_data_hash = ext_data.hash();
 
     let circuit_input = CircuitInput {
@@ -245,8 +393,8 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
 
     // Generate proof using pure Rust prover (iOS compatible, no Node.js needed)
     log::info!("Generating ZK proof using pure Rust prover...");
-    let prover = RustProver::new(key_base_path);
-    let (proof, public_signals) = prover.prove(&circuit_input).await?;
+    let prover = RustProver::new(key_base_path).with_timeout(proof_timeout);
+    let (proof, public_signals) = prover.prove_recorded(&circuit_input, storage).await?;
 
     let proof_bytes = parse_proof_to_bytes(&proof)?;
     let signals_bytes = parse_public_signals_to_bytes(&public_signals)?;
@@ -280,21 +428,104 @@ pub async fn withdraw_spl(params: WithdrawSplParams<'_>) -> Result<WithdrawSplRe
         "referralWalletAddress": referrer
     });
 
+    approve_for_relay(
+        approval,
+        before_relay,
+        TxPreview {
+            kind: TransactionKind::Withdraw,
+            token: token.name.to_string(),
+            recipient: Some(recipient.to_string()),
+            amount: base_units,
+            fee: fee_base_units,
+            input_nullifiers: input_nullifiers.clone(),
+            output_commitments: output_commitments.clone(),
+        },
+    )?;
+
     log::info!("Submitting SPL withdrawal to relayer...");
     let signature = submit_spl_withdraw_to_indexer(withdraw_params).await?;
 
     log::info!("Waiting for confirmation...");
-    wait_for_spl_confirmation(&encrypted_output1, token.name).await?;
+    wait_for_spl_confirmation(&signature, &encrypted_output1, token.name).await?;
+
+    // The withdrawal has landed, so a failed transfer must not lose its signature
+    let (transfer_signature, transfer_error) = if via_self {
+        log::info!("Forwarding {} base units to {}", base_units, final_recipient);
+        forward_to_recipient(connection, keypair, mint_address, final_recipient, base_units)
+    } else {
+        (None, None)
+    };
 
+    metrics().inc_counter(WITHDRAWALS_TOTAL, &[("token", token.name)]);
     Ok(WithdrawSplResult {
         signature,
-        recipient: recipient.to_string(),
+        recipient: final_recipient.to_string(),
         base_units,
         fee_base_units,
         is_partial,
+        transfer_signature,
+        transfer_error,
     })
 }
 
+/// Run [`transfer_to_recipient`], returning its signature or its error
+fn forward_to_recipient(
+    connection: &RpcClient,
+    keypair: &Keypair,
+    mint_address: &Pubkey,
+    recipient: &Pubkey,
+    base_units: u64,
+) -> (Option<String>, Option<String>) {
+    match transfer_to_recipient(connection, keypair, mint_address, recipient, base_units) {
+        Ok(signature) => (Some(signature), None),
+        Err(e) => {
+            log::warn!("Withdrawal landed but the transfer to {} failed: {}", recipient, e);
+            (None, Some(e.to_string()))
+        }
+    }
+}
+
+/// Transfer tokens from the signer's token account to the recipient's,
+/// creating the recipient's account first if it is missing
+fn transfer_to_recipient(
+    connection: &RpcClient,
+    keypair: &Keypair,
+    mint_address: &Pubkey,
+    recipient: &Pubkey,
+    base_units: u64,
+) -> Result<String> {
+    let owner = keypair.pubkey();
+    let source_ata = get_associated_token_address(&owner, mint_address);
+    let recipient_ata = get_associated_token_address(recipient, mint_address);
+
+    let create_ata_ix =
+        spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            &owner,
+            recipient,
+            mint_address,
+            &spl_token::id(),
+        );
+    let transfer_ix = spl_token::instruction::transfer(
+        &spl_token::id(),
+        &source_ata,
+        &recipient_ata,
+        &owner,
+        &[],
+        base_units,
+    )
+    .map_err(|e| PrivacyCashError::TransactionError(e.to_string()))?;
+
+    let recent_blockhash = connection.get_latest_blockhash()?;
+    let tx = Transaction::new_signed_with_payer(
+        &[create_ata_ix, transfer_ix],
+        Some(&owner),
+        &[keypair],
+        recent_blockhash,
+    );
+
+    Ok(connection.send_and_confirm_transaction(&tx)?.to_string())
+}
+
 fn serialize_spl_proof(
     proof_bytes: &crate::prover::ProofBytes,
     signals: &[[u8; 32]],
@@ -308,7 +539,8 @@ fn serialize_spl_proof(
     data.extend_from_slice(&proof_bytes.proof_b);
     data.extend_from_slice(&proof_bytes.proof_c);
 
-    for signal in signals.iter().take(7) {
+    let signal_count = CircuitArity::TRANSACTION2.public_signal_count();
+    for signal in signals.iter().take(signal_count) {
         data.extend_from_slice(signal);
     }
 
@@ -324,16 +556,14 @@ fn serialize_spl_proof(
 }
 
 async fn submit_spl_withdraw_to_indexer(params: serde_json::Value) -> Result<String> {
-    let client = reqwest::Client::new();
-    let response = client
-        .post(format!("{}/withdraw/spl", *RELAYER_API_URL))
-        .json(&params)
-        .send()
+    let response = transport()
+        .post_json(&format!("{}/withdraw/spl", *RELAYER_API_URL), &params)
         .await
         .map_err(|e| PrivacyCashError::ApiError(format!("SPL withdraw submit failed: {}", e)))?;
 
-    if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_default();
+    if !response.is_success() {
+        record_relayer_error("/withdraw/spl", response.status);
+        let error_text = response.body;
         return Err(PrivacyCashError::ApiError(format!(
             "SPL withdraw failed: {}",
             error_text
@@ -345,31 +575,32 @@ async fn submit_spl_withdraw_to_indexer(params: serde_json::Value) -> Result<Str
         signature: String,
     }
 
-    let result: Response = response
-        .json()
-        .await
-        .map_err(|e| PrivacyCashError::ApiError(format!("Parse response: {}", e)))?;
+    let result: Response = response.json_with_context("Failed to parse relayer response")?;
 
     Ok(result.signature)
 }
 
-async fn wait_for_spl_confirmation(encrypted_output: &[u8], token_name: &str) -> Result<()> {
+async fn wait_for_spl_confirmation(
+    signature: &str,
+    encrypted_output: &[u8],
+    token_name: &str,
+) -> Result<()> {
     let encrypted_hex = hex::encode(encrypted_output);
     let mut retries = 0;
     let max_retries = 10;
 
     loop {
-        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+        clock::sleep(Duration::from_secs(2)).await;
 
         let url = format!(
             "{}/utxos/check/{}?token={}",
             *RELAYER_API_URL, encrypted_hex, token_name
         );
 
-        let response = reqwest::get(&url).await;
+        let response = transport().get_json(&url).await;
 
         if let Ok(resp) = response {
-            if let Ok(data) = resp.json::<serde_json::Value>().await {
+            if let Ok(data) = resp.json::<serde_json::Value>() {
                 if data.get("exists").and_then(|v| v.as_bool()).unwrap_or(false) {
                     return Ok(());
                 }
@@ -378,9 +609,45 @@ async fn wait_for_spl_confirmation(encrypted_output: &[u8], token_name: &str) ->
 
         retries += 1;
         if retries >= max_retries {
-            return Err(PrivacyCashError::ConfirmationTimeout { retries });
+            return Err(PrivacyCashError::ConfirmationTimeout {
+                signature: signature.to_string(),
+                retries,
+            });
         }
 
         log::info!("Confirming SPL transaction... (retry {})", retries);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gross_for_net() {
+        // 0.35% plus 0.85 USDC rent
+        let (rate, rent) = (0.0035, 850_000.0);
+        for net in [1u64, 999_999, 10_000_000, 123_456_789] {
+            let gross = gross_for_net(net, rate, rent).unwrap();
+            assert_eq!(gross - spl_withdraw_fee(gross, rate, rent), net);
+            assert!(gross - 1 - spl_withdraw_fee(gross - 1, rate, rent) < net);
+        }
+        assert_eq!(gross_for_net(100, 0.0, 0.0).unwrap(), 100);
+        assert!(gross_for_net(100, 1.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_failed_forward_is_reported() {
+        let connection = RpcClient::new_mock("fails".to_string());
+        let keypair = Keypair::new();
+        let (signature, error) = forward_to_recipient(
+            &connection,
+            &keypair,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            1_000,
+        );
+        assert_eq!(signature, None);
+        assert!(error.is_some());
+    }
+}